@@ -0,0 +1,74 @@
+// Alerts: pushes that stay up until someone at the panel acknowledges them.
+//
+// Pressing the configured button dismisses the alert and tells whoever raised
+// it, over MQTT and/or a webhook, so on-call and machine-fault displays can
+// see that someone has actually looked.
+
+use chrono::Local;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, StrokeAlignment};
+use serde::Deserialize;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::metrics;
+use crate::mqtt::{self, MqttOptions};
+use crate::push::Push;
+
+// Width of the red frame around an alert, in pixels
+const BORDER_WIDTH: u32 = 4;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertConfig {
+    /// BCM pin of the acknowledge button
+    pub button: Option<u64>,
+    /// The button pulls the pin high rather than to ground
+    pub active_high: bool,
+    /// Topic to publish acknowledgements on; needs the top-level `[mqtt]` broker
+    pub mqtt_topic: Option<String>,
+    /// URL to POST acknowledgements to
+    pub webhook: Option<String>,
+}
+
+/// Frames the whole panel in red, on top of whatever is already drawn.
+pub fn draw_border(fb: &mut Framebuffer) {
+    let style: PrimitiveStyle<Color> = PrimitiveStyleBuilder::new()
+        .stroke_color(Color::Red)
+        .stroke_width(BORDER_WIDTH)
+        .stroke_alignment(StrokeAlignment::Inside)
+        .build();
+    let Ok(()) = Rectangle::new(Point::zero(), fb.size()).into_styled(style).draw(fb);
+}
+
+/// Tells every configured listener that `alert` was acknowledged. Every
+/// listener is tried; the first error is returned.
+pub fn acknowledge(config: &AlertConfig, broker: Option<&MqttOptions>, alert: &Push) -> Result<(), String> {
+    let body = serde_json::json!({
+        "id": alert.alert.as_deref().unwrap_or_default(),
+        "text": alert.screen.text,
+        "acknowledged_at": Local::now().to_rfc3339(),
+    })
+    .to_string();
+    let mut result = Ok(());
+    if let (Some(topic), Some(broker)) = (&config.mqtt_topic, broker) {
+        let published = metrics::timed("alerts mqtt", || {
+            mqtt::Client::connect(broker).and_then(|mut client| client.publish(topic, body.as_bytes(), false))
+        });
+        if let Err(err) = published {
+            result = Err(format!("mqtt {topic}: {err}"));
+        }
+    }
+    if let Some(url) = &config.webhook {
+        let source = metrics::source_name("alerts webhook", url);
+        let posted = metrics::timed(&source, || {
+            ureq::post(url)
+                .set("Content-Type", "application/json")
+                .send_string(&body)
+                .map_err(|err| err.to_string())
+        });
+        if let Err(err) = posted {
+            result = result.and(Err(format!("{source}: {err}")));
+        }
+    }
+    result
+}
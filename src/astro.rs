@@ -0,0 +1,66 @@
+// When the sun rises and sets, worked out on the Pi rather than fetched: the
+// sunrise equation with the usual corrections for the equation of time and
+// refraction at the horizon, good to a minute or two away from the poles.
+// The moon's phase is in `widgets::icon`, beside the icons that show it.
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+
+// Julian date of 2000-01-01 12:00 UTC, and of the Unix epoch
+const J2000: f64 = 2_451_545.0;
+const UNIX_EPOCH: f64 = 2_440_587.5;
+const OBLIQUITY: f64 = 23.4397;
+// The sun's upper edge on the horizon, refraction included
+const HORIZON: f64 = -0.833;
+
+/// The sun on one day at one place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Daylight {
+    RisesAndSets { rise: DateTime<Utc>, set: DateTime<Utc> },
+    /// Midnight sun: up the whole day
+    AlwaysUp,
+    /// Polar night: down the whole day
+    AlwaysDown,
+}
+
+impl Daylight {
+    /// For `date` at `latitude` and `longitude` in degrees, north and east
+    /// positive. `date` is the day as it falls at that longitude.
+    pub fn on(date: NaiveDate, latitude: f64, longitude: f64) -> Self {
+        let days = (date - NaiveDate::from_ymd_opt(2000, 1, 1).unwrap_or_default()).num_days() as f64;
+        // Mean solar noon, then the sun's anomaly and ecliptic longitude then
+        let noon = days - longitude / 360.0;
+        let anomaly = (357.5291 + 0.985_600_28 * noon).rem_euclid(360.0).to_radians();
+        let center = 1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+        let ecliptic = (anomaly.to_degrees() + center + 180.0 + 102.9372).rem_euclid(360.0).to_radians();
+        let transit = J2000 + noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic).sin();
+        let declination = (ecliptic.sin() * OBLIQUITY.to_radians().sin()).asin();
+        let latitude = latitude.to_radians();
+        let cos_hour_angle = (HORIZON.to_radians().sin() - latitude.sin() * declination.sin())
+            / (latitude.cos() * declination.cos());
+        if cos_hour_angle < -1.0 {
+            return Daylight::AlwaysUp;
+        }
+        if cos_hour_angle > 1.0 {
+            return Daylight::AlwaysDown;
+        }
+        let half_day = cos_hour_angle.acos().to_degrees() / 360.0;
+        Daylight::RisesAndSets {
+            rise: from_julian(transit - half_day),
+            set: from_julian(transit + half_day),
+        }
+    }
+
+    /// How long the sun is up.
+    pub fn length(&self) -> TimeDelta {
+        match self {
+            Daylight::RisesAndSets { rise, set } => *set - *rise,
+            Daylight::AlwaysUp => TimeDelta::days(1),
+            Daylight::AlwaysDown => TimeDelta::zero(),
+        }
+    }
+}
+
+fn from_julian(date: f64) -> DateTime<Utc> {
+    let millis = ((date - UNIX_EPOCH) * 86_400_000.0).round() as i64;
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
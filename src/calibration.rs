@@ -0,0 +1,111 @@
+// How long the attached panel actually takes to do things.
+//
+// Resets and refreshes take very different times from one panel to the next
+// (and with temperature), so rather than guess, the daemon measures them once
+// and keeps the result: the scheduler takes the refresh time out of its waits
+// so pages land when they are due, and /healthz treats a refresh that runs
+// far past it as a stuck panel. `rust_raspi calibrate` measures again.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use embedded_hal::blocking::delay::DelayMs;
+use serde::{Deserialize, Serialize};
+
+use crate::epd::EpdController;
+use crate::pack::row_bytes;
+
+/// Where measurements are kept unless the config says otherwise.
+pub const DEFAULT_PATH: &str = "/var/lib/rust_raspi/calibration.json";
+
+// A refresh running this many times longer than measured has stalled
+const STALL_FACTOR: u32 = 3;
+// ... and never less than this, so a quick panel isn't called stuck on a slow day
+const MIN_STALL: Duration = Duration::from_secs(10);
+
+/// Measured durations for one panel, in milliseconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Calibration {
+    /// Which panel was measured: a descriptor's name, or the stock pHAT
+    pub panel: String,
+    /// Reset and init, until the controller is ready for drawing
+    pub reset_ms: u64,
+    pub full_ms: u64,
+    pub partial_ms: u64,
+    /// Unix time of the measurement
+    pub measured_at: u64,
+}
+
+impl Calibration {
+    /// Measures `epd` as `panel`: an init, then a full and a fast refresh of a
+    /// blank frame. The panel is left white and initialised.
+    pub fn measure<E, D>(epd: &mut E, panel: &str, delay: &mut D) -> Result<Self, E::Error>
+    where
+        E: EpdController,
+        D: DelayMs<u8>,
+    {
+        let (width, height) = epd.dimensions();
+        let len = row_bytes(width as usize) * height as usize;
+        let (bw, red) = (vec![0xFF; len], vec![0x00; len]);
+
+        let started = Instant::now();
+        epd.init(delay)?;
+        let reset = started.elapsed();
+
+        epd.write_planes(&bw, &red)?;
+        let started = Instant::now();
+        epd.refresh(delay)?;
+        let full = started.elapsed();
+
+        epd.write_planes(&bw, &red)?;
+        let started = Instant::now();
+        epd.refresh_fast(delay)?;
+        let partial = started.elapsed();
+
+        Ok(Calibration {
+            panel: panel.to_string(),
+            reset_ms: reset.as_millis() as u64,
+            full_ms: full.as_millis() as u64,
+            partial_ms: partial.as_millis() as u64,
+            measured_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+        })
+    }
+
+    /// Reads `path`, if it holds a measurement of `panel`; a different panel
+    /// means the numbers no longer apply.
+    pub fn load(path: &Path, panel: &str) -> Option<Self> {
+        fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Calibration>(&bytes).ok())
+            .filter(|calibration| calibration.panel == panel)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self).map_err(io::Error::other)?)
+    }
+
+    pub fn reset(&self) -> Duration {
+        Duration::from_millis(self.reset_ms)
+    }
+
+    pub fn full(&self) -> Duration {
+        Duration::from_millis(self.full_ms)
+    }
+
+    pub fn partial(&self) -> Duration {
+        Duration::from_millis(self.partial_ms)
+    }
+
+    /// How long a full refresh may take before the panel counts as stuck.
+    pub fn stall_limit(&self) -> Duration {
+        (self.full() * STALL_FACTOR).max(MIN_STALL)
+    }
+}
@@ -0,0 +1,144 @@
+// A set of screens shown one at a time, turned by a timer or by buttons.
+//
+// Every dashboard with more than one page needs the same loop: keep an index,
+// move it on every so often or when asked, draw the page and refresh. The
+// carousel also redraws its page between turns (a clock ticking over, a new
+// reading) and refreshes only when what it drew is different from what the
+// panel already shows, so a page that hasn't changed costs no refresh.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_hal::blocking::delay::DelayMs;
+
+use crate::daemon::PageTurn;
+use crate::epd::EpdController;
+use crate::framebuffer::Framebuffer;
+use crate::screens::{self, RenderContext, Screen};
+use crate::theme::Theme;
+
+pub struct Carousel {
+    pages: Vec<Box<dyn Screen + Send>>,
+    // How long each page stays before the next; `None` turns only when asked
+    interval: Option<Duration>,
+    // How often the page showing is redrawn to see whether it changed
+    redraw: Duration,
+    current: usize,
+    turned: Instant,
+    // Hash of the frame the panel shows
+    shown: Option<u64>,
+    theme: Theme,
+}
+
+impl Carousel {
+    /// Turns to the next page every `interval`, if given, and redraws the
+    /// page showing every `redraw` in between.
+    pub fn new(pages: Vec<Box<dyn Screen + Send>>, interval: Option<Duration>, redraw: Duration) -> Self {
+        Carousel {
+            pages,
+            interval,
+            redraw,
+            current: 0,
+            turned: Instant::now(),
+            shown: None,
+            theme: Theme::default(),
+        }
+    }
+
+    /// Draws the pages in `theme` instead of the default one.
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Index of the page showing (or about to be).
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// Moves to another page, or `Next` on through a page with more than
+    /// fits; the next `tick` draws it. `Refresh` refreshes the page showing
+    /// even if it hasn't changed.
+    pub fn turn(&mut self, turn: PageTurn) {
+        let len = self.pages.len().max(1);
+        self.current = match turn {
+            // A page with more to show scrolls before the page turns
+            PageTurn::Next if self.pages.get_mut(self.current).is_some_and(|page| page.scroll()) => self.current,
+            PageTurn::Next => (self.current + 1) % len,
+            PageTurn::Previous => (self.current + len - 1) % len,
+            PageTurn::First => 0,
+            PageTurn::Refresh => {
+                self.shown = None;
+                self.current
+            }
+        };
+        self.turned = Instant::now();
+    }
+
+    /// Turns the page if its time is up, draws it, and refreshes if the frame
+    /// changed. Returns how long until the next tick is due.
+    pub fn tick<E, D>(&mut self, epd: &mut E, fb: &mut Framebuffer, delay: &mut D) -> Result<Duration, E::Error>
+    where
+        E: EpdController,
+        D: DelayMs<u8>,
+    {
+        if let Some(interval) = self.interval
+            && self.turned.elapsed() >= interval
+        {
+            self.turn(PageTurn::Next);
+        }
+        let Some(page) = self.pages.get_mut(self.current) else {
+            return Ok(self.redraw);
+        };
+        screens::draw(page.as_mut(), fb, &RenderContext::now().with_theme(self.theme));
+        let hash = frame_hash(fb);
+        if self.shown != Some(hash) {
+            epd.show(fb, delay)?;
+            self.shown = Some(hash);
+        }
+        let until_turn = self.interval.map(|interval| interval.saturating_sub(self.turned.elapsed()));
+        Ok(until_turn.map_or(self.redraw, |until_turn| until_turn.min(self.redraw)))
+    }
+
+    /// Ticks for ever, turning pages as `turns` asks in between (buttons, see
+    /// `input::Buttons`). Only returns if the panel fails.
+    pub fn run<E, D>(
+        &mut self,
+        epd: &mut E,
+        fb: &mut Framebuffer,
+        delay: &mut D,
+        turns: &Receiver<PageTurn>,
+    ) -> Result<(), E::Error>
+    where
+        E: EpdController,
+        D: DelayMs<u8>,
+    {
+        loop {
+            let wait = self.tick(epd, fb, delay)?;
+            match turns.recv_timeout(wait) {
+                Ok(turn) => self.turn(turn),
+                Err(RecvTimeoutError::Timeout) => {}
+                // Nothing to turn the pages but the timer
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(wait),
+            }
+        }
+    }
+}
+
+fn frame_hash(fb: &Framebuffer) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    fb.bw_plane().hash(&mut hasher);
+    fb.red_plane().hash(&mut hasher);
+    hasher.finish()
+}
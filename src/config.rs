@@ -0,0 +1,332 @@
+// Daemon configuration, read from a TOML file.
+//
+// Every field has a default so an empty (or missing) file gives a working
+// daemon; command-line flags override whatever the file says.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::alerts::AlertConfig;
+use crate::calibration;
+use crate::daemon::ButtonsConfig;
+use crate::epd::ColorCapability;
+use crate::images::Placement;
+use crate::inky_driver::{BorderColor, BusyPolarity};
+use crate::layout::LayoutConfig;
+use crate::mqtt::MqttOptions;
+#[cfg(feature = "i2c")]
+use crate::power::pijuice::PiJuiceConfig;
+#[cfg(feature = "i2c")]
+use crate::power::rtc::RtcWakeConfig;
+use crate::presence::PresenceConfig;
+use crate::refresh_policy::RefreshPolicy;
+use crate::retry::RetryPolicy;
+#[cfg(feature = "linux")]
+use crate::sensors::mcp3008::BatteryAdcConfig;
+use crate::schedule::{NightConfig, ProfileConfig, RuleConfig};
+use crate::screens::air_quality::AirQualityConfig;
+use crate::screens::almanac::AlmanacConfig;
+use crate::screens::calendar::CalendarConfig;
+use crate::screens::chess::ChessConfig;
+use crate::screens::daily::DailyConfig;
+use crate::screens::docker::DockerConfig;
+use crate::screens::door_sign::DoorSignConfig;
+use crate::screens::energy::EnergyConfig;
+use crate::screens::flights::FlightsConfig;
+use crate::screens::github::GitHubConfig;
+use crate::screens::habits::HabitsConfig;
+use crate::screens::homeassistant::HomeAssistantConfig;
+#[cfg(feature = "i2c")]
+use crate::screens::indoor_climate::IndoorClimateConfig;
+use crate::screens::mail::MailConfig;
+use crate::screens::mpd::MpdConfig;
+use crate::screens::network::NetworkConfig;
+use crate::screens::octoprint::OctoPrintConfig;
+use crate::screens::pomodoro::PomodoroConfig;
+use crate::screens::speedtest::SpeedtestConfig;
+use crate::screens::plugin::PluginConfig;
+use crate::screens::ticker::TickerConfig;
+use crate::screens::transit::TransitConfig;
+use crate::screens::uptime::UptimeConfig;
+use crate::theme::Theme;
+use crate::thermal::ThermalConfig;
+
+/// Config file used when `--config` isn't given, if it exists.
+pub const DEFAULT_PATH: &str = "/etc/rust_raspi.toml";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address the HTTP API listens on
+    pub listen: String,
+    /// Unix socket other processes on the Pi can send commands to (see
+    /// `control`); off unless set, e.g. to `control::DEFAULT_PATH`
+    pub control_socket: Option<PathBuf>,
+    /// Serve `org.rustraspi.Display` on the system bus (see `dbus`)
+    pub dbus: bool,
+    /// Seconds between refreshes during the day
+    pub interval: u64,
+    /// Pages to cycle through, by name (see `screens::by_name`)
+    pub pages: Vec<String>,
+    /// Schedule for Saturdays, Sundays and holidays
+    pub weekend: Option<ProfileConfig>,
+    /// iCalendar file of holidays that get the weekend schedule
+    pub holidays: Option<PathBuf>,
+    pub night: Option<NightConfig>,
+    /// Exceptions to the schedule above, tried in order (night hours still win)
+    pub rules: Vec<RuleConfig>,
+    /// Where rules with a `presence` condition find out whether anyone is home
+    pub presence: Option<PresenceConfig>,
+    /// Seconds pushed content stays up when the push doesn't say; 0 keeps it until cleared
+    pub push_ttl: u64,
+    /// How alerts get acknowledged
+    pub alerts: AlertConfig,
+    /// Buttons that flip between pages
+    pub buttons: ButtonsConfig,
+    /// Position and thresholds for the `air_quality` page
+    pub air_quality: AirQualityConfig,
+    /// Position and tide source for the `almanac` page
+    pub almanac: AlmanacConfig,
+    /// Feeds for the `calendar` page
+    pub calendar: CalendarConfig,
+    /// Where the `chess` page gets each day's puzzle
+    pub chess: ChessConfig,
+    /// Where the `daily` page gets each day's entry
+    pub daily: DailyConfig,
+    /// Where the `docker` page finds the daemon
+    pub docker: DockerConfig,
+    /// Name, feeds and override button for the `door_sign` page
+    pub door_sign: DoorSignConfig,
+    /// MQTT topics for the `energy` page
+    pub energy: EnergyConfig,
+    /// Receiver and position for the `flights` page
+    pub flights: FlightsConfig,
+    /// Token and repos for the `github` page
+    pub github: GitHubConfig,
+    /// Habits, their buttons and where their marks are kept for the `habits` page
+    pub habits: HabitsConfig,
+    /// Server and entities for the `homeassistant` page
+    pub homeassistant: HomeAssistantConfig,
+    /// Sensor and sampling for the `indoor_climate` page
+    #[cfg(feature = "i2c")]
+    pub indoor_climate: IndoorClimateConfig,
+    /// IMAP accounts for the `mail` page
+    pub mail: MailConfig,
+    /// Where to find MPD for the `mpd` page
+    pub mpd: MpdConfig,
+    /// Interface and ping host for the `network` page
+    pub network: NetworkConfig,
+    /// Server and API key for the `octoprint` page
+    pub octoprint: OctoPrintConfig,
+    /// Lengths and buttons for the `pomodoro` page
+    pub pomodoro: PomodoroConfig,
+    /// Backend and schedule for the `speedtest` page
+    pub speedtest: SpeedtestConfig,
+    /// Symbols and price source for the `ticker` page
+    pub ticker: TickerConfig,
+    /// Endpoint, JSON mapping and stops for the `transit` page
+    pub transit: TransitConfig,
+    /// Hosts and URLs for the `uptime` page
+    pub uptime: UptimeConfig,
+    /// Broker shared by everything that talks MQTT
+    pub mqtt: Option<MqttOptions>,
+    pub splash: SplashConfig,
+    /// Colours, frames and fonts for every page, and night mode (see `theme`)
+    pub theme: Theme,
+    /// How images that don't match the panel's shape are fitted
+    pub placement: Placement,
+    /// TrueType font for the characters ProFont has no glyphs for, such as
+    /// Japanese, Chinese or Korean, e.g. a Noto Sans CJK `.ttc`
+    #[cfg(feature = "ttf")]
+    pub fallback_font: Option<PathBuf>,
+    /// Descriptor to drive the panel with instead of the stock pHAT sequence:
+    /// a path to one, or a name from `panel::DEFAULT_DIR`
+    pub panel: Option<String>,
+    /// Colour of the strip round the edge of the panel (descriptor panels set their own)
+    pub border: BorderColor,
+    /// Level of the BUSY line while the controller works: `active_high` for
+    /// the pHAT, `active_low` for some clones
+    pub busy_polarity: BusyPolarity,
+    /// Which colours the panel shows: `black_white_red` for the red pHAT,
+    /// `black_white_yellow` for the yellow one, `black_white` for neither
+    pub colors: ColorCapability,
+    /// File the panel's measured reset and refresh times are kept in (see
+    /// `calibration`); they are measured at start-up if it has none
+    pub calibration: Option<PathBuf>,
+    /// Print how long init, the RAM transfer and the refresh itself took, after every refresh
+    pub log_refreshes: bool,
+    /// Limits on how often the panel refreshes, whatever the pages ask for
+    pub refresh_policy: RefreshPolicy,
+    /// How hard to try again when an SPI write or GPIO toggle fails
+    pub retry: RetryPolicy,
+    /// Seconds one cycle of the refresh loop (fetching, drawing and the
+    /// refresh itself) may take before the panel is reset and re-initialised;
+    /// if that doesn't help, the daemon stops answering systemd's watchdog
+    /// (see `watchdog`)
+    pub stall_timeout: u64,
+    /// File the last frame is kept in, so that after a restart a page that
+    /// hasn't changed isn't refreshed again (see `frame_store::DEFAULT_PATH`)
+    pub last_frame: Option<PathBuf>,
+    /// Refreshing less often when the Pi or the panel is too hot
+    pub thermal: ThermalConfig,
+    /// Battery voltage read through an MCP3008 ADC (see `sensors::mcp3008`)
+    #[cfg(feature = "linux")]
+    pub battery_adc: Option<BatteryAdcConfig>,
+    /// PiJuice battery HAT, which also halts the Pi when the battery runs low
+    /// (see `power::pijuice`)
+    #[cfg(feature = "i2c")]
+    pub pijuice: Option<PiJuiceConfig>,
+    /// DS3231 alarm that `once --power-off` wakes the Pi with (see `power::rtc`)
+    #[cfg(feature = "i2c")]
+    pub rtc_wake: Option<RtcWakeConfig>,
+    /// Pages drawn from scene files, by the page name they are listed under in `pages`
+    pub layouts: BTreeMap<String, LayoutConfig>,
+    /// WebAssembly screens, by the page name they are listed under in `pages`
+    pub plugins: BTreeMap<String, PluginConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: "0.0.0.0:8080".to_string(),
+            control_socket: None,
+            dbus: false,
+            interval: 300,
+            pages: vec!["clock".to_string()],
+            weekend: None,
+            holidays: None,
+            night: None,
+            rules: Vec::new(),
+            presence: None,
+            mqtt: None,
+            push_ttl: 3600,
+            alerts: AlertConfig::default(),
+            buttons: ButtonsConfig::default(),
+            air_quality: AirQualityConfig::default(),
+            almanac: AlmanacConfig::default(),
+            calendar: CalendarConfig::default(),
+            chess: ChessConfig::default(),
+            daily: DailyConfig::default(),
+            docker: DockerConfig::default(),
+            door_sign: DoorSignConfig::default(),
+            energy: EnergyConfig::default(),
+            flights: FlightsConfig::default(),
+            github: GitHubConfig::default(),
+            habits: HabitsConfig::default(),
+            homeassistant: HomeAssistantConfig::default(),
+            #[cfg(feature = "i2c")]
+            indoor_climate: IndoorClimateConfig::default(),
+            mail: MailConfig::default(),
+            mpd: MpdConfig::default(),
+            network: NetworkConfig::default(),
+            octoprint: OctoPrintConfig::default(),
+            pomodoro: PomodoroConfig::default(),
+            speedtest: SpeedtestConfig::default(),
+            ticker: TickerConfig::default(),
+            transit: TransitConfig::default(),
+            uptime: UptimeConfig::default(),
+            splash: SplashConfig::default(),
+            theme: Theme::default(),
+            placement: Placement::default(),
+            #[cfg(feature = "ttf")]
+            fallback_font: None,
+            panel: None,
+            border: BorderColor::default(),
+            busy_polarity: BusyPolarity::default(),
+            colors: ColorCapability::default(),
+            calibration: Some(PathBuf::from(calibration::DEFAULT_PATH)),
+            log_refreshes: false,
+            refresh_policy: RefreshPolicy::default(),
+            retry: RetryPolicy::default(),
+            stall_timeout: 600,
+            last_frame: None,
+            thermal: ThermalConfig::default(),
+            #[cfg(feature = "linux")]
+            battery_adc: None,
+            #[cfg(feature = "i2c")]
+            pijuice: None,
+            #[cfg(feature = "i2c")]
+            rtc_wake: None,
+            layouts: BTreeMap::new(),
+            plugins: BTreeMap::new(),
+        }
+    }
+}
+
+/// Screens shown when the daemon starts and when it stops cleanly.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SplashConfig {
+    pub start: Option<ScreenConfig>,
+    pub stop: Option<ScreenConfig>,
+}
+
+impl Default for SplashConfig {
+    fn default() -> Self {
+        SplashConfig {
+            start: Some(ScreenConfig {
+                image: None,
+                placement: None,
+                text: Some("rust_raspi {version}\n{hostname}".to_string()),
+            }),
+            stop: None,
+        }
+    }
+}
+
+/// A static screen: an optional background image with optional text on top.
+/// Text may use `{version}` and `{hostname}`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScreenConfig {
+    pub image: Option<PathBuf>,
+    /// How `image` is fitted; defaults to the top-level `placement`
+    pub placement: Option<Placement>,
+    pub text: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            ConfigError::Parse(path, err) => write!(f, "{}: {err}", path.display()),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for io::Error {
+    fn from(err: ConfigError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|err| ConfigError::Io(path.to_path_buf(), err))?;
+        toml::from_str(&text).map_err(|err| ConfigError::Parse(path.to_path_buf(), err))
+    }
+
+    /// Loads `path` if given, otherwise [`DEFAULT_PATH`] if it exists, otherwise the defaults.
+    pub fn load_or_default(path: Option<&Path>) -> Result<Config, ConfigError> {
+        match path {
+            Some(path) => Config::load(path),
+            None if Path::new(DEFAULT_PATH).exists() => Config::load(Path::new(DEFAULT_PATH)),
+            None => Ok(Config::default()),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+// Local control socket: other processes on the Pi drive the display without
+// going through HTTP.
+//
+// A client connects to the Unix socket and writes one JSON command per line;
+// every line gets one JSON line back, `{"ok":true}` or `{"ok":false,"error":
+// "..."}`. A connection can stay open for as many commands as it likes.
+//
+//     {"cmd":"show_text","text":"Back in 5 minutes","ttl":300}
+//     {"cmd":"show_image","path":"/home/pi/qr.png","placement":{"fit":"crop"}}
+//     {"cmd":"clear"}
+//     {"cmd":"sleep"}
+//     {"cmd":"wake"}
+//
+// From a shell: `echo '{"cmd":"clear"}' | socat - UNIX-CONNECT:/run/rust_raspi/control.sock`.
+
+use std::fs::{self, Permissions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use serde::Deserialize;
+
+use crate::images::Placement;
+
+/// Where the daemon listens when the config turns the socket on without a path.
+pub const DEFAULT_PATH: &str = "/run/rust_raspi/control.sock";
+
+// Lines longer than this are refused rather than buffered
+const MAX_LINE: usize = 64 * 1024;
+
+/// One line of the protocol.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ControlCommand {
+    /// Shown as a push would be, for `ttl` seconds (the config's `push_ttl` if not given; 0 for ever)
+    ShowText { text: String, ttl: Option<u64> },
+    /// An image on the Pi's filesystem, fitted as `placement` says or as the config does
+    ShowImage {
+        path: PathBuf,
+        placement: Option<Placement>,
+        ttl: Option<u64>,
+    },
+    /// Drops whatever was shown, and the schedule carries on
+    Clear,
+    /// Puts the panel into deep sleep and stops refreshing it until `wake`
+    Sleep,
+    Wake,
+}
+
+/// Listens on `path` for ever, answering every command with `handler`. A
+/// socket left behind by an earlier run is replaced; the new one is made
+/// readable and writable by the owner and group only.
+pub fn serve<F>(path: &Path, handler: F) -> io::Result<()>
+where
+    F: Fn(ControlCommand) -> Result<(), String> + Send + Sync + 'static,
+{
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, Permissions::from_mode(0o660))?;
+    let handler = Arc::new(handler);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Control socket accept failed: {err}");
+                continue;
+            }
+        };
+        // One thread per client, so one that stays connected doesn't lock the rest out
+        let handler = Arc::clone(&handler);
+        thread::spawn(move || {
+            if let Err(err) = converse(stream, &*handler) {
+                eprintln!("Control socket client failed: {err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn converse(stream: UnixStream, handler: &dyn Fn(ControlCommand) -> Result<(), String>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.by_ref().take(MAX_LINE as u64).read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        if !line.ends_with('\n') && line.len() == MAX_LINE {
+            writeln!(writer, "{}", reply(Err("line too long".to_string())))?;
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = serde_json::from_str(&line)
+            .map_err(|err| err.to_string())
+            .and_then(handler);
+        writeln!(writer, "{}", reply(result))?;
+    }
+}
+
+fn reply(result: Result<(), String>) -> serde_json::Value {
+    match result {
+        Ok(()) => serde_json::json!({ "ok": true }),
+        Err(error) => serde_json::json!({ "ok": false, "error": error }),
+    }
+}
@@ -0,0 +1,72 @@
+// Best-effort cleanup when the process panics.
+//
+// A kiosk that crashes keeps showing whatever was last drawn, possibly for
+// days. The hook installed here records what went wrong next to a copy of the
+// last frame, then hands control to a caller-supplied function that should
+// blank the panel and put it to sleep.
+
+use std::fs;
+use std::io::Write;
+use std::panic::{self, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::framebuffer::Framebuffer;
+
+// Black/white plane followed by the red plane of the last frame sent to the panel
+static LAST_FRAME: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+/// Default directory for crash reports.
+pub const DEFAULT_REPORT_DIR: &str = "/var/lib/rust_raspi/crash";
+
+/// Keeps a copy of `fb` for the crash report. Call after every successful refresh.
+pub fn remember_frame(fb: &Framebuffer) {
+    if let Ok(mut last) = LAST_FRAME.lock() {
+        last.clear();
+        last.extend_from_slice(fb.bw_plane());
+        last.extend_from_slice(fb.red_plane());
+    }
+}
+
+/// Installs a panic hook that writes a report into `report_dir` and then calls `blank`.
+///
+/// The previous hook still runs first, so the message is printed as usual.
+/// `blank` runs inside the panic hook: it must not panic itself, and should
+/// swallow errors since there is no one left to report them to.
+pub fn install_panic_hook<F>(report_dir: impl Into<PathBuf>, blank: F)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let report_dir = report_dir.into();
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        previous(info);
+        match write_report(&report_dir, info) {
+            Ok(path) => eprintln!("Crash report written to {}", path.display()),
+            Err(err) => eprintln!("Could not write crash report: {err}"),
+        }
+        blank();
+    }));
+}
+
+// Writes <dir>/<unix time>.txt with the panic message and <unix time>.frame with the planes
+fn write_report(dir: &Path, info: &PanicHookInfo) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+
+    let report = dir.join(format!("{stamp}.txt"));
+    let mut file = fs::File::create(&report)?;
+    writeln!(file, "{info}")?;
+
+    // try_lock: the panic may have happened while the frame was being copied
+    if let Ok(frame) = LAST_FRAME.try_lock()
+        && !frame.is_empty()
+    {
+        fs::write(dir.join(format!("{stamp}.frame")), &*frame)?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,321 @@
+// The daemon's refresh loop: decides what to show and when.
+
+use std::time::{Duration, Instant};
+
+use embedded_hal::blocking::delay::DelayMs;
+use serde::Deserialize;
+
+use crate::calibration::Calibration;
+use crate::config::{Config, ConfigError};
+use crate::epd::EpdController;
+use crate::framebuffer::{Color, Framebuffer};
+use crate::presence::Presence;
+use crate::schedule::{Holidays, NightConfig, RuleConfig};
+use crate::screens::clock::NightClock;
+use crate::screens::{self, RenderContext, Screen};
+use crate::theme::Theme;
+
+// Never spin faster than this, whatever the schedule works out to
+const MIN_WAIT: Duration = Duration::from_secs(1);
+
+// One schedule: how often to refresh and which pages to rotate through
+struct Profile {
+    interval: Duration,
+    pages: Vec<Box<dyn Screen + Send>>,
+    next: usize,
+    // When the page showing was turned to; live pages are redrawn until its interval is up
+    turned: Option<Instant>,
+    // A button press the page showing used, which it wants seen straight away
+    pressed: bool,
+}
+
+impl Profile {
+    fn new(interval: u64, names: &[String], config: &Config) -> Result<Self, ConfigError> {
+        let pages = names
+            .iter()
+            .map(|name| screens::by_name(name, config).ok_or_else(|| ConfigError::Invalid(format!("unknown page {name:?}"))))
+            .collect::<Result<Vec<_>, _>>()?;
+        if pages.is_empty() {
+            return Err(ConfigError::Invalid("no pages configured".to_string()));
+        }
+        Ok(Profile {
+            interval: Duration::from_secs(interval),
+            pages,
+            next: 0,
+            turned: None,
+            pressed: false,
+        })
+    }
+
+    // `next` is the page after the one showing
+    fn showing(&self) -> usize {
+        let len = self.pages.len();
+        (self.next % len + len - 1) % len
+    }
+
+    fn turn(&mut self, turn: PageTurn) {
+        // next_page is about to show whichever page this leaves `next` at
+        let len = self.pages.len();
+        let showing = self.showing();
+        self.turned = None;
+        self.next = match turn {
+            // A page with more to show scrolls before the page turns
+            PageTurn::Next if self.pages[showing].scroll() => showing,
+            PageTurn::Next => self.next,
+            PageTurn::Previous => (self.next % len + len * 2 - 2) % len,
+            PageTurn::First => 0,
+            PageTurn::Refresh => showing,
+        };
+    }
+
+    // Offers a press to the page showing; true if it used it
+    fn press(&mut self, button: usize) -> bool {
+        let showing = self.showing();
+        self.pressed = self.pages[showing].press(button);
+        self.pressed
+    }
+
+    // The page to draw now, and whether it is the one showing drawn again
+    // (live or pressed) rather than a new one
+    fn due(&mut self) -> (&mut (dyn Screen + Send), bool) {
+        let showing = self.showing();
+        let staying = self.turned.is_some_and(|turned| turned.elapsed() < self.interval)
+            && (self.pressed || self.pages[showing].live().is_some());
+        self.pressed = false;
+        if staying {
+            return (self.pages[showing].as_mut(), true);
+        }
+        self.turned = Some(Instant::now());
+        (self.next_page(), false)
+    }
+
+    fn next_page(&mut self) -> &mut (dyn Screen + Send) {
+        let index = self.next % self.pages.len();
+        self.next = index + 1;
+        self.pages[index].as_mut()
+    }
+
+    // Until the page showing wants drawing again, if that is before its interval is up
+    fn until_live(&self) -> Option<Duration> {
+        let left = self.interval.saturating_sub(self.turned?.elapsed());
+        self.pages[self.showing()].live().filter(|&live| live < left)
+    }
+}
+
+/// A page flip asked for from outside the schedule, e.g. by a button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageTurn {
+    /// The page after, or the next screenful of one with more than fits
+    Next,
+    Previous,
+    First,
+    /// Redraw the page that is showing
+    Refresh,
+}
+
+/// Buttons that flip pages (see `input::IMPRESSION_PINS` for the usual pins).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ButtonsConfig {
+    /// BCM pins, in the same order as `actions`; none means no buttons
+    pub pins: Vec<u64>,
+    /// What each button does
+    pub actions: Vec<PageTurn>,
+    /// The buttons pull their pins high rather than to ground
+    pub active_high: bool,
+}
+
+impl Default for ButtonsConfig {
+    fn default() -> Self {
+        ButtonsConfig {
+            pins: Vec::new(),
+            actions: vec![PageTurn::Previous, PageTurn::Next, PageTurn::First, PageTurn::Refresh],
+            active_high: false,
+        }
+    }
+}
+
+pub struct Scheduler {
+    weekday: Profile,
+    // Saturdays, Sundays and holidays; `None` means same as weekdays
+    weekend: Option<Profile>,
+    holidays: Holidays,
+    night: Option<NightConfig>,
+    rules: Vec<(RuleConfig, Profile)>,
+    presence: Option<Presence>,
+    night_screen: Box<dyn Screen + Send>,
+    theme: Theme,
+    // Panel was cleared and put to sleep for the night
+    blanked: bool,
+    // Applied to whichever profile the next tick picks
+    turn: Option<PageTurn>,
+    // The button behind `turn`, offered to the page showing first
+    button: Option<usize>,
+    // How long a refresh takes, which has already used up that much of every wait
+    refresh_time: Duration,
+}
+
+impl Scheduler {
+    pub fn new(config: &Config) -> Result<Self, ConfigError> {
+        config.theme.check().map_err(ConfigError::Invalid)?;
+        for (name, plugin) in &config.plugins {
+            plugin.check().map_err(|err| ConfigError::Invalid(format!("[plugins.{name}] {err}")))?;
+        }
+        let weekday = Profile::new(config.interval, &config.pages, config)?;
+        let weekend = match &config.weekend {
+            Some(weekend) => Some(Profile::new(
+                weekend.interval.unwrap_or(config.interval),
+                weekend.pages.as_deref().unwrap_or(&config.pages),
+                config,
+            )?),
+            None => None,
+        };
+        let holidays = match &config.holidays {
+            Some(path) => Holidays::load(path).map_err(|err| ConfigError::Io(path.clone(), err))?,
+            None => Holidays::default(),
+        };
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| Ok((rule.clone(), Profile::new(rule.interval.unwrap_or(config.interval), &rule.pages, config)?)))
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+        for rule in &config.rules {
+            if rule.from.is_some() != rule.to.is_some() {
+                return Err(ConfigError::Invalid("rules need both `from` and `to`, or neither".to_string()));
+            }
+            if rule.presence.is_some() && config.presence.is_none() {
+                return Err(ConfigError::Invalid("rule uses presence but [presence] is missing".to_string()));
+            }
+        }
+        if let Some(presence) = &config.presence
+            && presence.mqtt.is_some()
+            && config.mqtt.is_none()
+        {
+            return Err(ConfigError::Invalid("[presence.mqtt] needs an [mqtt] broker".to_string()));
+        }
+        let presence = config
+            .presence
+            .as_ref()
+            .map(|presence| Presence::start(presence, config.mqtt.as_ref()));
+        Ok(Scheduler {
+            weekday,
+            weekend,
+            holidays,
+            night: config.night.clone(),
+            rules,
+            presence,
+            night_screen: Box::new(NightClock),
+            theme: config.theme,
+            blanked: false,
+            turn: None,
+            button: None,
+            refresh_time: Duration::ZERO,
+        })
+    }
+
+    /// Takes the measured refresh time out of every wait, so that pages keep
+    /// to the schedule instead of drifting later by a refresh each time.
+    pub fn calibrate(&mut self, calibration: &Calibration) {
+        self.refresh_time = calibration.full();
+    }
+
+    /// Makes the next `tick` show a different page than the schedule would.
+    /// Ignored at night.
+    pub fn turn(&mut self, turn: PageTurn) {
+        self.turn = Some(turn);
+        self.button = None;
+    }
+
+    /// Like `turn`, for a press of the button at `button` in `[buttons] pins`,
+    /// which the page showing gets to use first (see `Screen::press`).
+    pub fn press(&mut self, button: usize, turn: PageTurn) {
+        self.turn = Some(turn);
+        self.button = Some(button);
+    }
+
+    /// Draws and shows whatever is due now, and returns how long to wait before calling again.
+    pub fn tick<E, D>(&mut self, epd: &mut E, fb: &mut Framebuffer, delay: &mut D) -> Result<Duration, E::Error>
+    where
+        E: EpdController,
+        D: DelayMs<u8>,
+    {
+        let ctx = RenderContext::now().with_theme(self.theme);
+        let time = ctx.now.time();
+        let turn = self.turn.take();
+        let button = self.button.take();
+        let wait = match self.night.as_ref().filter(|night| night.is_night(time)) {
+            Some(night) if night.blank => {
+                if !self.blanked {
+                    fb.clear(Color::White);
+                    epd.show(fb, delay)?;
+                    epd.sleep()?;
+                    self.blanked = true;
+                }
+                night.until_change(time)
+            }
+            Some(night) => {
+                screens::draw(self.night_screen.as_mut(), fb, &ctx);
+                epd.show(fb, delay)?;
+                let wait = night.interval().min(night.until_change(time));
+                self.theme.until_change(time).map_or(wait, |change| wait.min(change))
+            }
+            None => {
+                if self.blanked {
+                    // Good morning: the controller has been in deep sleep since dusk
+                    epd.init(delay)?;
+                    self.blanked = false;
+                }
+                let day_off = self.holidays.is_day_off(ctx.now.date_naive());
+                // Wake up again when any rule's window opens or closes
+                let until_rule_change = self.rules.iter().filter_map(|(rule, _)| rule.until_change(time)).min();
+                let mut presence = None;
+                let rule = self.rules.iter_mut().find(|(rule, _)| {
+                    rule.matches_time(day_off, time)
+                        && rule.presence.is_none_or(|wanted| {
+                            let state = match (presence, self.presence.as_mut()) {
+                                (Some(state), _) => state,
+                                (None, Some(source)) => *presence.insert(source.state()),
+                                (None, None) => return false,
+                            };
+                            state == wanted
+                        })
+                });
+                let profile = match (rule, self.weekend.as_mut()) {
+                    (Some((_, profile)), _) => profile,
+                    (None, Some(weekend)) if day_off => weekend,
+                    _ => &mut self.weekday,
+                };
+                if let Some(turn) = turn
+                    && !button.is_some_and(|button| profile.press(button))
+                {
+                    profile.turn(turn);
+                }
+                let (page, staying) = profile.due();
+                screens::draw(page, fb, &ctx);
+                if staying {
+                    // Only a countdown ticking over or the like; the fast waveform will do
+                    epd.write_planes(fb.bw_plane(), fb.red_plane())?;
+                    epd.refresh_fast(delay)?;
+                } else {
+                    epd.show(fb, delay)?;
+                }
+                let wait = [
+                    self.night.as_ref().map(|night| night.until_change(time)),
+                    until_rule_change,
+                    // Night mode turning the panel over
+                    self.theme.until_change(time),
+                ]
+                .into_iter()
+                .flatten()
+                .fold(profile.interval, Duration::min);
+                // A live page keeps its own time, so nothing comes off its wait for the refresh
+                if let Some(live) = profile.until_live().filter(|&live| live < wait) {
+                    return Ok(live.max(MIN_WAIT));
+                }
+                wait
+            }
+        };
+        Ok(wait.saturating_sub(self.refresh_time).max(MIN_WAIT))
+    }
+}
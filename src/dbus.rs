@@ -0,0 +1,648 @@
+// The display as a service on the system D-Bus.
+//
+// Enough of the wire protocol to own one name and answer calls on one
+// object: EXTERNAL authentication over the bus's Unix socket, and messages
+// with strings, integers and the odd dictionary of variants. No unix fds, no
+// TCP buses, no match rules.
+//
+//     busctl call org.rustraspi.Display /org/rustraspi/Display org.rustraspi.Display ShowText s "Back in 5"
+//     busctl call org.rustraspi.Display /org/rustraspi/Display org.rustraspi.Display ShowImagePath s /home/pi/qr.png
+//     busctl call org.rustraspi.Display /org/rustraspi/Display org.rustraspi.Display Clear
+//
+// `RefreshCount` and `LastRefresh` (Unix seconds) are properties of the
+// object, and `PropertiesChanged` is sent after every refresh, so anything
+// watching the bus hears when the panel changes.
+//
+// The system bus only lets a process own a name its policy allows, so the
+// daemon needs /etc/dbus-1/system.d/org.rustraspi.Display.conf:
+//
+//     <busconfig>
+//       <policy user="root"><allow own="org.rustraspi.Display"/></policy>
+//       <policy context="default"><allow send_destination="org.rustraspi.Display"/></policy>
+//     </busconfig>
+
+use std::env;
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::control::ControlCommand;
+
+/// Name the daemon owns on the bus, and the interface its methods are in.
+pub const NAME: &str = "org.rustraspi.Display";
+/// The one object the daemon serves.
+pub const PATH: &str = "/org/rustraspi/Display";
+
+const SYSTEM_BUS: &str = "/run/dbus/system_bus_socket";
+
+const BUS_NAME: &str = "org.freedesktop.DBus";
+const BUS_PATH: &str = "/org/freedesktop/DBus";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+const PEER: &str = "org.freedesktop.DBus.Peer";
+
+// Message types
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+const SIGNAL: u8 = 4;
+
+// Message flags
+const NO_REPLY_EXPECTED: u8 = 0x1;
+
+// Header fields
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+
+// RequestName: fail rather than wait in line behind another owner
+const DO_NOT_QUEUE: u32 = 0x4;
+const PRIMARY_OWNER: u32 = 1;
+
+// Far more than any call to this service needs, though the bus allows 128MiB
+const MAX_MESSAGE: usize = 1 << 20;
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.rustraspi.Display">
+    <method name="ShowText"><arg name="text" type="s" direction="in"/></method>
+    <method name="ShowImagePath"><arg name="path" type="s" direction="in"/></method>
+    <method name="Clear"/>
+    <property name="RefreshCount" type="t" access="read"/>
+    <property name="LastRefresh" type="x" access="read"/>
+  </interface>
+  <interface name="org.freedesktop.DBus.Properties">
+    <method name="Get"><arg type="s" direction="in"/><arg type="s" direction="in"/><arg type="v" direction="out"/></method>
+    <method name="GetAll"><arg type="s" direction="in"/><arg type="a{sv}" direction="out"/></method>
+    <signal name="PropertiesChanged"><arg type="s"/><arg type="a{sv}"/><arg type="as"/></signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect"><arg type="s" direction="out"/></method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+  </interface>
+</node>
+"#;
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.into())
+}
+
+// Where the system bus listens: $DBUS_SYSTEM_BUS_ADDRESS if it names a
+// socket path, or the usual one
+fn system_bus() -> PathBuf {
+    let address = env::var("DBUS_SYSTEM_BUS_ADDRESS").unwrap_or_default();
+    address
+        .split(';')
+        .find_map(|address| address.strip_prefix("unix:"))
+        .and_then(|options| options.split(',').find_map(|option| option.strip_prefix("path=")))
+        .map_or_else(|| PathBuf::from(SYSTEM_BUS), PathBuf::from)
+}
+
+// Marshals values in little-endian order, aligned from the start of `buf`
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+// A header field's value
+#[derive(Clone, Copy)]
+enum Field<'a> {
+    Path(&'a str),
+    Str(&'a str),
+    Signature(&'a str),
+    U32(u32),
+}
+
+impl Writer {
+    fn pad(&mut self, align: usize) {
+        self.buf.resize(self.buf.len().next_multiple_of(align), 0);
+    }
+
+    fn byte(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.pad(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.pad(8);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, value: &str) {
+        self.byte(value.len() as u8);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    // An array whose elements start on `align`; its length leaves out the padding before them
+    fn array(&mut self, align: usize, elements: impl FnOnce(&mut Self)) {
+        self.u32(0);
+        let length_at = self.buf.len() - 4;
+        self.pad(align);
+        let start = self.buf.len();
+        elements(self);
+        let length = (self.buf.len() - start) as u32;
+        self.buf[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    fn field(&mut self, code: u8, value: Field) {
+        self.pad(8);
+        self.byte(code);
+        match value {
+            Field::Path(path) => {
+                self.signature("o");
+                self.string(path);
+            }
+            Field::Str(text) => {
+                self.signature("s");
+                self.string(text);
+            }
+            Field::Signature(signature) => {
+                self.signature("g");
+                self.signature(signature);
+            }
+            Field::U32(number) => {
+                self.signature("u");
+                self.u32(number);
+            }
+        }
+    }
+
+    // The properties as an a{sv}
+    fn properties(&mut self, refreshes: &Refreshes) {
+        self.array(8, |w| {
+            for (name, signature, value) in refreshes.properties() {
+                w.pad(8);
+                w.string(name);
+                w.signature(signature);
+                w.u64(value);
+            }
+        });
+    }
+}
+
+// A message's body, with the signature that goes in its header
+#[derive(Default)]
+struct Body {
+    signature: &'static str,
+    bytes: Vec<u8>,
+}
+
+impl Body {
+    fn new(signature: &'static str, write: impl FnOnce(&mut Writer)) -> Self {
+        let mut writer = Writer::default();
+        write(&mut writer);
+        Body {
+            signature,
+            bytes: writer.buf,
+        }
+    }
+}
+
+fn encode(kind: u8, flags: u8, serial: u32, fields: &[(u8, Field)], body: &Body) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.byte(b'l');
+    w.byte(kind);
+    w.byte(flags);
+    // Protocol version
+    w.byte(1);
+    w.u32(body.bytes.len() as u32);
+    w.u32(serial);
+    w.array(8, |w| {
+        for &(code, value) in fields {
+            w.field(code, value);
+        }
+        if !body.signature.is_empty() {
+            w.field(FIELD_SIGNATURE, Field::Signature(body.signature));
+        }
+    });
+    w.pad(8);
+    w.buf.extend_from_slice(&body.bytes);
+    w.buf
+}
+
+// Unmarshals values in the byte order the sender chose
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("D-Bus message cut short"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn align(&mut self, align: usize) -> io::Result<()> {
+        let padding = self.pos.next_multiple_of(align) - self.pos;
+        self.take(padding).map(|_| ())
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.align(4)?;
+        let bytes: [u8; 4] = self.take(4)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn text(&mut self, len: usize) -> io::Result<String> {
+        let bytes = self.take(len)?;
+        // And the nul after it
+        self.take(1)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("D-Bus string isn't UTF-8"))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.u32()? as usize;
+        self.text(len)
+    }
+
+    fn signature(&mut self) -> io::Result<String> {
+        let len = self.byte()? as usize;
+        self.text(len)
+    }
+}
+
+// A message from the bus, with the header fields this service looks at
+#[derive(Default)]
+struct Message {
+    kind: u8,
+    flags: u8,
+    serial: u32,
+    path: Option<String>,
+    interface: Option<String>,
+    member: Option<String>,
+    error_name: Option<String>,
+    reply_serial: Option<u32>,
+    sender: Option<String>,
+    signature: String,
+    body: Vec<u8>,
+    big_endian: bool,
+}
+
+impl Message {
+    fn read(stream: &mut impl Read) -> io::Result<Message> {
+        let mut fixed = [0; 16];
+        stream.read_exact(&mut fixed)?;
+        let big_endian = match fixed[0] {
+            b'l' => false,
+            b'B' => true,
+            _ => return Err(invalid("not a D-Bus message")),
+        };
+        let mut reader = Reader {
+            buf: &fixed,
+            pos: 4,
+            big_endian,
+        };
+        let body_len = reader.u32()? as usize;
+        let serial = reader.u32()?;
+        let fields_len = reader.u32()? as usize;
+        if body_len > MAX_MESSAGE || fields_len > MAX_MESSAGE {
+            return Err(invalid("D-Bus message too big"));
+        }
+        // The fields are aligned from the start of the message, so keep the fixed part in front
+        let mut header = vec![0; (16 + fields_len).next_multiple_of(8)];
+        header[..16].copy_from_slice(&fixed);
+        stream.read_exact(&mut header[16..])?;
+        let mut body = vec![0; body_len];
+        stream.read_exact(&mut body)?;
+
+        let mut message = Message {
+            kind: fixed[1],
+            flags: fixed[2],
+            serial,
+            body,
+            big_endian,
+            ..Message::default()
+        };
+        let mut reader = Reader {
+            buf: &header[..16 + fields_len],
+            pos: 16,
+            big_endian,
+        };
+        while reader.pos < reader.buf.len() {
+            reader.align(8)?;
+            let code = reader.byte()?;
+            let (text, number) = match reader.signature()?.as_str() {
+                "s" | "o" => (Some(reader.string()?), None),
+                "g" => (Some(reader.signature()?), None),
+                "u" => (None, Some(reader.u32()?)),
+                other => return Err(invalid(format!("unexpected D-Bus header field type {other}"))),
+            };
+            match code {
+                FIELD_PATH => message.path = text,
+                FIELD_INTERFACE => message.interface = text,
+                FIELD_MEMBER => message.member = text,
+                FIELD_ERROR_NAME => message.error_name = text,
+                FIELD_REPLY_SERIAL => message.reply_serial = number,
+                FIELD_SENDER => message.sender = text,
+                FIELD_SIGNATURE => message.signature = text.unwrap_or_default(),
+                // Destination, unix fds, and whatever later versions add
+                _ => {}
+            }
+        }
+        Ok(message)
+    }
+
+    fn args(&self) -> Reader<'_> {
+        Reader {
+            buf: &self.body,
+            pos: 0,
+            big_endian: self.big_endian,
+        }
+    }
+
+    // The arguments as strings, if the signature is exactly that many of them
+    fn strings(&self, count: usize) -> Option<Vec<String>> {
+        if self.signature != "s".repeat(count) {
+            return None;
+        }
+        let mut args = self.args();
+        (0..count).map(|_| args.string().ok()).collect()
+    }
+}
+
+// What the properties say
+#[derive(Clone, Copy, Default)]
+struct Refreshes {
+    count: u64,
+    // Unix seconds, 0 before the first
+    last: i64,
+}
+
+impl Refreshes {
+    // (name, signature, value as the 8 bytes of either type)
+    fn properties(&self) -> [(&'static str, &'static str, u64); 2] {
+        [("RefreshCount", "t", self.count), ("LastRefresh", "x", self.last as u64)]
+    }
+}
+
+// What the serving thread and `Signals` share
+struct Shared {
+    writer: Mutex<UnixStream>,
+    serial: AtomicU32,
+    refreshes: Mutex<Refreshes>,
+}
+
+impl Shared {
+    fn send(&self, kind: u8, flags: u8, fields: &[(u8, Field)], body: &Body) -> io::Result<u32> {
+        // Serials are never 0
+        let serial = self.serial.fetch_add(1, Ordering::SeqCst);
+        let message = encode(kind, flags, serial, fields, body);
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        writer.write_all(&message)?;
+        Ok(serial)
+    }
+}
+
+/// Sends `PropertiesChanged` for the display's object; cheap to clone.
+#[derive(Clone)]
+pub struct Signals {
+    shared: Arc<Shared>,
+}
+
+impl Signals {
+    /// Records a refresh, the panel's `count`th, as having just happened,
+    /// and tells the bus.
+    pub fn refreshed(&self, count: u64) -> io::Result<()> {
+        let last = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() as i64);
+        let refreshes = {
+            let mut refreshes = self.shared.refreshes.lock().unwrap_or_else(PoisonError::into_inner);
+            *refreshes = Refreshes { count, last };
+            *refreshes
+        };
+        let body = Body::new("sa{sv}as", |w| {
+            w.string(NAME);
+            w.properties(&refreshes);
+            // Nothing invalidated
+            w.array(4, |_| {});
+        });
+        let fields = [
+            (FIELD_PATH, Field::Path(PATH)),
+            (FIELD_INTERFACE, Field::Str(PROPERTIES)),
+            (FIELD_MEMBER, Field::Str("PropertiesChanged")),
+        ];
+        self.shared.send(SIGNAL, NO_REPLY_EXPECTED, &fields, &body).map(|_| ())
+    }
+}
+
+/// A connection to the system bus owning `NAME`.
+pub struct Service {
+    reader: UnixStream,
+    shared: Arc<Shared>,
+}
+
+// What a method call comes to: a body to return, or an error name and message
+type Outcome = Result<Body, (&'static str, String)>;
+
+impl Service {
+    /// Connects to the system bus and claims `NAME`. Fails if some other
+    /// process has it, or the bus policy doesn't allow this one to.
+    pub fn connect() -> io::Result<Service> {
+        let mut stream = UnixStream::connect(system_bus())?;
+        authenticate(&mut stream)?;
+        let mut service = Service {
+            reader: stream.try_clone()?,
+            shared: Arc::new(Shared {
+                writer: Mutex::new(stream),
+                serial: AtomicU32::new(1),
+                refreshes: Mutex::new(Refreshes::default()),
+            }),
+        };
+        service.call_bus("Hello", Body::default())?;
+        let body = Body::new("su", |w| {
+            w.string(NAME);
+            w.u32(DO_NOT_QUEUE);
+        });
+        let reply = service.call_bus("RequestName", body)?;
+        if reply.signature != "u" || reply.args().u32()? != PRIMARY_OWNER {
+            return Err(io::Error::new(ErrorKind::AddrInUse, format!("{NAME} is already owned on the bus")));
+        }
+        Ok(service)
+    }
+
+    pub fn signals(&self) -> Signals {
+        Signals {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    // Calls a method of the bus itself and waits for the answer, dropping anything else that comes first
+    fn call_bus(&mut self, member: &str, body: Body) -> io::Result<Message> {
+        let fields = [
+            (FIELD_PATH, Field::Path(BUS_PATH)),
+            (FIELD_INTERFACE, Field::Str(BUS_NAME)),
+            (FIELD_MEMBER, Field::Str(member)),
+            (FIELD_DESTINATION, Field::Str(BUS_NAME)),
+        ];
+        let serial = self.shared.send(METHOD_CALL, 0, &fields, &body)?;
+        loop {
+            let message = Message::read(&mut self.reader)?;
+            if message.reply_serial != Some(serial) {
+                continue;
+            }
+            return match message.kind {
+                METHOD_RETURN => Ok(message),
+                _ => {
+                    let detail = message.strings(1).map(|mut args| args.remove(0)).unwrap_or_default();
+                    let error = message.error_name.unwrap_or_default();
+                    Err(io::Error::new(ErrorKind::PermissionDenied, format!("{member}: {error} {detail}")))
+                }
+            };
+        }
+    }
+
+    /// Answers method calls for ever, passing the display's own methods to
+    /// `handler` as the control socket's commands.
+    pub fn serve<F>(mut self, handler: F) -> io::Result<()>
+    where
+        F: Fn(ControlCommand) -> Result<(), String>,
+    {
+        loop {
+            let message = Message::read(&mut self.reader)?;
+            if message.kind != METHOD_CALL {
+                continue;
+            }
+            let outcome = self.dispatch(&message, &handler);
+            if message.flags & NO_REPLY_EXPECTED != 0 {
+                continue;
+            }
+            let destination = message.sender.as_deref().unwrap_or_default();
+            let mut fields = vec![
+                (FIELD_REPLY_SERIAL, Field::U32(message.serial)),
+                (FIELD_DESTINATION, Field::Str(destination)),
+            ];
+            match outcome {
+                Ok(body) => self.shared.send(METHOD_RETURN, NO_REPLY_EXPECTED, &fields, &body)?,
+                Err((name, detail)) => {
+                    fields.push((FIELD_ERROR_NAME, Field::Str(name)));
+                    let body = Body::new("s", |w| w.string(&detail));
+                    self.shared.send(ERROR, NO_REPLY_EXPECTED, &fields, &body)?
+                }
+            };
+        }
+    }
+
+    fn dispatch(&self, message: &Message, handler: &dyn Fn(ControlCommand) -> Result<(), String>) -> Outcome {
+        let interface = message.interface.as_deref();
+        let member = message.member.as_deref().unwrap_or_default();
+        if message.path.as_deref() != Some(PATH) {
+            return Err(("org.freedesktop.DBus.Error.UnknownObject", format!("no object at {:?}", message.path)));
+        }
+        let bad_args = || ("org.freedesktop.DBus.Error.InvalidArgs", format!("wrong arguments for {member}"));
+        // The interface is optional in a call, and the members' names don't clash
+        let command = match (interface.unwrap_or(NAME), member) {
+            (NAME, "ShowText") => {
+                let mut args = message.strings(1).ok_or_else(bad_args)?;
+                ControlCommand::ShowText {
+                    text: args.remove(0),
+                    ttl: None,
+                }
+            }
+            (NAME, "ShowImagePath") => {
+                let mut args = message.strings(1).ok_or_else(bad_args)?;
+                ControlCommand::ShowImage {
+                    path: PathBuf::from(args.remove(0)),
+                    placement: None,
+                    ttl: None,
+                }
+            }
+            (NAME, "Clear") => ControlCommand::Clear,
+            (NAME | PROPERTIES, "Get") => return self.get(message.strings(2).ok_or_else(bad_args)?),
+            (NAME | PROPERTIES, "GetAll") => {
+                message.strings(1).ok_or_else(bad_args)?;
+                let refreshes = *self.shared.refreshes.lock().unwrap_or_else(PoisonError::into_inner);
+                return Ok(Body::new("a{sv}", |w| w.properties(&refreshes)));
+            }
+            (NAME | INTROSPECTABLE, "Introspect") => {
+                return Ok(Body::new("s", |w| w.string(INTROSPECTION)));
+            }
+            (NAME | PEER, "Ping") => return Ok(Body::default()),
+            _ => {
+                return Err((
+                    "org.freedesktop.DBus.Error.UnknownMethod",
+                    format!("no method {member} in {}", interface.unwrap_or(NAME)),
+                ));
+            }
+        };
+        handler(command)
+            .map(|()| Body::default())
+            .map_err(|err| ("org.rustraspi.Display.Error.Failed", err))
+    }
+
+    // Properties.Get(interface, name)
+    fn get(&self, args: Vec<String>) -> Outcome {
+        let refreshes = *self.shared.refreshes.lock().unwrap_or_else(PoisonError::into_inner);
+        let found = refreshes
+            .properties()
+            .into_iter()
+            .find(|(name, _, _)| args[0] == NAME && *name == args[1]);
+        let Some((_, signature, value)) = found else {
+            return Err(("org.freedesktop.DBus.Error.UnknownProperty", format!("no property {}.{}", args[0], args[1])));
+        };
+        Ok(Body::new("v", |w| {
+            w.signature(signature);
+            w.u64(value);
+        }))
+    }
+}
+
+// SASL EXTERNAL: the bus checks the uid given against the socket's peer credentials
+fn authenticate(stream: &mut UnixStream) -> io::Result<()> {
+    // The owner of /proc/self is this process's effective uid
+    let uid = fs::metadata("/proc/self")?.uid().to_string();
+    let hex: String = uid.bytes().map(|byte| format!("{byte:02x}")).collect();
+    stream.write_all(format!("\0AUTH EXTERNAL {hex}\r\n").as_bytes())?;
+    // One byte at a time, so nothing after the line is read out from under Message::read
+    let mut line = Vec::new();
+    while !line.ends_with(b"\r\n") {
+        let mut byte = [0];
+        stream.read_exact(&mut byte)?;
+        line.push(byte[0]);
+        if line.len() > 512 {
+            return Err(invalid("D-Bus authentication reply too long"));
+        }
+    }
+    if !line.starts_with(b"OK ") {
+        let reply = String::from_utf8_lossy(&line);
+        return Err(io::Error::new(ErrorKind::PermissionDenied, format!("D-Bus refused authentication: {}", reply.trim())));
+    }
+    stream.write_all(b"BEGIN\r\n")
+}
@@ -0,0 +1,400 @@
+// A curated set of emoji drawn as built-in one-bit glyphs, so that text from
+// MQTT, Home Assistant or a calendar that has them in shows something close
+// rather than a `?`. Weather, moon, status and battery emoji reuse the icons;
+// hearts, stars, faces, arrows and a few others have glyphs of their own.
+// Colour variants of one symbol (the coloured hearts, say) share its glyph.
+//
+// Every glyph is 16x16 like the icons and takes two cells in monospaced text,
+// scaled to the line's height.
+
+use embedded_graphics::pixelcolor::PixelColor;
+use embedded_graphics::prelude::*;
+
+use crate::widgets::icon::{self, Icon};
+
+/// The 16x16 glyph `c` is drawn with, if it is one of the emoji covered.
+pub fn glyph(c: char) -> Option<&'static [u16; 16]> {
+    let icon = match c {
+        '☀' | '🌞' | '🔆' => Icon::Sun,
+        '⛅' | '🌤' | '🌥' => Icon::PartlyCloudy,
+        '☁' => Icon::Cloud,
+        '🌧' | '🌦' | '☔' | '☂' => Icon::Rain,
+        '❄' | '🌨' | '☃' | '⛄' => Icon::Snow,
+        '⛈' | '🌩' | '⚡' => Icon::Thunder,
+        '🌫' | '🌁' => Icon::Fog,
+        '🌅' | '🌄' => Icon::Sunrise,
+        '🌇' => Icon::Sunset,
+        '🌊' => Icon::Tide,
+        '🌑' | '🌚' => Icon::MoonNew,
+        '🌒' => Icon::MoonWaxingCrescent,
+        '🌓' => Icon::MoonFirstQuarter,
+        '🌔' => Icon::MoonWaxingGibbous,
+        '🌕' | '🌝' => Icon::MoonFull,
+        '🌖' => Icon::MoonWaningGibbous,
+        '🌗' => Icon::MoonLastQuarter,
+        '🌘' => Icon::MoonWaningCrescent,
+        '⚠' | '🚨' => Icon::Warning,
+        '✅' | '✔' | '✓' | '☑' => Icon::Check,
+        '❌' | '❎' | '✖' | '✗' => Icon::Cross,
+        '⏸' => Icon::Pause,
+        '🔄' | '🔃' | '🔁' => Icon::Sync,
+        '🔋' => Icon::BatteryFull,
+        '🪫' => Icon::BatteryEmpty,
+        '🔌' => Icon::BatteryCharging,
+        '📶' => Icon::Wifi4,
+        _ => {
+            let own = match c {
+                '❤' | '♥' | '💖' | '💗' | '💙' | '💚' | '💛' | '💜' | '🧡' | '🖤' => Own::Heart,
+                '⭐' | '★' | '🌟' => Own::Star,
+                '🙂' | '😀' | '😃' | '😄' | '😊' | '☺' => Own::Smile,
+                '🙁' | '☹' | '😞' | '😢' => Own::Frown,
+                '⬆' | '↑' => Own::ArrowUp,
+                '⬇' | '↓' => Own::ArrowDown,
+                '⬅' | '←' => Own::ArrowLeft,
+                '➡' | '→' => Own::ArrowRight,
+                '🔔' => Own::Bell,
+                '💧' | '💦' => Own::Droplet,
+                '🏠' | '🏡' => Own::House,
+                '🌡' => Own::Thermometer,
+                '❗' | '❕' | '‼' => Own::Exclamation,
+                '❓' | '❔' => Own::Question,
+                '🔥' => Own::Fire,
+                _ => return None,
+            };
+            return Some(&ATLAS[own as usize]);
+        }
+    };
+    Some(icon::rows(icon))
+}
+
+/// Draws `rows` `size` pixels square with its top-left corner at `point`.
+/// Shrunk, a pixel is set if any it stands for is, so thin lines survive.
+pub fn draw<C, D>(rows: &[u16; 16], point: Point, size: u32, color: C, target: &mut D) -> Result<(), D::Error>
+where
+    C: PixelColor,
+    D: DrawTarget<Color = C>,
+{
+    let source = icon::SIZE;
+    let span = |at: u32| (at * source / size.max(1), ((at + 1) * source).div_ceil(size.max(1)).min(source));
+    let mut pixels = Vec::new();
+    for y in 0..size {
+        let (top, bottom) = span(y);
+        for x in 0..size {
+            let (left, right) = span(x);
+            let mask = (0x8000_u32 >> left) - (0x8000_u32 >> right);
+            if (top..bottom).any(|row| u32::from(rows[row as usize]) & mask != 0) {
+                pixels.push(Pixel(point + Point::new(x as i32, y as i32), color));
+            }
+        }
+    }
+    target.draw_iter(pixels)
+}
+
+// Indexed by `Own as usize`, so it must stay in the same order
+#[derive(Clone, Copy)]
+enum Own {
+    Heart,
+    Star,
+    Smile,
+    Frown,
+    ArrowUp,
+    ArrowDown,
+    ArrowLeft,
+    ArrowRight,
+    Bell,
+    Droplet,
+    House,
+    Thermometer,
+    Exclamation,
+    Question,
+    Fire,
+}
+
+#[rustfmt::skip]
+const ATLAS: [[u16; 16]; 15] = [
+    // Heart
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0011110000111100,
+        0b0111111001111110,
+        0b1111111111111111,
+        0b1111111111111111,
+        0b1111111111111111,
+        0b0111111111111110,
+        0b0011111111111100,
+        0b0001111111111000,
+        0b0000111111110000,
+        0b0000011111100000,
+        0b0000001111000000,
+        0b0000000110000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Star
+    [
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000011111100000,
+        0b1111111111111111,
+        0b0111111111111110,
+        0b0011111111111100,
+        0b0001111111111000,
+        0b0000111111110000,
+        0b0001111111111000,
+        0b0001111001111000,
+        0b0011110000111100,
+        0b0011100000011100,
+        0b0111000000001110,
+        0b0100000000000010,
+    ],
+    // Smile
+    [
+        0b0000011111100000,
+        0b0001100000011000,
+        0b0010000000000100,
+        0b0100000000000010,
+        0b0100110000110010,
+        0b1000110000110001,
+        0b1000000000000001,
+        0b1000000000000001,
+        0b1000000000000001,
+        0b1001000000001001,
+        0b0100100000010010,
+        0b0100011111100010,
+        0b0010000000000100,
+        0b0001100000011000,
+        0b0000011111100000,
+        0b0000000000000000,
+    ],
+    // Frown
+    [
+        0b0000011111100000,
+        0b0001100000011000,
+        0b0010000000000100,
+        0b0100000000000010,
+        0b0100110000110010,
+        0b1000110000110001,
+        0b1000000000000001,
+        0b1000000000000001,
+        0b1000000000000001,
+        0b1000000000000001,
+        0b0100011111100010,
+        0b0100100000010010,
+        0b0010000000000100,
+        0b0001100000011000,
+        0b0000011111100000,
+        0b0000000000000000,
+    ],
+    // ArrowUp
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000110000000,
+        0b0000001111000000,
+        0b0000011111100000,
+        0b0000111111110000,
+        0b0001111111111000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000000000000,
+    ],
+    // ArrowDown
+    [
+        0b0000000000000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0001111111111000,
+        0b0000111111110000,
+        0b0000011111100000,
+        0b0000001111000000,
+        0b0000000110000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // ArrowLeft
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000100000000,
+        0b0000001100000000,
+        0b0000011100000000,
+        0b0000111100000000,
+        0b0001111111111110,
+        0b0001111111111110,
+        0b0000111100000000,
+        0b0000011100000000,
+        0b0000001100000000,
+        0b0000000100000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // ArrowRight
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000010000000,
+        0b0000000011000000,
+        0b0000000011100000,
+        0b0000000011110000,
+        0b0111111111111000,
+        0b0111111111111000,
+        0b0000000011110000,
+        0b0000000011100000,
+        0b0000000011000000,
+        0b0000000010000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Bell
+    [
+        0b0000000000000000,
+        0b0000000110000000,
+        0b0000011111100000,
+        0b0000111111110000,
+        0b0001111111111000,
+        0b0001111111111000,
+        0b0001111111111000,
+        0b0001111111111000,
+        0b0011111111111100,
+        0b0011111111111100,
+        0b0111111111111110,
+        0b1111111111111111,
+        0b0000000000000000,
+        0b0000001111000000,
+        0b0000000110000000,
+        0b0000000000000000,
+    ],
+    // Droplet
+    [
+        0b0000000000000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000011111100000,
+        0b0000111111110000,
+        0b0001111111111000,
+        0b0011111111111100,
+        0b0011111111111100,
+        0b0011111111111100,
+        0b0011111111111100,
+        0b0001111111111000,
+        0b0000111111110000,
+        0b0000001111000000,
+        0b0000000000000000,
+    ],
+    // House
+    [
+        0b0000000000000000,
+        0b0000000110000000,
+        0b0000001001000000,
+        0b0000010000100000,
+        0b0000100000010000,
+        0b0001000000001000,
+        0b0010000000000100,
+        0b0011111111111100,
+        0b0010000000000100,
+        0b0010000000000100,
+        0b0010001111000100,
+        0b0010001001000100,
+        0b0010001001000100,
+        0b0010001001000100,
+        0b0011111111111100,
+        0b0000000000000000,
+    ],
+    // Thermometer
+    [
+        0b0000001111000000,
+        0b0000010000100000,
+        0b0000010000100000,
+        0b0000010110100000,
+        0b0000010110100000,
+        0b0000010110100000,
+        0b0000010110100000,
+        0b0000010110100000,
+        0b0000010110100000,
+        0b0000100110010000,
+        0b0001001111001000,
+        0b0001011111101000,
+        0b0001011111101000,
+        0b0001001111001000,
+        0b0000100000010000,
+        0b0000011111100000,
+    ],
+    // Exclamation
+    [
+        0b0000000000000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Question
+    [
+        0b0000000000000000,
+        0b0000011111100000,
+        0b0000111111110000,
+        0b0001110000111000,
+        0b0001100000011000,
+        0b0000000000011100,
+        0b0000000000111000,
+        0b0000000001110000,
+        0b0000000011100000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000000000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Fire
+    [
+        0b0000000100000000,
+        0b0000001100000000,
+        0b0000001110000000,
+        0b0000011110000000,
+        0b0000011111000000,
+        0b0000111111000100,
+        0b0001111111001100,
+        0b0001111111111100,
+        0b0011111011111110,
+        0b0011110011111110,
+        0b0011110001111110,
+        0b0011100000111110,
+        0b0001100000111100,
+        0b0001110001111000,
+        0b0000111111110000,
+        0b0000001111000000,
+    ],
+];
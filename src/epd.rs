@@ -0,0 +1,83 @@
+// Controller-agnostic interface to an e-paper panel.
+//
+// The framebuffer and application code only talk to panels through this
+// trait, so supporting another controller means implementing it for a new
+// driver rather than touching everything above.
+
+use core::fmt::Debug;
+
+use embedded_hal::blocking::delay::DelayMs;
+
+#[cfg(feature = "std")]
+use crate::framebuffer::Framebuffer;
+
+/// The colours a panel shows. Its third colour, where it has one, is what
+/// `Color::Red` draws in: red or yellow glass, or black on a two-colour panel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum ColorCapability {
+    BlackWhite,
+    #[default]
+    BlackWhiteRed,
+    BlackWhiteYellow,
+}
+
+impl ColorCapability {
+    /// Whether the panel has a third colour at all.
+    pub fn has_accent(self) -> bool {
+        self != ColorCapability::BlackWhite
+    }
+}
+
+pub trait EpdController {
+    type Error: Debug;
+
+    /// Native (unrotated) panel size in pixels: (source lines, gate lines).
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Wakes the controller and programs it ready for drawing.
+    fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error>;
+
+    /// The colours the panel shows. Defaults to black, white and red.
+    fn colors(&self) -> ColorCapability {
+        ColorCapability::BlackWhiteRed
+    }
+
+    /// Uploads the black/white and red planes in the native layout (see `Framebuffer`).
+    fn write_planes(&mut self, bw: &[u8], red: &[u8]) -> Result<(), Self::Error>;
+
+    /// Shows what was last written, blocking until the panel finishes.
+    fn refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error>;
+
+    /// Like `refresh`, but with a quicker waveform where the controller has one,
+    /// for animation frames; ghosting is the price. Defaults to a normal refresh.
+    fn refresh_fast<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.refresh(delay)
+    }
+
+    /// Puts the controller into its lowest-power state; `init` wakes it again.
+    fn sleep(&mut self) -> Result<(), Self::Error>;
+
+    /// Writes `fb` and refreshes.
+    #[cfg(feature = "std")]
+    fn show<D: DelayMs<u8>>(&mut self, fb: &Framebuffer, delay: &mut D) -> Result<(), Self::Error> {
+        self.write_planes(fb.bw_plane(), fb.red_plane())?;
+        self.refresh(delay)
+    }
+}
+
+/// A controller that can show four grey levels (see `gray`).
+pub trait GrayWriter: EpdController {
+    /// Shows a frame of 2-bit levels given as two planes in the native
+    /// layout: the high bit of every pixel's level, then the low bit, where
+    /// level 3 is white. Blocks until the panel finishes.
+    fn show_gray<D: DelayMs<u8>>(&mut self, msb: &[u8], lsb: &[u8], delay: &mut D) -> Result<(), Self::Error>;
+}
+
+/// A controller that can be sent a frame a few native rows at a time, so that
+/// a large frame never has to be held in memory whole (see `tiled`).
+pub trait RowWriter: EpdController {
+    /// Writes both planes of the native rows starting at `first_row`; each
+    /// holds whole rows in the layout `write_planes` uses.
+    fn write_rows(&mut self, first_row: u32, bw: &[u8], red: &[u8]) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,46 @@
+// Colour separations: each controller plane as a PNG of its own.
+//
+// The panel is driven from two one-bit planes, and a pixel's colour is
+// whatever the pair of bits makes. When a red element comes out black (or
+// black comes out red) after palette mapping, looking at the planes one at a
+// time shows which of them is wrong; the preview next to them is what the
+// panel would show.
+
+use std::path::{Path, PathBuf};
+
+use image::{GrayImage, ImageResult, Luma, RgbImage};
+
+use crate::framebuffer::{Color, Framebuffer, panel_rgb, rgb};
+
+/// The black/white plane in logical orientation: black where the bit is
+/// clear, which is black on the panel unless the red plane says otherwise.
+pub fn black_plane(fb: &Framebuffer) -> GrayImage {
+    GrayImage::from_fn(fb.width(), fb.height(), |x, y| match fb.bits(x, y) {
+        Some((false, _)) => Luma([0]),
+        _ => Luma([255]),
+    })
+}
+
+/// The red plane in logical orientation, in the panel's third colour where
+/// the bit is set.
+pub fn accent_plane(fb: &Framebuffer) -> RgbImage {
+    RgbImage::from_fn(fb.width(), fb.height(), |x, y| match fb.bits(x, y) {
+        Some((_, true)) => panel_rgb(Color::Red, fb.colors()),
+        _ => rgb(Color::White),
+    })
+}
+
+/// Writes `<stem>-black.png`, `<stem>-red.png` and `<stem>-preview.png`, and
+/// returns their paths in that order.
+pub fn write_separations(fb: &Framebuffer, stem: &Path) -> ImageResult<[PathBuf; 3]> {
+    let path = |suffix: &str| {
+        let mut name = stem.file_name().unwrap_or_default().to_os_string();
+        name.push(format!("-{suffix}.png"));
+        stem.with_file_name(name)
+    };
+    let paths = [path("black"), path("red"), path("preview")];
+    black_plane(fb).save(&paths[0])?;
+    accent_plane(fb).save(&paths[1])?;
+    fb.to_image().save(&paths[2])?;
+    Ok(paths)
+}
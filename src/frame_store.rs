@@ -0,0 +1,159 @@
+// The last frame sent to the panel, kept on disk across restarts.
+//
+// E-paper holds its image with the power off, so after a restart the panel
+// still shows the last frame even though the process has forgotten it.
+// `FrameStore` remembers it in a file (both planes, back to back) and skips
+// refreshes that would put the same image up again; anything diffing against
+// the previous frame can read it back with `last_frame`.
+
+use std::fs;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+
+use embedded_hal::blocking::delay::DelayMs;
+
+use crate::epd::{ColorCapability, EpdController};
+use crate::pack::row_bytes;
+
+/// Suggested place for the frame file.
+pub const DEFAULT_PATH: &str = "/var/lib/rust_raspi/frame.bin";
+
+/// Wraps a panel and skips refreshing to a frame that is already showing.
+pub struct FrameStore<E> {
+    epd: E,
+    path: Option<PathBuf>,
+    // Black/white plane followed by the red plane
+    shown: Option<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl<E: EpdController> FrameStore<E> {
+    /// Loads the frame saved in `path`, if it is there and fits this panel.
+    /// With no `path` the frame is only remembered until the process exits.
+    pub fn new(epd: E, path: Option<PathBuf>) -> Self {
+        let (width, height) = epd.dimensions();
+        let len = 2 * row_bytes(width as usize) * height as usize;
+        let shown = path
+            .as_deref()
+            .and_then(|path| fs::read(path).ok())
+            .filter(|frame| frame.len() == len);
+        FrameStore {
+            epd,
+            path,
+            shown,
+            pending: Vec::new(),
+        }
+    }
+
+    /// The planes currently on the panel, as far as anyone here knows.
+    pub fn last_frame(&self) -> Option<(&[u8], &[u8])> {
+        self.shown.as_ref().map(|frame| frame.split_at(frame.len() / 2))
+    }
+
+    /// Forgets the last frame, so the next refresh happens whatever it shows.
+    pub fn forget(&mut self) {
+        self.shown = None;
+        if let Some(path) = &self.path {
+            invalidate(path);
+        }
+    }
+
+    pub fn into_inner(self) -> E {
+        self.epd
+    }
+
+    fn is_showing_pending(&self) -> bool {
+        self.shown.as_ref() == Some(&self.pending)
+    }
+
+    fn shown(&mut self) {
+        // A refresh without a write first leaves nothing new to remember
+        if self.pending.is_empty() {
+            return;
+        }
+        if let Some(path) = &self.path
+            && let Err(err) = save(path, &self.pending)
+        {
+            eprintln!("Could not save last frame to {}: {err}", path.display());
+        }
+        self.shown = Some(self.pending.clone());
+    }
+}
+
+/// Deletes a frame file written at `path`, for when the panel was changed
+/// behind the store's back (say, blanked by the panic hook).
+pub fn invalidate(path: &Path) {
+    let _ = fs::remove_file(path);
+}
+
+// Written to a temporary file first so a power cut can't leave half a frame
+fn save(path: &Path, frame: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension("tmp");
+    fs::write(&partial, frame)?;
+    fs::rename(partial, path)
+}
+
+impl<E> Deref for FrameStore<E> {
+    type Target = E;
+
+    fn deref(&self) -> &E {
+        &self.epd
+    }
+}
+
+impl<E> DerefMut for FrameStore<E> {
+    fn deref_mut(&mut self) -> &mut E {
+        &mut self.epd
+    }
+}
+
+impl<E: EpdController> EpdController for FrameStore<E> {
+    type Error = E::Error;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.epd.dimensions()
+    }
+
+    fn colors(&self) -> ColorCapability {
+        self.epd.colors()
+    }
+
+    fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.epd.init(delay)
+    }
+
+    fn write_planes(&mut self, bw: &[u8], red: &[u8]) -> Result<(), Self::Error> {
+        // Always written: a reset loses the controller's RAM even though the image stays
+        self.epd.write_planes(bw, red)?;
+        self.pending.clear();
+        self.pending.extend_from_slice(bw);
+        self.pending.extend_from_slice(red);
+        Ok(())
+    }
+
+    fn refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        if self.is_showing_pending() {
+            return Ok(());
+        }
+        self.epd.refresh(delay)?;
+        self.shown();
+        Ok(())
+    }
+
+    fn refresh_fast<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        if self.is_showing_pending() {
+            return Ok(());
+        }
+        self.epd.refresh_fast(delay)?;
+        self.shown();
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        self.epd.sleep()
+    }
+}
@@ -0,0 +1,362 @@
+use std::convert::Infallible;
+use std::io::Cursor;
+use std::path::Path;
+
+use embedded_graphics::pixelcolor::PixelColor;
+use embedded_graphics::prelude::{DrawTarget, OriginDimensions, Pixel, Point, Size};
+use image::{ImageFormat, ImageResult, Rgb, RgbImage};
+
+use crate::epd::{ColorCapability, EpdController};
+use crate::inky_driver::{HEIGHT, WIDTH};
+use crate::pack::row_bytes;
+use crate::sprite::Sprite;
+
+/// A pixel colour. `Red` is the panel's third colour, whatever that is
+/// (see `ColorCapability`): yellow on a yellow pHAT and black on a panel
+/// with none. Config files may call it `accent` or `yellow` too.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    White,
+    Black,
+    #[serde(alias = "accent", alias = "yellow")]
+    Red,
+}
+
+impl Color {
+    /// The panel's third colour, for code that shouldn't care which it is.
+    pub const ACCENT: Color = Color::Red;
+}
+
+impl PixelColor for Color {
+    type Raw = ();
+}
+
+/// How each panel colour is written to PNG.
+pub fn rgb(color: Color) -> Rgb<u8> {
+    match color {
+        Color::White => Rgb([255, 255, 255]),
+        Color::Black => Rgb([0, 0, 0]),
+        Color::Red => Rgb([255, 0, 0]),
+    }
+}
+
+/// How a panel with `colors` shows each colour; `rgb` is the red pHAT's.
+pub fn panel_rgb(color: Color, colors: ColorCapability) -> Rgb<u8> {
+    match (color, colors) {
+        (Color::Red, ColorCapability::BlackWhite) => rgb(Color::Black),
+        (Color::Red, ColorCapability::BlackWhiteYellow) => Rgb([255, 200, 0]),
+        _ => rgb(color),
+    }
+}
+
+/// Orientation of the logical drawing surface relative to the controller RAM.
+/// The pHAT is mounted landscape, so most users want `Rotate90`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+/// How `Framebuffer::blit` colours a sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlitOptions {
+    /// Colour of the sprite's set pixels
+    pub color: Color,
+    /// Colour of the clear pixels inside its mask
+    pub background: Color,
+    /// Colour of the pixels in its red plane, e.g. `Black` to keep a sprite off the third colour
+    pub red: Color,
+    /// Draws every pixel of the sprite, as if its mask were full
+    pub opaque: bool,
+}
+
+impl Default for BlitOptions {
+    fn default() -> Self {
+        BlitOptions {
+            color: Color::Black,
+            background: Color::White,
+            red: Color::Red,
+            opaque: false,
+        }
+    }
+}
+
+/// In-memory copy of the two controller RAM planes.
+///
+/// Pixels are addressed in logical (rotated) coordinates; the planes are kept
+/// in the native layout so they can be sent straight to `update_bw`/`update_red`.
+#[derive(Clone)]
+pub struct Framebuffer {
+    native_width: u32,
+    native_height: u32,
+    rotation: Rotation,
+    map: RotationMap,
+    // What `Red` looks like on the panel, for images of the frame
+    colors: ColorCapability,
+    // 1 = white, matching WRITE_RAM_BW
+    bw: Vec<u8>,
+    // 1 = red, matching WRITE_RAM_RED
+    red: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn new(native_width: u32, native_height: u32, rotation: Rotation) -> Self {
+        let len = row_bytes(native_width as usize) * native_height as usize;
+        Framebuffer {
+            native_width,
+            native_height,
+            rotation,
+            map: RotationMap::new(native_width, native_height, rotation),
+            colors: ColorCapability::default(),
+            bw: vec![0xFF; len],
+            red: vec![0x00; len],
+        }
+    }
+
+    /// Framebuffer matching whatever panel `epd` drives, colours included.
+    pub fn for_panel<E: EpdController>(epd: &E, rotation: Rotation) -> Self {
+        let (width, height) = epd.dimensions();
+        Self::new(width, height, rotation).with_colors(epd.colors())
+    }
+
+    /// Images of the frame show `Red` as a panel with `colors` would.
+    pub fn with_colors(mut self, colors: ColorCapability) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    pub fn colors(&self) -> ColorCapability {
+        self.colors
+    }
+
+    /// Framebuffer matching the Inky pHAT panel geometry.
+    pub fn inky_phat(rotation: Rotation) -> Self {
+        Self::new(WIDTH as u32, HEIGHT as u32, rotation)
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    /// Logical width after rotation.
+    pub fn width(&self) -> u32 {
+        match self.rotation {
+            Rotation::Rotate0 | Rotation::Rotate180 => self.native_width,
+            Rotation::Rotate90 | Rotation::Rotate270 => self.native_height,
+        }
+    }
+
+    /// Logical height after rotation.
+    pub fn height(&self) -> u32 {
+        match self.rotation {
+            Rotation::Rotate0 | Rotation::Rotate180 => self.native_height,
+            Rotation::Rotate90 | Rotation::Rotate270 => self.native_width,
+        }
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        let (bw, red) = match color {
+            Color::White => (0xFF, 0x00),
+            Color::Black => (0x00, 0x00),
+            Color::Red => (0xFF, 0xFF),
+        };
+        self.bw.fill(bw);
+        self.red.fill(red);
+    }
+
+    /// Repaints every pixel in the colour `map` gives for its own.
+    pub fn recolor(&mut self, map: impl Fn(Color) -> Color) {
+        let [white, black, red] = [Color::White, Color::Black, Color::Red].map(map);
+        for (bw, red_bits) in self.bw.iter_mut().zip(&mut self.red) {
+            // A byte at a time: which of its pixels are each colour now
+            let masks = [(*bw & !*red_bits, white), (!*bw & !*red_bits, black), (*red_bits, red)];
+            (*bw, *red_bits) = (0, 0);
+            for (mask, color) in masks {
+                match color {
+                    Color::White => *bw |= mask,
+                    Color::Black => {}
+                    Color::Red => {
+                        *bw |= mask;
+                        *red_bits |= mask;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        let Some((index, mask)) = self.locate(x, y) else {
+            return;
+        };
+        match color {
+            Color::White => {
+                self.bw[index] |= mask;
+                self.red[index] &= !mask;
+            }
+            Color::Black => {
+                self.bw[index] &= !mask;
+                self.red[index] &= !mask;
+            }
+            Color::Red => {
+                self.bw[index] |= mask;
+                self.red[index] |= mask;
+            }
+        }
+    }
+
+    /// Draws `sprite` with its top-left corner at `at`, leaving the pixels
+    /// outside its mask as they were. Whatever falls off the panel is clipped.
+    pub fn blit(&mut self, sprite: &Sprite, at: Point, options: BlitOptions) {
+        for y in 0..sprite.height {
+            for x in 0..sprite.width {
+                if !options.opaque && !sprite.is_masked(x, y) {
+                    continue;
+                }
+                let color = if sprite.is_red(x, y) {
+                    options.red
+                } else if sprite.is_set(x, y) {
+                    options.color
+                } else {
+                    options.background
+                };
+                let (px, py) = (at.x + x as i32, at.y + y as i32);
+                if px >= 0 && py >= 0 {
+                    self.set_pixel(px as u32, py as u32, color);
+                }
+            }
+        }
+    }
+
+    /// Returns the pixel colour, or `None` when the point is off the panel.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<Color> {
+        let (index, mask) = self.locate(x, y)?;
+        Some(if self.red[index] & mask != 0 {
+            Color::Red
+        } else if self.bw[index] & mask != 0 {
+            Color::White
+        } else {
+            Color::Black
+        })
+    }
+
+    /// The raw plane bits behind a pixel: (black/white, red), where a set
+    /// black/white bit is white and a set red bit is red.
+    pub fn bits(&self, x: u32, y: u32) -> Option<(bool, bool)> {
+        let (index, mask) = self.locate(x, y)?;
+        Some((self.bw[index] & mask != 0, self.red[index] & mask != 0))
+    }
+
+    pub fn bw_plane(&self) -> &[u8] {
+        &self.bw
+    }
+
+    pub fn red_plane(&self) -> &[u8] {
+        &self.red
+    }
+
+    /// Replaces both planes with copies in the native layout, as a panel
+    /// would receive them; planes of the wrong size are ignored.
+    pub fn load_planes(&mut self, bw: &[u8], red: &[u8]) {
+        if bw.len() == self.bw.len() && red.len() == self.red.len() {
+            self.bw.copy_from_slice(bw);
+            self.red.copy_from_slice(red);
+        }
+    }
+
+    /// The frame in logical orientation as the panel would show it, in pure
+    /// white, black and its third colour.
+    pub fn to_image(&self) -> RgbImage {
+        RgbImage::from_fn(self.width(), self.height(), |x, y| {
+            panel_rgb(self.get_pixel(x, y).unwrap_or(Color::White), self.colors)
+        })
+    }
+
+    /// Saves `to_image` as a PNG, whatever the extension of `path`.
+    pub fn to_png(&self, path: impl AsRef<Path>) -> ImageResult<()> {
+        self.to_image().save_with_format(path, ImageFormat::Png)
+    }
+
+    /// `to_image` encoded as PNG, e.g. for an HTTP response.
+    pub fn png_bytes(&self) -> ImageResult<Vec<u8>> {
+        let mut png = Cursor::new(Vec::new());
+        self.to_image().write_to(&mut png, ImageFormat::Png)?;
+        Ok(png.into_inner())
+    }
+
+    // Map a logical point to a byte index and bit mask in the native planes
+    fn locate(&self, x: u32, y: u32) -> Option<(usize, u8)> {
+        let (col_offset, col_mask) = *self.map.cols.get(x as usize)?;
+        let (row_offset, row_mask) = *self.map.rows.get(y as usize)?;
+        Some((col_offset + row_offset, col_mask | row_mask))
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        Size::new(self.width(), self.height())
+    }
+}
+
+// Lets embedded-graphics primitives, fonts and images draw in logical coordinates
+impl DrawTarget for Framebuffer {
+    type Color = Color;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 {
+                self.set_pixel(point.x as u32, point.y as u32, color);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Precomputed logical-to-native address tables for one panel and rotation.
+///
+/// Every rotation sends logical x to exactly one native axis and logical y to
+/// the other, so a plane address splits into a part that depends only on x
+/// and a part that depends only on y. Exactly one of the two carries the bit
+/// mask (the one landing on native x); the other's mask is zero. Lookups are
+/// then two table reads per pixel, and the tables are only width + height long.
+#[derive(Clone)]
+pub(crate) struct RotationMap {
+    // Indexed by logical x: (byte offset contribution, bit mask contribution)
+    pub(crate) cols: Vec<(usize, u8)>,
+    // Indexed by logical y
+    pub(crate) rows: Vec<(usize, u8)>,
+}
+
+impl RotationMap {
+    pub(crate) fn new(native_width: u32, native_height: u32, rotation: Rotation) -> Self {
+        let stride = row_bytes(native_width as usize);
+        let (w, h) = (native_width as usize, native_height as usize);
+        // Contribution of a coordinate that lands on native x or native y
+        let along_x = |nx: usize| (nx / 8, 0x80u8 >> (nx % 8));
+        let along_y = |ny: usize| (ny * stride, 0u8);
+
+        let (cols, rows): (Vec<_>, Vec<_>) = match rotation {
+            Rotation::Rotate0 => ((0..w).map(along_x).collect(), (0..h).map(along_y).collect()),
+            Rotation::Rotate90 => (
+                (0..h).map(along_y).collect(),
+                (0..w).map(|y| along_x(w - 1 - y)).collect(),
+            ),
+            Rotation::Rotate180 => (
+                (0..w).map(|x| along_x(w - 1 - x)).collect(),
+                (0..h).map(|y| along_y(h - 1 - y)).collect(),
+            ),
+            Rotation::Rotate270 => (
+                (0..h).map(|x| along_y(h - 1 - x)).collect(),
+                (0..w).map(along_x).collect(),
+            ),
+        };
+        RotationMap { cols, rows }
+    }
+}
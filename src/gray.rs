@@ -0,0 +1,152 @@
+// Four grey levels instead of black, white and red.
+//
+// The panel itself only knows black and white, but a pixel driven towards
+// the other colour for a moment stops part way; see `GrayWriter` for how the
+// two refresh passes get there. Photos and charts look far better with four
+// levels than dithered to two, at the price of a refresh twice as long and no
+// red. Images go through `ImageRenderer::draw_gray`, which dithers to four
+// levels rather than two.
+//
+//     let mut fb = GrayFramebuffer::for_panel(&inky, Rotation::Rotate90);
+//     ImageRenderer::new(fb.width()).draw_gray(&mut fb, &photo, &Placement::default());
+//     fb.show(&mut inky, &mut delay)?;
+
+use std::convert::Infallible;
+
+use embedded_graphics::pixelcolor::{Gray2, GrayColor};
+use embedded_graphics::prelude::{DrawTarget, OriginDimensions, Pixel, Size};
+use embedded_hal::blocking::delay::DelayMs;
+
+use crate::epd::GrayWriter;
+use crate::framebuffer::{Rotation, RotationMap};
+use crate::pack::row_bytes;
+
+/// A frame of 2-bit grey levels, in two planes in the native layout: the high
+/// bit of every pixel's level and its low bit. Level 3 is white, so a set high
+/// bit reads as white on the black/white plane.
+#[derive(Clone)]
+pub struct GrayFramebuffer {
+    native_width: u32,
+    native_height: u32,
+    rotation: Rotation,
+    map: RotationMap,
+    msb: Vec<u8>,
+    lsb: Vec<u8>,
+}
+
+impl GrayFramebuffer {
+    pub fn new(native_width: u32, native_height: u32, rotation: Rotation) -> Self {
+        let len = row_bytes(native_width as usize) * native_height as usize;
+        GrayFramebuffer {
+            native_width,
+            native_height,
+            rotation,
+            map: RotationMap::new(native_width, native_height, rotation),
+            msb: vec![0xFF; len],
+            lsb: vec![0xFF; len],
+        }
+    }
+
+    /// Framebuffer matching whatever panel `epd` drives.
+    pub fn for_panel<E: GrayWriter>(epd: &E, rotation: Rotation) -> Self {
+        let (width, height) = epd.dimensions();
+        Self::new(width, height, rotation)
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    /// Logical width after rotation.
+    pub fn width(&self) -> u32 {
+        match self.rotation {
+            Rotation::Rotate0 | Rotation::Rotate180 => self.native_width,
+            Rotation::Rotate90 | Rotation::Rotate270 => self.native_height,
+        }
+    }
+
+    /// Logical height after rotation.
+    pub fn height(&self) -> u32 {
+        match self.rotation {
+            Rotation::Rotate0 | Rotation::Rotate180 => self.native_height,
+            Rotation::Rotate90 | Rotation::Rotate270 => self.native_width,
+        }
+    }
+
+    pub fn clear(&mut self, color: Gray2) {
+        let (msb, lsb) = bits(color);
+        self.msb.fill(if msb { 0xFF } else { 0x00 });
+        self.lsb.fill(if lsb { 0xFF } else { 0x00 });
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Gray2) {
+        let Some((index, mask)) = self.locate(x, y) else {
+            return;
+        };
+        let (msb, lsb) = bits(color);
+        for (plane, set) in [(&mut self.msb, msb), (&mut self.lsb, lsb)] {
+            if set {
+                plane[index] |= mask;
+            } else {
+                plane[index] &= !mask;
+            }
+        }
+    }
+
+    /// Returns the pixel's level, or `None` when the point is off the panel.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<Gray2> {
+        let (index, mask) = self.locate(x, y)?;
+        let msb = (self.msb[index] & mask != 0) as u8;
+        let lsb = (self.lsb[index] & mask != 0) as u8;
+        Some(Gray2::new(msb << 1 | lsb))
+    }
+
+    pub fn msb_plane(&self) -> &[u8] {
+        &self.msb
+    }
+
+    pub fn lsb_plane(&self) -> &[u8] {
+        &self.lsb
+    }
+
+    /// Sends the frame to `epd` and refreshes, in both passes.
+    pub fn show<E: GrayWriter, D: DelayMs<u8>>(&self, epd: &mut E, delay: &mut D) -> Result<(), E::Error> {
+        epd.show_gray(&self.msb, &self.lsb, delay)
+    }
+
+    // Map a logical point to a byte index and bit mask in the native planes
+    fn locate(&self, x: u32, y: u32) -> Option<(usize, u8)> {
+        let (col_offset, col_mask) = *self.map.cols.get(x as usize)?;
+        let (row_offset, row_mask) = *self.map.rows.get(y as usize)?;
+        Some((col_offset + row_offset, col_mask | row_mask))
+    }
+}
+
+// (high, low) bit of a level
+fn bits(color: Gray2) -> (bool, bool) {
+    let luma = color.luma();
+    (luma & 0b10 != 0, luma & 0b01 != 0)
+}
+
+impl OriginDimensions for GrayFramebuffer {
+    fn size(&self) -> Size {
+        Size::new(self.width(), self.height())
+    }
+}
+
+impl DrawTarget for GrayFramebuffer {
+    type Color = Gray2;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 {
+                self.set_pixel(point.x as u32, point.y as u32, color);
+            }
+        }
+        Ok(())
+    }
+}
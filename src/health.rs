@@ -0,0 +1,82 @@
+// Health reporting for the daemon's /healthz endpoint.
+
+use std::fs::OpenOptions;
+use std::path::Path;
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    #[default]
+    Ok,
+    Degraded,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Check {
+    pub fn pass(name: &'static str) -> Self {
+        Check {
+            name,
+            ok: true,
+            detail: None,
+        }
+    }
+
+    pub fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            ok: false,
+            detail: Some(detail.into()),
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// Overall status plus the individual checks that produced it.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct HealthReport {
+    pub status: Status,
+    pub checks: Vec<Check>,
+}
+
+impl HealthReport {
+    /// Adds a check; any failing check marks the whole report degraded.
+    pub fn push(&mut self, check: Check) {
+        if !check.ok {
+            self.status = Status::Degraded;
+        }
+        self.checks.push(check);
+    }
+
+    /// 200 when healthy, 503 when degraded, so orchestrators can act on the code alone.
+    pub fn http_status(&self) -> u16 {
+        match self.status {
+            Status::Ok => 200,
+            Status::Degraded => 503,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| r#"{"status":"degraded"}"#.to_string())
+    }
+}
+
+/// Checks that the SPI device node still exists and can be opened for writing.
+pub fn check_spi_node(path: &Path) -> Check {
+    match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(_) => Check::pass("spi"),
+        Err(err) => Check::fail("spi", format!("{}: {err}", path.display())),
+    }
+}
@@ -0,0 +1,160 @@
+// Just enough iCalendar (RFC 5545) to read exported calendars.
+//
+// We only look at VEVENTs and their SUMMARY, DTSTART, DTEND and a yearly
+// RRULE. Time zones are not resolved: UTC times (`...Z`) are converted to
+// local time, anything with a TZID is assumed to already be local.
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventTime {
+    /// All-day value (`VALUE=DATE`)
+    Date(NaiveDate),
+    /// Local date and time
+    DateTime(NaiveDateTime),
+}
+
+impl EventTime {
+    pub fn date(&self) -> NaiveDate {
+        match self {
+            EventTime::Date(date) => *date,
+            EventTime::DateTime(datetime) => datetime.date(),
+        }
+    }
+
+    /// Start of the value as a local timestamp (midnight for all-day values).
+    pub fn naive(&self) -> NaiveDateTime {
+        match self {
+            EventTime::Date(date) => date.and_hms_opt(0, 0, 0).unwrap_or_default(),
+            EventTime::DateTime(datetime) => *datetime,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub summary: String,
+    pub start: EventTime,
+    pub end: Option<EventTime>,
+    /// `RRULE:FREQ=YEARLY`, typical for holidays and birthdays
+    pub yearly: bool,
+}
+
+impl Event {
+    pub fn is_all_day(&self) -> bool {
+        matches!(self.start, EventTime::Date(_))
+    }
+
+    /// Every date the event touches, ignoring recurrence. DTEND is exclusive
+    /// for all-day events, so a one-day holiday covers exactly its start date.
+    pub fn dates(&self) -> impl Iterator<Item = NaiveDate> + '_ {
+        let first = self.start.date();
+        let last = match self.end {
+            Some(EventTime::Date(end)) => end.pred_opt().unwrap_or(end).max(first),
+            Some(EventTime::DateTime(end)) => end.date().max(first),
+            None => first,
+        };
+        first.iter_days().take_while(move |date| *date <= last)
+    }
+}
+
+/// Parses every VEVENT in `text`. Malformed events are skipped rather than failing the whole file.
+pub fn parse(text: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut current: Option<Partial> = None;
+    for line in unfold(text) {
+        let Some((name, params, value)) = split_property(&line) else {
+            continue;
+        };
+        match (name.as_str(), current.as_mut()) {
+            ("BEGIN", _) if value.eq_ignore_ascii_case("VEVENT") => current = Some(Partial::default()),
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+                if let Some(event) = current.take().and_then(Partial::finish) {
+                    events.push(event);
+                }
+            }
+            ("SUMMARY", Some(event)) => event.summary = unescape(value),
+            ("DTSTART", Some(event)) => event.start = parse_time(params, value),
+            ("DTEND", Some(event)) => event.end = parse_time(params, value),
+            ("RRULE", Some(event)) => {
+                event.yearly = value.split(';').any(|part| part.eq_ignore_ascii_case("FREQ=YEARLY"));
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
+#[derive(Default)]
+struct Partial {
+    summary: String,
+    start: Option<EventTime>,
+    end: Option<EventTime>,
+    yearly: bool,
+}
+
+impl Partial {
+    fn finish(self) -> Option<Event> {
+        Some(Event {
+            summary: self.summary,
+            start: self.start?,
+            end: self.end,
+            yearly: self.yearly,
+        })
+    }
+}
+
+// Joins continuation lines (those starting with a space or tab) onto the previous line
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in text.lines() {
+        let raw = raw.trim_end_matches('\r');
+        match (raw.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    lines
+}
+
+// "DTSTART;VALUE=DATE:20240101" -> ("DTSTART", "VALUE=DATE", "20240101")
+fn split_property(line: &str) -> Option<(String, &str, &str)> {
+    let (head, value) = line.split_once(':')?;
+    let (name, params) = head.split_once(';').unwrap_or((head, ""));
+    Some((name.to_ascii_uppercase(), params, value.trim()))
+}
+
+fn parse_time(params: &str, value: &str) -> Option<EventTime> {
+    let is_date = params
+        .split(';')
+        .any(|param| param.eq_ignore_ascii_case("VALUE=DATE"))
+        || value.len() == 8;
+    if is_date {
+        return NaiveDate::parse_from_str(value, "%Y%m%d").ok().map(EventTime::Date);
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        let local: DateTime<Local> = Utc.from_utc_datetime(&naive).with_timezone(&Local);
+        return Some(EventTime::DateTime(local.naive_local()));
+    }
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .map(EventTime::DateTime)
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push(' '),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
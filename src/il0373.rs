@@ -0,0 +1,258 @@
+// Driver for the IL0373, the controller on Adafruit's tri-colour 2.13"
+// FeatherWing and ThinkInk panels.
+//
+// It speaks a different command set from the pHAT's SSD1675 (a panel
+// setting and explicit power on/off rather than update-control registers,
+// and whole-frame transmissions instead of a RAM window), but sits on the
+// same four lines, so it takes the same SPI and pins as `InkyPhat` and
+// reports failures as `InkyError`. Wired as Adafruit's Pi examples wire it
+// (CS on CE0, DC 22, RST 27, BUSY 17) it opens with `Pins::INKY_PHAT`.
+//
+// Unlike the SSD1675, BUSY reads low while the controller works, and the
+// second plane is drawn where its bits are clear, so red is sent inverted.
+
+use embedded_hal as hal;
+use hal::blocking::delay::DelayMs;
+use hal::blocking::spi::Write;
+use hal::digital::v2::{InputPin, OutputPin};
+
+use crate::epd::EpdController;
+use crate::inky_driver::{DEFAULT_MAX_TRANSFER, InkyError, gpio, spi};
+
+// command constants for IL0373 controller from datasheet
+pub const PANEL_SETTING: u8 = 0x00;
+pub const POWER_SETTING: u8 = 0x01;
+pub const POWER_OFF: u8 = 0x02;
+pub const POWER_ON: u8 = 0x04;
+pub const BOOSTER_SOFT_START: u8 = 0x06;
+pub const DEEP_SLEEP: u8 = 0x07;
+pub const DATA_START_TRANSMISSION_1: u8 = 0x10;
+pub const DISPLAY_REFRESH: u8 = 0x12;
+pub const DATA_START_TRANSMISSION_2: u8 = 0x13;
+pub const PLL_CONTROL: u8 = 0x30;
+pub const VCOM_AND_DATA_INTERVAL: u8 = 0x50;
+pub const RESOLUTION_SETTING: u8 = 0x61;
+pub const VCM_DC_SETTING: u8 = 0x82;
+
+/// Native size (source lines, gate lines) of the tri-colour 2.13" FeatherWing.
+pub const FEATHERWING_2IN13: (u32, u32) = (104, 212);
+/// Native size of the tri-colour 2.9" ThinkInk panel.
+pub const THINKINK_2IN9: (u32, u32) = (128, 296);
+/// Native size of the tri-colour 1.54" ThinkInk panel.
+pub const THINKINK_1IN54: (u32, u32) = (152, 152);
+
+// Red is inverted through a buffer this big, since there is no heap to do it in
+const INVERT_CHUNK: usize = 64;
+
+// Name of a command constant, for error messages
+pub fn command_name(command: u8) -> &'static str {
+    match command {
+        PANEL_SETTING => "PANEL_SETTING",
+        POWER_SETTING => "POWER_SETTING",
+        POWER_OFF => "POWER_OFF",
+        POWER_ON => "POWER_ON",
+        BOOSTER_SOFT_START => "BOOSTER_SOFT_START",
+        DEEP_SLEEP => "DEEP_SLEEP",
+        DATA_START_TRANSMISSION_1 => "DATA_START_TRANSMISSION_1",
+        DISPLAY_REFRESH => "DISPLAY_REFRESH",
+        DATA_START_TRANSMISSION_2 => "DATA_START_TRANSMISSION_2",
+        PLL_CONTROL => "PLL_CONTROL",
+        VCOM_AND_DATA_INTERVAL => "VCOM_AND_DATA_INTERVAL",
+        RESOLUTION_SETTING => "RESOLUTION_SETTING",
+        VCM_DC_SETTING => "VCM_DC_SETTING",
+        _ => "unknown command",
+    }
+}
+
+pub struct Il0373<SPI, CS, BUSY, DC, RESET> {
+    spi: SPI,
+    cs: CS,
+    busy: BUSY,
+    dc: DC,
+    reset: RESET,
+    width: u32,
+    height: u32,
+    // Data longer than this is split into several SPI writes
+    max_transfer: usize,
+}
+
+impl<SPI, CS, BUSY, DC, RESET, SPIE, GPIOE> Il0373<SPI, CS, BUSY, DC, RESET>
+where
+    SPI: Write<u8, Error = SPIE>,
+    CS: OutputPin<Error = GPIOE>,
+    BUSY: InputPin<Error = GPIOE>,
+    DC: OutputPin<Error = GPIOE>,
+    RESET: OutputPin<Error = GPIOE>,
+{
+    /// A panel `size` native pixels big, e.g. `THINKINK_2IN9`. The width is
+    /// rounded up to whole bytes.
+    pub fn new(spi: SPI, cs: CS, busy: BUSY, dc: DC, reset: RESET, size: (u32, u32)) -> Self {
+        Il0373 {
+            spi,
+            cs,
+            busy,
+            dc,
+            reset,
+            width: size.0.div_ceil(8) * 8,
+            height: size.1,
+            max_transfer: DEFAULT_MAX_TRANSFER,
+        }
+    }
+
+    pub fn with_max_transfer(mut self, bytes: usize) -> Self {
+        // Cap each SPI write, for kernels with a smaller spidev.bufsiz (zero is treated as one)
+        self.max_transfer = bytes.max(1);
+        self
+    }
+
+    pub fn into_parts(self) -> (SPI, CS, BUSY, DC, RESET) {
+        // Hand the bus and pins back; call sleep() first
+        (self.spi, self.cs, self.busy, self.dc, self.reset)
+    }
+
+    pub fn reset<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // The datasheet asks for RST low at least 10ms; hold it longer, as for the SSD1675
+        self.reset.set_low().map_err(gpio("reset"))?;
+        delay.delay_ms(100);
+        self.reset.set_high().map_err(gpio("reset"))?;
+        delay.delay_ms(100);
+        Ok(())
+    }
+
+    fn send_command(&mut self, command: u8) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Set DC low for command, pull CS low, send command byte, then pull CS high to release
+        let context = command_name(command);
+        self.dc.set_low().map_err(gpio(context))?;
+        self.selected(context, |epd| epd.spi.write(&[command]).map_err(spi(context)))
+    }
+
+    // Runs `transfer` with CS low, raising it again whether or not that worked, so
+    // a failed write never leaves the panel selected (or the SPI bus held)
+    fn selected<T>(
+        &mut self,
+        context: &'static str,
+        transfer: impl FnOnce(&mut Self) -> Result<T, InkyError<SPIE, GPIOE>>,
+    ) -> Result<T, InkyError<SPIE, GPIOE>> {
+        self.cs.set_low().map_err(gpio(context))?;
+        let transferred = transfer(self);
+        let raised = self.cs.set_high().map_err(gpio(context));
+        let value = transferred?;
+        raised?;
+        Ok(value)
+    }
+
+    fn send_command_data(&mut self, command: u8, data: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Command, then its data with DC high in one CS-low transfer, split into max_transfer writes
+        self.send_command(command)?;
+        let context = command_name(command);
+        self.dc.set_high().map_err(gpio(context))?;
+        self.selected(context, |epd| {
+            for chunk in data.chunks(epd.max_transfer) {
+                epd.spi.write(chunk).map_err(spi(context))?;
+            }
+            Ok(())
+        })
+    }
+
+    fn busy_wait<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // BUSY is low while the controller works. is_low never blocks, even on an
+        // edge-triggered pin, so this always polls
+        while self.busy.is_low().map_err(gpio("busy wait"))? {
+            delay.delay_ms(10);
+        }
+        Ok(())
+    }
+
+    pub fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Init sequence, as Adafruit's driver sends it:
+        // reset, boost and power the charge pumps, then program the panel
+        self.reset(delay)?;
+        self.send_command_data(BOOSTER_SOFT_START, &[0x17, 0x17, 0x17])?;
+        self.send_command(POWER_ON)?;
+        self.busy_wait(delay)?;
+        delay.delay_ms(200);
+        // Resolution from the register below, tri-colour (KWR), LUT from OTP, scan up and right
+        self.send_command_data(PANEL_SETTING, &[0xCF])?;
+        // White border; data polarity with 0 as black (and as red, in the second plane)
+        self.send_command_data(VCOM_AND_DATA_INTERVAL, &[0x37])?;
+        // 50Hz frame rate
+        self.send_command_data(PLL_CONTROL, &[0x29])?;
+        self.send_command_data(VCM_DC_SETTING, &[0x0A])?;
+        delay.delay_ms(20);
+        let (width, height) = (self.width, self.height);
+        self.send_command_data(RESOLUTION_SETTING, &[width as u8, (height >> 8) as u8, height as u8])?;
+        Ok(())
+    }
+
+    pub fn update_bw(&mut self, buffer: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Black/white plane in the framebuffer's own polarity: 1 white, 0 black
+        self.send_command_data(DATA_START_TRANSMISSION_1, buffer)
+    }
+
+    pub fn update_red(&mut self, buffer: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // The framebuffer's red plane has 1 for red; the controller wants 0
+        self.send_command(DATA_START_TRANSMISSION_2)?;
+        let context = command_name(DATA_START_TRANSMISSION_2);
+        self.dc.set_high().map_err(gpio(context))?;
+        self.selected(context, |epd| {
+            let mut inverted = [0u8; INVERT_CHUNK];
+            for chunk in buffer.chunks(INVERT_CHUNK.min(epd.max_transfer)) {
+                for (out, byte) in inverted.iter_mut().zip(chunk) {
+                    *out = !byte;
+                }
+                epd.spi.write(&inverted[..chunk.len()]).map_err(spi(context))?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn display_refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // BUSY takes a moment to drop after DISPLAY_REFRESH, so don't look at it straight away
+        self.send_command(DISPLAY_REFRESH)?;
+        delay.delay_ms(100);
+        self.busy_wait(delay)
+    }
+
+    pub fn sleep(&mut self) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Float the border and VCOM, cut the power, then deep sleep (0xA5 is the check code).
+        // RAM is lost; init resets the controller and the next frame is sent whole anyway
+        self.send_command_data(VCOM_AND_DATA_INTERVAL, &[0x17])?;
+        self.send_command_data(VCM_DC_SETTING, &[0x00])?;
+        self.send_command(POWER_OFF)?;
+        self.send_command_data(DEEP_SLEEP, &[0xA5])
+    }
+}
+
+impl<SPI, CS, BUSY, DC, RESET, SPIE, GPIOE> EpdController for Il0373<SPI, CS, BUSY, DC, RESET>
+where
+    SPI: Write<u8, Error = SPIE>,
+    CS: OutputPin<Error = GPIOE>,
+    BUSY: InputPin<Error = GPIOE>,
+    DC: OutputPin<Error = GPIOE>,
+    RESET: OutputPin<Error = GPIOE>,
+    SPIE: core::fmt::Debug,
+    GPIOE: core::fmt::Debug,
+{
+    type Error = InkyError<SPIE, GPIOE>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        Il0373::init(self, delay)
+    }
+
+    fn write_planes(&mut self, bw: &[u8], red: &[u8]) -> Result<(), Self::Error> {
+        self.update_bw(bw)?;
+        self.update_red(red)
+    }
+
+    fn refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.display_refresh(delay)
+    }
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        Il0373::sleep(self)
+    }
+}
@@ -0,0 +1,387 @@
+use std::path::Path;
+
+use embedded_graphics::pixelcolor::{Gray2, GrayColor};
+use image::{Rgb, RgbImage};
+use serde::Deserialize;
+use serde::de::IntoDeserializer;
+
+pub use image::ImageError;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::gray::GrayFramebuffer;
+use crate::pack::Ditherer;
+
+/// How an image whose aspect ratio differs from the panel's is fitted to it,
+/// and how its tones are brought down to the panel's three colours.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Placement {
+    pub fit: Fit,
+    /// Where the image sits in the leftover space (letterbox), or which part is kept (crop)
+    pub anchor: Anchor,
+    /// Colour of letterbox bars
+    pub background: Color,
+    pub tone: Tone,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement {
+            fit: Fit::Letterbox,
+            anchor: Anchor::TopLeft,
+            background: Color::White,
+            tone: Tone::default(),
+        }
+    }
+}
+
+/// Adjustments made to an image's grey levels before it is dithered. The
+/// defaults leave it as it is, which suits drawings; photos mostly want some
+/// `stretch` and a `gamma` above 1.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tone {
+    /// Above 1 lightens the mid-tones, below 1 darkens them
+    pub gamma: f32,
+    /// Contrast stretch: the darkest and the lightest this percentage of
+    /// pixels go to pure black and white, and everything between is spread out
+    pub stretch: f32,
+    /// Grey level (0-255) from which a pixel counts as white; higher gives more black
+    pub threshold: u8,
+    /// A pixel goes to the red plane when its red is at least `red_min` and
+    /// green and blue are at most `red_max_other`
+    pub red_min: u8,
+    pub red_max_other: u8,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone {
+            gamma: 1.0,
+            stretch: 0.0,
+            threshold: 128,
+            red_min: 128,
+            red_max_other: 96,
+        }
+    }
+}
+
+impl Tone {
+    fn is_red(&self, pixel: &Rgb<u8>) -> bool {
+        let [r, g, b] = pixel.0;
+        r >= self.red_min && g <= self.red_max_other && b <= self.red_max_other
+    }
+
+    // Grey level for every luminance in `img`: the stretch's ends come from its histogram
+    fn curve(&self, img: &RgbImage) -> [u8; 256] {
+        let (mut low, mut high) = (0u32, 255u32);
+        if self.stretch > 0.0 {
+            let mut histogram = [0u32; 256];
+            for pixel in img.pixels().filter(|pixel| !self.is_red(pixel)) {
+                histogram[luminance(pixel) as usize] += 1;
+            }
+            let total: u32 = histogram.iter().sum();
+            let clipped = (total as f64 * self.stretch.min(50.0) as f64 / 100.0) as u32;
+            let dark = clip_end(&histogram, clipped, 0..256);
+            let light = clip_end(&histogram, clipped, (0..256).rev());
+            if let (Some(dark), Some(light)) = (dark, light)
+                && dark < light
+            {
+                (low, high) = (dark as u32, light as u32);
+            }
+        }
+        let exponent = 1.0 / self.gamma.max(0.01);
+        let mut curve = [0; 256];
+        for (level, out) in curve.iter_mut().enumerate() {
+            let stretched = (level as u32).clamp(low, high) - low;
+            let share = stretched as f32 / (high - low) as f32;
+            *out = (share.powf(exponent) * 255.0).round() as u8;
+        }
+        curve
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fit {
+    /// Scale to fit inside the panel, filling the rest with the background
+    #[default]
+    Letterbox,
+    /// Scale to cover the whole panel, cutting off what sticks out
+    Crop,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    // (x, y) share of the slack that goes before the image: 0, 1/2 or 1, as halves
+    fn halves(self) -> (i64, i64) {
+        match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        }
+    }
+}
+
+impl std::str::FromStr for Placement {
+    type Err = String;
+
+    /// Parses `fit[,anchor[,background]]`, e.g. `crop,center` or `letterbox,bottom,black`.
+    fn from_str(spec: &str) -> Result<Self, String> {
+        let mut placement = Placement::default();
+        let mut parts = spec.split(',').map(str::trim);
+        if let Some(part) = parts.next() {
+            placement.fit = parse_name(part, "fit")?;
+        }
+        if let Some(part) = parts.next() {
+            placement.anchor = parse_name(part, "anchor")?;
+        }
+        if let Some(part) = parts.next() {
+            placement.background = parse_name(part, "colour")?;
+        }
+        Ok(placement)
+    }
+}
+
+// A unit enum variant by its config-file name
+fn parse_name<'de, T: Deserialize<'de>>(name: &'de str, what: &str) -> Result<T, String> {
+    T::deserialize(name.into_deserializer()).map_err(|_: serde::de::value::Error| format!("unknown {what} {name:?}"))
+}
+
+/// Loads any supported image file (PNG, JPEG) as 8-bit RGB.
+pub fn load(path: &Path) -> Result<RgbImage, ImageError> {
+    Ok(image::open(path)?.to_rgb8())
+}
+
+/// Scales `img` to fit the framebuffer, keeping its aspect ratio, and draws it
+/// anchored top-left on a white background (see [`draw_placed`] for other
+/// placements). Strong reds go to the red plane, everything else is dithered
+/// to black and white.
+///
+/// This allocates scratch space on every call; long-running loops should keep
+/// an [`ImageRenderer`] around instead.
+pub fn draw(fb: &mut Framebuffer, img: &RgbImage) {
+    draw_placed(fb, img, &Placement::default());
+}
+
+/// [`draw`] with a choice of letterbox or crop, anchor and background.
+pub fn draw_placed(fb: &mut Framebuffer, img: &RgbImage, placement: &Placement) {
+    ImageRenderer::new(fb.width()).draw_placed(fb, img, placement);
+}
+
+/// Image-to-framebuffer conversion with all scratch buffers allocated up front.
+///
+/// After construction, [`ImageRenderer::draw`] performs no heap allocation for
+/// any framebuffer up to the width it was created for. (Decoding the source
+/// image still allocates; that happens before rendering.)
+pub struct ImageRenderer {
+    max_width: u32,
+    ditherer: Ditherer,
+    luma: Vec<u8>,
+    red: Vec<bool>,
+}
+
+impl ImageRenderer {
+    /// Renderer for framebuffers up to `max_width` logical pixels wide.
+    pub fn new(max_width: u32) -> Self {
+        ImageRenderer {
+            max_width,
+            ditherer: Ditherer::new(max_width as usize),
+            luma: vec![0; max_width as usize],
+            red: vec![false; max_width as usize],
+        }
+    }
+
+    /// Same output as [`draw`], reusing this renderer's buffers.
+    pub fn draw(&mut self, fb: &mut Framebuffer, img: &RgbImage) {
+        self.draw_placed(fb, img, &Placement::default());
+    }
+
+    /// Same output as [`draw_placed`], reusing this renderer's buffers.
+    pub fn draw_placed(&mut self, fb: &mut Framebuffer, img: &RgbImage, placement: &Placement) {
+        let Window { left, top, width, height, x0, x1, y0, y1 } =
+            Window::new(img, fb.width().min(self.max_width), fb.height(), placement);
+
+        fb.clear(placement.background);
+        let tone = &placement.tone;
+        let curve = tone.curve(img);
+        self.ditherer.reset();
+        self.ditherer.set_levels(2);
+        self.ditherer.set_threshold(tone.threshold);
+        let visible = x1.saturating_sub(x0) as usize;
+        let luma = &mut self.luma[..visible];
+        let red = &mut self.red[..visible];
+        for y in y0..y1 {
+            let sy = (y as i64 - top) as u32;
+            for (i, x) in (x0..x1).enumerate() {
+                let sx = (x as i64 - left) as u32;
+                let pixel = area_sample(img, sx, sy, width, height);
+                red[i] = tone.is_red(&pixel);
+                // Red pixels are light on the black plane, so don't let them spread dark error
+                luma[i] = if red[i] { 255 } else { curve[luminance(&pixel) as usize] };
+            }
+            let row = self.ditherer.quantise_row(luma);
+            for (i, &level) in row.iter().enumerate() {
+                let color = if red[i] {
+                    Color::Red
+                } else if level == 0 {
+                    Color::Black
+                } else {
+                    Color::White
+                };
+                fb.set_pixel(x0 + i as u32, y, color);
+            }
+        }
+    }
+
+    /// Draws `img` in four grey levels, placed as [`draw_placed`] would place
+    /// it. There is no red: red pixels are dithered by their luminance like
+    /// the rest, a red background is drawn dark grey, and the tone's thresholds
+    /// are unused.
+    pub fn draw_gray(&mut self, fb: &mut GrayFramebuffer, img: &RgbImage, placement: &Placement) {
+        let Window { left, top, width, height, x0, x1, y0, y1 } =
+            Window::new(img, fb.width().min(self.max_width), fb.height(), placement);
+
+        fb.clear(match placement.background {
+            Color::White => Gray2::WHITE,
+            Color::Black => Gray2::BLACK,
+            Color::Red => Gray2::new(1),
+        });
+        let curve = Tone { stretch: placement.tone.stretch, gamma: placement.tone.gamma, ..Tone::default() }.curve(img);
+        self.ditherer.reset();
+        self.ditherer.set_levels(4);
+        let luma = &mut self.luma[..x1.saturating_sub(x0) as usize];
+        for y in y0..y1 {
+            let sy = (y as i64 - top) as u32;
+            for (i, x) in (x0..x1).enumerate() {
+                let sx = (x as i64 - left) as u32;
+                luma[i] = curve[luminance(&area_sample(img, sx, sy, width, height)) as usize];
+            }
+            let row = self.ditherer.quantise_row(luma);
+            for (i, &level) in row.iter().enumerate() {
+                // 0, 85, 170 or 255
+                fb.set_pixel(x0 + i as u32, y, Gray2::new(level / 85));
+            }
+        }
+    }
+}
+
+// Where a placed image lands on a `fb_width` x `fb_height` panel
+struct Window {
+    // Top-left of the scaled image on the panel; negative when cropping
+    left: i64,
+    top: i64,
+    // Size of the scaled image
+    width: u32,
+    height: u32,
+    // The part of the panel the image covers
+    x0: u32,
+    x1: u32,
+    y0: u32,
+    y1: u32,
+}
+
+impl Window {
+    fn new(img: &RgbImage, fb_width: u32, fb_height: u32, placement: &Placement) -> Self {
+        let (width, height) = match placement.fit {
+            Fit::Letterbox => fit(img.width(), img.height(), fb_width, fb_height),
+            Fit::Crop => cover(img.width(), img.height(), fb_width, fb_height),
+        };
+        let (half_x, half_y) = placement.anchor.halves();
+        let left = (fb_width as i64 - width as i64) * half_x / 2;
+        let top = (fb_height as i64 - height as i64) * half_y / 2;
+        Window {
+            left,
+            top,
+            width,
+            height,
+            x0: left.max(0) as u32,
+            x1: (left + width as i64).min(fb_width as i64) as u32,
+            y0: top.max(0) as u32,
+            y1: (top + height as i64).min(fb_height as i64) as u32,
+        }
+    }
+}
+
+// First level, going through `levels`, past the `clipped` pixels at that end
+fn clip_end(histogram: &[u32; 256], clipped: u32, levels: impl Iterator<Item = usize>) -> Option<usize> {
+    let mut seen = 0;
+    for level in levels {
+        seen += histogram[level];
+        if seen > clipped {
+            return Some(level);
+        }
+    }
+    None
+}
+
+// Average of the source pixels covered by destination pixel (x, y) when the
+// whole image is scaled to `width` x `height`. Always covers at least one pixel,
+// so it doubles as nearest-neighbour when enlarging.
+fn area_sample(img: &RgbImage, x: u32, y: u32, width: u32, height: u32) -> Rgb<u8> {
+    let (src_width, src_height) = img.dimensions();
+    let x0 = (x as u64 * src_width as u64 / width as u64) as u32;
+    let y0 = (y as u64 * src_height as u64 / height as u64) as u32;
+    let x1 = (((x as u64 + 1) * src_width as u64 / width as u64) as u32).max(x0 + 1);
+    let y1 = (((y as u64 + 1) * src_height as u64 / height as u64) as u32).max(y0 + 1);
+
+    let mut sum = [0u32; 3];
+    for sy in y0..y1 {
+        for sx in x0..x1 {
+            let pixel = img.get_pixel(sx, sy);
+            for (total, channel) in sum.iter_mut().zip(pixel.0) {
+                *total += channel as u32;
+            }
+        }
+    }
+    let count = (x1 - x0) * (y1 - y0);
+    Rgb(sum.map(|total| (total / count) as u8))
+}
+
+/// Largest size with the source aspect ratio that fits in `max_width` x `max_height`.
+pub fn fit(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    if width == 0 || height == 0 {
+        return (0, 0);
+    }
+    let scale = f64::min(max_width as f64 / width as f64, max_height as f64 / height as f64);
+    let fitted_width = ((width as f64 * scale).round() as u32).clamp(1, max_width);
+    let fitted_height = ((height as f64 * scale).round() as u32).clamp(1, max_height);
+    (fitted_width, fitted_height)
+}
+
+/// Smallest size with the source aspect ratio that covers `min_width` x `min_height`.
+pub fn cover(width: u32, height: u32, min_width: u32, min_height: u32) -> (u32, u32) {
+    if width == 0 || height == 0 {
+        return (0, 0);
+    }
+    let scale = f64::max(min_width as f64 / width as f64, min_height as f64 / height as f64);
+    let covering_width = ((width as f64 * scale).round() as u32).max(min_width);
+    let covering_height = ((height as f64 * scale).round() as u32).max(min_height);
+    (covering_width, covering_height)
+}
+
+/// ITU-R BT.601 luma in integer arithmetic.
+pub fn luminance(pixel: &Rgb<u8>) -> u8 {
+    let [r, g, b] = pixel.0;
+    ((r as u32 * 77 + g as u32 * 150 + b as u32 * 29) >> 8) as u8
+}
@@ -1,35 +1,257 @@
+use core::marker::PhantomData;
+use core::time::Duration;
+
 use embedded_hal as hal;
 use hal::digital::v2::{InputPin, OutputPin};
 use hal::blocking::spi::Write;
 use hal::blocking::delay::DelayMs;
 
+use crate::epd::{ColorCapability, EpdController, GrayWriter, RowWriter};
+
 // command constants for SSD1675 controller from datasheet
-const DRIVER_OUTPUT_CONTROL: u8 = 0x01;
-const BOOSTER_SOFT_START_CONTROL: u8 = 0x0C;
-const GATE_SCAN_START_POSITION: u8 = 0x0F;
-const DEEP_SLEEP_MODE: u8 = 0x10;
-const DATA_ENTRY_MODE_SETTING: u8 = 0x11;
-const SW_RESET: u8 = 0x12;
-const TEMPERATURE_SENSOR_CONTROL: u8 = 0x1A;
-const MASTER_ACTIVATION: u8 = 0x20;
-const DISPLAY_UPDATE_CONTROL_1: u8 = 0x21;
-const DISPLAY_UPDATE_CONTROL_2: u8 = 0x22;
-const WRITE_RAM_BW: u8 = 0x24;
-const WRITE_RAM_RED: u8 = 0x26;
-const WRITE_VCOM_REGISTER: u8 = 0x2C;
-const WRITE_LUT_REGISTER: u8 = 0x32;
-const SET_DUMMY_LINE_PERIOD: u8 = 0x3A;
-const SET_GATE_TIME: u8 = 0x3B;
-const BORDER_WAVEFORM_CONTROL: u8 = 0x3C;
-const SET_RAM_X_ADDRESS_START_END_POSITION: u8 = 0x44;
-const SET_RAM_Y_ADDRESS_START_END_POSITION: u8 = 0x45;
-const SET_RAM_X_ADDRESS_COUNTER: u8 = 0x4E;
-const SET_RAM_Y_ADDRESS_COUNTER: u8 = 0x4F;
+pub const DRIVER_OUTPUT_CONTROL: u8 = 0x01;
+pub const BOOSTER_SOFT_START_CONTROL: u8 = 0x0C;
+pub const GATE_SCAN_START_POSITION: u8 = 0x0F;
+pub const DEEP_SLEEP_MODE: u8 = 0x10;
+pub const DATA_ENTRY_MODE_SETTING: u8 = 0x11;
+pub const SW_RESET: u8 = 0x12;
+pub const TEMPERATURE_SENSOR_CONTROL: u8 = 0x1A;
+pub const READ_TEMPERATURE_REGISTER: u8 = 0x1B;
+pub const MASTER_ACTIVATION: u8 = 0x20;
+pub const DISPLAY_UPDATE_CONTROL_1: u8 = 0x21;
+pub const DISPLAY_UPDATE_CONTROL_2: u8 = 0x22;
+pub const WRITE_RAM_BW: u8 = 0x24;
+pub const WRITE_RAM_RED: u8 = 0x26;
+pub const READ_RAM: u8 = 0x27;
+pub const WRITE_VCOM_REGISTER: u8 = 0x2C;
+pub const WRITE_LUT_REGISTER: u8 = 0x32;
+pub const SET_DUMMY_LINE_PERIOD: u8 = 0x3A;
+pub const SET_GATE_TIME: u8 = 0x3B;
+pub const BORDER_WAVEFORM_CONTROL: u8 = 0x3C;
+pub const READ_RAM_OPTION: u8 = 0x41;
+pub const SET_RAM_X_ADDRESS_START_END_POSITION: u8 = 0x44;
+pub const SET_RAM_Y_ADDRESS_START_END_POSITION: u8 = 0x45;
+pub const SET_RAM_X_ADDRESS_COUNTER: u8 = 0x4E;
+pub const SET_RAM_Y_ADDRESS_COUNTER: u8 = 0x4F;
+
+// Native panel geometry: 104 source lines (13 bytes of X) by 212 gate lines
+pub const WIDTH: usize = 104;
+pub const HEIGHT: usize = 212;
+// Size of one RAM plane in bytes
+pub const BUFFER_SIZE: usize = WIDTH / 8 * HEIGHT;
+// Largest single SPI write by default; matches the kernel's default spidev.bufsiz
+pub const DEFAULT_MAX_TRANSFER: usize = 4096;
+
+// Waveform for deghost: every pixel is driven hard one way and the other for
+// several long phases, whatever its colour, then left at its colour. Voltage
+// groups are 2 bits a phase (00 VSS, 01 VSH, 10 VSL), one row of 7 groups
+// per LUT (black, white, red, red, VCOM); then 7 timing groups of 4 phase
+// lengths and a repeat count.
+pub const CLEANING_LUT: [u8; 70] = [
+    0b10011001, 0b10011001, 0b10101010, 0x00, 0x00, 0x00, 0x00, // LUT0: black
+    0b01100110, 0b01100110, 0b01010101, 0x00, 0x00, 0x00, 0x00, // LUT1: white
+    0b10011001, 0b10011001, 0b10101010, 0x00, 0x00, 0x00, 0x00, // LUT2: red, driven as black
+    0b10011001, 0b10011001, 0b10101010, 0x00, 0x00, 0x00, 0x00, // LUT3
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // LUT4: VCOM held at DC
+    0x30, 0x30, 0x30, 0x30, 0x03, // TP0: shake
+    0x30, 0x30, 0x30, 0x30, 0x03, // TP1: shake again
+    0x20, 0x20, 0x20, 0x20, 0x01, // TP2: settle
+    0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+// Waveform for the yellow pHAT, whose OTP one is tuned for red glass: yellow
+// particles need longer, gentler phases to come up through the white (after
+// Pimoroni's yellow LUT). Same layout as CLEANING_LUT.
+pub const YELLOW_LUT: [u8; 70] = [
+    0b11111010, 0b10010100, 0b10001100, 0b11000000, 0b11010000, 0x00, 0x00, // LUT0: black
+    0b11111010, 0b10010100, 0b00101100, 0b10000000, 0b11100000, 0x00, 0x00, // LUT1: white
+    0b11111010, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // LUT2: yellow
+    0b11111010, 0b10010100, 0b11111000, 0b10000000, 0b01010000, 0x00, 0b11001100, // LUT3: yellow
+    0b10111111, 0b01011000, 0b11111100, 0b10000000, 0b11010000, 0x00, 0b00010001, // LUT4: VCOM
+    0x40, 0x10, 0x40, 0x10, 0x08,
+    0x08, 0x10, 0x04, 0x04, 0x10,
+    0x08, 0x08, 0x03, 0x08, 0x20,
+    0x08, 0x04, 0x00, 0x00, 0x10,
+    0x10, 0x08, 0x08, 0x00, 0x20,
+    0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+// Bytes of black/white folded with red at a time, for panels with no third colour
+const FOLD_CHUNK: usize = 64;
+
+// Second pass of a greyscale frame: the black/white RAM holds each pixel's
+// low bit and the red RAM its high bit, so the LUT a pixel gets says which of
+// the four levels it is. Black and white stay put; a black pixel with the low
+// bit moves briefly towards white (dark grey) and a white one towards black
+// (light grey). Same layout as CLEANING_LUT.
+pub const GRAY_LUT: [u8; 70] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // LUT0: black
+    0b01000000, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // LUT1: dark grey, VSH for TP0A
+    0b10000000, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // LUT2: light grey, VSL for TP0A
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // LUT3: white
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // LUT4: VCOM held at DC
+    0x0A, 0x00, 0x00, 0x00, 0x01, // TP0: the nudge; longer makes the greys darker and lighter
+    0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+// Name of a command constant, for error messages
+pub fn command_name(command: u8) -> &'static str {
+    match command {
+        DRIVER_OUTPUT_CONTROL => "DRIVER_OUTPUT_CONTROL",
+        BOOSTER_SOFT_START_CONTROL => "BOOSTER_SOFT_START_CONTROL",
+        GATE_SCAN_START_POSITION => "GATE_SCAN_START_POSITION",
+        DEEP_SLEEP_MODE => "DEEP_SLEEP_MODE",
+        DATA_ENTRY_MODE_SETTING => "DATA_ENTRY_MODE_SETTING",
+        SW_RESET => "SW_RESET",
+        TEMPERATURE_SENSOR_CONTROL => "TEMPERATURE_SENSOR_CONTROL",
+        READ_TEMPERATURE_REGISTER => "READ_TEMPERATURE_REGISTER",
+        MASTER_ACTIVATION => "MASTER_ACTIVATION",
+        DISPLAY_UPDATE_CONTROL_1 => "DISPLAY_UPDATE_CONTROL_1",
+        DISPLAY_UPDATE_CONTROL_2 => "DISPLAY_UPDATE_CONTROL_2",
+        WRITE_RAM_BW => "WRITE_RAM_BW",
+        WRITE_RAM_RED => "WRITE_RAM_RED",
+        READ_RAM => "READ_RAM",
+        WRITE_VCOM_REGISTER => "WRITE_VCOM_REGISTER",
+        WRITE_LUT_REGISTER => "WRITE_LUT_REGISTER",
+        SET_DUMMY_LINE_PERIOD => "SET_DUMMY_LINE_PERIOD",
+        SET_GATE_TIME => "SET_GATE_TIME",
+        BORDER_WAVEFORM_CONTROL => "BORDER_WAVEFORM_CONTROL",
+        READ_RAM_OPTION => "READ_RAM_OPTION",
+        SET_RAM_X_ADDRESS_START_END_POSITION => "SET_RAM_X_ADDRESS_START_END_POSITION",
+        SET_RAM_Y_ADDRESS_START_END_POSITION => "SET_RAM_Y_ADDRESS_START_END_POSITION",
+        SET_RAM_X_ADDRESS_COUNTER => "SET_RAM_X_ADDRESS_COUNTER",
+        SET_RAM_Y_ADDRESS_COUNTER => "SET_RAM_Y_ADDRESS_COUNTER",
+        _ => "unknown command",
+    }
+}
+
+/// How long the last refresh took, from the end of the previous one.
+///
+/// Without `std` there is no clock: `init` and `transfer` read zero, and
+/// `busy` is counted in BUSY polls, so it is only as good as the delay.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RefreshStats {
+    /// Reset and init sequence, if the panel was initialised since the last refresh
+    pub init: Option<Duration>,
+    /// Writing RAM: both planes, or however many bands were sent
+    pub transfer: Duration,
+    /// MASTER_ACTIVATION until BUSY went low: the refresh itself
+    pub busy: Duration,
+}
 
+// Measures elapsed time where there is a clock to measure it with
+#[derive(Clone, Copy)]
+struct Stopwatch {
+    #[cfg(feature = "std")]
+    started: std::time::Instant,
+}
+
+impl Stopwatch {
+    fn start() -> Self {
+        Stopwatch {
+            #[cfg(feature = "std")]
+            started: std::time::Instant::now(),
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        #[cfg(feature = "std")]
+        return self.started.elapsed();
+        #[cfg(not(feature = "std"))]
+        return Duration::ZERO;
+    }
+}
+
+/// Colour of the strip round the edge of the panel, outside the pixel area.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(serde::Deserialize), serde(rename_all = "lowercase"))]
+pub enum BorderColor {
+    #[default]
+    White,
+    Black,
+    Red,
+}
+
+impl BorderColor {
+    // BORDER_WAVEFORM_CONTROL value: which LUT (or fixed level) drives the border
+    pub fn waveform(self) -> u8 {
+        match self {
+            // GS transition, following LUT1
+            BorderColor::White => 0x05,
+            // GS transition, following LUT0
+            BorderColor::Black => 0x00,
+            // Fixed level VSH2, LUT3
+            BorderColor::Red => 0x73,
+        }
+    }
+}
+
+// `context` says what the driver was doing: a command name, or a step such as "reset"
 #[derive(Debug)]
 pub enum InkyError<SPIE, GPIOE> {
-    Spi(SPIE),
-    Gpio(GPIOE),
+    Spi { context: &'static str, error: SPIE },
+    Gpio { context: &'static str, error: GPIOE },
+    /// A refresh policy refused the refresh; it would be allowed after `retry_after`
+    RateLimited { retry_after: core::time::Duration },
+    /// BUSY stayed set for longer than the driver's busy timeout
+    BusyTimeout { context: &'static str, waited: core::time::Duration },
+}
+
+impl<SPIE, GPIOE> InkyError<SPIE, GPIOE> {
+    pub fn context(&self) -> &'static str {
+        match self {
+            InkyError::Spi { context, .. } | InkyError::Gpio { context, .. } | InkyError::BusyTimeout { context, .. } => {
+                context
+            }
+            InkyError::RateLimited { .. } => "refresh policy",
+        }
+    }
+}
+
+impl<SPIE: core::fmt::Debug, GPIOE: core::fmt::Debug> core::fmt::Display for InkyError<SPIE, GPIOE> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        // HAL errors are only guaranteed to be Debug
+        match self {
+            InkyError::Spi { context, error } => write!(f, "SPI write failed during {context}: {error:?}"),
+            InkyError::Gpio { context, error } => write!(f, "GPIO failed during {context}: {error:?}"),
+            InkyError::RateLimited { retry_after } => {
+                write!(f, "refresh refused to protect the panel; retry in {:.1}s", retry_after.as_secs_f32())
+            }
+            InkyError::BusyTimeout { context, waited } => {
+                write!(f, "BUSY still set after {:.1}s during {context}", waited.as_secs_f32())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<SPIE: core::fmt::Debug, GPIOE: core::fmt::Debug> std::error::Error for InkyError<SPIE, GPIOE> {}
+
+pub(crate) fn spi<SPIE, GPIOE>(context: &'static str) -> impl FnOnce(SPIE) -> InkyError<SPIE, GPIOE> {
+    move |error| InkyError::Spi { context, error }
+}
+
+pub(crate) fn gpio<SPIE, GPIOE>(context: &'static str) -> impl FnOnce(GPIOE) -> InkyError<SPIE, GPIOE> {
+    move |error| InkyError::Gpio { context, error }
+}
+
+/// Which level of the BUSY line means the controller is working. The pHAT's
+/// SSD1675 drives it high; some SSD16xx clones drive it low.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum BusyPolarity {
+    #[default]
+    ActiveHigh,
+    ActiveLow,
 }
 
 pub struct InkyPhat<SPI, CS, BUSY, DC, RESET> {
@@ -38,6 +260,49 @@ pub struct InkyPhat<SPI, CS, BUSY, DC, RESET> {
     busy: BUSY,
     dc: DC,
     reset: RESET,
+    busy_polarity: BusyPolarity,
+    colors: ColorCapability,
+    // MASTER_ACTIVATION sent and BUSY not yet seen idle
+    refreshing: bool,
+    // Timings gathered towards the next refresh, when the one in flight started, and the last finished
+    pending: RefreshStats,
+    refresh_started: Option<Stopwatch>,
+    last_refresh: Option<RefreshStats>,
+    refresh_count: u64,
+    // Data longer than this is split into several SPI writes
+    max_transfer: usize,
+    border: BorderColor,
+    // Give up on BUSY after this long; None waits for ever
+    busy_timeout: Option<Duration>,
+}
+
+/// Stands in for the CS pin when the SPI peripheral drives chip select
+/// itself (spidev's native CE lines, say): setting it does nothing. `E` is
+/// only there to match the other pins' error type.
+pub struct NoPin<E>(PhantomData<fn() -> E>);
+
+impl<E> NoPin<E> {
+    pub fn new() -> Self {
+        NoPin(PhantomData)
+    }
+}
+
+impl<E> Default for NoPin<E> {
+    fn default() -> Self {
+        NoPin::new()
+    }
+}
+
+impl<E> OutputPin for NoPin<E> {
+    type Error = E;
+
+    fn set_low(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), E> {
+        Ok(())
+    }
 }
 
 // Inky pHAT pinout:
@@ -64,52 +329,178 @@ where
             busy, 
             dc, 
             reset,
+            busy_polarity: BusyPolarity::default(),
+            colors: ColorCapability::default(),
+            refreshing: false,
+            pending: RefreshStats::default(),
+            refresh_started: None,
+            last_refresh: None,
+            refresh_count: 0,
+            max_transfer: DEFAULT_MAX_TRANSFER,
+            border: BorderColor::default(),
+            busy_timeout: None,
         }
     }
 
+    pub fn with_max_transfer(mut self, bytes: usize) -> Self {
+        // Cap each SPI write, for kernels with a smaller spidev.bufsiz (zero is treated as one)
+        self.max_transfer = bytes.max(1);
+        self
+    }
+
+    pub fn with_border(mut self, border: BorderColor) -> Self {
+        // Border colour programmed by init (white unless set)
+        self.border = border;
+        self
+    }
+
+    pub fn border(&self) -> BorderColor {
+        self.border
+    }
+
+    pub fn set_border(&mut self, border: BorderColor) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Change the border now, for the next refresh, and in every init after
+        self.border = border;
+        self.send_command_data(BORDER_WAVEFORM_CONTROL, Some(&[border.waveform()]))
+    }
+
+    pub fn with_busy_polarity(mut self, polarity: BusyPolarity) -> Self {
+        // Level of BUSY that means busy (high unless set)
+        self.busy_polarity = polarity;
+        self
+    }
+
+    pub fn busy_polarity(&self) -> BusyPolarity {
+        self.busy_polarity
+    }
+
+    pub fn set_busy_polarity(&mut self, polarity: BusyPolarity) {
+        // Takes effect from the next wait on BUSY
+        self.busy_polarity = polarity;
+    }
+
+    pub fn with_busy_timeout(mut self, timeout: Duration) -> Self {
+        // Fail with BusyTimeout rather than wait for ever on a BUSY line that's stuck
+        self.busy_timeout = Some(timeout);
+        self
+    }
+
+    pub fn set_busy_timeout(&mut self, timeout: Option<Duration>) {
+        // Takes effect from the next wait on BUSY
+        self.busy_timeout = timeout;
+    }
+
+    pub fn with_colors(mut self, colors: ColorCapability) -> Self {
+        // Which variant of the pHAT this is (red unless set); init programs the panel for it
+        self.colors = colors;
+        self
+    }
+
+    pub fn colors(&self) -> ColorCapability {
+        self.colors
+    }
+
+    pub fn set_colors(&mut self, colors: ColorCapability) {
+        // Takes effect from the next init
+        self.colors = colors;
+    }
+
+    pub fn into_parts(self) -> (SPI, CS, BUSY, DC, RESET) {
+        // Hand the bus and pins back, e.g. to free them for something else; call sleep() first
+        (self.spi, self.cs, self.busy, self.dc, self.reset)
+    }
+
     pub fn reset<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), InkyError<SPIE, GPIOE>> {
         // Reset sequence to wake up screen: pull RST low, wait, pull high, wait
-        self.reset.set_low().map_err(InkyError::Gpio)?;
+        self.reset.set_low().map_err(gpio("reset"))?;
         delay.delay_ms(100);
-        self.reset.set_high().map_err(InkyError::Gpio)?;
+        self.reset.set_high().map_err(gpio("reset"))?;
         delay.delay_ms(100);
         Ok(())
     }
 
     fn send_command(&mut self, command: u8) -> Result<(), InkyError<SPIE, GPIOE>> {
         // Set DC low for command, pull CS low, send command byte, then pull CS high to release
-        self.dc.set_low().map_err(InkyError::Gpio)?;
-        self.cs.set_low().map_err(InkyError::Gpio)?;
-        self.spi.write(&[command]).map_err(InkyError::Spi)?;
-        self.cs.set_high().map_err(InkyError::Gpio)?;
-        Ok(())
+        let context = command_name(command);
+        self.dc.set_low().map_err(gpio(context))?;
+        self.selected(context, |epd| epd.spi.write(&[command]).map_err(spi(context)))
     }
 
-    fn send_data(&mut self, data: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
+    // Runs `transfer` with CS low, raising it again whether or not that worked, so
+    // a failed write never leaves the panel selected (or the SPI bus held)
+    fn selected<T>(
+        &mut self,
+        context: &'static str,
+        transfer: impl FnOnce(&mut Self) -> Result<T, InkyError<SPIE, GPIOE>>,
+    ) -> Result<T, InkyError<SPIE, GPIOE>> {
+        self.cs.set_low().map_err(gpio(context))?;
+        let transferred = transfer(self);
+        let raised = self.cs.set_high().map_err(gpio(context));
+        let value = transferred?;
+        raised?;
+        Ok(value)
+    }
+
+    fn send_data(&mut self, command: u8, data: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
         // Set DC high for data, pull CS low, send data bytes, then pull CS high to release
-        self.dc.set_high().map_err(InkyError::Gpio)?;
-        self.cs.set_low().map_err(InkyError::Gpio)?;
-        self.spi.write(data).map_err(InkyError::Spi)?;
-        self.cs.set_high().map_err(InkyError::Gpio)?;
-        Ok(())
+        // CS stays low across chunks, so the controller sees one continuous transfer
+        // (`command` is only used to say which command's data failed)
+        let context = command_name(command);
+        self.dc.set_high().map_err(gpio(context))?;
+        self.selected(context, |epd| {
+            for chunk in data.chunks(epd.max_transfer) {
+                epd.spi.write(chunk).map_err(spi(context))?;
+            }
+            Ok(())
+        })
     }
 
     fn send_command_data(&mut self, command: u8, data: Option<&[u8]>) -> Result<(), InkyError<SPIE, GPIOE>> {
         // Helper function to send a command followed by optional data
         self.send_command(command)?;
         if let Some(data) = data {
-            self.send_data(data)?;
+            self.send_data(command, data)?;
         }
         Ok(())
     }
 
-    fn busy_wait<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), InkyError<SPIE, GPIOE>> {
-        // While the busy pin is high,
-        while self.busy.is_high().map_err(InkyError::Gpio)? {
+    fn is_busy(&self, context: &'static str) -> Result<bool, InkyError<SPIE, GPIOE>> {
+        // The read an edge-triggered pin may block in: is_high for active-high panels
+        match self.busy_polarity {
+            BusyPolarity::ActiveHigh => self.busy.is_high(),
+            BusyPolarity::ActiveLow => self.busy.is_low(),
+        }
+        .map_err(gpio(context))
+    }
+
+    fn is_idle(&self, context: &'static str) -> Result<bool, InkyError<SPIE, GPIOE>> {
+        // Never blocks: both polarities read is_low, which edge-triggered pins answer straight away
+        match self.busy_polarity {
+            BusyPolarity::ActiveHigh => self.busy.is_low(),
+            BusyPolarity::ActiveLow => self.busy.is_low().map(|low| !low),
+        }
+        .map_err(gpio(context))
+    }
+
+    fn busy_wait<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<Duration, InkyError<SPIE, GPIOE>> {
+        // While the busy pin reads busy,
+        // (an edge-triggered pin such as linux::EdgeBusyPin blocks inside is_high,
+        // so for active-high panels this only loops when its timeout expires)
+        // Returns how long that took, or at least how long was spent in delays
+        let stopwatch = Stopwatch::start();
+        let mut polls = 0;
+        while self.is_busy("busy wait")? {
+            let waited = stopwatch.elapsed().max(Duration::from_millis(10 * polls));
+            if self.busy_timeout.is_some_and(|timeout| waited > timeout) {
+                // The refresh is abandoned, as far as poll_refresh is concerned
+                self.refreshing = false;
+                return Err(InkyError::BusyTimeout { context: "busy wait", waited });
+            }
             // Wait 10ms 
             delay.delay_ms(10);
+            polls += 1;
         }
-        Ok(())
+        Ok(stopwatch.elapsed().max(Duration::from_millis(10 * polls)))
     }
 
     fn set_ram_address_counter(&mut self, x: u8, y: u16) -> Result<(), InkyError<SPIE, GPIOE>> {
@@ -127,6 +518,7 @@ where
         // Send DRIVER_OUTPUT_CONTROL command with parameters to set resolution
         // Send DATA_ENTRY_MODE_SETTING command with parameters to set data entry mode
 
+        let stopwatch = Stopwatch::start();
         self.reset(delay)?;
         self.busy_wait(delay)?;
 
@@ -141,36 +533,316 @@ where
         // Set RAM Y address start to 0 and end to 211 (0xD3) for 212 pixels
         self.send_command_data(SET_RAM_Y_ADDRESS_START_END_POSITION, Some(&[0x00, 0x00, 0xD3, 0x00]))?; 
         // Set border waveform control to set the colour of the very edge of the screen
-        self.send_command_data(BORDER_WAVEFORM_CONTROL, Some(&[0x05]))?; 
-        // Set display update control 1
-        self.send_command_data(DISPLAY_UPDATE_CONTROL_1, Some(&[0x00, 0x80]))?; 
+        self.send_command_data(BORDER_WAVEFORM_CONTROL, Some(&[self.border.waveform()]))?;
+        // Set display update control 1: a panel with no third colour reads its red RAM as 0
+        let red_ram = if self.colors.has_accent() { 0x00 } else { 0x40 };
+        self.send_command_data(DISPLAY_UPDATE_CONTROL_1, Some(&[red_ram, 0x80]))?; 
         // Set display update control 2
         self.send_command_data(DISPLAY_UPDATE_CONTROL_2, Some(&[0xC7]))?; 
+        // The yellow glass wants its own waveform
+        if self.colors == ColorCapability::BlackWhiteYellow {
+            self.write_lut(&YELLOW_LUT)?;
+        }
+        self.pending.init = Some(stopwatch.elapsed());
         
        // set resolution, data entry modes, etc...
         Ok(())
     }   
 
     pub fn update_bw(&mut self, buffer: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
+        let stopwatch = Stopwatch::start();
         // Set RAM address counter to (0,0)
         self.set_ram_address_counter(0, 0)?;
         // Send WRITE_RAM_BW command followed by the black/white buffer data
         self.send_command_data(WRITE_RAM_BW, Some(buffer))?;
+        self.pending.transfer += stopwatch.elapsed();
         Ok(())
     }
 
     pub fn update_red(&mut self, buffer: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
+        let stopwatch = Stopwatch::start();
         // Set RAM address counter to (0,0)
         self.set_ram_address_counter(0, 0)?;
         // Send WRITE_RAM_RED command followed by the red buffer data
         self.send_command_data(WRITE_RAM_RED, Some(buffer))?;
+        self.pending.transfer += stopwatch.elapsed();
         Ok(())
     }
 
-    pub fn display_refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), InkyError<SPIE, GPIOE>> {
-        self.send_command(MASTER_ACTIVATION)?; // Trigger display refresh
-        self.busy_wait(delay)?; // Wait for refresh to complete
+    fn update_bw_folded(&mut self, bw: &[u8], red: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Black/white RAM with red drawn black, for panels that can't show it: else it would
+        // come out white, and red text would vanish
+        let stopwatch = Stopwatch::start();
+        self.set_ram_address_counter(0, 0)?;
+        self.write_folded(bw, red)?;
+        self.pending.transfer += stopwatch.elapsed();
+        Ok(())
+    }
+
+    fn write_folded(&mut self, bw: &[u8], red: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // WRITE_RAM_BW of bw & !red, through a small buffer since there may be no heap
+        self.send_command(WRITE_RAM_BW)?;
+        let context = command_name(WRITE_RAM_BW);
+        self.dc.set_high().map_err(gpio(context))?;
+        self.selected(context, |epd| {
+            let mut folded = [0u8; FOLD_CHUNK];
+            let chunk = FOLD_CHUNK.min(epd.max_transfer);
+            for (bw, red) in bw.chunks(chunk).zip(red.chunks(chunk)) {
+                for ((out, bw), red) in folded.iter_mut().zip(bw).zip(red) {
+                    *out = bw & !red;
+                }
+                epd.spi.write(&folded[..bw.len()]).map_err(spi(context))?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn update_rows(&mut self, first_row: u16, bw: &[u8], red: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Write a band of whole rows into both planes, starting at gate line `first_row`
+        let stopwatch = Stopwatch::start();
+        self.set_ram_address_counter(0, first_row)?;
+        if self.colors.has_accent() {
+            self.send_command_data(WRITE_RAM_BW, Some(bw))?;
+        } else {
+            self.write_folded(bw, red)?;
+        }
+        self.set_ram_address_counter(0, first_row)?;
+        self.send_command_data(WRITE_RAM_RED, Some(red))?;
+        self.pending.transfer += stopwatch.elapsed();
+        Ok(())
+    }
+
+    pub fn read_bw(
+        &mut self,
+        buffer: &mut [u8],
+        mut read: impl FnMut(&mut SPI, &mut [u8]) -> Result<(), SPIE>,
+    ) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Read the black/white RAM back into `buffer`, to check the link is clean.
+        // The HAL's SPI only writes, so `read` does the receiving (on Linux, a 3-wire spidev read)
+        self.set_ram_address_counter(0, 0)?;
+        self.send_command_data(READ_RAM_OPTION, Some(&[0x00]))?;
+        self.send_command(READ_RAM)?;
+        let context = command_name(READ_RAM);
+        self.dc.set_high().map_err(gpio(context))?;
+        self.selected(context, |epd| {
+            // The first byte out is a dummy
+            read(&mut epd.spi, &mut [0]).map_err(spi(context))?;
+            for chunk in buffer.chunks_mut(epd.max_transfer) {
+                read(&mut epd.spi, chunk).map_err(spi(context))?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn read_temperature(
+        &mut self,
+        mut read: impl FnMut(&mut SPI, &mut [u8]) -> Result<(), SPIE>,
+    ) -> Result<i8, InkyError<SPIE, GPIOE>> {
+        // Read the controller's temperature register, in whole degrees C. Every refresh
+        // (DISPLAY_UPDATE_CONTROL_2 0xC7) loads it from the sensor, so this is the
+        // temperature the last refresh ran at. `read` receives, as for read_bw
+        self.send_command(READ_TEMPERATURE_REGISTER)?;
+        let context = command_name(READ_TEMPERATURE_REGISTER);
+        self.dc.set_high().map_err(gpio(context))?;
+        // 12 bits in 1/16 degree steps, most significant byte first
+        let mut value = [0u8; 2];
+        self.selected(context, |epd| read(&mut epd.spi, &mut value).map_err(spi(context)))?;
+        Ok(value[0] as i8)
+    }
+
+    pub fn command(&mut self, command: u8, data: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Send any command, for init sequences that come from outside the driver (see panel::Described)
+        self.send_command_data(command, (!data.is_empty()).then_some(data))
+    }
+
+    pub fn wait_idle<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Block until BUSY goes low, e.g. after a command that makes the controller work
+        self.busy_wait(delay)?;
         Ok(())
     }
 
-}
\ No newline at end of file
+    pub fn write_lut(&mut self, lut: &[u8]) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Replace the waveform used by the next refresh(es), e.g. with a faster vendor LUT
+        self.send_command_data(WRITE_LUT_REGISTER, Some(lut))?;
+        Ok(())
+    }
+
+    pub fn deghost<D: DelayMs<u8>>(&mut self, cycles: u8, delay: &mut D) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Flash black, white, black `cycles` times with the cleaning waveform, to shake out
+        // the ghosting many fast refreshes leave behind; then init again, which resets the
+        // waveform, ready for the real frame. RAM is left black.
+        self.write_lut(&CLEANING_LUT)?;
+        for _ in 0..cycles {
+            for bw in [0x00, 0xFF, 0x00] {
+                self.update_bw(&[bw; BUFFER_SIZE])?;
+                self.update_red(&[0x00; BUFFER_SIZE])?;
+                self.display_refresh(delay)?;
+            }
+        }
+        self.init(delay)
+    }
+
+    pub fn display_gray<D: DelayMs<u8>>(
+        &mut self,
+        msb: &[u8],
+        lsb: &[u8],
+        delay: &mut D,
+    ) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Four grey levels in two passes: the high bits as plain black and white with the
+        // normal waveform, then GRAY_LUT nudges the pixels whose low bit differs. Init
+        // afterwards puts the normal waveform back; the red RAM is left holding the high bits
+        self.update_bw(msb)?;
+        self.update_red(&[0x00; BUFFER_SIZE])?;
+        self.display_refresh(delay)?;
+        self.write_lut(&GRAY_LUT)?;
+        self.update_bw(lsb)?;
+        self.update_red(msb)?;
+        self.display_refresh(delay)?;
+        self.init(delay)
+    }
+
+    pub fn clear<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Fill both planes with white and refresh
+        self.update_bw(&[0xFF; BUFFER_SIZE])?;
+        self.update_red(&[0x00; BUFFER_SIZE])?;
+        self.display_refresh(delay)?;
+        Ok(())
+    }
+
+    pub fn sleep(&mut self) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Enter deep sleep mode 1: RAM is kept, but the controller ignores everything until reset
+        self.send_command_data(DEEP_SLEEP_MODE, Some(&[0x01]))?;
+        Ok(())
+    }
+
+    pub fn self_check(&mut self) -> Result<bool, InkyError<SPIE, GPIOE>> {
+        // Toggle DC while CS is high (the controller ignores it) to prove the GPIO still works,
+        // then check BUSY reads idle: outside a refresh it should never read busy
+        self.dc.set_low().map_err(gpio("self-check"))?;
+        self.dc.set_high().map_err(gpio("self-check"))?;
+        self.is_idle("self-check")
+    }
+
+    pub fn display_refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<RefreshStats, InkyError<SPIE, GPIOE>> {
+        self.start_refresh()?; // Trigger display refresh
+        let busy = self.busy_wait(delay)?; // Wait for refresh to complete
+        self.refreshing = false;
+        Ok(self.finish_refresh(busy))
+    }
+
+    pub fn start_refresh(&mut self) -> Result<(), InkyError<SPIE, GPIOE>> {
+        // Kick off a refresh and return straight away; call poll_refresh until it reports done
+        self.send_command(MASTER_ACTIVATION)?;
+        self.refreshing = true;
+        self.refresh_started = Some(Stopwatch::start());
+        Ok(())
+    }
+
+    pub fn poll_refresh(&mut self) -> Result<bool, InkyError<SPIE, GPIOE>> {
+        // Single non-blocking look at BUSY: true once the refresh started by start_refresh is done
+        if self.refreshing && self.is_idle("refresh poll")? {
+            self.refreshing = false;
+            let busy = self.refresh_started.map_or(Duration::ZERO, |started| started.elapsed());
+            self.finish_refresh(busy);
+        }
+        Ok(!self.refreshing)
+    }
+
+    pub fn last_refresh(&self) -> Option<RefreshStats> {
+        // Timings of the last refresh to finish, however it was started
+        self.last_refresh
+    }
+
+    pub fn refresh_count(&self) -> u64 {
+        // Refreshes finished since the driver was created, so callers can tell a new `last_refresh` from the old
+        self.refresh_count
+    }
+
+    fn finish_refresh(&mut self, busy: Duration) -> RefreshStats {
+        let stats = RefreshStats {
+            busy,
+            ..core::mem::take(&mut self.pending)
+        };
+        self.refresh_started = None;
+        self.last_refresh = Some(stats);
+        self.refresh_count += 1;
+        stats
+    }
+
+    pub fn is_refreshing(&self) -> bool {
+        self.refreshing
+    }
+}
+
+impl<SPI, CS, BUSY, DC, RESET, SPIE, GPIOE> EpdController for InkyPhat<SPI, CS, BUSY, DC, RESET>
+where
+    SPI: Write<u8, Error = SPIE>,
+    CS: OutputPin<Error = GPIOE>,
+    BUSY: InputPin<Error = GPIOE>,
+    DC: OutputPin<Error = GPIOE>,
+    RESET: OutputPin<Error = GPIOE>,
+    SPIE: core::fmt::Debug,
+    GPIOE: core::fmt::Debug,
+{
+    type Error = InkyError<SPIE, GPIOE>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (WIDTH as u32, HEIGHT as u32)
+    }
+
+    fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        InkyPhat::init(self, delay)
+    }
+
+    fn colors(&self) -> ColorCapability {
+        self.colors
+    }
+
+    fn write_planes(&mut self, bw: &[u8], red: &[u8]) -> Result<(), Self::Error> {
+        if self.colors.has_accent() {
+            self.update_bw(bw)?;
+        } else {
+            self.update_bw_folded(bw, red)?;
+        }
+        self.update_red(red)
+    }
+
+    fn refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.display_refresh(delay)?;
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        InkyPhat::sleep(self)
+    }
+}
+
+impl<SPI, CS, BUSY, DC, RESET, SPIE, GPIOE> RowWriter for InkyPhat<SPI, CS, BUSY, DC, RESET>
+where
+    SPI: Write<u8, Error = SPIE>,
+    CS: OutputPin<Error = GPIOE>,
+    BUSY: InputPin<Error = GPIOE>,
+    DC: OutputPin<Error = GPIOE>,
+    RESET: OutputPin<Error = GPIOE>,
+    SPIE: core::fmt::Debug,
+    GPIOE: core::fmt::Debug,
+{
+    fn write_rows(&mut self, first_row: u32, bw: &[u8], red: &[u8]) -> Result<(), Self::Error> {
+        self.update_rows(first_row as u16, bw, red)
+    }
+}
+
+impl<SPI, CS, BUSY, DC, RESET, SPIE, GPIOE> GrayWriter for InkyPhat<SPI, CS, BUSY, DC, RESET>
+where
+    SPI: Write<u8, Error = SPIE>,
+    CS: OutputPin<Error = GPIOE>,
+    BUSY: InputPin<Error = GPIOE>,
+    DC: OutputPin<Error = GPIOE>,
+    RESET: OutputPin<Error = GPIOE>,
+    SPIE: core::fmt::Debug,
+    GPIOE: core::fmt::Debug,
+{
+    fn show_gray<D: DelayMs<u8>>(&mut self, msb: &[u8], lsb: &[u8], delay: &mut D) -> Result<(), Self::Error> {
+        self.display_gray(msb, lsb, delay)
+    }
+}
@@ -0,0 +1,413 @@
+// Snapshot testing for screens, for this crate's users as much as for itself.
+//
+// A screen is rendered into a `Framebuffer` exactly as the daemon would and
+// compared, pixel for pixel, with a reference PNG checked in next to the
+// tests. References are read back through the same three-colour model as the
+// panel, so a PNG saved from an image editor works as long as its colours are
+// close to white, black and red.
+//
+//     let fb = inky_test::render(&mut MyScreen::new(), inky_test::fixed_time());
+//     assert_frame_matches!(fb, "tests/snapshots/my_screen.png");
+//
+// Run with `INKY_UPDATE_SNAPSHOTS=1` to write (or overwrite) the references
+// from the current output instead of comparing.
+//
+// `GOLDENS` does the same for every built-in screen and widget that draws the
+// same thing each time; `rust_raspi golden snapshots` checks them all against
+// the references in the repository.
+
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, TimeZone};
+use embedded_graphics::prelude::{Point, Size};
+use embedded_graphics::primitives::Rectangle;
+use image::{Rgb, RgbImage};
+
+use crate::framebuffer::{Color, Framebuffer, Rotation};
+pub use crate::framebuffer::{panel_rgb, rgb};
+use crate::screens::clock::{Clock, NightClock, SegmentClock};
+use crate::screens::{RenderContext, Screen};
+use crate::theme::Theme;
+use crate::widgets::chart::{BarChart, LineChart, Sparkline};
+use crate::widgets::icon::{self, Icon};
+use crate::widgets::placeholder;
+use crate::widgets::seven_segment::SevenSegment;
+
+pub use crate::assert_frame_matches;
+
+/// Environment variable that turns comparisons into reference updates.
+pub const UPDATE_VAR: &str = "INKY_UPDATE_SNAPSHOTS";
+
+/// The panel colour an RGB pixel is nearest to.
+pub fn nearest(pixel: Rgb<u8>) -> Color {
+    let distance = |color| {
+        let Rgb(target) = rgb(color);
+        (0..3)
+            .map(|i| (pixel.0[i] as i32 - target[i] as i32).pow(2))
+            .sum::<i32>()
+    };
+    [Color::White, Color::Black, Color::Red]
+        .into_iter()
+        .min_by_key(|&color| distance(color))
+        .unwrap_or(Color::White)
+}
+
+/// A fixed moment, noon on 2024-01-15 local time, so snapshots of clocks and
+/// calendars don't change from one run to the next.
+pub fn fixed_time() -> DateTime<Local> {
+    Local
+        .with_ymd_and_hms(2024, 1, 15, 12, 0, 0)
+        .earliest()
+        .unwrap_or_else(Local::now)
+}
+
+/// Renders `screen` at `now` onto a blank Inky pHAT framebuffer in the
+/// daemon's landscape orientation.
+pub fn render(screen: &mut dyn Screen, now: DateTime<Local>) -> Framebuffer {
+    let mut fb = Framebuffer::inky_phat(Rotation::Rotate90);
+    screen.render(&mut fb, &RenderContext { now, theme: Theme::default() });
+    fb
+}
+
+/// A built-in screen or widget drawn onto a blank landscape pHAT frame,
+/// compared against `<name>.png`.
+pub struct Golden {
+    pub name: &'static str,
+    pub draw: fn(&mut Framebuffer),
+}
+
+// Made up, and the same everywhere, for charts
+const SERIES: [f32; 12] = [3.0, 4.5, 4.0, 6.5, 8.0, 7.0, 9.5, 9.0, 6.0, 5.5, 7.5, 10.0];
+
+/// Everything checked by `check_goldens`.
+pub const GOLDENS: &[Golden] = &[
+    Golden {
+        name: "clock",
+        draw: |fb| screen(fb, &mut Clock::with_hostname("inky")),
+    },
+    Golden {
+        name: "night_clock",
+        draw: |fb| screen(fb, &mut NightClock),
+    },
+    Golden {
+        name: "segment_clock",
+        draw: |fb| screen(fb, &mut SegmentClock),
+    },
+    Golden {
+        name: "icons",
+        draw: |fb| {
+            // Twelve to a row
+            for (i, &icon) in Icon::ALL.iter().enumerate() {
+                let point = Point::new(4 + (i % 12) as i32 * 17, 4 + (i / 12) as i32 * 20);
+                let color = if i % 2 == 0 { Color::Black } else { Color::Red };
+                let Ok(()) = icon::draw(icon, point, color, fb);
+            }
+        },
+    },
+    Golden {
+        name: "seven_segment",
+        draw: |fb| {
+            let Ok(()) = SevenSegment::new(40).draw("12:34", Point::new(4, 4), fb);
+            let Ok(()) = SevenSegment::new(24).color(Color::Red).draw("-5.6 78", Point::new(4, 56), fb);
+        },
+    },
+    Golden {
+        name: "charts",
+        draw: |fb| {
+            let Ok(()) = Sparkline::new(Rectangle::new(Point::new(4, 4), Size::new(96, 20))).draw(&SERIES, fb);
+            let Ok(()) = LineChart::new(Rectangle::new(Point::new(4, 30), Size::new(96, 70))).draw(&SERIES, fb);
+            let Ok(()) = BarChart::new(Rectangle::new(Point::new(108, 4), Size::new(100, 96))).draw(&SERIES, fb);
+        },
+    },
+    Golden {
+        name: "placeholder",
+        draw: |fb| {
+            let Ok(()) = placeholder::draw(Rectangle::new(Point::new(20, 20), Size::new(120, 50)), "VAR", fb);
+        },
+    },
+];
+
+fn screen(fb: &mut Framebuffer, screen: &mut dyn Screen) {
+    let ctx = RenderContext {
+        now: fixed_time(),
+        theme: Theme::default(),
+    };
+    screen.render(fb, &ctx);
+}
+
+/// Draws every golden and compares it with its reference in `dir` (see
+/// [`compare`]); returns the ones that don't match.
+pub fn check_goldens(dir: impl AsRef<Path>, tolerance: usize) -> Vec<(&'static str, Mismatch)> {
+    let dir = dir.as_ref();
+    GOLDENS
+        .iter()
+        .filter_map(|golden| {
+            let mut fb = Framebuffer::inky_phat(Rotation::Rotate90);
+            fb.clear(Color::White);
+            (golden.draw)(&mut fb);
+            compare(&fb, dir.join(format!("{}.png", golden.name)), tolerance)
+                .err()
+                .map(|mismatch| (golden.name, mismatch))
+        })
+        .collect()
+}
+
+/// Why a frame didn't match its reference.
+#[derive(Debug)]
+pub enum Mismatch {
+    /// No reference yet; the actual frame was written next to where it should be
+    Missing { reference: PathBuf, actual: PathBuf },
+    Unreadable { reference: PathBuf, error: String },
+    Size { reference: PathBuf, expected: (u32, u32), actual: (u32, u32) },
+    Pixels {
+        reference: PathBuf,
+        actual: PathBuf,
+        differing: usize,
+        first: (u32, u32),
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Missing { reference, actual } => write!(
+                f,
+                "no reference {}; actual frame written to {} (set {UPDATE_VAR}=1 to accept it)",
+                reference.display(),
+                actual.display()
+            ),
+            Mismatch::Unreadable { reference, error } => write!(f, "{}: {error}", reference.display()),
+            Mismatch::Size {
+                reference,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} is {}x{} but the frame is {}x{}",
+                reference.display(),
+                expected.0,
+                expected.1,
+                actual.0,
+                actual.1
+            ),
+            Mismatch::Pixels {
+                reference,
+                actual,
+                differing,
+                first,
+            } => write!(
+                f,
+                "{differing} pixels differ from {} (first at {},{}); actual frame written to {}",
+                reference.display(),
+                first.0,
+                first.1,
+                actual.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// Compares `fb` with the PNG at `reference`, allowing up to `tolerance`
+/// differing pixels. On a mismatch the actual frame is saved as
+/// `<reference>.actual.png` for inspection.
+pub fn compare(fb: &Framebuffer, reference: impl AsRef<Path>, tolerance: usize) -> Result<(), Mismatch> {
+    let reference = reference.as_ref();
+    let image = fb.to_image();
+    let actual = reference.with_extension("actual.png");
+    if env::var_os(UPDATE_VAR).is_some() {
+        return save(&image, reference);
+    }
+    if !reference.exists() {
+        save(&image, &actual)?;
+        return Err(Mismatch::Missing {
+            reference: reference.to_path_buf(),
+            actual,
+        });
+    }
+    let expected = image::open(reference)
+        .map_err(|err| Mismatch::Unreadable {
+            reference: reference.to_path_buf(),
+            error: err.to_string(),
+        })?
+        .to_rgb8();
+    if expected.dimensions() != image.dimensions() {
+        return Err(Mismatch::Size {
+            reference: reference.to_path_buf(),
+            expected: expected.dimensions(),
+            actual: image.dimensions(),
+        });
+    }
+    let mut differing = expected
+        .enumerate_pixels()
+        .filter(|&(x, y, pixel)| nearest(*pixel) != fb.get_pixel(x, y).unwrap_or(Color::White))
+        .map(|(x, y, _)| (x, y));
+    let Some(first) = differing.next() else {
+        return Ok(());
+    };
+    let differing = 1 + differing.count();
+    if differing <= tolerance {
+        return Ok(());
+    }
+    save(&image, &actual)?;
+    Err(Mismatch::Pixels {
+        reference: reference.to_path_buf(),
+        actual,
+        differing,
+        first,
+    })
+}
+
+fn save(image: &RgbImage, path: &Path) -> Result<(), Mismatch> {
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    image.save(path).map_err(|err| Mismatch::Unreadable {
+        reference: path.to_path_buf(),
+        error: err.to_string(),
+    })
+}
+
+/// Panics unless a framebuffer matches a reference PNG (see [`compare`]).
+///
+/// `assert_frame_matches!(fb, "tests/snapshots/clock.png")`, or with
+/// `tolerance = N` to allow up to N differing pixels.
+#[macro_export]
+macro_rules! assert_frame_matches {
+    ($fb:expr, $reference:expr $(,)?) => {
+        $crate::assert_frame_matches!($fb, $reference, tolerance = 0)
+    };
+    ($fb:expr, $reference:expr, tolerance = $tolerance:expr $(,)?) => {
+        if let Err(mismatch) = $crate::inky_test::compare(&$fb, $reference, $tolerance) {
+            panic!("frame does not match: {mismatch}");
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::{Mutex, MutexGuard, PoisonError};
+
+    use super::*;
+
+    // Held by any test that compares, since one of them sets `UPDATE_VAR` for the whole process
+    static ENV: Mutex<()> = Mutex::new(());
+
+    fn lock() -> MutexGuard<'static, ()> {
+        ENV.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // A directory of its own for each test, emptied first
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rust_raspi-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn frame() -> Framebuffer {
+        let mut fb = Framebuffer::inky_phat(Rotation::Rotate90);
+        fb.clear(Color::White);
+        for x in 10..40 {
+            fb.set_pixel(x, 20, Color::Black);
+            fb.set_pixel(x, 30, Color::Red);
+        }
+        fb
+    }
+
+    // `frame`, saved as the reference
+    fn reference(dir: &Path) -> PathBuf {
+        let path = dir.join("frame.png");
+        save(&frame().to_image(), &path).unwrap();
+        path
+    }
+
+    // `frame` with `count` pixels changed
+    fn changed(count: u32) -> Framebuffer {
+        let mut fb = frame();
+        for x in 0..count {
+            fb.set_pixel(100 + x, 50, Color::Black);
+        }
+        fb
+    }
+
+    #[test]
+    fn matches_an_identical_frame() {
+        let _env = lock();
+        let reference = reference(&scratch("identical"));
+        assert_frame_matches!(frame(), &reference);
+        assert!(!reference.with_extension("actual.png").exists());
+    }
+
+    #[test]
+    fn allows_differences_up_to_the_tolerance() {
+        let _env = lock();
+        let reference = reference(&scratch("tolerance"));
+        assert_frame_matches!(changed(3), &reference, tolerance = 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "4 pixels differ")]
+    fn fails_past_the_tolerance() {
+        let _env = lock();
+        let reference = reference(&scratch("past_tolerance"));
+        assert_frame_matches!(changed(4), &reference, tolerance = 3);
+    }
+
+    #[test]
+    fn saves_the_frame_that_failed() {
+        let _env = lock();
+        let reference = reference(&scratch("failed"));
+        let Err(Mismatch::Pixels { actual, differing, first, .. }) = compare(&changed(2), &reference, 0) else {
+            panic!("expected a pixel mismatch");
+        };
+        assert_eq!((differing, first), (2, (100, 50)));
+        assert_eq!(image::open(actual).unwrap().to_rgb8(), changed(2).to_image());
+    }
+
+    #[test]
+    #[should_panic(expected = "no reference")]
+    fn fails_without_a_reference() {
+        let _env = lock();
+        let reference = scratch("missing").join("frame.png");
+        assert_frame_matches!(frame(), &reference);
+    }
+
+    #[test]
+    fn writes_the_frame_where_the_reference_was_missing() {
+        let _env = lock();
+        let reference = scratch("missing_actual").join("frame.png");
+        let Err(Mismatch::Missing { actual, .. }) = compare(&frame(), &reference, 0) else {
+            panic!("expected a missing reference");
+        };
+        assert!(!reference.exists());
+        assert_eq!(image::open(actual).unwrap().to_rgb8(), frame().to_image());
+    }
+
+    #[test]
+    fn updates_the_reference_when_asked() {
+        let _env = lock();
+        let dir = scratch("update");
+        let (old, new) = (dir.join("old.png"), dir.join("new.png"));
+        save(&frame().to_image(), &old).unwrap();
+        // SAFETY: every test that reads the variable waits on `ENV`
+        unsafe { env::set_var(UPDATE_VAR, "1") };
+        let updated = (compare(&changed(5), &old, 0), compare(&frame(), &new, 0));
+        unsafe { env::remove_var(UPDATE_VAR) };
+        assert!(matches!(updated, (Ok(()), Ok(()))));
+        assert_frame_matches!(changed(5), &old);
+        assert_frame_matches!(frame(), &new);
+    }
+
+    #[test]
+    fn goldens_match_the_references() {
+        let _env = lock();
+        let failed = check_goldens(Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots"), 0);
+        let report: Vec<String> = failed.iter().map(|(name, mismatch)| format!("{name}: {mismatch}")).collect();
+        assert!(report.is_empty(), "{}", report.join("\n"));
+    }
+}
@@ -0,0 +1,77 @@
+// Tactile buttons on GPIO, delivered as events.
+//
+// Each button gets a thread blocked on its edge interrupt (see
+// `linux::Button`, which also does the debouncing), and every press is sent
+// down one channel, so the app can wait on all of them at once or hand them
+// to a callback.
+
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use linux_embedded_hal::sysfs_gpio;
+use crate::linux::Button;
+
+/// BCM pins of the four buttons on the Inky Impression (A, B, C, D), which
+/// most other button HATs copy; `ButtonsConfig` in the daemon maps them to page
+/// turns.
+pub const IMPRESSION_PINS: [u64; 4] = [5, 6, 16, 24];
+
+/// One press of one button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Press {
+    /// Position of the button in the list it was opened from
+    pub index: usize,
+    pub pin: u64,
+}
+
+pub struct Buttons {
+    presses: Receiver<Press>,
+}
+
+impl Buttons {
+    /// Starts listening on `pins`. Every pin is exported before any thread
+    /// starts, so a bad pin fails the whole call.
+    pub fn open(pins: &[u64], active_high: bool) -> Result<Self, sysfs_gpio::Error> {
+        let buttons = pins
+            .iter()
+            .map(|&pin| Button::open(pin, active_high))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (sender, presses) = mpsc::channel();
+        for (index, (mut button, &pin)) in buttons.into_iter().zip(pins).enumerate() {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                loop {
+                    if let Err(err) = button.wait_press() {
+                        eprintln!("Button on GPIO {pin} failed: {err}");
+                        return;
+                    }
+                    // Nobody listening any more
+                    if sender.send(Press { index, pin }).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        Ok(Buttons { presses })
+    }
+
+    /// The next press, waiting up to `timeout` for one.
+    pub fn next_press(&self, timeout: Duration) -> Option<Press> {
+        self.presses.recv_timeout(timeout).ok()
+    }
+
+    /// Presses that have already happened, without waiting.
+    pub fn pending(&self) -> impl Iterator<Item = Press> + '_ {
+        self.presses.try_iter()
+    }
+
+    /// Calls `callback` for every press, on a thread of its own.
+    pub fn on_press(self, mut callback: impl FnMut(Press) + Send + 'static) -> JoinHandle<()> {
+        thread::spawn(move || {
+            for press in self.presses {
+                callback(press);
+            }
+        })
+    }
+}
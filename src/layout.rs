@@ -0,0 +1,497 @@
+// Pages described in a file instead of in Rust.
+//
+// A scene is a tree of rows and columns whose leaves are text, icons and
+// charts. Text may contain `{name}` placeholders, and icons and chart series
+// are named the same way; all of them are looked up in a data map when the
+// page is drawn. Editing the scene and restarting the daemon is enough to
+// change a dashboard, with no rebuild on the Pi. Rows and columns are laid out
+// by `flex`, which the built-in screens use directly to fit any panel size.
+//
+// A leaf that can't be drawn (a variable that isn't set, an icon name that
+// doesn't exist, a chart with no numbers) shows a placeholder in its own box
+// and the rest of the page draws as normal; `/status` says what went wrong,
+// under the node's `id` or else its position, e.g. `root/1/0`.
+//
+//     [root]
+//     type = "column"
+//     children = [
+//         { type = "text", text = "{time}", font = 24, align = "center" },
+//         { type = "row", size = 16, children = [
+//             { type = "icon", icon = "{weather}" },
+//             { type = "text", text = "{temperature}°C" },
+//         ] },
+//         { type = "chart", kind = "line", values = "history" },
+//         { type = "progress", size = 10, value = "battery", below = 20 },
+//         { type = "markdown", file = "/home/pi/todo.md" },
+//     ]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use profont::PROFONT_9_POINT;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::markdown;
+use crate::metrics::{self, WidgetError};
+use crate::power;
+use crate::screens::{RenderContext, Screen};
+use crate::splash::hostname;
+use crate::text::{self, Alignment, TextBox};
+use crate::theme::Theme;
+use crate::widgets::chart::{BarChart, LineChart, Sparkline};
+use crate::widgets::icon::{self, Icon};
+use crate::widgets::placeholder;
+use crate::widgets::progress::{Fill, Gauge, ProgressBar, Threshold};
+
+pub mod flex;
+pub use flex::{Align, Direction, Edges, Flex, Item, Justify};
+
+/// Variables a scene is filled in from.
+pub type Data = BTreeMap<String, Value>;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    #[serde(default = "white")]
+    pub background: Color,
+    pub root: Node,
+}
+
+fn white() -> Color {
+    Color::White
+}
+
+fn black() -> Color {
+    Color::Black
+}
+
+fn one() -> u32 {
+    1
+}
+
+fn hundred() -> f32 {
+    100.0
+}
+
+impl Scene {
+    /// Reads a scene from TOML, or from JSON if the file ends in `.json`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        let scene = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            serde_json::from_str(&text).map_err(|err| err.to_string())
+        } else {
+            toml::from_str(&text).map_err(|err| err.to_string())
+        };
+        scene.map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    /// Draws the scene in `theme`, and returns the widgets that drew a placeholder instead.
+    pub fn draw(&self, fb: &mut Framebuffer, data: &Data, theme: &Theme) -> Vec<(String, WidgetError)> {
+        fb.clear(self.background);
+        let bounds = Rectangle::new(Point::zero(), fb.size());
+        let mut failed = Vec::new();
+        self.root.draw("root", bounds, data, theme, fb, &mut failed);
+        failed
+    }
+}
+
+/// A box in the scene. Along its parent's axis it takes `size` pixels if
+/// given, otherwise a `weight`ed share of whatever the fixed-size boxes leave.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Node {
+    /// Name to report the node under if it fails to draw
+    pub id: Option<String>,
+    pub size: Option<u32>,
+    #[serde(default = "one")]
+    pub weight: u32,
+    #[serde(flatten)]
+    pub kind: Kind,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Kind {
+    /// Children side by side, left to right
+    Row {
+        children: Vec<Node>,
+        #[serde(default)]
+        gap: u32,
+    },
+    /// Children stacked top to bottom
+    Column {
+        children: Vec<Node>,
+        #[serde(default)]
+        gap: u32,
+    },
+    /// `font` is a ProFont point size; without one the largest that fits is used.
+    /// With `ttf`, a TrueType font file, it is a size in pixels instead.
+    Text {
+        text: String,
+        #[serde(default = "black")]
+        color: Color,
+        font: Option<u32>,
+        #[serde(default)]
+        align: Alignment,
+        #[cfg(feature = "ttf")]
+        ttf: Option<PathBuf>,
+    },
+    /// An icon name such as `wifi3`, or a placeholder that holds one
+    Icon {
+        icon: String,
+        #[serde(default = "black")]
+        color: Color,
+    },
+    /// A chart of the number array named by `values`
+    Chart {
+        kind: ChartKind,
+        values: String,
+        #[serde(default = "black")]
+        color: Color,
+    },
+    /// A bar filled to the number named by `value`
+    Progress(Meter),
+    /// A half dial filled to the number named by `value`
+    Gauge(Meter),
+    /// Markdown from `file`, read every time the page is drawn, or else
+    /// `text`; `font` is the ProFont point size of the body text, the
+    /// theme's if not given.
+    Markdown {
+        #[serde(default)]
+        text: String,
+        file: Option<PathBuf>,
+        font: Option<u32>,
+        #[serde(default = "black")]
+        color: Color,
+    },
+    /// Empty space
+    Spacer,
+}
+
+/// How a `progress` or `gauge` node reads its number: `value` is a variable
+/// such as `battery`, out of `max`, and the fill turns red `above` or `below`
+/// a level in the same units.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Meter {
+    pub value: String,
+    #[serde(default = "hundred")]
+    pub max: f32,
+    #[serde(default)]
+    pub fill: Fill,
+    pub above: Option<f32>,
+    pub below: Option<f32>,
+    #[serde(default = "black")]
+    pub color: Color,
+}
+
+impl Meter {
+    // The variable as a fraction of `max`, and the threshold in the same terms
+    fn read(&self, data: &Data) -> Result<(f32, Option<Threshold>), WidgetError> {
+        let value = match data.get(&self.value) {
+            Some(Value::Number(number)) => number.as_f64(),
+            Some(Value::String(text)) => text.trim().trim_end_matches('%').parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| WidgetError::new("DATA", format!("no number {:?}", self.value)))?;
+        let max = if self.max > 0.0 { self.max } else { 100.0 };
+        let threshold = match (self.above, self.below) {
+            (Some(level), _) => Some(Threshold::Above(level / max)),
+            (None, Some(level)) => Some(Threshold::Below(level / max)),
+            (None, None) => None,
+        };
+        Ok((value as f32 / max, threshold))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChartKind {
+    Sparkline,
+    Line,
+    Bar,
+}
+
+impl Node {
+    /// Draws the node into `bounds`. `path` names it in `failed`, where
+    /// every leaf that drew a placeholder instead of itself is added.
+    pub fn draw(
+        &self,
+        path: &str,
+        bounds: Rectangle,
+        data: &Data,
+        theme: &Theme,
+        fb: &mut Framebuffer,
+        failed: &mut Vec<(String, WidgetError)>,
+    ) {
+        let path = self.id.as_deref().unwrap_or(path);
+        if let Err(error) = self.draw_kind(path, bounds, data, theme, fb, failed) {
+            let Ok(()) = placeholder::draw(bounds, error.code, fb);
+            failed.push((path.to_string(), error));
+        }
+    }
+
+    fn draw_kind(
+        &self,
+        path: &str,
+        bounds: Rectangle,
+        data: &Data,
+        theme: &Theme,
+        fb: &mut Framebuffer,
+        failed: &mut Vec<(String, WidgetError)>,
+    ) -> Result<(), WidgetError> {
+        match &self.kind {
+            Kind::Row { children, gap } => {
+                let boxes = Flex::row().gap(*gap).layout(bounds, &items(children));
+                for (i, (child, child_bounds)) in children.iter().zip(boxes).enumerate() {
+                    child.draw(&format!("{path}/{i}"), child_bounds, data, theme, fb, failed);
+                }
+            }
+            Kind::Column { children, gap } => {
+                let boxes = Flex::column().gap(*gap).layout(bounds, &items(children));
+                for (i, (child, child_bounds)) in children.iter().zip(boxes).enumerate() {
+                    child.draw(&format!("{path}/{i}"), child_bounds, data, theme, fb, failed);
+                }
+            }
+            Kind::Text {
+                text,
+                color,
+                font,
+                align,
+                #[cfg(feature = "ttf")]
+                ttf,
+            } => {
+                if let Some(name) = unknown_variable(text, data) {
+                    return Err(WidgetError::new("VAR", format!("no variable {{{name}}}")));
+                }
+                let text = fill(text, data);
+                #[cfg(feature = "ttf")]
+                if let Some(path) = ttf {
+                    let ttf = crate::ttf::load_cached(path).map_err(|err| WidgetError::new("FONT", err))?;
+                    let Ok(_) = ttf.draw_box(&text, bounds, *font, *align, *color, fb);
+                    return Ok(());
+                }
+                let font = match font {
+                    Some(points) => {
+                        Some(text::profont(*points).ok_or_else(|| WidgetError::new("FONT", format!("no {points}pt font")))?)
+                    }
+                    None => None,
+                };
+                let single = font.as_slice();
+                let mut text_box = TextBox::new(bounds, *color).alignment(*align);
+                if font.is_some() {
+                    text_box = text_box.fonts(single);
+                }
+                let Ok(_) = text_box.draw(&text, fb);
+            }
+            Kind::Icon { icon, color } => {
+                if let Some(name) = unknown_variable(icon, data) {
+                    return Err(WidgetError::new("VAR", format!("no variable {{{name}}}")));
+                }
+                let name = fill(icon, data);
+                let icon = serde_json::from_value::<Icon>(Value::String(name.clone()))
+                    .map_err(|_| WidgetError::new("ICON", format!("no icon {name:?}")))?;
+                // Centred in its box
+                let size = Size::new_equal(icon::SIZE);
+                let offset = (bounds.size.saturating_sub(size)) / 2;
+                let Ok(()) = icon::draw(icon, bounds.top_left + offset, *color, fb);
+            }
+            Kind::Chart { kind, values: name, color } => {
+                let values = series(data.get(name))
+                    .ok_or_else(|| WidgetError::new("DATA", format!("no array {name:?}")))?;
+                let Ok(()) = match kind {
+                    ChartKind::Sparkline => Sparkline {
+                        color: *color,
+                        ..Sparkline::new(bounds)
+                    }
+                    .draw(&values, fb),
+                    ChartKind::Line => LineChart {
+                        color: *color,
+                        ..LineChart::new(bounds)
+                    }
+                    .draw(&values, fb),
+                    ChartKind::Bar => BarChart {
+                        color: *color,
+                        ..BarChart::new(bounds)
+                    }
+                    .draw(&values, fb),
+                };
+            }
+            Kind::Progress(meter) => {
+                let (value, threshold) = meter.read(data)?;
+                let Ok(()) = ProgressBar {
+                    fill: meter.fill,
+                    color: meter.color,
+                    threshold,
+                    ..ProgressBar::new(bounds).themed(theme)
+                }
+                .draw(value, fb);
+            }
+            Kind::Gauge(meter) => {
+                let (value, threshold) = meter.read(data)?;
+                let Ok(()) = Gauge {
+                    fill: meter.fill,
+                    color: meter.color,
+                    threshold,
+                    ..Gauge::new(bounds).themed(theme)
+                }
+                .draw(value, fb);
+            }
+            Kind::Markdown { text, file, font, color } => {
+                let source = match file {
+                    Some(path) => fs::read_to_string(path)
+                        .map_err(|err| WidgetError::new("FILE", format!("{}: {err}", path.display())))?,
+                    None => fill(text, data),
+                };
+                let font = match font {
+                    Some(points) => {
+                        text::profont(*points).ok_or_else(|| WidgetError::new("FONT", format!("no {points}pt font")))?
+                    }
+                    None => theme.font(),
+                };
+                let Ok(_) = markdown::draw(&markdown::parse(&source), bounds, font, *color, fb);
+            }
+            Kind::Spacer => {}
+        }
+        Ok(())
+    }
+}
+
+// Fixed boxes as they are, the rest sharing what's left by weight
+fn items(children: &[Node]) -> Vec<Item> {
+    children
+        .iter()
+        .map(|child| match child.size {
+            Some(size) => Item::fixed(size),
+            None => Item::grow(child.weight),
+        })
+        .collect()
+}
+
+/// Replaces every `{name}` in `template` with `data[name]`. Strings go in as
+/// they are, anything else as JSON; unknown names are left as they were.
+pub fn fill(template: &str, data: &Data) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}').map(|end| (&after[..end], &after[end + 1..])) {
+            Some((name, tail)) if data.contains_key(name) => {
+                match &data[name] {
+                    Value::String(value) => out.push_str(value),
+                    value => out.push_str(&value.to_string()),
+                }
+                rest = tail;
+            }
+            _ => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// The first `{name}` in `template` that looks like a variable but isn't in `data`
+fn unknown_variable<'a>(template: &'a str, data: &Data) -> Option<&'a str> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        let end = after.find('}')?;
+        let name = &after[..end];
+        let is_name = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if is_name && !data.contains_key(name) {
+            return Some(name);
+        }
+        rest = after;
+    }
+    None
+}
+
+// An array of numbers, with anything that isn't one as a gap
+fn series(value: Option<&Value>) -> Option<Vec<f32>> {
+    match value {
+        Some(Value::Array(values)) => Some(
+            values
+                .iter()
+                .map(|value| value.as_f64().map_or(f32::NAN, |value| value as f32))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    /// The scene, TOML or JSON
+    pub file: PathBuf,
+    /// Fixed variables
+    pub data: Data,
+    /// JSON object of variables, re-read on every refresh so another program can keep it up to date
+    pub data_file: Option<PathBuf>,
+}
+
+/// A page drawn from a scene file. On top of the configured data, `{time}`,
+/// `{date}` and `{hostname}` are always defined, and `{battery}` and friends
+/// while something is measuring one (see `power`).
+pub struct Layout {
+    name: String,
+    config: LayoutConfig,
+    scene: Result<Scene, String>,
+    hostname: String,
+}
+
+impl Layout {
+    /// `name` is the page name the layout is listed under.
+    pub fn new(name: &str, config: &LayoutConfig) -> Self {
+        Layout {
+            name: name.to_string(),
+            config: config.clone(),
+            scene: Scene::load(&config.file),
+            hostname: hostname(),
+        }
+    }
+
+    fn data(&self, ctx: &RenderContext) -> Data {
+        let mut data = Data::new();
+        data.insert("time".to_string(), Value::from(ctx.now.format("%H:%M").to_string()));
+        data.insert("date".to_string(), Value::from(ctx.now.format("%a %e %b").to_string()));
+        data.insert("hostname".to_string(), Value::from(self.hostname.clone()));
+        if let Some(battery) = power::latest() {
+            data.extend(battery.variables());
+        }
+        data.extend(self.config.data.clone());
+        if let Some(path) = &self.config.data_file {
+            match fs::read(path).map_err(|err| err.to_string()).and_then(|bytes| {
+                serde_json::from_slice::<Data>(&bytes).map_err(|err| err.to_string())
+            }) {
+                Ok(values) => data.extend(values),
+                Err(err) => eprintln!("{}: {err}", path.display()),
+            }
+        }
+        data
+    }
+}
+
+impl Screen for Layout {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        match &self.scene {
+            Ok(scene) => {
+                let failed = scene.draw(fb, &self.data(ctx), &ctx.theme);
+                metrics::report_widgets(&self.name, failed);
+            }
+            Err(err) => {
+                fb.clear(Color::White);
+                let fonts = [&PROFONT_9_POINT];
+                let bounds = Rectangle::new(Point::new(2, 2), fb.size() - Size::new(4, 4));
+                let Ok(_) = TextBox::new(bounds, Color::Red).fonts(&fonts).draw(err, fb);
+            }
+        }
+    }
+}
@@ -0,0 +1,323 @@
+// Boxes laid out along a row or a column, after CSS flexbox, so that a page
+// is described by what goes where rather than by coordinates and fits any
+// panel: the pHAT's 212x104 as well as the wHAT's 400x300.
+//
+// Each item has a `basis`, its size along the line before anything else
+// happens. If the items and their gaps come up short of the line, the spare
+// pixels go to the items that `grow`, by weight, or failing that are placed
+// by `justify`; if they overrun it, items that `shrink` give pixels back in
+// proportion to weight times basis, never going below nothing. Across the
+// line each item is as long as its `cross`, or else the whole line, placed
+// by `align`.
+// Shares are rounded cumulatively, so they always add up to the space given.
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    /// Left to right
+    #[default]
+    Row,
+    /// Top to bottom
+    Column,
+}
+
+/// Where items go along the line when none of them grows into the spare room.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Justify {
+    #[default]
+    Start,
+    Center,
+    End,
+    /// The spare room between items, none at the ends
+    SpaceBetween,
+    /// The spare room between items and at both ends alike
+    SpaceEvenly,
+}
+
+/// Where an item goes across the line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+    /// Across the whole line, unless it has a `cross` of its own
+    #[default]
+    Stretch,
+}
+
+/// Space inside a box's edges, in pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Edges {
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub left: u32,
+}
+
+impl Edges {
+    /// Clockwise from the top, as in CSS.
+    pub const fn new(top: u32, right: u32, bottom: u32, left: u32) -> Self {
+        Edges { top, right, bottom, left }
+    }
+
+    pub const fn all(size: u32) -> Self {
+        Edges {
+            top: size,
+            right: size,
+            bottom: size,
+            left: size,
+        }
+    }
+
+    /// `vertical` at the top and bottom, `horizontal` at either side.
+    pub const fn axes(vertical: u32, horizontal: u32) -> Self {
+        Edges {
+            top: vertical,
+            right: horizontal,
+            bottom: vertical,
+            left: horizontal,
+        }
+    }
+
+    /// What is left of `bounds` inside these edges.
+    pub fn inset(&self, bounds: Rectangle) -> Rectangle {
+        Rectangle::new(
+            bounds.top_left + Point::new(self.left as i32, self.top as i32),
+            Size::new(
+                bounds.size.width.saturating_sub(self.left + self.right),
+                bounds.size.height.saturating_sub(self.top + self.bottom),
+            ),
+        )
+    }
+}
+
+impl From<u32> for Edges {
+    fn from(size: u32) -> Self {
+        Edges::all(size)
+    }
+}
+
+/// One box's wishes about its size and place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Item {
+    /// Size along the line to start from
+    pub basis: u32,
+    /// Share of spare room taken; 0 for none
+    pub grow: u32,
+    /// Share of an overrun given back; 0 for none
+    pub shrink: u32,
+    /// Size across the line; `None` for the whole line
+    pub cross: Option<u32>,
+    /// `None` for the container's `align`
+    pub align: Option<Align>,
+}
+
+impl Item {
+    /// Always `size` along the line.
+    pub const fn fixed(size: u32) -> Self {
+        Item {
+            basis: size,
+            grow: 0,
+            shrink: 0,
+            cross: None,
+            align: None,
+        }
+    }
+
+    /// A `weight` share of whatever room the other items leave.
+    pub const fn grow(weight: u32) -> Self {
+        Item {
+            basis: 0,
+            grow: weight,
+            shrink: 1,
+            cross: None,
+            align: None,
+        }
+    }
+
+    /// `size` along the line if there is room, less if there isn't.
+    pub const fn flexible(size: u32) -> Self {
+        Item {
+            basis: size,
+            grow: 0,
+            shrink: 1,
+            cross: None,
+            align: None,
+        }
+    }
+
+    pub const fn with_grow(mut self, weight: u32) -> Self {
+        self.grow = weight;
+        self
+    }
+
+    pub const fn with_shrink(mut self, weight: u32) -> Self {
+        self.shrink = weight;
+        self
+    }
+
+    pub const fn cross(mut self, size: u32) -> Self {
+        self.cross = Some(size);
+        self
+    }
+
+    pub const fn align(mut self, align: Align) -> Self {
+        self.align = Some(align);
+        self
+    }
+}
+
+/// A row or column of items in a box.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Flex {
+    pub direction: Direction,
+    /// Pixels between neighbouring items
+    pub gap: u32,
+    pub padding: Edges,
+    pub justify: Justify,
+    pub align: Align,
+}
+
+impl Flex {
+    pub fn row() -> Self {
+        Flex::default()
+    }
+
+    pub fn column() -> Self {
+        Flex {
+            direction: Direction::Column,
+            ..Flex::default()
+        }
+    }
+
+    pub fn gap(mut self, gap: u32) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    pub fn padding(mut self, padding: impl Into<Edges>) -> Self {
+        self.padding = padding.into();
+        self
+    }
+
+    pub fn justify(mut self, justify: Justify) -> Self {
+        self.justify = justify;
+        self
+    }
+
+    pub fn align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// The box each of `items` gets inside `bounds`, in order.
+    pub fn layout(&self, bounds: Rectangle, items: &[Item]) -> Vec<Rectangle> {
+        let inner = self.padding.inset(bounds);
+        let (main, cross) = self.axes(inner.size);
+        let gaps = self.gap as i64 * items.len().saturating_sub(1) as i64;
+        let basis: i64 = items.iter().map(|item| item.basis as i64).sum();
+        let free = main as i64 - gaps - basis;
+
+        let mut sizes: Vec<i64> = items.iter().map(|item| item.basis as i64).collect();
+        let grows: i64 = items.iter().map(|item| item.grow as i64).sum();
+        let shrinks: i64 = items.iter().map(|item| item.shrink as i64 * item.basis as i64).sum();
+        let mut spare = free.max(0);
+        if free > 0 && grows > 0 {
+            let weights: Vec<i64> = items.iter().map(|item| item.grow as i64).collect();
+            for (size, share) in sizes.iter_mut().zip(shares(free, &weights, grows)) {
+                *size += share;
+            }
+            spare = 0;
+        } else if free < 0 && shrinks > 0 {
+            let weights: Vec<i64> = items.iter().map(|item| item.shrink as i64 * item.basis as i64).collect();
+            for (size, share) in sizes.iter_mut().zip(shares(-free, &weights, shrinks)) {
+                *size = (*size - share).max(0);
+            }
+        }
+
+        // Spare room left over goes before, between and after the items
+        let n = items.len() as i64;
+        let (lead, between) = match self.justify {
+            Justify::Start => (0, Vec::new()),
+            Justify::Center => (spare / 2, Vec::new()),
+            Justify::End => (spare, Vec::new()),
+            Justify::SpaceBetween => (0, shares(spare, &vec![1; (n - 1).max(0) as usize], n - 1)),
+            Justify::SpaceEvenly => {
+                let slots = shares(spare, &vec![1; n as usize + 1], n + 1);
+                (slots[0], slots[1..].to_vec())
+            }
+        };
+        let mut along = lead;
+        items
+            .iter()
+            .zip(sizes)
+            .enumerate()
+            .map(|(i, (item, size))| {
+                let align = item.align.unwrap_or(self.align);
+                let length = item.cross.unwrap_or(cross).min(cross);
+                let slack = (cross - length) as i32;
+                let offset = match align {
+                    Align::Start | Align::Stretch => 0,
+                    Align::Center => slack / 2,
+                    Align::End => slack,
+                };
+                let start = along as i32;
+                along += size + self.gap as i64 + between.get(i).copied().unwrap_or(0);
+                let (position, size) = match self.direction {
+                    Direction::Row => (Point::new(start, offset), Size::new(size as u32, length)),
+                    Direction::Column => (Point::new(offset, start), Size::new(length, size as u32)),
+                };
+                Rectangle::new(inner.top_left + position, size)
+            })
+            .collect()
+    }
+
+    /// `layout` for a fixed number of items, to destructure.
+    pub fn split<const N: usize>(&self, bounds: Rectangle, items: [Item; N]) -> [Rectangle; N] {
+        let boxes = self.layout(bounds, &items);
+        core::array::from_fn(|i| boxes[i])
+    }
+
+    /// As many boxes `size` long as fit in `bounds` one after another, for
+    /// the lines of a list. `justify` and shrinking don't come into it.
+    pub fn repeat(&self, bounds: Rectangle, size: u32) -> impl Iterator<Item = Rectangle> + use<> {
+        let inner = self.padding.inset(bounds);
+        let (main, cross) = self.axes(inner.size);
+        let pitch = (size + self.gap).max(1);
+        let count = (main + self.gap) / pitch;
+        let direction = self.direction;
+        (0..count).map(move |i| {
+            let start = (i * pitch) as i32;
+            match direction {
+                Direction::Row => Rectangle::new(inner.top_left + Point::new(start, 0), Size::new(size, cross)),
+                Direction::Column => Rectangle::new(inner.top_left + Point::new(0, start), Size::new(cross, size)),
+            }
+        })
+    }
+
+    // Length along the line, then across it
+    fn axes(&self, size: Size) -> (u32, u32) {
+        match self.direction {
+            Direction::Row => (size.width, size.height),
+            Direction::Column => (size.height, size.width),
+        }
+    }
+}
+
+// `total` split by `weights` (which add up to `sum`), rounding cumulatively
+fn shares(total: i64, weights: &[i64], sum: i64) -> Vec<i64> {
+    let mut given = 0;
+    let mut seen = 0;
+    weights
+        .iter()
+        .map(|&weight| {
+            seen += weight;
+            let upto = total * seen / sum.max(1);
+            let share = upto - given;
+            given = upto;
+            share
+        })
+        .collect()
+}
@@ -0,0 +1,107 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// The driver core only needs embedded-hal
+pub mod epd;
+pub mod il0373;
+pub mod inky_driver;
+
+#[cfg(feature = "std")]
+pub mod alerts;
+#[cfg(feature = "std")]
+pub mod astro;
+#[cfg(feature = "std")]
+pub mod calibration;
+#[cfg(feature = "std")]
+pub mod carousel;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "linux")]
+pub mod control;
+#[cfg(feature = "std")]
+pub mod crash;
+#[cfg(feature = "std")]
+pub mod daemon;
+#[cfg(feature = "linux")]
+pub mod dbus;
+#[cfg(feature = "std")]
+pub mod emoji;
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "std")]
+pub mod frame_store;
+#[cfg(feature = "std")]
+pub mod framebuffer;
+#[cfg(feature = "std")]
+pub mod gray;
+#[cfg(feature = "std")]
+pub mod health;
+#[cfg(feature = "std")]
+pub mod http;
+#[cfg(feature = "std")]
+pub mod ical;
+#[cfg(feature = "std")]
+pub mod images;
+#[cfg(feature = "std")]
+pub mod inky_test;
+#[cfg(feature = "linux")]
+pub mod input;
+#[cfg(feature = "std")]
+pub mod layout;
+#[cfg(feature = "linux")]
+pub mod linux;
+#[cfg(feature = "std")]
+pub mod markdown;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod mqtt;
+#[cfg(feature = "std")]
+pub mod pack;
+#[cfg(feature = "std")]
+pub mod panel;
+#[cfg(feature = "std")]
+pub mod pipe;
+#[cfg(feature = "std")]
+pub mod power;
+#[cfg(feature = "std")]
+pub mod presence;
+#[cfg(feature = "std")]
+pub mod push;
+#[cfg(feature = "std")]
+pub mod record;
+#[cfg(feature = "std")]
+pub mod refresh_policy;
+#[cfg(feature = "std")]
+pub mod retry;
+#[cfg(feature = "std")]
+pub mod schedule;
+#[cfg(feature = "std")]
+pub mod screens;
+#[cfg(feature = "std")]
+pub mod script;
+#[cfg(feature = "linux")]
+pub mod sensors;
+#[cfg(feature = "linux")]
+pub mod shutdown;
+#[cfg(feature = "std")]
+pub mod slideshow;
+#[cfg(feature = "std")]
+pub mod splash;
+#[cfg(feature = "std")]
+pub mod sprite;
+#[cfg(feature = "std")]
+pub mod terminal;
+#[cfg(feature = "std")]
+pub mod text;
+#[cfg(feature = "std")]
+pub mod theme;
+#[cfg(feature = "std")]
+pub mod thermal;
+#[cfg(feature = "std")]
+pub mod tiled;
+#[cfg(feature = "ttf")]
+pub mod ttf;
+#[cfg(feature = "linux")]
+pub mod watchdog;
+#[cfg(feature = "std")]
+pub mod widgets;
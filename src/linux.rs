@@ -0,0 +1,518 @@
+// Linux-specific pin helpers, and the stock Inky pHAT wiring.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use std::ops::Deref;
+
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use linux_embedded_hal::spidev::{SpiModeFlags, SpidevOptions};
+use linux_embedded_hal::sysfs_gpio::{self, Direction, Edge, PinPoller};
+use linux_embedded_hal::{Delay, Pin, Spidev};
+
+use crate::il0373::Il0373;
+use crate::inky_driver::{BUFFER_SIZE, InkyError, InkyPhat, NoPin};
+use crate::panel::Pins;
+
+// A press must still read as pressed this long after the edge
+const DEBOUNCE: Duration = Duration::from_millis(30);
+// Longest the panel's chip select waits for the SPI bus before the transfer fails
+const CS_BUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// SPI device the pHAT sits on when plugged straight onto the header.
+pub const DEFAULT_SPI_PATH: &str = "/dev/spidev0.0";
+/// Clock rate every pHAT has been seen to cope with.
+pub const DEFAULT_SPI_SPEED: u32 = 4_000_000;
+/// Clock rates `tune_spi_speed` tries, slowest first; the controller is rated for 20 MHz writes.
+pub const SPI_SPEEDS: &[u32] = &[4_000_000, 6_000_000, 8_000_000, 10_000_000, 12_000_000, 16_000_000, 20_000_000];
+/// Where the learned clock rate for each SPI device is kept.
+pub const DEFAULT_SPI_STATE_PATH: &str = "/var/lib/rust_raspi/spi.json";
+/// BCM pin numbers of the pHAT's chip select, BUSY, data/command and reset lines.
+pub const CS_PIN: u64 = 8;
+pub const BUSY_PIN: u64 = 17;
+pub const DC_PIN: u64 = 22;
+pub const RESET_PIN: u64 = 27;
+
+/// An Inky pHAT on Linux spidev and sysfs GPIO. Dropping it unexports its pins.
+/// CS is a GPIO toggled by the driver unless `CS` is `HardwareCs`.
+pub type LinuxInkyPhat<CS = ChipSelect> = InkyPhat<Spidev, CS, EdgeBusyPin, ExportedPin, ExportedPin>;
+
+/// An IL0373 panel (Adafruit's tri-colour FeatherWing and ThinkInk) on
+/// Linux spidev and sysfs GPIO.
+pub type LinuxIl0373 = Il0373<Spidev, ChipSelect, EdgeBusyPin, ExportedPin, ExportedPin>;
+
+/// Chip select left to spidev, which asserts its CE line round every transfer.
+pub type HardwareCs = NoPin<sysfs_gpio::Error>;
+
+impl LinuxInkyPhat {
+    /// Opens `spi_path` at 4 MHz, mode 0, and sets up the pHAT's pins on BCM 8/17/22/27.
+    pub fn with_default_pins(spi_path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::with_speed(spi_path, DEFAULT_SPI_SPEED)
+    }
+
+    /// Like `with_default_pins`, with the SPI clock at `speed_hz`.
+    pub fn with_speed(spi_path: impl AsRef<Path>, speed_hz: u32) -> io::Result<Self> {
+        Self::with_pins(spi_path, speed_hz, &Pins::INKY_PHAT)
+    }
+
+    /// A panel wired to other pins than the pHAT's, e.g. `Pins::WAVESHARE`.
+    pub fn with_pins(spi_path: impl AsRef<Path>, speed_hz: u32, pins: &Pins) -> io::Result<Self> {
+        Self::open(spi_path.as_ref(), speed_hz, SpiModeFlags::SPI_MODE_0, pins)
+    }
+
+    /// Opens the panel at the fastest clock known to work with it, as kept in
+    /// `state`. The stored rate is checked first; if there is none, or it no
+    /// longer reads back cleanly (a longer cable, a different panel), the
+    /// rates in `SPI_SPEEDS` are probed again and the result saved.
+    pub fn with_tuned_speed(spi_path: impl AsRef<Path>, state: impl AsRef<Path>, pins: &Pins) -> io::Result<Self> {
+        let (spi_path, state) = (spi_path.as_ref(), state.as_ref());
+        let key = spi_path.display().to_string();
+        let mut speeds = load_spi_state(state);
+        let speed = match speeds.get(&key) {
+            Some(&speed) if probe_spi_speed(spi_path, speed, pins)? => speed,
+            _ => {
+                let speed = tune_spi_speed(spi_path, pins)?;
+                match speed {
+                    Some(speed) => {
+                        speeds.insert(key, speed);
+                        if let Err(err) = save_spi_state(state, &speeds) {
+                            eprintln!("Could not save SPI speed to {}: {err}", state.display());
+                        }
+                    }
+                    None => eprintln!("SPI read-back failed at every speed; staying at {DEFAULT_SPI_SPEED} Hz"),
+                }
+                speed.unwrap_or(DEFAULT_SPI_SPEED)
+            }
+        };
+        Self::with_pins(spi_path, speed, pins)
+    }
+
+    /// The panel's own temperature in degrees C, as its sensor read at the
+    /// last refresh. Needs a Pi whose SPI can do 3-wire reads.
+    pub fn panel_temperature(&mut self) -> Result<i8, InkyError<io::Error, sysfs_gpio::Error>> {
+        self.read_temperature(|spi, buffer| {
+            // Turn MOSI around just for the read, then back to normal writes
+            spi.configure(&SpidevOptions::new().mode(SpiModeFlags::SPI_MODE_0 | SpiModeFlags::SPI_3WIRE).build())?;
+            let read = spi.0.read_exact(buffer);
+            spi.configure(&SpidevOptions::new().mode(SpiModeFlags::SPI_MODE_0).build())?;
+            read
+        })
+    }
+
+    fn open(spi_path: &Path, speed_hz: u32, mode: SpiModeFlags, pins: &Pins) -> io::Result<Self> {
+        // Each pin is exported with its direction set, and unexported again on drop
+        let cs = ChipSelect::export(pins.cs)?;
+        open_with_cs(spi_path, speed_hz, mode, cs, pins)
+    }
+}
+
+impl LinuxInkyPhat<HardwareCs> {
+    /// Opens `spi_path` with spidev doing chip select on its own CE line, so
+    /// no GPIO is spent on CS and nothing is toggled in software. The panel's
+    /// CS must be wired to that CE line: `/dev/spidev0.0` for the pHAT on
+    /// BCM 8. Reads back from the panel (speed tuning, its temperature) need
+    /// CS held across a command and its reply, so they are software-CS only.
+    /// `pins.cs` is not used.
+    pub fn with_hardware_cs(spi_path: impl AsRef<Path>, speed_hz: u32, pins: &Pins) -> io::Result<Self> {
+        open_with_cs(spi_path.as_ref(), speed_hz, SpiModeFlags::SPI_MODE_0, NoPin::new(), pins)
+    }
+}
+
+impl<CS: OutputPin<Error = sysfs_gpio::Error>> LinuxInkyPhat<CS> {
+    /// Closes the SPI device and unexports the pins, after putting the panel
+    /// into deep sleep if `sleep` is set. The pins are unexported even if that fails.
+    pub fn release(mut self, sleep: bool) -> Result<(), InkyError<io::Error, sysfs_gpio::Error>> {
+        if sleep {
+            self.sleep()?;
+        }
+        Ok(())
+    }
+}
+
+impl LinuxIl0373 {
+    /// Opens `spi_path` at `speed_hz`, mode 0, for a panel `size` native
+    /// pixels big (see `il0373::THINKINK_2IN9` and friends) wired to `pins`.
+    pub fn with_pins(spi_path: impl AsRef<Path>, speed_hz: u32, pins: &Pins, size: (u32, u32)) -> io::Result<Self> {
+        let cs = ChipSelect::export(pins.cs)?;
+        let (spi, busy, dc, reset) = open_parts(spi_path.as_ref(), speed_hz, SpiModeFlags::SPI_MODE_0, pins)?;
+        Ok(Il0373::new(spi, cs, busy, dc, reset, size))
+    }
+}
+
+fn open_with_cs<CS>(spi_path: &Path, speed_hz: u32, mode: SpiModeFlags, cs: CS, pins: &Pins) -> io::Result<LinuxInkyPhat<CS>>
+where
+    CS: OutputPin<Error = sysfs_gpio::Error>,
+{
+    let (spi, busy, dc, reset) = open_parts(spi_path, speed_hz, mode, pins)?;
+    Ok(InkyPhat::new(spi, cs, busy, dc, reset))
+}
+
+// The bus and every pin but CS, which is up to the caller
+fn open_parts(
+    spi_path: &Path,
+    speed_hz: u32,
+    mode: SpiModeFlags,
+    pins: &Pins,
+) -> io::Result<(Spidev, EdgeBusyPin, ExportedPin, ExportedPin)> {
+    // 1. SPI Setup
+    let mut spi = Spidev::open(spi_path)?;
+    let options = SpidevOptions::new()
+        .bits_per_word(8)
+        .max_speed_hz(speed_hz)
+        .mode(mode)
+        .build();
+    spi.configure(&options)?;
+    // 2. GPIO Setup (Using BCM pin numbers)
+    let busy = ExportedPin::export(pins.busy, Direction::In)?;
+    let dc = ExportedPin::export(pins.dc, Direction::Out)?;
+    let reset = ExportedPin::export(pins.reset, Direction::Out)?;
+    // Sleep on the BUSY edge interrupt rather than polling it
+    let busy = EdgeBusyPin::new(busy)?;
+    Ok((spi, busy, dc, reset))
+}
+
+/// Whether the panel on `spi_path` works at `speed_hz`: a pseudo-random
+/// pattern is written into its black/white RAM and read back over 3-wire SPI.
+/// Nothing is refreshed, so the panel keeps showing what it was.
+pub fn probe_spi_speed(spi_path: impl AsRef<Path>, speed_hz: u32, pins: &Pins) -> io::Result<bool> {
+    // 3-wire mode lets the controller drive MOSI for the read
+    let mode = SpiModeFlags::SPI_MODE_0 | SpiModeFlags::SPI_3WIRE;
+    let mut inky = LinuxInkyPhat::open(spi_path.as_ref(), speed_hz, mode, pins)?;
+    let mut pattern = [0u8; BUFFER_SIZE];
+    let mut seed = 0x2545_f491_u32 ^ speed_hz;
+    for byte in &mut pattern {
+        // xorshift32
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        *byte = seed as u8;
+    }
+    let mut readback = [0u8; BUFFER_SIZE];
+    let verified = inky.init(&mut Delay {}).is_ok()
+        && inky.update_bw(&pattern).is_ok()
+        && inky.read_bw(&mut readback, |spi, buffer| spi.0.read_exact(buffer)).is_ok()
+        && readback == pattern;
+    Ok(verified)
+}
+
+/// The fastest of `SPI_SPEEDS` that passes `probe_spi_speed`, stopping at the
+/// first that fails; `None` if even the slowest does.
+pub fn tune_spi_speed(spi_path: impl AsRef<Path>, pins: &Pins) -> io::Result<Option<u32>> {
+    let mut best = None;
+    for &speed in SPI_SPEEDS {
+        if !probe_spi_speed(spi_path.as_ref(), speed, pins)? {
+            break;
+        }
+        best = Some(speed);
+    }
+    Ok(best)
+}
+
+// Learned clock rates, by SPI device path
+fn load_spi_state(path: &Path) -> BTreeMap<String, u32> {
+    fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save_spi_state(path: &Path, speeds: &BTreeMap<String, u32>) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_vec_pretty(speeds).map_err(io::Error::other)?)
+}
+
+/// A sysfs pin that stays exported for as long as this handle lives.
+///
+/// Unexporting on drop means an interrupted run doesn't leave lines claimed
+/// and configured behind it.
+pub struct ExportedPin(Pin);
+
+impl ExportedPin {
+    /// Exports BCM pin `number` and sets its direction.
+    pub fn export(number: u64, direction: Direction) -> Result<Self, sysfs_gpio::Error> {
+        let pin = Pin::new(number);
+        pin.export()?;
+        // Wrap before configuring so a failure below still unexports
+        let pin = ExportedPin(pin);
+        pin.set_direction(direction)?;
+        Ok(pin)
+    }
+
+    /// Gives up ownership of the line without unexporting it.
+    pub fn into_inner(self) -> Pin {
+        let pin = Pin::new(self.0.get_pin());
+        std::mem::forget(self);
+        pin
+    }
+}
+
+/// Keeps transfers to the devices sharing the SPI bus from landing in each
+/// other's chip-select windows.
+///
+/// spidev serialises transfers on its own, and asserts the CE line of each
+/// device only around that device's transfers. A panel whose CS is a GPIO
+/// toggled by the driver is selected for a whole command and its data, over
+/// several transfers, and anything the kernel slots in between would be
+/// clocked into its RAM too. `ChipSelect` holds `SPI_BUS` while it is low, and
+/// drivers for other devices on the bus (`sensors::mcp3008`) hold it around
+/// theirs.
+pub struct SpiBus {
+    held: Mutex<bool>,
+    freed: Condvar,
+}
+
+/// The Pi's SPI0, shared by the panel and any other device on it.
+pub static SPI_BUS: SpiBus = SpiBus {
+    held: Mutex::new(false),
+    freed: Condvar::new(),
+};
+
+impl SpiBus {
+    /// Waits up to `timeout` for the bus; `None` if it stayed taken.
+    pub fn hold(&self, timeout: Duration) -> Option<BusHold<'_>> {
+        self.acquire(timeout).then_some(BusHold(self))
+    }
+
+    fn acquire(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut held = self.held.lock().unwrap_or_else(PoisonError::into_inner);
+        while *held {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return false;
+            }
+            held = self.freed.wait_timeout(held, left).unwrap_or_else(PoisonError::into_inner).0;
+        }
+        *held = true;
+        true
+    }
+
+    fn release(&self) {
+        *self.held.lock().unwrap_or_else(PoisonError::into_inner) = false;
+        self.freed.notify_one();
+    }
+}
+
+/// The bus, held until dropped.
+pub struct BusHold<'a>(&'a SpiBus);
+
+impl Drop for BusHold<'_> {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// A panel's chip select on a sysfs pin, holding `SPI_BUS` while asserted.
+pub struct ChipSelect {
+    pin: ExportedPin,
+    holding: bool,
+}
+
+impl ChipSelect {
+    pub fn export(number: u64) -> Result<Self, sysfs_gpio::Error> {
+        Ok(ChipSelect {
+            pin: ExportedPin::export(number, Direction::Out)?,
+            holding: false,
+        })
+    }
+}
+
+impl OutputPin for ChipSelect {
+    type Error = sysfs_gpio::Error;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        // Bounded, so a bus that is never let go fails this transfer rather than hanging it
+        if !self.holding {
+            if !SPI_BUS.acquire(CS_BUS_TIMEOUT) {
+                return Err(sysfs_gpio::Error::Unexpected("SPI bus busy".to_string()));
+            }
+            self.holding = true;
+        }
+        let lowered = self.pin.set_low();
+        if lowered.is_err() {
+            SPI_BUS.release();
+            self.holding = false;
+        }
+        lowered
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        let released = self.pin.set_high();
+        if self.holding {
+            SPI_BUS.release();
+            self.holding = false;
+        }
+        released
+    }
+}
+
+impl Drop for ChipSelect {
+    fn drop(&mut self) {
+        if self.holding {
+            SPI_BUS.release();
+        }
+    }
+}
+
+/// Pulses the panel's RESET line from outside the driver that owns it, for a
+/// controller stuck holding BUSY: it stops what it was doing and lets BUSY go.
+/// The line must already be exported; the panel needs an init afterwards.
+pub fn pulse_reset(pins: &Pins) -> Result<(), sysfs_gpio::Error> {
+    let pin = Pin::new(pins.reset);
+    pin.set_value(0)?;
+    thread::sleep(Duration::from_millis(100));
+    pin.set_value(1)
+}
+
+/// Unexports `pins` by number, for a process about to exit with the
+/// `ExportedPin`s still held by threads that will never drop them. Lines
+/// that aren't exported (CS under `with_hardware_cs`) are skipped.
+pub fn unexport(pins: &Pins) {
+    for number in [pins.cs, pins.busy, pins.dc, pins.reset] {
+        let pin = Pin::new(number);
+        if pin.is_exported() {
+            let _ = pin.unexport();
+        }
+    }
+}
+
+impl Deref for ExportedPin {
+    type Target = Pin;
+
+    fn deref(&self) -> &Pin {
+        &self.0
+    }
+}
+
+impl Drop for ExportedPin {
+    fn drop(&mut self) {
+        let _ = self.0.unexport();
+    }
+}
+
+impl OutputPin for ExportedPin {
+    type Error = sysfs_gpio::Error;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.set_high()
+    }
+}
+
+impl InputPin for ExportedPin {
+    type Error = sysfs_gpio::Error;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self.0.is_high()
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        self.0.is_low()
+    }
+}
+
+/// BUSY input that sleeps in the kernel (epoll on the sysfs edge interrupt)
+/// instead of being sampled every 10 ms.
+///
+/// The driver's `busy_wait` loop only asks "is BUSY still high?"; this pin
+/// answers that question by blocking until the line falls (or `timeout_ms`
+/// passes) whenever it is currently high. A full refresh then costs one or two
+/// wakeups rather than ~1500.
+///
+/// `is_low` stays a plain, non-blocking read, so `InkyPhat::poll_refresh` and
+/// the health self-check never stall on it. With `BusyPolarity::ActiveLow`
+/// the driver only ever calls `is_low`, so such panels are polled instead.
+pub struct EdgeBusyPin {
+    pin: ExportedPin,
+    poller: RefCell<PinPoller>,
+    timeout_ms: isize,
+}
+
+impl EdgeBusyPin {
+    /// Arms falling-edge interrupts on an exported input pin.
+    pub fn new(pin: ExportedPin) -> Result<Self, sysfs_gpio::Error> {
+        pin.set_edge(Edge::FallingEdge)?;
+        let poller = pin.get_poller()?;
+        Ok(EdgeBusyPin {
+            pin,
+            poller: RefCell::new(poller),
+            timeout_ms: 1000,
+        })
+    }
+
+    /// Longest single sleep before the level is re-read, as a guard against missed edges.
+    pub fn with_timeout_ms(mut self, timeout_ms: u32) -> Self {
+        self.timeout_ms = timeout_ms as isize;
+        self
+    }
+
+    pub fn into_inner(self) -> ExportedPin {
+        self.pin
+    }
+}
+
+impl InputPin for EdgeBusyPin {
+    type Error = sysfs_gpio::Error;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        if self.pin.get_value()? != 0 {
+            // Still busy: wait for the falling edge, then report whatever the line says now
+            self.poller.borrow_mut().poll(self.timeout_ms)?;
+        }
+        Ok(self.pin.get_value()? != 0)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(self.pin.get_value()? == 0)
+    }
+}
+
+/// A push button on a GPIO line, waited on with edge interrupts.
+pub struct Button {
+    pin: ExportedPin,
+    poller: PinPoller,
+    active_high: bool,
+}
+
+impl Button {
+    /// Exports BCM pin `number` as an input. Buttons normally pull the line to
+    /// ground (`active_high == false`) against a pull-up.
+    pub fn open(number: u64, active_high: bool) -> Result<Self, sysfs_gpio::Error> {
+        let pin = ExportedPin::export(number, Direction::In)?;
+        pin.set_edge(if active_high { Edge::RisingEdge } else { Edge::FallingEdge })?;
+        let poller = pin.get_poller()?;
+        Ok(Button {
+            pin,
+            poller,
+            active_high,
+        })
+    }
+
+    pub fn is_pressed(&self) -> Result<bool, sysfs_gpio::Error> {
+        Ok((self.pin.get_value()? != 0) == self.active_high)
+    }
+
+    /// Blocks until the button is pressed, ignoring contact bounce and
+    /// presses shorter than `DEBOUNCE`.
+    pub fn wait_press(&mut self) -> Result<(), sysfs_gpio::Error> {
+        loop {
+            if self.poller.poll(-1)?.is_some() {
+                thread::sleep(DEBOUNCE);
+                if self.is_pressed()? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
@@ -1,50 +1,1162 @@
-mod inky_driver;
-
 extern crate linux_embedded_hal;
-use linux_embedded_hal::spidev::{SpiModeFlags, SpidevOptions};
-use linux_embedded_hal::sysfs_gpio::Direction;
+use std::io::{BufReader, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, mpsc};
+use std::thread;
+use std::time::Duration;
+
 use linux_embedded_hal::Delay;
-use linux_embedded_hal::{Pin, Spidev};
+use rust_raspi::alerts;
+use rust_raspi::calibration::{self, Calibration};
+use rust_raspi::config::{Config, ConfigError, ScreenConfig};
+use rust_raspi::control::{self, ControlCommand};
+use rust_raspi::crash;
+use rust_raspi::dbus;
+use rust_raspi::daemon::Scheduler;
+use rust_raspi::epd::EpdController;
+use rust_raspi::export;
+use rust_raspi::frame_store::{self, FrameStore};
+use rust_raspi::framebuffer::{Framebuffer, Rotation};
+use rust_raspi::gray::GrayFramebuffer;
+use rust_raspi::health::{self, Check, HealthReport};
+use rust_raspi::http::{self, Request, Response};
+use rust_raspi::images::{self, Placement};
+use rust_raspi::inky_test;
+use rust_raspi::input::Buttons;
+use rust_raspi::inky_driver::{InkyError, RefreshStats, BUFFER_SIZE};
+use rust_raspi::linux::{self, Button, DEFAULT_SPI_SPEED, DEFAULT_SPI_STATE_PATH, LinuxInkyPhat, SPI_BUS};
+use rust_raspi::metrics;
+use rust_raspi::panel::{self, Described, PanelDescriptor, Pins};
+use rust_raspi::pipe::{self, PipeOptions};
+#[cfg(feature = "i2c")]
+use rust_raspi::power::{pijuice, rtc};
+use rust_raspi::push::{Inbox, Push, PushRequest};
+use rust_raspi::record::{self, Recorder, Recording};
+use rust_raspi::refresh_policy::{Guarded, RefreshPolicy};
+use rust_raspi::retry::{RetryPolicy, Retrying};
+use rust_raspi::screens::{self, RenderContext};
+use rust_raspi::script::Script;
+use rust_raspi::sensors::mcp3008;
+use rust_raspi::shutdown;
+use rust_raspi::slideshow::{Slideshow, SlideshowOptions};
+use rust_raspi::splash;
+use rust_raspi::terminal::TerminalPanel;
+use rust_raspi::text;
+use rust_raspi::thermal::{Temperatures, Throttle};
+#[cfg(feature = "ttf")]
+use rust_raspi::ttf;
+use rust_raspi::watchdog::{self, Heartbeat, Notifier};
+
+type Display = FrameStore<Recorder<Guarded<Retrying<Described<LinuxInkyPhat>>>>>;
+
+const SPI_PATH: &str = "/dev/spidev0.1";
+// How long the start-up splash stays before the daemon's first page
+const SPLASH_HOLD: Duration = Duration::from_secs(60);
+// How often the refresh loop looks up while a push without a TTL is showing
+const IDLE_WAIT: Duration = Duration::from_secs(3600);
+// How long the panic hook waits for the SPI bus before giving up on blanking the panel
+const BLANK_BUS_WAIT: Duration = Duration::from_secs(1);
+// How long BUSY may stay set when the panel has no calibration to go by
+const BUSY_TIMEOUT: Duration = Duration::from_secs(120);
+// Black/white/black flashes POST /deghost does unless asked for more, and the most it will do
+const DEGHOST_CYCLES: u8 = 2;
+const MAX_DEGHOST_CYCLES: u8 = 10;
+
+// The daemon's last-frame file, which the panic hook must invalidate when it blanks the panel
+static FRAME_FILE: OnceLock<PathBuf> = OnceLock::new();
+// The pins of the panel that was opened, for the panic hook to blank it through
+static PANEL_PINS: OnceLock<Pins> = OnceLock::new();
+
+const USAGE: &str = "usage: rust_raspi [slideshow <dir> [--interval SECS] [--min-interval SECS] [--shuffle] [--gray] [--placement FIT[,ANCHOR[,COLOUR]]]]
+       rust_raspi daemon [--config FILE] [--listen ADDR]
+       rust_raspi once [--config FILE] [--page NAME] [--power-off]
+       rust_raspi calibrate [--config FILE]
+       rust_raspi script FILE [--terminal]
+       rust_raspi pipe [--interval SECS] [--full-every N] [--font POINTS]
+       rust_raspi preview [--config FILE] [PAGE...]
+       rust_raspi export STEM [--config FILE] (PAGE|IMAGE)
+       rust_raspi golden DIR [--tolerance PIXELS]
+       rust_raspi record FILE (slideshow|daemon|script) ...
+       rust_raspi replay FILE [--speed FACTOR] [--terminal | --png DIR]
+       rust_raspi panels [DIR]";
 
 fn main() -> Result<(), std::io::Error> {
-    // 1. SPI Setup
-    let mut spi = Spidev::open("/dev/spidev0.1").expect("SPI device");
-    let options = SpidevOptions::new()
-        .bits_per_word(8)
-        .max_speed_hz(4_000_000)
-        .mode(SpiModeFlags::SPI_MODE_0)
-        .build();
-    spi.configure(&options).expect("SPI configuration");
-    // 2. GPIO Setup (Using BCM pin numbers)
-    let cs = Pin::new(8);
-    let busy = Pin::new(17);
-    let dc = Pin::new(22);
-    let reset = Pin::new(27);
-    
-    // We need to 'export' and set directions for the pins
-    cs.export().expect("Export CS pin");
-    busy.export().expect("Export BUSY pin");
-    dc.export().expect("Export DC pin");
-    reset.export().expect("Export RESET pin");
-    cs.set_direction(Direction::Out).expect("Set CS direction");
-    busy.set_direction(Direction::In).expect("Set BUSY direction");
-    dc.set_direction(Direction::Out).expect("Set DC direction");
-    reset.set_direction(Direction::Out).expect("Set RESET direction");
-    // 3. Create our Driver
-    let mut inky = inky_driver::InkyPhat::new(spi, cs, busy, dc, reset);
+    crash::install_panic_hook(crash::DEFAULT_REPORT_DIR, blank_panel);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => demo(),
+        Some("slideshow") => slideshow(&args[1..], None),
+        Some("daemon") => daemon(&args[1..], None),
+        Some("once") => once(&args[1..]),
+        Some("calibrate") => calibrate(&args[1..]),
+        Some("script") => script(&args[1..], None),
+        Some("pipe") => pipe(&args[1..]),
+        Some("preview") => preview(&args[1..]),
+        Some("export") => export(&args[1..]),
+        Some("golden") => golden(&args[1..]),
+        Some("record") => match (args.get(1), args.get(2).map(String::as_str)) {
+            (Some(file), Some("slideshow")) => slideshow(&args[3..], Some(Path::new(file))),
+            (Some(file), Some("daemon")) => daemon(&args[3..], Some(Path::new(file))),
+            (Some(file), Some("script")) => script(&args[3..], Some(Path::new(file))),
+            _ => Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+        },
+        Some("replay") => replay(&args[1..]),
+        Some("panels") => panels(args.get(1).map_or(Path::new(panel::DEFAULT_DIR), Path::new)),
+        Some(_) => Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+    }
+}
+
+// Opens the panel (as described by `panel`, if given) held to `policy` and retrying
+// errors as `retry` says, recording every refresh into `record` if given and
+// remembering the last frame in `last_frame`
+fn open_display(
+    record: Option<&Path>,
+    panel: Option<PanelDescriptor>,
+    policy: RefreshPolicy,
+    retry: RetryPolicy,
+    last_frame: Option<PathBuf>,
+) -> Result<Display, std::io::Error> {
+    let pins = panel.as_ref().map_or(Pins::INKY_PHAT, PanelDescriptor::pins);
+    let _ = PANEL_PINS.set(pins);
+    let inky = Described::new(LinuxInkyPhat::with_tuned_speed(SPI_PATH, DEFAULT_SPI_STATE_PATH, &pins)?, panel);
+    let inky = Retrying::new(inky, retry);
+    let inky = Guarded::new(inky, policy);
+    let inky = match record {
+        Some(path) => Recorder::create(inky, path)?,
+        None => Recorder::new(inky),
+    };
+    Ok(FrameStore::new(inky, last_frame))
+}
+
+// The descriptor the config's `panel` names, if any
+fn configured_panel(config: &Config) -> Result<Option<PanelDescriptor>, ConfigError> {
+    match &config.panel {
+        Some(panel) => PanelDescriptor::find(panel, Path::new(panel::DEFAULT_DIR)).map(Some).map_err(ConfigError::Invalid),
+        None => Ok(None),
+    }
+}
+
+// Draws what ProFont can't in the config's fallback font; pages still draw without one
+#[cfg(feature = "ttf")]
+fn use_fallback_font(config: &Config) {
+    if let Some(path) = &config.fallback_font {
+        match ttf::load_cached(path) {
+            Ok(font) => text::set_fallback(Some(font)),
+            Err(err) => eprintln!("Fallback font: {err}"),
+        }
+    }
+}
+
+// What calibrations are filed under: the descriptor's name, or the stock pHAT
+fn panel_name(inky: &Display) -> &str {
+    inky.panel().map_or("inky-phat", |panel| panel.name.as_str())
+}
+
+// Measures the panel, bypassing the refresh policy so its limits don't end up
+// in the numbers; the panel is left white and initialised
+fn measure(inky: &mut Display, delay: &mut Delay) -> Result<Calibration, std::io::Error> {
+    let name = panel_name(inky).to_string();
+    let measured = Calibration::measure(&mut ***inky, &name, delay);
+    // Whatever the store thought was showing, it's white now
+    inky.forget();
+    measured.map_err(Error::other)
+}
+
+// The measurements kept in `path` for this panel, or new ones if there are none
+fn calibrated(inky: &mut Display, path: &Path, delay: &mut Delay) -> Result<Calibration, std::io::Error> {
+    if let Some(calibration) = Calibration::load(path, panel_name(inky)) {
+        return Ok(calibration);
+    }
+    println!("Calibrating {}", panel_name(inky));
+    let calibration = measure(inky, delay)?;
+    if let Err(err) = calibration.save(path) {
+        eprintln!("Could not save calibration to {}: {err}", path.display());
+    }
+    Ok(calibration)
+}
+
+// Prints the timings of a refresh, for `log_refreshes`
+fn log_refresh(stats: Option<RefreshStats>) {
+    let Some(stats) = stats else {
+        return;
+    };
+    let init = stats.init.map_or_else(|| "-".to_string(), |init| format!("{init:.2?}"));
+    eprintln!("Refresh: init {init}, transfer {:.2?}, busy {:.2?}", stats.transfer, stats.busy);
+}
+
+// Sleeps the panel if asked, then closes SPI and unexports the pins
+fn close_display(inky: Display, sleep: bool) -> Result<(), std::io::Error> {
+    inky.into_inner().into_inner().into_inner().into_inner().into_inner().release(sleep).map_err(Error::other)
+}
+
+// Called from the panic hook: whoever panicked may still own the display, so
+// open a fresh handle to the same pins and ignore every error along the way
+fn blank_panel() {
+    // A thread that died holding the bus never lets it go, and the process must still exit
+    if SPI_BUS.hold(BLANK_BUS_WAIT).is_none() {
+        eprintln!("SPI bus still held; leaving the panel as it is");
+        return;
+    }
+    let pins = PANEL_PINS.get().copied().unwrap_or(Pins::INKY_PHAT);
+    let Ok(mut inky) = LinuxInkyPhat::with_pins(SPI_PATH, DEFAULT_SPI_SPEED, &pins) else {
+        return;
+    };
+    let mut delay = Delay {};
+    let cleared = inky.init(&mut delay).is_ok() && inky.clear(&mut delay).is_ok();
+    let _ = inky.release(cleared);
+    if let Some(path) = FRAME_FILE.get() {
+        frame_store::invalidate(path);
+    }
+}
+
+fn demo() -> Result<(), std::io::Error> {
+    let mut inky = open_display(None, None, RefreshPolicy::default(), RetryPolicy::default(), None)?;
     let mut delay = Delay {};
     // 4. Initialization
     println!("Initializing...");
-    inky.init(&mut delay).expect("Init failed");
+    inky.init(&mut delay).map_err(Error::other)?;
     // 5. Create Buffers (all white for now)
-    let bw_buffer = [0xFFu8; 2756];
-    let red_buffer = [0x00u8; 2756];
+    let bw_buffer = [0xFFu8; BUFFER_SIZE];
+    let red_buffer = [0x00u8; BUFFER_SIZE];
     // 6. Draw!
     println!("Sending pixels...");
     inky.update_bw(&bw_buffer).expect("BW update failed");
     inky.update_red(&red_buffer).expect("Red update failed");
     println!("Refreshing display...");
     inky.display_refresh(&mut delay).expect("Refresh failed");
+    close_display(inky, true)?;
     println!("Done!");
     Ok(())
-}
\ No newline at end of file
+}
+
+fn slideshow(args: &[String], record: Option<&Path>) -> Result<(), std::io::Error> {
+    let mut dir = None;
+    let mut options = SlideshowOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--interval" => options.interval = parse_secs(args.next())?,
+            "--min-interval" => options.min_refresh_interval = parse_secs(args.next())?,
+            "--shuffle" => options.shuffle = true,
+            "--gray" => options.gray = true,
+            "--placement" => {
+                let spec = args.next().ok_or_else(|| Error::new(ErrorKind::InvalidInput, USAGE))?;
+                options.placement = spec.parse().map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+            }
+            path if dir.is_none() => dir = Some(PathBuf::from(path)),
+            _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+        }
+    }
+    let dir = dir.ok_or_else(|| Error::new(ErrorKind::InvalidInput, USAGE))?;
+
+    if options.interval < options.min_refresh_interval {
+        println!("Interval raised to {}s to protect the panel", options.min_refresh_interval.as_secs());
+    }
+    let policy = RefreshPolicy {
+        min_full_interval: options.min_refresh_interval.as_secs(),
+        ..RefreshPolicy::default()
+    };
+    let gray = options.gray;
+    let mut show = Slideshow::new(dir, options);
+
+    let mut inky = open_display(record, None, policy, RetryPolicy::default(), None)?;
+    let mut delay = Delay {};
+    let mut fb = Framebuffer::for_panel(&inky, Rotation::Rotate90);
+    let mut gray_fb = GrayFramebuffer::for_panel(&***inky, Rotation::Rotate90);
+    let mut renderer = images::ImageRenderer::new(fb.width());
+    inky.init(&mut delay).map_err(Error::other)?;
+    shutdown::install(|| {})?;
+
+    loop {
+        if shutdown::wait(show.until_next()) {
+            return close_display(inky, true);
+        }
+        let Some(path) = show.next_image()? else {
+            return Err(Error::new(ErrorKind::NotFound, "no images in slideshow directory"));
+        };
+        let img = match images::load(&path) {
+            Ok(img) => img,
+            Err(err) => {
+                eprintln!("Skipping {}: {err}", path.display());
+                continue;
+            }
+        };
+        println!("Showing {}", path.display());
+        if gray {
+            // Straight to the panel: the slideshow keeps to its own interval, and a grey
+            // frame is neither recorded nor stored
+            renderer.draw_gray(&mut gray_fb, &img, &show.placement(&path));
+            gray_fb.show(&mut ***inky, &mut delay).expect("Refresh failed");
+            inky.forget();
+            show.mark_refreshed();
+            continue;
+        }
+        renderer.draw_placed(&mut fb, &img, &show.placement(&path));
+        inky.show(&fb, &mut delay).expect("Refresh failed");
+        show.mark_refreshed();
+        crash::remember_frame(&fb);
+    }
+}
+
+fn daemon(args: &[String], record: Option<&Path>) -> Result<(), std::io::Error> {
+    let mut config_path = None;
+    let mut listen = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--config", Some(path)) => config_path = Some(PathBuf::from(path)),
+            ("--listen", Some(addr)) => listen = Some(addr.clone()),
+            _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+        }
+    }
+    let mut config = Config::load_or_default(config_path.as_deref())?;
+    #[cfg(feature = "ttf")]
+    use_fallback_font(&config);
+    if let Some(listen) = listen {
+        config.listen = listen;
+    }
+    let mut scheduler = Scheduler::new(&config)?;
+
+    if let Some(path) = &config.last_frame {
+        let _ = FRAME_FILE.set(path.clone());
+    }
+    let panel = configured_panel(&config)?;
+    if let Some(adc) = &config.battery_adc {
+        let cs = panel.as_ref().map_or(Pins::INKY_PHAT, PanelDescriptor::pins).cs;
+        adc.check(Path::new(SPI_PATH), cs).map_err(ConfigError::Invalid)?;
+    }
+    // Waiting out the policy would hold the panel's lock the whole time; the loop below reschedules instead
+    let policy = RefreshPolicy {
+        wait: false,
+        ..config.refresh_policy.clone()
+    };
+    let mut inky = open_display(record, panel, policy, config.retry.clone(), config.last_frame.clone())?;
+    inky.set_busy_polarity(config.busy_polarity);
+    inky.set_colors(config.colors);
+    let mut delay = Delay {};
+    let mut fb = Framebuffer::for_panel(&inky, Rotation::Rotate90);
+    inky.init(&mut delay).map_err(Error::other)?;
+    let calibration = match &config.calibration {
+        Some(path) => Some(calibrated(&mut inky, path, &mut delay)?),
+        None => None,
+    };
+    if let Some(calibration) = &calibration {
+        scheduler.calibrate(calibration);
+    }
+    if inky.panel().is_none() {
+        inky.set_border(config.border).map_err(Error::other)?;
+    }
+    let stall_limit = calibration.as_ref().map(Calibration::stall_limit);
+    // Rather than wait for ever on a stuck BUSY line, fail the refresh so it's retried after a re-init
+    inky.set_busy_timeout(Some(stall_limit.unwrap_or(BUSY_TIMEOUT)));
+    // Leave the splash up for a while before the first real page
+    let mut wait = Duration::ZERO;
+    if let Some(screen) = &config.splash.start {
+        wait = match show_screen(&mut inky, &mut fb, screen, &config.placement, false) {
+            Ok(()) => SPLASH_HOLD,
+            // Too soon after the last run's final refresh: no splash, and the first page once it's allowed
+            Err(InkyError::RateLimited { retry_after }) => retry_after,
+            Err(err) => {
+                metrics::record_panel_error(&err);
+                return Err(Error::other(err));
+            }
+        };
+    }
+    let pins = inky.panel().map_or(Pins::INKY_PHAT, PanelDescriptor::pins);
+    let display = Arc::new(Mutex::new(inky));
+    let inbox = Arc::new(Inbox::default());
+    // A signal stops the loop below at its next look, once any refresh under way is done
+    {
+        let inbox = Arc::clone(&inbox);
+        shutdown::install(move || inbox.wake())?;
+    }
+
+    if let Some(adc) = &config.battery_adc {
+        mcp3008::monitor(adc)?;
+    }
+    // Set once the battery runs low, which stops the loop below to halt the Pi
+    let low_battery = Arc::new(AtomicBool::new(false));
+    #[cfg(feature = "i2c")]
+    if let Some(hat) = &config.pijuice {
+        let low_battery = Arc::clone(&low_battery);
+        let inbox = Arc::clone(&inbox);
+        pijuice::monitor(hat, move || {
+            low_battery.store(true, Ordering::SeqCst);
+            inbox.wake();
+        })?;
+    }
+
+    if let Some(number) = config.alerts.button {
+        if config.alerts.mqtt_topic.is_some() && config.mqtt.is_none() {
+            return Err(ConfigError::Invalid("[alerts] mqtt_topic needs an [mqtt] broker".to_string()).into());
+        }
+        let mut button = Button::open(number, config.alerts.active_high).map_err(Error::other)?;
+        let inbox = Arc::clone(&inbox);
+        let (alerts, broker) = (config.alerts.clone(), config.mqtt.clone());
+        thread::spawn(move || {
+            loop {
+                if let Err(err) = button.wait_press() {
+                    eprintln!("Acknowledge button failed: {err}");
+                    return;
+                }
+                if let Some(alert) = inbox.acknowledge()
+                    && let Err(err) = alerts::acknowledge(&alerts, broker.as_ref(), &alert)
+                {
+                    eprintln!("Acknowledgement failed: {err}");
+                }
+            }
+        });
+    }
+
+    // Page turns from the buttons, picked up by the refresh loop below
+    let (turns, turned) = mpsc::channel();
+    if !config.buttons.pins.is_empty() {
+        let buttons = &config.buttons;
+        if buttons.actions.len() < buttons.pins.len() {
+            return Err(ConfigError::Invalid("[buttons] needs an action for every pin".to_string()).into());
+        }
+        if config.alerts.button.is_some_and(|pin| buttons.pins.contains(&pin)) {
+            return Err(ConfigError::Invalid("[buttons] pins include the alert button".to_string()).into());
+        }
+        let actions = buttons.actions.clone();
+        let inbox = Arc::clone(&inbox);
+        Buttons::open(&buttons.pins, buttons.active_high)
+            .map_err(Error::other)?
+            .on_press(move |press| {
+                if turns.send((press.index, actions[press.index])).is_ok() {
+                    inbox.wake();
+                }
+            });
+    }
+
+    // Set by the control socket's `sleep`, cleared by its `wake`; the loop below leaves the panel alone meanwhile
+    let asleep = Arc::new(AtomicBool::new(false));
+    if let Some(path) = config.control_socket.clone() {
+        let display = Arc::clone(&display);
+        let inbox = Arc::clone(&inbox);
+        let asleep = Arc::clone(&asleep);
+        let push_ttl = config.push_ttl;
+        println!("Control socket at {}", path.display());
+        thread::spawn(move || {
+            let served = control::serve(&path, move |command| control(&display, &inbox, &asleep, push_ttl, command));
+            if let Err(err) = served {
+                eprintln!("Control socket failed: {err}");
+            }
+        });
+    }
+
+    // Refreshes are announced on the bus once it's connected; a daemon that can't get its name carries on without
+    let mut signals = None;
+    if config.dbus {
+        match dbus::Service::connect() {
+            Ok(service) => {
+                signals = Some(service.signals());
+                let display = Arc::clone(&display);
+                let inbox = Arc::clone(&inbox);
+                let asleep = Arc::clone(&asleep);
+                let push_ttl = config.push_ttl;
+                println!("Serving {} on the system bus", dbus::NAME);
+                thread::spawn(move || {
+                    let served = service.serve(move |command| control(&display, &inbox, &asleep, push_ttl, command));
+                    if let Err(err) = served {
+                        eprintln!("D-Bus connection failed: {err}");
+                    }
+                });
+            }
+            Err(err) => eprintln!("D-Bus unavailable: {err}"),
+        }
+    }
+
+    println!("Listening on {}", config.listen);
+    let server = {
+        let display = Arc::clone(&display);
+        let inbox = Arc::clone(&inbox);
+        let listen = config.listen.clone();
+        let push_ttl = config.push_ttl;
+        thread::spawn(move || {
+            http::serve(listen.as_str(), |request| {
+                match (request.method.as_str(), request.path.as_str()) {
+                    ("GET", "/healthz") => healthz(&display, request, stall_limit),
+                    ("GET", "/status") => status(),
+                    ("GET", "/frame.png") => frame(&display),
+                    ("GET", "/metrics") => Response {
+                        content_type: "text/plain; version=0.0.4",
+                        ..Response::text(200, metrics::prometheus())
+                    },
+                    ("POST", "/push") => push(&inbox, request, push_ttl),
+                    ("DELETE", "/push") if inbox.clear() => Response::text(200, "cleared\n"),
+                    ("POST", "/deghost") => deghost(&display, request),
+                    _ => Response::not_found(),
+                }
+            })
+        })
+    };
+
+    // Under systemd, say the daemon is up and keep its watchdog fed while cycles keep finishing;
+    // a cycle that stops finishing gets the panel reset under it either way
+    let heartbeat = Arc::new(Heartbeat::default());
+    let notifier = match Notifier::from_env() {
+        Ok(notifier) => notifier,
+        Err(err) => {
+            eprintln!("Could not open systemd's notification socket: {err}");
+            None
+        }
+    };
+    if let Some(notifier) = &notifier
+        && let Err(err) = notifier.notify("READY=1")
+    {
+        eprintln!("Could not notify systemd: {err}");
+    }
+    watchdog::watch(notifier, Arc::clone(&heartbeat), Duration::from_secs(config.stall_timeout), move || {
+        linux::pulse_reset(&pins).map_err(Error::other)
+    });
+
+    // Keep refreshing for as long as the API is up. A push wakes the loop
+    // straight away and holds the schedule off until it expires.
+    let (mut seen, _) = inbox.current();
+    let mut shown = None;
+    // Refreshes already counted in the metrics
+    let mut counted = 0;
+    let mut throttle = Throttle::new(&config.thermal);
+    let mut panel_sensor = throttle.wants_panel();
+    while !server.is_finished() {
+        inbox.wait(seen, wait);
+        if shutdown::requested() || low_battery.load(Ordering::SeqCst) {
+            break;
+        }
+        for (button, turn) in turned.try_iter() {
+            scheduler.press(button, turn);
+        }
+        if asleep.load(Ordering::SeqCst) {
+            wait = IDLE_WAIT;
+            continue;
+        }
+        heartbeat.begin();
+        let (generation, pushed) = inbox.current();
+        seen = generation;
+        let mut inky = display.lock().unwrap_or_else(PoisonError::into_inner);
+        match pushed {
+            Some(push) => {
+                let remaining = push.remaining().unwrap_or(IDLE_WAIT);
+                wait = remaining;
+                if shown != Some(generation) {
+                    match show_screen(&mut *inky, &mut fb, &push.screen, &config.placement, push.is_alert()) {
+                        Ok(()) => shown = Some(generation),
+                        // Still waiting to be shown, so try it again once the policy allows
+                        Err(InkyError::RateLimited { retry_after }) => wait = retry_after.min(remaining),
+                        Err(err) => {
+                            metrics::record_panel_error(&err);
+                            panic!("Refresh failed: {err}")
+                        }
+                    }
+                }
+            }
+            None => {
+                shown = None;
+                wait = match scheduler.tick(&mut *inky, &mut fb, &mut delay) {
+                    Ok(wait) => {
+                        crash::remember_frame(&fb);
+                        let temperatures = Temperatures {
+                            soc: throttle.read_soc(),
+                            panel: panel_temperature(&mut inky, &mut panel_sensor),
+                        };
+                        throttle.stretch(wait, temperatures)
+                    }
+                    // Only when the policy says not to wait: skip this page and try again later
+                    Err(InkyError::RateLimited { retry_after }) => retry_after,
+                    Err(err) => {
+                        metrics::record_panel_error(&err);
+                        panic!("Refresh failed: {err}")
+                    }
+                };
+            }
+        }
+        // Pushes refresh too, so count after either
+        if inky.refresh_count() != counted {
+            counted = inky.refresh_count();
+            if let Some(stats) = inky.last_refresh() {
+                metrics::record_refresh(stats, stall_limit);
+            }
+            if config.log_refreshes {
+                log_refresh(inky.last_refresh());
+            }
+            if let Some(signals) = &signals
+                && let Err(err) = signals.refreshed(counted)
+            {
+                eprintln!("PropertiesChanged failed: {err}");
+            }
+        }
+        // The watchdog reset the panel under a stuck cycle, which leaves it to be set up again
+        if heartbeat.reinit_wanted() {
+            metrics::record_reinit();
+            let reinit = inky.init(&mut delay);
+            // Whatever the store thinks, the next frame goes whole
+            inky.forget();
+            if let Err(err) = &reinit {
+                metrics::record_panel_error(err);
+                eprintln!("Re-init after a stuck refresh failed: {err}");
+            }
+            heartbeat.reinit_done(reinit.is_ok());
+        }
+        heartbeat.end();
+    }
+    // The server never returns of its own accord unless it failed
+    let low_battery = low_battery.load(Ordering::SeqCst);
+    let served = if shutdown::requested() || low_battery {
+        Ok(())
+    } else {
+        server
+            .join()
+            .unwrap_or_else(|_| Err(Error::other("HTTP server panicked")))
+    };
+
+    // Held until exit, so nothing else gets at the panel after this
+    let mut inky = display.lock().unwrap_or_else(PoisonError::into_inner);
+    #[cfg(feature = "i2c")]
+    let stop = match &config.pijuice {
+        Some(hat) if low_battery => Some(&hat.screen),
+        _ => config.splash.stop.as_ref(),
+    };
+    #[cfg(not(feature = "i2c"))]
+    let stop = config.splash.stop.as_ref();
+    if let Some(screen) = stop
+        && let Err(err) = show_screen(&mut *inky, &mut fb, screen, &config.placement, false)
+    {
+        eprintln!("Stop screen not shown: {err}");
+    }
+    if let Err(err) = inky.sleep() {
+        eprintln!("Sleep failed: {err}");
+    }
+    // Other threads still hold the display, so it is never dropped to unexport the pins itself
+    linux::unexport(&pins);
+    #[cfg(feature = "i2c")]
+    if let Some(hat) = config.pijuice.as_ref().filter(|_| low_battery) {
+        println!("Battery low, halting");
+        pijuice::halt(hat)?;
+    }
+    served
+}
+
+// Show what the daemon would show right now (or one page), then sleep the panel and exit:
+// for badges and door signs on batteries, woken by a timer rather than kept running.
+// With --power-off the Pi is halted as well, once the RTC is set to wake it for the next page.
+fn once(args: &[String]) -> Result<(), std::io::Error> {
+    let mut config_path = None;
+    let mut page = None;
+    let mut power_off = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = Some(PathBuf::from(args.next().ok_or_else(|| Error::new(ErrorKind::InvalidInput, USAGE))?)),
+            "--page" => page = Some(args.next().ok_or_else(|| Error::new(ErrorKind::InvalidInput, USAGE))?.clone()),
+            "--power-off" => power_off = true,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+        }
+    }
+    let mut config = Config::load_or_default(config_path.as_deref())?;
+    #[cfg(feature = "ttf")]
+    use_fallback_font(&config);
+    if let Some(page) = page {
+        // Just this page, whatever the schedule says
+        config.pages = vec![page];
+        config.weekend = None;
+        config.night = None;
+        config.rules.clear();
+    }
+    let mut scheduler = Scheduler::new(&config)?;
+
+    if let Some(path) = &config.last_frame {
+        let _ = FRAME_FILE.set(path.clone());
+    }
+    let panel = configured_panel(&config)?;
+    let mut inky = open_display(None, panel, config.refresh_policy.clone(), config.retry.clone(), config.last_frame.clone())?;
+    inky.set_busy_polarity(config.busy_polarity);
+    inky.set_colors(config.colors);
+    let mut delay = Delay {};
+    let mut fb = Framebuffer::for_panel(&inky, Rotation::Rotate90);
+    inky.init(&mut delay).map_err(Error::other)?;
+    if inky.panel().is_none() {
+        inky.set_border(config.border).map_err(Error::other)?;
+    }
+    let shown = scheduler.tick(&mut inky, &mut fb, &mut delay);
+    if config.log_refreshes {
+        log_refresh(inky.last_refresh());
+    }
+    // Sleep and let go of the pins whether or not the refresh worked
+    close_display(inky, true)?;
+    if power_off {
+        return power_off_until_due(&config, shown.map_err(Error::other), &fb);
+    }
+    shown.map_err(Error::other)?;
+    crash::remember_frame(&fb);
+    Ok(())
+}
+
+// The end of `once --power-off`: a refresh that failed is only logged, so a
+// sign out of reach still wakes up to try again rather than staying on
+#[cfg(feature = "i2c")]
+fn power_off_until_due(config: &Config, shown: Result<Duration, Error>, fb: &Framebuffer) -> Result<(), std::io::Error> {
+    let rtc_wake = config
+        .rtc_wake
+        .as_ref()
+        .ok_or_else(|| ConfigError::Invalid("--power-off needs an [rtc_wake] section".to_string()))?;
+    let wait = match shown {
+        Ok(wait) => {
+            crash::remember_frame(fb);
+            wait
+        }
+        Err(err) => {
+            eprintln!("Refresh failed: {err}");
+            Duration::ZERO
+        }
+    };
+    rtc::power_off(rtc_wake, rtc_wake.sleep(wait))
+}
+
+#[cfg(not(feature = "i2c"))]
+fn power_off_until_due(_config: &Config, _shown: Result<Duration, Error>, _fb: &Framebuffer) -> Result<(), std::io::Error> {
+    Err(Error::new(ErrorKind::Unsupported, "--power-off needs the i2c feature"))
+}
+
+// Check the built-in screens and widgets against the reference PNGs in a directory
+// (set INKY_UPDATE_SNAPSHOTS=1 to rewrite them instead)
+fn golden(args: &[String]) -> Result<(), std::io::Error> {
+    let (dir, tolerance) = match args {
+        [dir] => (dir, 0),
+        [dir, flag, pixels] if flag == "--tolerance" => {
+            (dir, pixels.parse().map_err(|_| Error::new(ErrorKind::InvalidInput, USAGE))?)
+        }
+        _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+    };
+    let failed = inky_test::check_goldens(dir, tolerance);
+    for (name, mismatch) in &failed {
+        eprintln!("{name}: {mismatch}");
+    }
+    println!("{} of {} match", inky_test::GOLDENS.len() - failed.len(), inky_test::GOLDENS.len());
+    if !failed.is_empty() {
+        return Err(Error::other(format!("{} goldens differ", failed.len())));
+    }
+    Ok(())
+}
+
+// Measure the panel's reset and refresh times again and store them for the daemon
+fn calibrate(args: &[String]) -> Result<(), std::io::Error> {
+    let config = match args {
+        [] => Config::load_or_default(None)?,
+        [flag, path] if flag == "--config" => Config::load_or_default(Some(Path::new(path)))?,
+        _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+    };
+    let panel = configured_panel(&config)?;
+    let mut inky = open_display(None, panel, config.refresh_policy.clone(), config.retry.clone(), config.last_frame.clone())?;
+    inky.set_busy_polarity(config.busy_polarity);
+    inky.set_colors(config.colors);
+    let mut delay = Delay {};
+    let measured = measure(&mut inky, &mut delay);
+    close_display(inky, true)?;
+    let calibration = measured?;
+    println!(
+        "{}: reset {:.2?}, full refresh {:.2?}, fast refresh {:.2?}",
+        calibration.panel,
+        calibration.reset(),
+        calibration.full(),
+        calibration.partial()
+    );
+    let path = config.calibration.unwrap_or_else(|| PathBuf::from(calibration::DEFAULT_PATH));
+    calibration.save(&path)?;
+    println!("Saved to {}", path.display());
+    Ok(())
+}
+
+// Run a script of drawing and refresh commands (see `script`), on the panel or in the terminal
+fn script(args: &[String], record: Option<&Path>) -> Result<(), std::io::Error> {
+    let (file, terminal) = match args {
+        [file] => (file, false),
+        [file, flag] if flag == "--terminal" && record.is_none() => (file, true),
+        _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+    };
+    // Parse everything before touching the panel
+    let script = Script::load(Path::new(file)).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+    if terminal {
+        let mut terminal = TerminalPanel::inky_phat(Rotation::Rotate90);
+        let mut fb = Framebuffer::for_panel(&terminal, Rotation::Rotate90);
+        let refreshes = script.run(&mut terminal, &mut fb, &mut Delay {}).map_err(Error::other)?;
+        println!("{file}: done after {refreshes} refreshes");
+        return Ok(());
+    }
+
+    let mut inky = open_display(record, None, RefreshPolicy::default(), RetryPolicy::default(), None)?;
+    let mut delay = Delay {};
+    let mut fb = Framebuffer::for_panel(&inky, Rotation::Rotate90);
+    inky.init(&mut delay).map_err(Error::other)?;
+    let refreshes = script.run(&mut inky, &mut fb, &mut delay).map_err(Error::other)?;
+    close_display(inky, true)?;
+    println!("{file}: done after {refreshes} refreshes");
+    Ok(())
+}
+
+// Show standard input as a scrolling log, until it ends
+fn pipe(args: &[String]) -> Result<(), std::io::Error> {
+    let mut options = PipeOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--interval", value) => options.interval = parse_secs(value)?,
+            ("--full-every", Some(value)) => {
+                options.full_every = value.parse().map_err(|_| Error::new(ErrorKind::InvalidInput, USAGE))?;
+            }
+            ("--font", Some(value)) => {
+                options.font = value
+                    .parse()
+                    .ok()
+                    .and_then(text::profont)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("no ProFont of {value} points")))?;
+            }
+            _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+        }
+    }
+    let mut inky = open_display(None, None, RefreshPolicy::default(), RetryPolicy::default(), None)?;
+    let mut delay = Delay {};
+    let mut fb = Framebuffer::for_panel(&inky, Rotation::Rotate90);
+    inky.init(&mut delay).map_err(Error::other)?;
+    // Ctrl-C reaches the command piping in too; once it stops, the input ends and the log gets its last refresh
+    shutdown::install(|| {})?;
+    pipe::run(BufReader::new(std::io::stdin()), &mut inky, &mut fb, &mut delay, &options).map_err(Error::other)?;
+    close_display(inky, true)
+}
+
+// Draw each page once into the terminal, to see what the panel would show without waiting for it
+fn preview(args: &[String]) -> Result<(), std::io::Error> {
+    let mut config_path = None;
+    let mut pages = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = Some(PathBuf::from(args.next().ok_or_else(|| Error::new(ErrorKind::InvalidInput, USAGE))?)),
+            page => pages.push(page.to_string()),
+        }
+    }
+    let config = Config::load_or_default(config_path.as_deref())?;
+    #[cfg(feature = "ttf")]
+    use_fallback_font(&config);
+    if pages.is_empty() {
+        pages = config.pages.clone();
+    }
+    let mut terminal = match configured_panel(&config)? {
+        Some(panel) => TerminalPanel::new(panel.width, panel.height, Rotation::Rotate90),
+        None => TerminalPanel::inky_phat(Rotation::Rotate90),
+    };
+    let mut fb = Framebuffer::for_panel(&terminal, Rotation::Rotate90);
+    let ctx = RenderContext::now().with_theme(config.theme);
+    for name in &pages {
+        let mut page = screens::by_name(name, &config).ok_or_else(|| ConfigError::Invalid(format!("unknown page {name:?}")))?;
+        println!("{name}");
+        screens::draw(page.as_mut(), &mut fb, &ctx);
+        terminal.show(&fb, &mut Delay {})?;
+    }
+    Ok(())
+}
+
+// Write the planes of a page, or of an image as the panel would get it, to PNGs for inspection
+fn export(args: &[String]) -> Result<(), std::io::Error> {
+    let mut config_path = None;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = Some(PathBuf::from(args.next().ok_or_else(|| Error::new(ErrorKind::InvalidInput, USAGE))?)),
+            arg => positional.push(arg),
+        }
+    }
+    let [stem, source] = positional[..] else {
+        return Err(Error::new(ErrorKind::InvalidInput, USAGE));
+    };
+    let config = Config::load_or_default(config_path.as_deref())?;
+    #[cfg(feature = "ttf")]
+    use_fallback_font(&config);
+    let mut fb = match configured_panel(&config)? {
+        Some(panel) => Framebuffer::new(panel.width, panel.height, Rotation::Rotate90),
+        None => Framebuffer::inky_phat(Rotation::Rotate90),
+    }
+    .with_colors(config.colors);
+    if Path::new(source).is_file() {
+        let image = images::load(Path::new(source)).map_err(Error::other)?;
+        images::draw_placed(&mut fb, &image, &config.placement);
+    } else {
+        let mut page = screens::by_name(source, &config).ok_or_else(|| ConfigError::Invalid(format!("unknown page {source:?}")))?;
+        screens::draw(page.as_mut(), &mut fb, &RenderContext::now().with_theme(config.theme));
+    }
+    for path in export::write_separations(&fb, Path::new(stem)).map_err(Error::other)? {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+// Play a recording made with `record` back on this panel
+fn replay(args: &[String]) -> Result<(), std::io::Error> {
+    let mut file = None;
+    let mut speed = 1.0;
+    let mut terminal = false;
+    let mut png = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--terminal" if png.is_none() => terminal = true,
+            "--png" if !terminal => png = Some(PathBuf::from(args.next().ok_or_else(|| Error::new(ErrorKind::InvalidInput, USAGE))?)),
+            "--speed" => {
+                speed = args
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .filter(|speed: &f32| *speed > 0.0)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidInput, USAGE))?;
+            }
+            path if file.is_none() => file = Some(PathBuf::from(path)),
+            _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+        }
+    }
+    let file = file.ok_or_else(|| Error::new(ErrorKind::InvalidInput, USAGE))?;
+    let mut recording = Recording::open(&file)?;
+    let log = |frame: &record::Frame| {
+        let kind = if frame.fast { "fast" } else { "full" };
+        println!("{:>10.1}s  {kind} refresh", frame.at.as_secs_f32());
+    };
+
+    // Off the device: frames as PNGs as fast as they can be written, or in the terminal at the recorded pace
+    let count = if let Some(dir) = png {
+        record::replay_to_png(&mut recording, &dir, Rotation::Rotate90, |frame, path| {
+            let kind = if frame.fast { "fast" } else { "full" };
+            println!("{:>10.1}s  {kind} refresh  {}", frame.at.as_secs_f32(), path.display());
+        })?
+    } else if terminal {
+        let (width, height) = recording.dimensions();
+        let mut panel = TerminalPanel::new(width, height, Rotation::Rotate90);
+        record::replay(&mut panel, &mut recording, &mut Delay {}, speed, log)?
+    } else {
+        let mut inky = open_display(None, None, RefreshPolicy::default(), RetryPolicy::default(), None)?;
+        let mut delay = Delay {};
+        inky.init(&mut delay).map_err(Error::other)?;
+        let count = record::replay(&mut inky, &mut recording, &mut delay, speed, log)?;
+        close_display(inky, true)?;
+        count
+    };
+    println!("Replayed {count} frames from {}", file.display());
+    Ok(())
+}
+
+// List the panel descriptors in `dir`
+fn panels(dir: &Path) -> Result<(), std::io::Error> {
+    let panels = panel::load_dir(dir);
+    if panels.is_empty() {
+        println!("No panel descriptors in {}", dir.display());
+    }
+    // Files of the same name override the presets
+    let presets = panel::presets().into_iter().filter(|preset| !panels.contains_key(&preset.name));
+    for (panel, built_in) in panels.values().map(|panel| (panel.clone(), false)).chain(presets.map(|preset| (preset, true))) {
+        let lut = if panel.waveform.is_empty() { "" } else { ", own waveform" };
+        let built_in = if built_in { ", built in" } else { "" };
+        println!("{:<20} {}x{}, {} init steps{lut}{built_in}", panel.name, panel.width, panel.height, panel.init.len());
+    }
+    Ok(())
+}
+
+// Render a splash screen (framed in red for alerts) and refresh; a broken image shouldn't stop the daemon,
+// but the refresh failing is left to the caller, since the policy may only be saying "not yet"
+fn show_screen<E: EpdController>(
+    epd: &mut E,
+    fb: &mut Framebuffer,
+    screen: &ScreenConfig,
+    placement: &Placement,
+    alert: bool,
+) -> Result<(), E::Error> {
+    if let Err(err) = splash::render(fb, screen, placement) {
+        eprintln!("Splash image failed: {err}");
+    }
+    if alert {
+        alerts::draw_border(fb);
+    }
+    let mut delay = Delay {};
+    epd.show(fb, &mut delay)?;
+    crash::remember_frame(fb);
+    Ok(())
+}
+
+// The panel's temperature, if `enabled`; the first failure turns it off for good, since
+// it means the SPI controller can't do 3-wire reads
+fn panel_temperature(inky: &mut Display, enabled: &mut bool) -> Option<f32> {
+    if !*enabled {
+        return None;
+    }
+    match inky.panel_temperature() {
+        Ok(degrees) => Some(degrees as f32),
+        Err(err) => {
+            eprintln!("Panel temperature sensor unavailable: {err}");
+            *enabled = false;
+            None
+        }
+    }
+}
+
+// GET /healthz is a cheap liveness probe; add ?hardware to also exercise the panel
+fn healthz(display: &Mutex<Display>, request: &Request, stall_limit: Option<Duration>) -> Response {
+    let mut report = HealthReport::default();
+    if request.query_param("hardware").is_some() {
+        report.push(health::check_spi_node(Path::new(SPI_PATH)));
+        match display.try_lock() {
+            Ok(mut inky) => {
+                report.push(match inky.self_check() {
+                    Ok(true) => Check::pass("panel"),
+                    Ok(false) => Check::fail("panel", "BUSY is high while idle"),
+                    Err(err) => {
+                        metrics::record_panel_error(&err);
+                        Check::fail("panel", err.to_string())
+                    }
+                });
+                // A refresh that ran far past the calibrated time is a panel about to hang
+                if let (Some(limit), Some(stats)) = (stall_limit, inky.last_refresh()) {
+                    report.push(if stats.busy > limit {
+                        Check::fail("refresh", format!("last refresh took {:.1?}, limit {limit:.1?}", stats.busy))
+                    } else {
+                        Check::pass("refresh")
+                    });
+                }
+            }
+            // Someone is mid-refresh, which is exactly what a working panel does
+            Err(_) => report.push(Check::pass("panel").with_detail("refresh in progress")),
+        }
+    }
+    Response::json(report.http_status(), report.to_json())
+}
+
+// GET /status: how every data source has been doing, and which widgets failed to draw
+fn status() -> Response {
+    let body = serde_json::json!({
+        "sources": metrics::snapshot(),
+        "widgets": metrics::widget_errors(),
+    });
+    Response::json(200, body.to_string())
+}
+
+// GET /frame.png: what the panel is showing, as far as the frame store knows
+fn frame(display: &Mutex<Display>) -> Response {
+    // Don't hold the request up behind a refresh
+    let Ok(inky) = display.try_lock() else {
+        return Response::text(503, "refresh in progress\n");
+    };
+    let Some((bw, red)) = inky.last_frame() else {
+        return Response::text(404, "nothing shown yet\n");
+    };
+    let mut fb = Framebuffer::for_panel(&*inky, Rotation::Rotate90);
+    fb.load_planes(bw, red);
+    drop(inky);
+    match fb.png_bytes() {
+        Ok(png) => Response {
+            status: 200,
+            content_type: "image/png",
+            body: png,
+        },
+        Err(err) => Response::text(500, format!("{err}\n")),
+    }
+}
+
+// POST /deghost: flash the ghosting out of the panel, then put back what it was showing
+fn deghost(display: &Mutex<Display>, request: &Request) -> Response {
+    let cycles = match request.query_param("cycles").map(str::parse::<u8>) {
+        None => DEGHOST_CYCLES,
+        Some(Ok(cycles)) if (1..=MAX_DEGHOST_CYCLES).contains(&cycles) => cycles,
+        _ => return Response::text(400, format!("cycles must be 1 to {MAX_DEGHOST_CYCLES}\n")),
+    };
+    let mut inky = display.lock().unwrap_or_else(PoisonError::into_inner);
+    let frame = inky.last_frame().map(|(bw, red)| (bw.to_vec(), red.to_vec()));
+    let mut delay = Delay {};
+    let mut cleaned = inky.deghost(cycles, &mut delay);
+    // The panel is black now, whatever the store thinks
+    inky.forget();
+    if let (Ok(()), Some((bw, red))) = (&cleaned, frame) {
+        cleaned = inky.write_planes(&bw, &red).and_then(|()| inky.refresh(&mut delay));
+    }
+    match cleaned {
+        Ok(()) => Response::text(200, "cleaned\n"),
+        Err(InkyError::RateLimited { retry_after }) => {
+            Response::text(429, format!("refresh policy allows the next full refresh in {}s\n", retry_after.as_secs()))
+        }
+        Err(err) => {
+            metrics::record_panel_error(&err);
+            Response::text(500, format!("{err}\n"))
+        }
+    }
+}
+
+// One command from the control socket or the bus
+fn control(
+    display: &Mutex<Display>,
+    inbox: &Inbox,
+    asleep: &AtomicBool,
+    default_ttl: u64,
+    command: ControlCommand,
+) -> Result<(), String> {
+    let request = match command {
+        ControlCommand::ShowText { text, ttl } => PushRequest {
+            text: Some(text),
+            ttl,
+            ..PushRequest::default()
+        },
+        ControlCommand::ShowImage { path, placement, ttl } => {
+            if !path.is_file() {
+                return Err(format!("no image at {}", path.display()));
+            }
+            PushRequest {
+                image: Some(path),
+                placement,
+                ttl,
+                ..PushRequest::default()
+            }
+        }
+        ControlCommand::Clear => {
+            inbox.clear();
+            return Ok(());
+        }
+        ControlCommand::Sleep => {
+            let mut inky = display.lock().unwrap_or_else(PoisonError::into_inner);
+            asleep.store(true, Ordering::SeqCst);
+            return inky.sleep().map_err(|err| err.to_string());
+        }
+        ControlCommand::Wake => {
+            let mut inky = display.lock().unwrap_or_else(PoisonError::into_inner);
+            if asleep.swap(false, Ordering::SeqCst) {
+                inky.init(&mut Delay {}).map_err(|err| err.to_string())?;
+                // Sleep lost the controller's RAM, so the next frame goes whole
+                inky.forget();
+            }
+            drop(inky);
+            inbox.wake();
+            return Ok(());
+        }
+    };
+    if !inbox.push(Push::new(request, default_ttl)) {
+        return Err("an alert is waiting to be acknowledged".to_string());
+    }
+    Ok(())
+}
+
+// POST /push: show text or an image until the TTL runs out
+fn push(inbox: &Inbox, request: &Request, default_ttl: u64) -> Response {
+    match PushRequest::parse(request) {
+        Ok(request) => {
+            let push = Push::new(request, default_ttl);
+            let ttl = push.remaining().map_or(0, |ttl| ttl.as_secs());
+            if !inbox.push(push) {
+                return Response::text(409, "an alert is waiting to be acknowledged\n");
+            }
+            Response::json(202, format!("{{\"ttl\":{ttl}}}"))
+        }
+        Err(reason) => Response::text(400, format!("{reason}\n")),
+    }
+}
+
+fn parse_secs(value: Option<&String>) -> Result<Duration, std::io::Error> {
+    value
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, USAGE))
+}
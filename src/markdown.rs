@@ -0,0 +1,255 @@
+// Notes and to-do lists written as Markdown, drawn with their structure.
+//
+// Only a subset is understood, the parts that read well on a small panel:
+//
+//     # Heading, ## smaller, ### smaller still
+//     Paragraphs, joined up across lines, with **bold** or __bold__ words
+//     - bullets (or * or +), nested two spaces a level
+//     - [ ] to-dos, and - [x] done ones
+//     ---
+//
+// the last being a rule across the page. Anything else is shown as text.
+// ProFont has no bold, so bold text is drawn twice a pixel apart.
+
+use std::ops::Range;
+
+use embedded_graphics::mono_font::MonoFont;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use profont::{PROFONT_12_POINT, PROFONT_14_POINT, PROFONT_18_POINT};
+
+use crate::framebuffer::Color;
+use crate::text;
+
+/// Text with the byte ranges that are bold.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Spans {
+    pub text: String,
+    pub bold: Vec<Range<usize>>,
+}
+
+impl Spans {
+    /// Takes the `**` and `__` markers out of `source`, noting what they enclosed.
+    pub fn parse(source: &str) -> Self {
+        let mut spans = Spans::default();
+        let mut open: Option<(&str, usize)> = None;
+        let mut rest = source;
+        while !rest.is_empty() {
+            let marker = ["**", "__"].into_iter().find(|marker| rest.starts_with(marker));
+            match (marker, open) {
+                (Some(marker), Some((opened, start))) if marker == opened => {
+                    spans.bold.push(start..spans.text.len());
+                    open = None;
+                    rest = &rest[2..];
+                }
+                // Only opens if it closes again later on
+                (Some(marker), None) if rest[2..].contains(marker) => {
+                    open = Some((marker, spans.text.len()));
+                    rest = &rest[2..];
+                }
+                _ => {
+                    let c = rest.chars().next().unwrap_or_default();
+                    spans.text.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+        spans
+    }
+
+    fn is_bold(&self, index: usize) -> bool {
+        self.bold.iter().any(|range| range.contains(&index))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Block {
+    /// Level 1 to 3
+    Heading(u8, Spans),
+    Paragraph(Spans),
+    /// A list item `depth` levels in, with its box for a to-do
+    Item {
+        depth: usize,
+        done: Option<bool>,
+        text: Spans,
+    },
+    Rule,
+    /// A blank line between blocks
+    Gap,
+}
+
+/// Splits a document into blocks.
+pub fn parse(source: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let flush = |paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>| {
+        if !paragraph.is_empty() {
+            blocks.push(Block::Paragraph(Spans::parse(&paragraph.join(" "))));
+            paragraph.clear();
+        }
+    };
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            flush(&mut paragraph, &mut blocks);
+            if !matches!(blocks.last(), None | Some(Block::Gap)) {
+                blocks.push(Block::Gap);
+            }
+            continue;
+        }
+        // Three or more of the same mark, perhaps spaced out
+        let mark = trimmed.chars().next().filter(|c| matches!(c, '-' | '*' | '_'));
+        if trimmed.chars().filter(|&c| c != ' ').count() >= 3 && trimmed.chars().all(|c| c == ' ' || Some(c) == mark) {
+            flush(&mut paragraph, &mut blocks);
+            blocks.push(Block::Rule);
+            continue;
+        }
+        let hashes = trimmed.chars().take_while(|&c| c == '#').count();
+        if (1..=3).contains(&hashes) && trimmed[hashes..].starts_with(' ') {
+            flush(&mut paragraph, &mut blocks);
+            blocks.push(Block::Heading(hashes as u8, Spans::parse(trimmed[hashes..].trim())));
+            continue;
+        }
+        if let Some(item) = ["- ", "* ", "+ "].iter().find_map(|bullet| trimmed.strip_prefix(bullet)) {
+            flush(&mut paragraph, &mut blocks);
+            let indent = line.len() - line.trim_start().len();
+            let (done, item) = match item.get(..4) {
+                Some("[ ] ") => (Some(false), &item[4..]),
+                Some("[x] " | "[X] ") => (Some(true), &item[4..]),
+                _ => (None, item),
+            };
+            blocks.push(Block::Item {
+                depth: indent / 2,
+                done,
+                text: Spans::parse(item.trim()),
+            });
+            continue;
+        }
+        paragraph.push(trimmed);
+    }
+    flush(&mut paragraph, &mut blocks);
+    blocks
+}
+
+/// Draws `blocks` into `bounds`, body text in `font` and headings larger,
+/// as far as they fit. Returns whether all of them did.
+pub fn draw<T: DrawTarget<Color = Color>>(
+    blocks: &[Block],
+    bounds: Rectangle,
+    font: &MonoFont,
+    color: Color,
+    target: &mut T,
+) -> Result<bool, T::Error> {
+    let left = bounds.top_left.x;
+    let bottom = bounds.top_left.y + bounds.size.height as i32;
+    let width = bounds.size.width;
+    let cell = (font.character_size.width + font.character_spacing) as i32;
+    let line = font.character_size.height as i32 + 1;
+    let mut y = bounds.top_left.y;
+    for block in blocks {
+        let fits = match block {
+            Block::Heading(level, spans) => {
+                let heading = match level {
+                    1 => &PROFONT_18_POINT,
+                    2 => &PROFONT_14_POINT,
+                    _ => &PROFONT_12_POINT,
+                };
+                let heading = if heading.character_size.height > font.character_size.height { heading } else { font };
+                let fits = draw_spans(spans, true, Point::new(left, y), width, heading, color, bottom, &mut y, target)?;
+                y += 2;
+                fits
+            }
+            Block::Paragraph(spans) => draw_spans(spans, false, Point::new(left, y), width, font, color, bottom, &mut y, target)?,
+            Block::Item { depth, done, text } => {
+                if y + line > bottom {
+                    return Ok(false);
+                }
+                // The marker in the first two cells past the indent, the text hanging after it
+                let indent = (*depth as i32 * 2) * cell;
+                let side = (font.character_size.height as i32 * 2 / 3).min(cell * 2 - 2).max(3);
+                let marker = Point::new(left + indent, y + (font.character_size.height as i32 - side) / 2);
+                match done {
+                    None => {
+                        let dot = (side / 2).max(2);
+                        let at = marker + Point::new((side - dot) / 2, (side - dot) / 2);
+                        target.fill_solid(&Rectangle::new(at, Size::new_equal(dot as u32)), color)?;
+                    }
+                    Some(done) => {
+                        let square = Rectangle::new(marker, Size::new_equal(side as u32));
+                        square.into_styled(PrimitiveStyle::with_stroke(color, 1)).draw(target)?;
+                        if *done {
+                            target.fill_solid(&square.offset(-2), color)?;
+                        }
+                    }
+                }
+                let hang = indent + 2 * cell;
+                draw_spans(
+                    text,
+                    false,
+                    Point::new(left + hang, y),
+                    width.saturating_sub(hang as u32),
+                    font,
+                    color,
+                    bottom,
+                    &mut y,
+                    target,
+                )?
+            }
+            Block::Rule => {
+                if y + line > bottom {
+                    return Ok(false);
+                }
+                let rule = Rectangle::new(Point::new(left, y + line / 2 - 1), Size::new(width, 1));
+                target.fill_solid(&rule, color)?;
+                y += line;
+                true
+            }
+            Block::Gap => {
+                y += line / 2;
+                true
+            }
+        };
+        if !fits {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Wraps `spans` into `width` from `at`, all in bold if `bold`, moving `y`
+// down past them; false if they ran past `bottom`
+#[allow(clippy::too_many_arguments)]
+fn draw_spans<T: DrawTarget<Color = Color>>(
+    spans: &Spans,
+    bold: bool,
+    at: Point,
+    width: u32,
+    font: &MonoFont,
+    color: Color,
+    bottom: i32,
+    y: &mut i32,
+    target: &mut T,
+) -> Result<bool, T::Error> {
+    let cell = (font.character_size.width + font.character_spacing) as i32;
+    let height = font.character_size.height as i32;
+    *y = at.y;
+    for line in text::wrap(&spans.text, text::columns(font, width.saturating_sub(1))) {
+        if *y + height > bottom {
+            return Ok(false);
+        }
+        let origin = Point::new(at.x, *y);
+        text::draw_line(line, font, color, origin, target)?;
+        // Bold runs again, a pixel to the right
+        let start = line.as_ptr() as usize - spans.text.as_ptr() as usize;
+        let mut x = origin.x;
+        for (offset, c) in line.char_indices() {
+            if bold || spans.is_bold(start + offset) {
+                let mut buffer = [0; 4];
+                text::draw_line(c.encode_utf8(&mut buffer), font, color, Point::new(x + 1, *y), target)?;
+            }
+            x += text::char_columns(c) as i32 * cell;
+        }
+        *y += height + 1;
+    }
+    Ok(true)
+}
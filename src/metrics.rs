@@ -0,0 +1,239 @@
+// Per-data-source health: how long fetches take, how often they fail, and
+// when each source last worked; which widgets couldn't be drawn; and how the
+// panel itself is doing.
+//
+// Sources record into one process-wide table so that anything fetching data
+// can report without being handed a registry; the status API and the
+// diagnostics page read it back, and `prometheus` renders the panel's numbers
+// for the daemon's /metrics.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::inky_driver::{InkyError, RefreshStats};
+
+static SOURCES: Mutex<BTreeMap<String, SourceStats>> = Mutex::new(BTreeMap::new());
+// Widgets drawing a placeholder, by page and then widget
+static WIDGETS: Mutex<BTreeMap<String, BTreeMap<String, WidgetError>>> = Mutex::new(BTreeMap::new());
+static PANEL: Mutex<PanelStats> = Mutex::new(PanelStats::new());
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SourceStats {
+    pub fetches: u64,
+    pub failures: u64,
+    /// Failures since the last success
+    pub consecutive_failures: u64,
+    /// Duration of the most recent fetch, successful or not
+    pub last_latency_ms: Option<u64>,
+    pub last_success: Option<DateTime<Local>>,
+    pub last_error: Option<String>,
+}
+
+impl SourceStats {
+    /// Whether the most recent fetch worked.
+    pub fn is_ok(&self) -> bool {
+        self.fetches > 0 && self.consecutive_failures == 0
+    }
+}
+
+/// Records one fetch from `source` that began at `started`.
+pub fn record<T, E: Display>(source: &str, started: Instant, result: &Result<T, E>) {
+    let latency = started.elapsed();
+    let mut sources = SOURCES.lock().unwrap_or_else(PoisonError::into_inner);
+    let stats = sources.entry(source.to_string()).or_default();
+    stats.fetches += 1;
+    stats.last_latency_ms = Some(latency.as_millis() as u64);
+    match result {
+        Ok(_) => {
+            stats.consecutive_failures = 0;
+            stats.last_success = Some(Local::now());
+        }
+        Err(err) => {
+            stats.failures += 1;
+            stats.consecutive_failures += 1;
+            stats.last_error = Some(err.to_string());
+        }
+    }
+}
+
+/// Runs `fetch` and records how it went under `source`.
+pub fn timed<T, E: Display>(source: &str, fetch: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let started = Instant::now();
+    let result = fetch();
+    record(source, started, &result);
+    result
+}
+
+/// Every source seen so far, by name.
+pub fn snapshot() -> BTreeMap<String, SourceStats> {
+    SOURCES.lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Why a widget drew a placeholder instead of itself.
+#[derive(Clone, Debug, Serialize)]
+pub struct WidgetError {
+    /// What the placeholder shows, e.g. `VAR`
+    pub code: &'static str,
+    pub detail: String,
+    /// First render that failed this way
+    pub since: DateTime<Local>,
+}
+
+impl WidgetError {
+    pub fn new(code: &'static str, detail: impl Into<String>) -> Self {
+        WidgetError {
+            code,
+            detail: detail.into(),
+            since: Local::now(),
+        }
+    }
+}
+
+/// Records which widgets on `page` failed in its latest render; widgets not
+/// listed are taken to have drawn fine.
+pub fn report_widgets(page: &str, failed: Vec<(String, WidgetError)>) {
+    let mut widgets = WIDGETS.lock().unwrap_or_else(PoisonError::into_inner);
+    if failed.is_empty() {
+        widgets.remove(page);
+        return;
+    }
+    let previous = widgets.remove(page).unwrap_or_default();
+    let errors = failed
+        .into_iter()
+        .map(|(widget, mut error)| {
+            // Keep counting from the first failure while it's the same one
+            if let Some(before) = previous.get(&widget).filter(|before| before.code == error.code) {
+                error.since = before.since;
+            }
+            (widget, error)
+        })
+        .collect();
+    widgets.insert(page.to_string(), errors);
+}
+
+/// Widgets showing a placeholder, by page and widget.
+pub fn widget_errors() -> BTreeMap<String, BTreeMap<String, WidgetError>> {
+    WIDGETS.lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Refreshes and failures of the panel since the process started.
+#[derive(Clone, Debug, Default)]
+pub struct PanelStats {
+    pub refreshes: u64,
+    /// Summed over all refreshes, for averages
+    pub busy_seconds: f64,
+    pub transfer_seconds: f64,
+    pub spi_errors: u64,
+    pub gpio_errors: u64,
+    /// Refreshes that kept BUSY high past the calibrated stall limit, or
+    /// past the driver's busy timeout
+    pub busy_timeouts: u64,
+    /// Operations tried again after an error (see `retry`)
+    pub retries: u64,
+    /// Times the controller was re-initialised after errors in a row
+    pub reinits: u64,
+    pub last_refresh: Option<RefreshStats>,
+    pub last_success: Option<DateTime<Local>>,
+}
+
+impl PanelStats {
+    const fn new() -> Self {
+        PanelStats {
+            refreshes: 0,
+            busy_seconds: 0.0,
+            transfer_seconds: 0.0,
+            spi_errors: 0,
+            gpio_errors: 0,
+            busy_timeouts: 0,
+            retries: 0,
+            reinits: 0,
+            last_refresh: None,
+            last_success: None,
+        }
+    }
+}
+
+/// Records a refresh that finished; it counts as a busy timeout if it took
+/// longer than `stall_limit`.
+pub fn record_refresh(stats: RefreshStats, stall_limit: Option<Duration>) {
+    let mut panel = PANEL.lock().unwrap_or_else(PoisonError::into_inner);
+    panel.refreshes += 1;
+    panel.busy_seconds += stats.busy.as_secs_f64();
+    panel.transfer_seconds += stats.transfer.as_secs_f64();
+    if stall_limit.is_some_and(|limit| stats.busy > limit) {
+        panel.busy_timeouts += 1;
+    }
+    panel.last_refresh = Some(stats);
+    panel.last_success = Some(Local::now());
+}
+
+/// Records a driver error; refusals by the refresh policy aren't faults and
+/// aren't counted.
+pub fn record_panel_error<SPIE, GPIOE>(err: &InkyError<SPIE, GPIOE>) {
+    let mut panel = PANEL.lock().unwrap_or_else(PoisonError::into_inner);
+    match err {
+        InkyError::Spi { .. } => panel.spi_errors += 1,
+        InkyError::Gpio { .. } => panel.gpio_errors += 1,
+        InkyError::BusyTimeout { .. } => panel.busy_timeouts += 1,
+        InkyError::RateLimited { .. } => {}
+    }
+}
+
+/// Records a driver error that is about to be retried; it counts as an error too.
+pub fn record_retry<SPIE, GPIOE>(err: &InkyError<SPIE, GPIOE>) {
+    record_panel_error(err);
+    PANEL.lock().unwrap_or_else(PoisonError::into_inner).retries += 1;
+}
+
+pub fn record_reinit() {
+    PANEL.lock().unwrap_or_else(PoisonError::into_inner).reinits += 1;
+}
+
+pub fn panel_stats() -> PanelStats {
+    PANEL.lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// The panel's numbers in the Prometheus text format.
+pub fn prometheus() -> String {
+    let panel = panel_stats();
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}");
+    };
+    metric("inky_refreshes_total", "counter", "Refreshes finished.", panel.refreshes as f64);
+    metric("inky_refresh_busy_seconds_total", "counter", "Time spent waiting on BUSY during refreshes.", panel.busy_seconds);
+    metric("inky_refresh_transfer_seconds_total", "counter", "Time spent writing frames to RAM.", panel.transfer_seconds);
+    if let Some(last) = panel.last_refresh {
+        metric("inky_last_refresh_busy_seconds", "gauge", "BUSY time of the last refresh.", last.busy.as_secs_f64());
+    }
+    metric("inky_spi_errors_total", "counter", "SPI writes that failed.", panel.spi_errors as f64);
+    metric("inky_gpio_errors_total", "counter", "GPIO operations that failed.", panel.gpio_errors as f64);
+    metric("inky_retries_total", "counter", "Panel operations retried after an error.", panel.retries as f64);
+    metric("inky_reinits_total", "counter", "Controller re-inits after errors in a row.", panel.reinits as f64);
+    metric("inky_busy_timeouts_total", "counter", "Refreshes that ran past the calibrated stall limit.", panel.busy_timeouts as f64);
+    if let Some(last) = panel.last_success {
+        metric(
+            "inky_last_success_timestamp_seconds",
+            "gauge",
+            "Unix time of the last finished refresh.",
+            last.timestamp() as f64,
+        );
+    }
+    out
+}
+
+/// A name for a URL that is safe to show: ICS and webhook URLs often carry
+/// a secret in the path, so only the host is kept.
+pub fn source_name(kind: &str, url: &str) -> String {
+    let host = url
+        .split_once("://")
+        .map(|(_, rest)| rest.split(['/', '?']).next().unwrap_or(rest))
+        .unwrap_or_else(|| url.rsplit('/').next().unwrap_or(url));
+    let host = host.rsplit('@').next().unwrap_or(host);
+    format!("{kind} {host}")
+}
@@ -0,0 +1,259 @@
+// Minimal blocking MQTT 3.1.1 client.
+//
+// Enough for what a sign needs from a home broker: subscribe to a few topics
+// at QoS 0, publish the odd message, keep the connection alive. No TLS, no
+// persistent sessions, no QoS 2.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xC0;
+
+// How long `poll` waits for something to arrive before returning
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+// Once a packet has started arriving, allow this long for the rest of it
+const PACKET_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttOptions {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Seconds between keep-alive pings
+    #[serde(default = "default_keep_alive")]
+    pub keep_alive: u16,
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_client_id() -> String {
+    format!("rust_raspi-{}", std::process::id())
+}
+
+fn default_keep_alive() -> u16 {
+    60
+}
+
+impl MqttOptions {
+    pub fn new(host: impl Into<String>) -> Self {
+        MqttOptions {
+            host: host.into(),
+            port: default_port(),
+            client_id: default_client_id(),
+            username: None,
+            password: None,
+            keep_alive: default_keep_alive(),
+        }
+    }
+}
+
+/// A message received on a subscribed topic.
+#[derive(Clone, Debug)]
+pub struct Publish {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+pub struct Client {
+    stream: TcpStream,
+    keep_alive: Duration,
+    last_sent: Instant,
+    next_packet_id: u16,
+}
+
+impl Client {
+    /// Opens a clean session and waits for the broker to accept it.
+    pub fn connect(options: &MqttOptions) -> io::Result<Self> {
+        let stream = TcpStream::connect((options.host.as_str(), options.port))?;
+        stream.set_read_timeout(Some(PACKET_TIMEOUT))?;
+        let mut client = Client {
+            stream,
+            keep_alive: Duration::from_secs(options.keep_alive as u64),
+            last_sent: Instant::now(),
+            next_packet_id: 1,
+        };
+
+        let mut flags = 0x02; // clean session
+        let mut payload = Vec::new();
+        put_str(&mut payload, &options.client_id);
+        if let Some(username) = &options.username {
+            flags |= 0x80;
+            put_str(&mut payload, username);
+        }
+        if let Some(password) = &options.password {
+            flags |= 0x40;
+            put_str(&mut payload, password);
+        }
+        let mut body = Vec::new();
+        put_str(&mut body, "MQTT");
+        body.push(4); // protocol level 3.1.1
+        body.push(flags);
+        body.extend_from_slice(&options.keep_alive.to_be_bytes());
+        body.extend_from_slice(&payload);
+        client.send(CONNECT, &body)?;
+
+        let (header, body) = client.read_packet()?;
+        if header & 0xF0 != CONNACK || body.len() < 2 {
+            return Err(io::Error::new(ErrorKind::InvalidData, "expected CONNACK"));
+        }
+        if body[1] != 0 {
+            return Err(io::Error::new(
+                ErrorKind::ConnectionRefused,
+                format!("broker refused connection (code {})", body[1]),
+            ));
+        }
+        Ok(client)
+    }
+
+    /// Subscribes to `filters` (wildcards allowed) at QoS 0.
+    pub fn subscribe(&mut self, filters: &[&str]) -> io::Result<()> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&self.packet_id().to_be_bytes());
+        for filter in filters {
+            put_str(&mut body, filter);
+            body.push(0);
+        }
+        self.send(SUBSCRIBE, &body)
+    }
+
+    /// Publishes at QoS 0.
+    pub fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> io::Result<()> {
+        let mut body = Vec::new();
+        put_str(&mut body, topic);
+        body.extend_from_slice(payload);
+        self.send(PUBLISH | retain as u8, &body)
+    }
+
+    /// Waits up to a second for an incoming message, keeping the connection alive meanwhile.
+    pub fn poll(&mut self) -> io::Result<Option<Publish>> {
+        if self.last_sent.elapsed() >= self.keep_alive {
+            self.send(PINGREQ, &[])?;
+        }
+
+        self.stream.set_read_timeout(Some(POLL_TIMEOUT))?;
+        let mut first = [0u8; 1];
+        let read = self.stream.read(&mut first);
+        self.stream.set_read_timeout(Some(PACKET_TIMEOUT))?;
+        match read {
+            Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "broker closed the connection")),
+            Ok(_) => {}
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return Ok(None),
+            Err(err) => return Err(err),
+        }
+
+        let header = first[0];
+        let body = self.read_body()?;
+        if header & 0xF0 != PUBLISH {
+            // SUBACK, PINGRESP and friends need no action
+            return Ok(None);
+        }
+        let qos = (header >> 1) & 0x03;
+        let (topic, mut rest) = take_str(&body)?;
+        if qos > 0 {
+            // Some brokers ignore the requested QoS; acknowledge so they don't redeliver
+            let packet_id = rest
+                .get(..2)
+                .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "truncated PUBLISH"))?;
+            let packet_id = [packet_id[0], packet_id[1]];
+            rest = &rest[2..];
+            if qos == 1 {
+                self.send(PUBACK, &packet_id)?;
+            }
+        }
+        Ok(Some(Publish {
+            topic,
+            payload: rest.to_vec(),
+        }))
+    }
+
+    fn packet_id(&mut self) -> u16 {
+        let id = self.next_packet_id;
+        self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+        id
+    }
+
+    fn send(&mut self, header: u8, body: &[u8]) -> io::Result<()> {
+        let mut packet = vec![header];
+        let mut remaining = body.len();
+        loop {
+            let mut byte = (remaining % 128) as u8;
+            remaining /= 128;
+            if remaining > 0 {
+                byte |= 0x80;
+            }
+            packet.push(byte);
+            if remaining == 0 {
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+        self.stream.write_all(&packet)?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    fn read_packet(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut header = [0u8; 1];
+        self.stream.read_exact(&mut header)?;
+        Ok((header[0], self.read_body()?))
+    }
+
+    // Remaining-length varint followed by that many bytes
+    fn read_body(&mut self) -> io::Result<Vec<u8>> {
+        let mut length = 0usize;
+        for shift in 0..4 {
+            let mut byte = [0u8; 1];
+            self.stream.read_exact(&mut byte)?;
+            length |= ((byte[0] & 0x7F) as usize) << (7 * shift);
+            if byte[0] & 0x80 == 0 {
+                let mut body = vec![0u8; length];
+                self.stream.read_exact(&mut body)?;
+                return Ok(body);
+            }
+        }
+        Err(io::Error::new(ErrorKind::InvalidData, "bad remaining length"))
+    }
+}
+
+/// MQTT topic filter matching with `+` (one level) and `#` (all remaining levels).
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut filter_levels = filter.split('/');
+    let mut topic_levels = topic.split('/');
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => {}
+            (Some(expected), Some(level)) if expected == level => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn take_str(buf: &[u8]) -> io::Result<(String, &[u8])> {
+    let truncated = || io::Error::new(ErrorKind::InvalidData, "truncated string");
+    let len = buf.get(..2).ok_or_else(truncated)?;
+    let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+    let bytes = buf.get(2..2 + len).ok_or_else(truncated)?;
+    Ok((String::from_utf8_lossy(bytes).into_owned(), &buf[2 + len..]))
+}
@@ -0,0 +1,210 @@
+// Pixel packing and error-diffusion dithering for the controller RAM planes.
+//
+// The SSD1675 takes one bit per pixel, MSB first along X. In the black/white
+// plane a set bit is white (a buffer of 0xFF clears the screen), in the red
+// plane a set bit is red. Everything here works a row at a time so callers can
+// stream planes without holding a full-resolution intermediate image.
+//
+// On aarch64 the inner loops use NEON when the CPU reports it at runtime; the
+// scalar paths produce bit-identical output and are used everywhere else.
+// That includes 32-bit Raspberry Pi OS (armv7) on the same NEON-capable Pis:
+// `core::arch::arm`'s NEON intrinsics are still unstable, so a 32-bit build
+// packs with the scalar code. Run a 64-bit OS for the fast path.
+
+/// Number of bytes one packed row of `width` pixels occupies.
+pub const fn row_bytes(width: usize) -> usize {
+    width.div_ceil(8)
+}
+
+/// Packs one row of 8-bit luminance into 1bpp. Pixels at or above `threshold`
+/// get their bit set. Bits past the end of `luma` in the last byte are left clear.
+pub fn pack_threshold(luma: &[u8], threshold: u8, out: &mut [u8]) {
+    let out = &mut out[..row_bytes(luma.len())];
+
+    #[cfg(target_arch = "aarch64")]
+    let done = if std::arch::is_aarch64_feature_detected!("neon") {
+        // SAFETY: NEON support was just checked at runtime
+        unsafe { neon::pack_threshold(luma, threshold, out) }
+    } else {
+        0
+    };
+    #[cfg(not(target_arch = "aarch64"))]
+    let done = 0;
+
+    pack_threshold_scalar(&luma[done..], threshold, &mut out[done / 8..]);
+}
+
+/// Packs a row of flags (non-zero = set) into 1bpp, e.g. a red mask.
+pub fn pack_mask(mask: &[u8], out: &mut [u8]) {
+    pack_threshold(mask, 1, out);
+}
+
+fn pack_threshold_scalar(luma: &[u8], threshold: u8, out: &mut [u8]) {
+    for (byte, pixels) in out.iter_mut().zip(luma.chunks(8)) {
+        let mut packed = 0u8;
+        for (bit, &px) in pixels.iter().enumerate() {
+            if px >= threshold {
+                packed |= 0x80 >> bit;
+            }
+        }
+        *byte = packed;
+    }
+}
+
+/// Floyd-Steinberg ditherer with reusable row buffers.
+///
+/// Feed it rows top to bottom with [`Ditherer::dither_row`]; it carries the
+/// diffused error from one row into the next. The rightward 7/16 share is a
+/// serial dependency along the row and stays scalar, while the 3/5/1 spread
+/// into the row below is a plain stencil and is vectorised.
+pub struct Ditherer {
+    width: usize,
+    // Error carried into the current row, scaled by 16
+    below: Vec<i16>,
+    // Per-pixel quantisation error of the last row, padded by one on each side
+    errs: Vec<i16>,
+    // Quantised row (0 or 255, or the grey levels) waiting to be packed
+    quant: Vec<u8>,
+    // Luminance (after carried error) from which a pixel is white
+    threshold: i16,
+    // Grey levels rows are quantised to; the threshold only applies to two
+    levels: i16,
+}
+
+impl Ditherer {
+    pub fn new(width: usize) -> Self {
+        Ditherer {
+            width,
+            below: vec![0; width],
+            errs: vec![0; width + 2],
+            quant: vec![0; width],
+            threshold: 128,
+            levels: 2,
+        }
+    }
+
+    /// Moves the black/white cut from the middle: higher makes more black.
+    pub fn set_threshold(&mut self, threshold: u8) {
+        self.threshold = threshold as i16;
+    }
+
+    /// Quantises to `levels` evenly spaced grey levels (0, 85, 170, 255 for
+    /// four) rather than black and white; 2 or less is black and white.
+    pub fn set_levels(&mut self, levels: u8) {
+        self.levels = levels.max(2) as i16;
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Clears the carried error so the next row starts a fresh image.
+    pub fn reset(&mut self) {
+        self.below.fill(0);
+        self.errs.fill(0);
+    }
+
+    /// Dithers one row of luminance to black/white and packs it into `out`.
+    pub fn dither_row(&mut self, luma: &[u8], out: &mut [u8]) {
+        let width = self.quantise_row(luma).len();
+        pack_threshold(&self.quant[..width], 128, out);
+    }
+
+    /// Dithers one row of luminance and returns it unpacked, one byte (0 or
+    /// 255, or one of the grey levels) per pixel, for callers that place
+    /// pixels themselves.
+    pub fn quantise_row(&mut self, luma: &[u8]) -> &[u8] {
+        let width = self.width.min(luma.len());
+
+        // Serial pass: quantise, pushing 7/16 of each error to the right
+        let mut carry: i16 = 0;
+        for (x, &px) in luma[..width].iter().enumerate() {
+            let value = px as i16 + ((self.below[x] + carry + 8) >> 4);
+            let quantised = if self.levels == 2 {
+                if value >= self.threshold { 255 } else { 0 }
+            } else {
+                // Nearest level
+                let step = 255 / (self.levels - 1);
+                ((value.clamp(0, 255) + step / 2) / step * step).min(255)
+            };
+            let err = value - quantised;
+            self.quant[x] = quantised as u8;
+            self.errs[x + 1] = err;
+            carry = err * 7;
+        }
+        for err in &mut self.errs[width + 1..] {
+            *err = 0;
+        }
+
+        spread_below(&self.errs, &mut self.below);
+        &self.quant[..width]
+    }
+}
+
+/// Dithers a whole `width` x `height` luminance image into a packed plane.
+pub fn dither(luma: &[u8], width: usize, height: usize, out: &mut [u8]) {
+    let stride = row_bytes(width);
+    let mut ditherer = Ditherer::new(width);
+    for (row, packed) in luma.chunks(width).zip(out.chunks_mut(stride)).take(height) {
+        ditherer.dither_row(row, packed);
+    }
+}
+
+// below[x] = 3 * e[x + 1] + 5 * e[x] + e[x - 1], where errs is e shifted right by one
+fn spread_below(errs: &[i16], below: &mut [i16]) {
+    #[cfg(target_arch = "aarch64")]
+    let done = if std::arch::is_aarch64_feature_detected!("neon") {
+        // SAFETY: NEON support was just checked at runtime
+        unsafe { neon::spread_below(errs, below) }
+    } else {
+        0
+    };
+    #[cfg(not(target_arch = "aarch64"))]
+    let done = 0;
+
+    for x in done..below.len() {
+        below[x] = errs[x] + 5 * errs[x + 1] + 3 * errs[x + 2];
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    // Bit weights for one byte, MSB first, repeated for both halves of a q register
+    const WEIGHTS: [u8; 16] = [128, 64, 32, 16, 8, 4, 2, 1, 128, 64, 32, 16, 8, 4, 2, 1];
+
+    /// Packs whole 16-pixel chunks and returns how many pixels were consumed.
+    #[target_feature(enable = "neon")]
+    pub unsafe fn pack_threshold(luma: &[u8], threshold: u8, out: &mut [u8]) -> usize {
+        let chunks = (luma.len() / 16).min(out.len() / 2);
+        unsafe {
+            let weights = vld1q_u8(WEIGHTS.as_ptr());
+            let threshold = vdupq_n_u8(threshold);
+            for i in 0..chunks {
+                let pixels = vld1q_u8(luma.as_ptr().add(i * 16));
+                let bits = vandq_u8(vcgeq_u8(pixels, threshold), weights);
+                out[i * 2] = vaddv_u8(vget_low_u8(bits));
+                out[i * 2 + 1] = vaddv_u8(vget_high_u8(bits));
+            }
+        }
+        chunks * 16
+    }
+
+    /// Computes whole 8-lane chunks of the error stencil and returns how many were written.
+    #[target_feature(enable = "neon")]
+    pub unsafe fn spread_below(errs: &[i16], below: &mut [i16]) -> usize {
+        let chunks = below.len() / 8;
+        unsafe {
+            for i in 0..chunks {
+                let x = i * 8;
+                let left = vld1q_s16(errs.as_ptr().add(x));
+                let centre = vld1q_s16(errs.as_ptr().add(x + 1));
+                let right = vld1q_s16(errs.as_ptr().add(x + 2));
+                let sum = vmlaq_n_s16(vmlaq_n_s16(left, centre, 5), right, 3);
+                vst1q_s16(below.as_mut_ptr().add(x), sum);
+            }
+        }
+        chunks * 8
+    }
+}
@@ -0,0 +1,366 @@
+// Panels described by a file instead of by the driver.
+//
+// Most clone panels are the pHAT's SSD1675-style controller with a different
+// glass: another resolution, other init bytes, sometimes a vendor waveform.
+// A TOML descriptor dropped into `/usr/share/inky/panels` says what those are,
+// and `Described` drives the panel with them instead of the built-in sequence.
+//
+//     name = "clone-213"
+//     width = 104
+//     height = 212
+//     lut = "clone-213.lut"
+//     init = [
+//         { command = 0x12, wait = true },
+//         { command = 0x01, data = [0xD3, 0x00, 0x00] },
+//         { command = 0x11, data = [0x03] },
+//         { command = 0x44, data = [0x00, 0x0C] },
+//         { command = 0x45, data = [0x00, 0x00, 0xD3, 0x00] },
+//         { command = 0x3C, data = [0x05] },
+//         { command = 0x22, data = [0xC7] },
+//     ]
+//
+// The hardware reset always comes first, then the steps in order. The
+// sequence has to set the RAM window itself, since drawing always starts at
+// address (0, 0).
+//
+// Boards wired differently from the pHAT say so with `pins`. The Waveshare
+// 2.13" and 2.9" HATs are built in (see `presets`), so `panel =
+// "waveshare-2in9-v2"` works without a file.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::spi::Write;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use serde::Deserialize;
+
+use crate::epd::{ColorCapability, EpdController, GrayWriter, RowWriter};
+use crate::inky_driver::{InkyError, InkyPhat};
+
+/// Directory descriptors are looked up in by name.
+pub const DEFAULT_DIR: &str = "/usr/share/inky/panels";
+
+/// BCM pin numbers a panel's control lines are wired to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pins {
+    pub cs: u64,
+    pub busy: u64,
+    pub dc: u64,
+    pub reset: u64,
+}
+
+impl Pins {
+    pub const INKY_PHAT: Pins = Pins {
+        cs: 8,
+        busy: 17,
+        dc: 22,
+        reset: 27,
+    };
+    /// Every Waveshare e-Paper HAT, and their driver boards as wired in Waveshare's examples.
+    pub const WAVESHARE: Pins = Pins {
+        cs: 8,
+        busy: 24,
+        dc: 25,
+        reset: 17,
+    };
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PanelDescriptor {
+    /// What the config calls the panel; defaults to the file name without `.toml`
+    #[serde(default)]
+    pub name: String,
+    /// Native size: source lines (a multiple of 8) by gate lines
+    pub width: u32,
+    pub height: u32,
+    /// Commands sent after the hardware reset
+    pub init: Vec<Step>,
+    /// Raw waveform written with WRITE_LUT_REGISTER after `init`, relative to the descriptor
+    pub lut: Option<PathBuf>,
+    /// Contents of `lut`, read by `load`
+    #[serde(skip)]
+    pub waveform: Vec<u8>,
+    /// Where the panel is wired, if not where the pHAT is
+    pub pins: Option<Pins>,
+}
+
+/// One command of an init sequence.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    pub command: u8,
+    #[serde(default)]
+    pub data: Vec<u8>,
+    /// Wait for BUSY to go low afterwards
+    #[serde(default)]
+    pub wait: bool,
+    /// Milliseconds to pause afterwards
+    #[serde(default)]
+    pub delay: u8,
+}
+
+impl PanelDescriptor {
+    /// Reads a descriptor and the waveform it refers to.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let fail = |err: String| format!("{}: {err}", path.display());
+        let text = fs::read_to_string(path).map_err(|err| fail(err.to_string()))?;
+        let mut panel: PanelDescriptor = toml::from_str(&text).map_err(|err| fail(err.to_string()))?;
+        if panel.name.is_empty() {
+            panel.name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        }
+        if panel.width == 0 || panel.height == 0 || !panel.width.is_multiple_of(8) {
+            return Err(fail(format!("bad size {}x{}; width must be a multiple of 8", panel.width, panel.height)));
+        }
+        if let Some(lut) = &panel.lut {
+            let lut = path.parent().unwrap_or(Path::new("")).join(lut);
+            panel.waveform = fs::read(&lut).map_err(|err| fail(format!("{}: {err}", lut.display())))?;
+        }
+        Ok(panel)
+    }
+
+    /// Finds a descriptor: `panel` is either the path of one, or a name
+    /// among the descriptors in `dir`.
+    pub fn find(panel: &str, dir: &Path) -> Result<Self, String> {
+        let path = Path::new(panel);
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            return PanelDescriptor::load(path);
+        }
+        load_dir(dir)
+            .remove(panel)
+            .or_else(|| presets().into_iter().find(|preset| preset.name == panel))
+            .ok_or_else(|| format!("no panel {panel:?} in {} or built in", dir.display()))
+    }
+
+    /// Pins the panel is wired to.
+    pub fn pins(&self) -> Pins {
+        self.pins.unwrap_or(Pins::INKY_PHAT)
+    }
+}
+
+/// The built-in descriptors: Waveshare HATs on SSD1680 controllers, whose
+/// waveforms come from OTP. Glass 122 pixels across is driven as 128, the
+/// RAM's width, and the last 6 columns aren't visible.
+pub fn presets() -> Vec<PanelDescriptor> {
+    let preset = |name: &str, width: u32, height: u32, red_ram: u8| {
+        let last_gate = (height - 1) as u8;
+        let gates_high = ((height - 1) >> 8) as u8;
+        let step = |command, data: &[u8]| Step {
+            command,
+            data: data.to_vec(),
+            wait: false,
+            delay: 0,
+        };
+        PanelDescriptor {
+            name: name.to_string(),
+            width,
+            height,
+            init: vec![
+                Step {
+                    wait: true,
+                    ..step(0x12, &[])
+                },
+                step(0x01, &[last_gate, gates_high, 0x00]),
+                step(0x11, &[0x03]),
+                step(0x44, &[0x00, (width / 8 - 1) as u8]),
+                step(0x45, &[0x00, 0x00, last_gate, gates_high]),
+                step(0x3C, &[0x05]),
+                // Black-and-white glass has no red pixels; its red RAM is read as blank
+                step(0x21, &[red_ram, 0x80]),
+                // Built-in temperature sensor
+                step(0x18, &[0x80]),
+                // Load the temperature and the OTP waveform at every refresh
+                step(0x22, &[0xF7]),
+            ],
+            lut: None,
+            waveform: Vec::new(),
+            pins: Some(Pins::WAVESHARE),
+        }
+    };
+    vec![
+        preset("waveshare-2in13-v3", 128, 250, 0x40),
+        preset("waveshare-2in13b-v4", 128, 250, 0x00),
+        preset("waveshare-2in9-v2", 128, 296, 0x40),
+        preset("waveshare-2in9b-v4", 128, 296, 0x00),
+    ]
+}
+
+/// Every descriptor in `dir`, by name. Files that don't parse are reported
+/// and left out, so one bad contribution doesn't hide the rest.
+pub fn load_dir(dir: &Path) -> BTreeMap<String, PanelDescriptor> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return BTreeMap::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+    let mut panels = BTreeMap::new();
+    for path in paths {
+        match PanelDescriptor::load(&path) {
+            Ok(panel) => {
+                panels.insert(panel.name.clone(), panel);
+            }
+            Err(err) => eprintln!("Skipping panel {err}"),
+        }
+    }
+    panels
+}
+
+/// Wraps a driver and, if given a descriptor, initialises and sizes the panel
+/// from it; otherwise everything goes straight to the driver.
+pub struct Described<E> {
+    epd: E,
+    panel: Option<PanelDescriptor>,
+}
+
+impl<E> Described<E> {
+    pub fn new(epd: E, panel: Option<PanelDescriptor>) -> Self {
+        Described { epd, panel }
+    }
+
+    pub fn panel(&self) -> Option<&PanelDescriptor> {
+        self.panel.as_ref()
+    }
+
+    pub fn into_inner(self) -> E {
+        self.epd
+    }
+}
+
+impl<SPI, CS, BUSY, DC, RESET, SPIE, GPIOE> Described<InkyPhat<SPI, CS, BUSY, DC, RESET>>
+where
+    SPI: Write<u8, Error = SPIE>,
+    CS: OutputPin<Error = GPIOE>,
+    BUSY: InputPin<Error = GPIOE>,
+    DC: OutputPin<Error = GPIOE>,
+    RESET: OutputPin<Error = GPIOE>,
+    SPIE: Debug,
+    GPIOE: Debug,
+{
+    /// `InkyPhat::deghost`, finishing with the descriptor's init sequence
+    /// rather than the stock one.
+    pub fn deghost<D: DelayMs<u8>>(&mut self, cycles: u8, delay: &mut D) -> Result<(), InkyError<SPIE, GPIOE>> {
+        self.epd.deghost(cycles, delay)?;
+        if self.panel.is_some() {
+            self.init(delay)?;
+        }
+        Ok(())
+    }
+}
+
+impl<E> Deref for Described<E> {
+    type Target = E;
+
+    fn deref(&self) -> &E {
+        &self.epd
+    }
+}
+
+impl<E> DerefMut for Described<E> {
+    fn deref_mut(&mut self) -> &mut E {
+        &mut self.epd
+    }
+}
+
+impl<SPI, CS, BUSY, DC, RESET, SPIE, GPIOE> EpdController for Described<InkyPhat<SPI, CS, BUSY, DC, RESET>>
+where
+    SPI: Write<u8, Error = SPIE>,
+    CS: OutputPin<Error = GPIOE>,
+    BUSY: InputPin<Error = GPIOE>,
+    DC: OutputPin<Error = GPIOE>,
+    RESET: OutputPin<Error = GPIOE>,
+    SPIE: Debug,
+    GPIOE: Debug,
+{
+    type Error = InkyError<SPIE, GPIOE>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        match &self.panel {
+            Some(panel) => (panel.width, panel.height),
+            None => self.epd.dimensions(),
+        }
+    }
+
+    fn colors(&self) -> ColorCapability {
+        self.epd.colors()
+    }
+
+    fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        let Some(panel) = &self.panel else {
+            return self.epd.init(delay);
+        };
+        self.epd.reset(delay)?;
+        self.epd.wait_idle(delay)?;
+        for step in &panel.init {
+            self.epd.command(step.command, &step.data)?;
+            if step.wait {
+                self.epd.wait_idle(delay)?;
+            }
+            if step.delay > 0 {
+                delay.delay_ms(step.delay);
+            }
+        }
+        if !panel.waveform.is_empty() {
+            self.epd.write_lut(&panel.waveform)?;
+        }
+        Ok(())
+    }
+
+    fn write_planes(&mut self, bw: &[u8], red: &[u8]) -> Result<(), Self::Error> {
+        self.epd.write_planes(bw, red)
+    }
+
+    fn refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.epd.refresh(delay)
+    }
+
+    fn refresh_fast<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.epd.refresh_fast(delay)
+    }
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        self.epd.sleep()
+    }
+}
+
+impl<SPI, CS, BUSY, DC, RESET, SPIE, GPIOE> RowWriter for Described<InkyPhat<SPI, CS, BUSY, DC, RESET>>
+where
+    SPI: Write<u8, Error = SPIE>,
+    CS: OutputPin<Error = GPIOE>,
+    BUSY: InputPin<Error = GPIOE>,
+    DC: OutputPin<Error = GPIOE>,
+    RESET: OutputPin<Error = GPIOE>,
+    SPIE: Debug,
+    GPIOE: Debug,
+{
+    fn write_rows(&mut self, first_row: u32, bw: &[u8], red: &[u8]) -> Result<(), Self::Error> {
+        self.epd.write_rows(first_row, bw, red)
+    }
+}
+
+impl<SPI, CS, BUSY, DC, RESET, SPIE, GPIOE> GrayWriter for Described<InkyPhat<SPI, CS, BUSY, DC, RESET>>
+where
+    SPI: Write<u8, Error = SPIE>,
+    CS: OutputPin<Error = GPIOE>,
+    BUSY: InputPin<Error = GPIOE>,
+    DC: OutputPin<Error = GPIOE>,
+    RESET: OutputPin<Error = GPIOE>,
+    SPIE: Debug,
+    GPIOE: Debug,
+{
+    fn show_gray<D: DelayMs<u8>>(&mut self, msb: &[u8], lsb: &[u8], delay: &mut D) -> Result<(), Self::Error> {
+        // The driver's own init follows the grey frame; the descriptor's has to as well
+        self.epd.show_gray(msb, lsb, delay)?;
+        if self.panel.is_some() {
+            self.init(delay)?;
+        }
+        Ok(())
+    }
+}
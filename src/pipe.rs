@@ -0,0 +1,188 @@
+// Text read line by line from a pipe, shown as a scrolling log.
+//
+//     journalctl -f | rust_raspi pipe
+//
+// New lines go at the bottom, word-wrapped to the panel, and older ones
+// scroll off the top. Lines arriving in a burst are gathered into one
+// refresh, and refreshes are spaced at least `interval` apart; most are fast
+// ones, with a full refresh every `full_every` to clear the ghosting. When
+// the input ends, what is on the panel is given a last full refresh.
+
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_graphics::mono_font::{MonoFont, MonoTextStyle};
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Baseline, Text};
+use embedded_hal::blocking::delay::DelayMs;
+use profont::PROFONT_9_POINT;
+
+use crate::epd::EpdController;
+use crate::framebuffer::{Color, Framebuffer};
+use crate::text;
+
+// Input lines kept, far more than any panel can show
+const HISTORY: usize = 256;
+// Spaces a tab stands for
+const TAB: &str = "    ";
+
+#[derive(Clone, Copy, Debug)]
+pub struct PipeOptions {
+    /// Least time between refreshes
+    pub interval: Duration,
+    /// Fast refreshes between full ones
+    pub full_every: u32,
+    pub font: &'static MonoFont<'static>,
+}
+
+impl Default for PipeOptions {
+    fn default() -> Self {
+        PipeOptions {
+            interval: Duration::from_secs(5),
+            full_every: 10,
+            font: &PROFONT_9_POINT,
+        }
+    }
+}
+
+/// The last lines of a log, drawn newest at the bottom.
+pub struct LogView {
+    lines: VecDeque<String>,
+    font: &'static MonoFont<'static>,
+}
+
+impl LogView {
+    pub fn new(font: &'static MonoFont<'static>) -> Self {
+        LogView {
+            lines: VecDeque::new(),
+            font,
+        }
+    }
+
+    /// Adds a line at the bottom. Tabs become spaces, and terminal colour
+    /// codes and other control characters are dropped.
+    pub fn push(&mut self, line: &str) {
+        if self.lines.len() == HISTORY {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(clean(line));
+    }
+
+    pub fn draw(&self, fb: &mut Framebuffer) {
+        fb.clear(Color::White);
+        let columns = text::columns(self.font, fb.width());
+        let line_height = self.font.character_size.height;
+        let rows = (fb.height() / line_height.max(1)) as usize;
+        // Newest first, and each line's wrapped rows last first, until the panel is full
+        let mut shown = Vec::with_capacity(rows);
+        for line in self.lines.iter().rev() {
+            let wrapped: Vec<&str> = text::wrap(line, columns).collect();
+            // An empty line still takes a row
+            let wrapped = if wrapped.is_empty() { vec![""] } else { wrapped };
+            shown.extend(wrapped.into_iter().rev());
+            if shown.len() >= rows {
+                break;
+            }
+        }
+        shown.truncate(rows);
+        let style = MonoTextStyle::new(self.font, Color::Black);
+        for (row, line) in shown.iter().rev().enumerate() {
+            let top = Point::new(0, (row as u32 * line_height) as i32);
+            let Ok(_) = Text::with_baseline(line, top, style, Baseline::Top).draw(fb);
+        }
+    }
+}
+
+// Printable text only: CSI sequences (colours, cursor moves) and other
+// control characters are dropped, tabs expanded
+fn clean(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\t' => out.push_str(TAB),
+            // ESC [ parameters, then a final byte from @ to ~
+            '\x1b' => {
+                if chars.clone().next() == Some('[') {
+                    chars.next();
+                    for ch in chars.by_ref() {
+                        if ('@'..='~').contains(&ch) {
+                            break;
+                        }
+                    }
+                }
+            }
+            ch if ch.is_control() => {}
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Shows `input` on `epd` line by line until it ends. Reading happens on a
+/// thread of its own, so a slow refresh never holds the writer up.
+pub fn run<E, D>(
+    input: impl BufRead + Send + 'static,
+    epd: &mut E,
+    fb: &mut Framebuffer,
+    delay: &mut D,
+    options: &PipeOptions,
+) -> Result<(), E::Error>
+where
+    E: EpdController,
+    D: DelayMs<u8>,
+{
+    let (lines, received) = mpsc::channel();
+    thread::spawn(move || {
+        for line in input.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if lines.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut view = LogView::new(options.font);
+    let mut refreshed: Option<Instant> = None;
+    let mut fast = 0;
+    // Lines in the view that aren't on the panel yet
+    let mut dirty = false;
+    loop {
+        // Until the next refresh is allowed, or for ever if there is nothing to show
+        let wait = match (dirty, refreshed) {
+            (true, Some(at)) => options.interval.saturating_sub(at.elapsed()),
+            (true, None) => Duration::ZERO,
+            (false, _) => Duration::MAX,
+        };
+        // A steady stream of lines mustn't put the refresh off for ever
+        if !wait.is_zero() {
+            match received.recv_timeout(wait) {
+                Ok(line) => {
+                    view.push(&line);
+                    dirty = true;
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        view.draw(fb);
+        if fast < options.full_every {
+            epd.write_planes(fb.bw_plane(), fb.red_plane())?;
+            epd.refresh_fast(delay)?;
+            fast += 1;
+        } else {
+            epd.show(fb, delay)?;
+            fast = 0;
+        }
+        refreshed = Some(Instant::now());
+        dirty = false;
+    }
+    view.draw(fb);
+    epd.show(fb, delay)
+}
@@ -0,0 +1,96 @@
+// The battery the Pi runs from, when it runs from one.
+//
+// Whatever measures it (an ADC on a voltage divider, a UPS HAT) publishes a
+// `Battery` here, and layouts get the latest as variables:
+//
+//     { type = "icon", icon = "{battery_icon}" },
+//     { type = "text", text = "{battery}%" },
+//
+// `battery_voltage` and `battery_charging` are there too when the source
+// knows them, and `battery_faults` lists anything it reports wrong. A
+// reading that hasn't been refreshed in `STALE` is dropped, so a monitor
+// that has died doesn't leave a full battery showing.
+
+use std::io;
+use std::process::Command;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::layout::Data;
+use crate::widgets::icon::Icon;
+
+#[cfg(feature = "i2c")]
+pub mod pijuice;
+#[cfg(feature = "i2c")]
+pub mod rtc;
+
+/// How long a published reading is shown for.
+pub const STALE: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Battery {
+    /// Charge, 0 to 100
+    pub percent: f32,
+    pub voltage: Option<f32>,
+    pub charging: Option<bool>,
+    /// Whatever the source reports as wrong, in its own words
+    pub faults: Vec<String>,
+}
+
+impl Battery {
+    pub fn icon(&self) -> Icon {
+        if self.charging == Some(true) {
+            Icon::BatteryCharging
+        } else {
+            Icon::battery(self.percent.round().clamp(0.0, 100.0) as u8)
+        }
+    }
+
+    /// The layout variables for this reading.
+    pub fn variables(&self) -> Data {
+        let mut data = Data::new();
+        data.insert("battery".to_string(), Value::from(self.percent.round() as i64));
+        let icon = serde_json::to_value(self.icon()).unwrap_or_default();
+        data.insert("battery_icon".to_string(), icon);
+        if let Some(voltage) = self.voltage {
+            data.insert("battery_voltage".to_string(), Value::from(format!("{voltage:.2}")));
+        }
+        if let Some(charging) = self.charging {
+            data.insert("battery_charging".to_string(), Value::from(charging));
+        }
+        data.insert("battery_faults".to_string(), Value::from(self.faults.join(", ")));
+        data
+    }
+}
+
+static LATEST: Mutex<Option<(Battery, Instant)>> = Mutex::new(None);
+
+/// Makes `battery` the reading pages see.
+pub fn publish(battery: Battery) {
+    *LATEST.lock().unwrap_or_else(PoisonError::into_inner) = Some((battery, Instant::now()));
+}
+
+/// The last published reading, unless it is older than `STALE`.
+pub fn latest() -> Option<Battery> {
+    LATEST
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .filter(|(_, published)| published.elapsed() < STALE)
+        .map(|(battery, _)| battery.clone())
+}
+
+/// Runs `command`, a program and its arguments, to halt the Pi; nothing
+/// happens if it is empty.
+pub fn halt(command: &[String]) -> io::Result<()> {
+    let [program, args @ ..] = command else {
+        return Ok(());
+    };
+    let status = Command::new(program).args(args).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("{program} failed: {status}")));
+    }
+    Ok(())
+}
@@ -0,0 +1,330 @@
+// The PiJuice battery HAT, over I2C: charge level, battery voltage, whether
+// it is charging, and the fault flags its microcontroller keeps.
+//
+// Readings are published to `power` like any other battery. Below
+// `low_battery` percent, with nothing charging it, the daemon puts up the
+// low-battery screen, sends the panel into deep sleep and halts the Pi; the
+// PiJuice cuts the power `power_off_delay` seconds later, once the halt is
+// done, and with `wake_on_charge` set turns it back on when the battery has
+// charged that far.
+//
+//     [pijuice]
+//     low_battery = 10
+//     wake_on_charge = 50
+//
+// The protocol is the one in PiJuice's own Python library: a command byte,
+// then data bytes followed by a checksum, the data XORed together and with
+// 0xFF.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use linux_embedded_hal::I2cdev;
+use serde::Deserialize;
+
+use crate::config::ScreenConfig;
+use crate::metrics;
+use crate::power::{self, Battery};
+
+pub const DEFAULT_BUS: &str = "/dev/i2c-1";
+pub const DEFAULT_ADDRESS: u8 = 0x14;
+
+const STATUS: u8 = 0x40;
+const CHARGE_LEVEL: u8 = 0x41;
+const FAULT_EVENT: u8 = 0x44;
+const BATTERY_VOLTAGE: u8 = 0x49;
+const POWER_OFF: u8 = 0x62;
+const WAKEUP_ON_CHARGE: u8 = 0x63;
+// Written to WAKEUP_ON_CHARGE to turn it off
+const WAKEUP_DISABLED: u8 = 0x7F;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PiJuiceConfig {
+    pub bus: PathBuf,
+    pub address: u8,
+    /// Seconds between readings
+    pub interval: u64,
+    /// Charge in percent below which the Pi is halted; 0 never halts
+    pub low_battery: u8,
+    /// Shown before halting
+    pub screen: ScreenConfig,
+    /// Seconds after the halt starts that the PiJuice cuts the power
+    pub power_off_delay: u8,
+    /// Charge in percent at which the PiJuice turns the Pi back on
+    pub wake_on_charge: Option<u8>,
+    /// Program and arguments that halt the Pi
+    pub halt_command: Vec<String>,
+}
+
+impl Default for PiJuiceConfig {
+    fn default() -> Self {
+        PiJuiceConfig {
+            bus: PathBuf::from(DEFAULT_BUS),
+            address: DEFAULT_ADDRESS,
+            interval: 60,
+            low_battery: 10,
+            screen: ScreenConfig {
+                text: Some("Battery low\nCharge me".to_string()),
+                ..ScreenConfig::default()
+            },
+            power_off_delay: 60,
+            wake_on_charge: None,
+            halt_command: vec!["systemctl".to_string(), "poweroff".to_string()],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PiJuiceError<E> {
+    Bus(E),
+    /// A reply whose checksum didn't match, usually noise on the bus
+    Checksum { command: u8 },
+}
+
+impl<E: fmt::Debug> fmt::Display for PiJuiceError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PiJuiceError::Bus(error) => write!(f, "I2C failed: {error:?}"),
+            PiJuiceError::Checksum { command } => write!(f, "bad checksum in reply to {command:#04x}"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for PiJuiceError<E> {}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BatteryState {
+    #[default]
+    Normal,
+    ChargingFromIn,
+    ChargingFromIo,
+    NotPresent,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputState {
+    #[default]
+    NotPresent,
+    Bad,
+    Weak,
+    Present,
+}
+
+impl InputState {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0x03 {
+            0 => InputState::NotPresent,
+            1 => InputState::Bad,
+            2 => InputState::Weak,
+            _ => InputState::Present,
+        }
+    }
+}
+
+/// The status byte.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Some fault flag is set; see `PiJuice::faults`
+    pub fault: bool,
+    pub battery: BatteryState,
+    /// The HAT's own micro USB input
+    pub power_input: InputState,
+    /// The Pi's 5V rail, when the Pi is powered the usual way
+    pub io_input: InputState,
+}
+
+impl Status {
+    fn from_byte(byte: u8) -> Self {
+        Status {
+            fault: byte & 0x01 != 0,
+            battery: match (byte >> 2) & 0x03 {
+                0 => BatteryState::Normal,
+                1 => BatteryState::ChargingFromIn,
+                2 => BatteryState::ChargingFromIo,
+                _ => BatteryState::NotPresent,
+            },
+            power_input: InputState::from_bits(byte >> 4),
+            io_input: InputState::from_bits(byte >> 6),
+        }
+    }
+
+    pub fn charging(&self) -> bool {
+        matches!(self.battery, BatteryState::ChargingFromIn | BatteryState::ChargingFromIo)
+    }
+}
+
+/// The fault byte, by the PiJuice's names for its flags.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Faults(pub u8);
+
+impl Faults {
+    const NAMES: [(u8, &'static str); 5] = [
+        (0x01, "button_power_off"),
+        (0x02, "forced_power_off"),
+        (0x04, "forced_sys_power_off"),
+        (0x08, "watchdog_reset"),
+        (0x20, "battery_profile_invalid"),
+    ];
+
+    /// The flags set, including the charging temperature when it isn't normal.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = Self::NAMES
+            .iter()
+            .filter(|(bit, _)| self.0 & bit != 0)
+            .map(|&(_, name)| name)
+            .collect();
+        match self.0 >> 6 {
+            1 => names.push("charging_temperature_suspend"),
+            2 => names.push("charging_temperature_cool"),
+            3 => names.push("charging_temperature_warm"),
+            _ => {}
+        }
+        names
+    }
+}
+
+// XOR of the bytes, and 0xFF
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0xFF, |sum, byte| sum ^ byte)
+}
+
+/// A PiJuice at `address` on `i2c`.
+pub struct PiJuice<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C, E> PiJuice<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        PiJuice { i2c, address }
+    }
+
+    pub fn status(&mut self) -> Result<Status, PiJuiceError<E>> {
+        let mut status = [0];
+        self.read(STATUS, &mut status)?;
+        Ok(Status::from_byte(status[0]))
+    }
+
+    /// Charge in percent.
+    pub fn charge_level(&mut self) -> Result<u8, PiJuiceError<E>> {
+        let mut charge = [0];
+        self.read(CHARGE_LEVEL, &mut charge)?;
+        Ok(charge[0])
+    }
+
+    /// Battery voltage in volts.
+    pub fn battery_voltage(&mut self) -> Result<f32, PiJuiceError<E>> {
+        let mut millivolts = [0; 2];
+        self.read(BATTERY_VOLTAGE, &mut millivolts)?;
+        let millivolts = u16::from_le_bytes(millivolts);
+        Ok(f32::from(millivolts) / 1000.0)
+    }
+
+    pub fn faults(&mut self) -> Result<Faults, PiJuiceError<E>> {
+        let mut faults = [0];
+        self.read(FAULT_EVENT, &mut faults)?;
+        Ok(Faults(faults[0]))
+    }
+
+    /// Cuts the Pi's power in `delay` seconds.
+    pub fn power_off(&mut self, delay: u8) -> Result<(), PiJuiceError<E>> {
+        self.write(POWER_OFF, &[delay])
+    }
+
+    /// Turns the Pi back on once the battery reaches `percent`; `None` doesn't.
+    pub fn set_wakeup_on_charge(&mut self, percent: Option<u8>) -> Result<(), PiJuiceError<E>> {
+        self.write(WAKEUP_ON_CHARGE, &[percent.map_or(WAKEUP_DISABLED, |percent| percent.min(100))])
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    // Reads `data.len()` bytes of reply to `command`, and checks its checksum
+    fn read(&mut self, command: u8, data: &mut [u8]) -> Result<(), PiJuiceError<E>> {
+        let mut reply = [0; 8];
+        let reply = &mut reply[..data.len() + 1];
+        self.i2c.write_read(self.address, &[command], reply).map_err(PiJuiceError::Bus)?;
+        let (payload, sum) = reply.split_at(data.len());
+        if checksum(payload) != sum[0] {
+            return Err(PiJuiceError::Checksum { command });
+        }
+        data.copy_from_slice(payload);
+        Ok(())
+    }
+
+    fn write(&mut self, command: u8, data: &[u8]) -> Result<(), PiJuiceError<E>> {
+        let mut frame = vec![command];
+        frame.extend_from_slice(data);
+        frame.push(checksum(data));
+        self.i2c.write(self.address, &frame).map_err(PiJuiceError::Bus)
+    }
+}
+
+/// Opens the PiJuice on a Linux I2C bus.
+pub fn open(bus: &Path, address: u8) -> io::Result<PiJuice<I2cdev>> {
+    let i2c = I2cdev::new(bus).map_err(|err| io::Error::other(format!("{}: {err}", bus.display())))?;
+    Ok(PiJuice::new(i2c, address))
+}
+
+/// Reads the PiJuice every `interval` seconds, on a thread of its own, and
+/// publishes the battery to `power`. `on_low` is called once, the first time
+/// the charge is below `low_battery` with nothing charging it.
+pub fn monitor(config: &PiJuiceConfig, on_low: impl FnOnce() + Send + 'static) -> io::Result<()> {
+    let mut pijuice = open(&config.bus, config.address)?;
+    let config = config.clone();
+    let source = format!("pijuice {}@{:#04x}", config.bus.display(), config.address);
+    thread::spawn(move || {
+        let mut on_low = Some(on_low);
+        let mut faults = Faults::default();
+        loop {
+            let read = metrics::timed(&source, || {
+                let status = pijuice.status()?;
+                let charge = pijuice.charge_level()?;
+                let voltage = pijuice.battery_voltage()?;
+                let now = if status.fault { pijuice.faults()? } else { Faults::default() };
+                Ok::<_, PiJuiceError<_>>((status, charge, voltage, now))
+            });
+            match read {
+                Ok((status, charge, voltage, now)) => {
+                    if now != faults {
+                        if now.0 != 0 {
+                            eprintln!("{source}: faults {}", now.names().join(", "));
+                        }
+                        faults = now;
+                    }
+                    power::publish(Battery {
+                        percent: f32::from(charge),
+                        voltage: Some(voltage),
+                        charging: Some(status.charging()),
+                        faults: faults.names().iter().map(|name| name.to_string()).collect(),
+                    });
+                    let low = charge < config.low_battery && !status.charging() && status.battery != BatteryState::NotPresent;
+                    if low && let Some(on_low) = on_low.take() {
+                        eprintln!("{source}: battery at {charge}%, below {}%", config.low_battery);
+                        on_low();
+                    }
+                }
+                Err(err) => eprintln!("{source}: {err}"),
+            }
+            thread::sleep(Duration::from_secs(config.interval.max(1)));
+        }
+    });
+    Ok(())
+}
+
+/// Has the PiJuice cut the power once the Pi is down, and halts it.
+pub fn halt(config: &PiJuiceConfig) -> io::Result<()> {
+    let mut pijuice = open(&config.bus, config.address)?;
+    pijuice.set_wakeup_on_charge(config.wake_on_charge).map_err(io::Error::other)?;
+    pijuice.power_off(config.power_off_delay).map_err(io::Error::other)?;
+    power::halt(&config.halt_command)
+}
@@ -0,0 +1,238 @@
+// Waking the Pi on a DS3231's alarm, for signs that run off a battery or a
+// solar panel and spend most of their time switched off.
+//
+// `rust_raspi once --power-off` draws the page that is due, sets alarm 1 on
+// the RTC for when the next one is, flushes the filesystems and halts. The
+// DS3231 pulls its INT/SQW pin low when the alarm goes off, which has to be
+// wired to whatever switches the Pi's power back on (a HAT with a power
+// controller, or a latching switch); run the same command from a service at
+// boot to close the loop. With `last_frame` set, a page that hasn't changed
+// while the Pi was off isn't refreshed again after boot.
+//
+// The alarm is set on the RTC's own clock, so it doesn't matter whether the
+// system clock got synced this boot. The kernel's driver for the chip
+// (`dtoverlay=i2c-rtc,ds3231`) claims its address, so leave it out.
+//
+//     [rtc_wake]
+//     min_sleep = 300
+//     max_sleep = 86400
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, TimeDelta, Timelike, Utc};
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use linux_embedded_hal::I2cdev;
+use serde::Deserialize;
+
+use crate::power;
+
+pub const DEFAULT_BUS: &str = "/dev/i2c-1";
+pub const DEFAULT_ADDRESS: u8 = 0x68;
+/// Longest sleep an alarm can be set for: it matches on the day of the
+/// month, so it must go off before the same day comes round again.
+pub const MAX_ALARM: Duration = Duration::from_secs(28 * 24 * 60 * 60);
+
+const REG_SECONDS: u8 = 0x00;
+const REG_ALARM1: u8 = 0x07;
+const REG_CONTROL: u8 = 0x0E;
+const REG_STATUS: u8 = 0x0F;
+
+const CONTROL_INTCN: u8 = 1 << 2;
+const CONTROL_A2IE: u8 = 1 << 1;
+const CONTROL_A1IE: u8 = 1 << 0;
+// Oscillator stopped: the time was lost, usually to a flat backup cell
+const STATUS_OSF: u8 = 1 << 7;
+const STATUS_A2F: u8 = 1 << 1;
+const STATUS_A1F: u8 = 1 << 0;
+const HOURS_12H: u8 = 1 << 6;
+const HOURS_PM: u8 = 1 << 5;
+const MONTH_CENTURY: u8 = 1 << 7;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RtcWakeConfig {
+    pub bus: PathBuf,
+    pub address: u8,
+    /// Shortest time off, in seconds, however soon the next page is due
+    pub min_sleep: u64,
+    /// Longest time off, in seconds; at most `MAX_ALARM`
+    pub max_sleep: u64,
+    /// Program and arguments that halt the Pi
+    pub halt_command: Vec<String>,
+}
+
+impl Default for RtcWakeConfig {
+    fn default() -> Self {
+        RtcWakeConfig {
+            bus: PathBuf::from(DEFAULT_BUS),
+            address: DEFAULT_ADDRESS,
+            min_sleep: 300,
+            max_sleep: 24 * 60 * 60,
+            halt_command: vec!["systemctl".to_string(), "poweroff".to_string()],
+        }
+    }
+}
+
+impl RtcWakeConfig {
+    /// How long to stay off when the next page is due in `wait`.
+    pub fn sleep(&self, wait: Duration) -> Duration {
+        let max = Duration::from_secs(self.max_sleep).min(MAX_ALARM);
+        wait.max(Duration::from_secs(self.min_sleep)).min(max)
+    }
+}
+
+#[derive(Debug)]
+pub enum RtcError<E> {
+    Bus(E),
+    /// The registers held something that isn't a time
+    InvalidTime,
+}
+
+impl<E: fmt::Debug> fmt::Display for RtcError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RtcError::Bus(error) => write!(f, "I2C failed: {error:?}"),
+            RtcError::InvalidTime => write!(f, "the RTC doesn't hold a valid time"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for RtcError<E> {}
+
+fn bcd(value: u32) -> u8 {
+    (((value / 10) << 4) | (value % 10)) as u8
+}
+
+fn from_bcd(byte: u8) -> u32 {
+    u32::from(byte >> 4) * 10 + u32::from(byte & 0x0F)
+}
+
+// The hours register, in whichever of its 12 and 24 hour modes it is in
+fn hours(byte: u8) -> u32 {
+    if byte & HOURS_12H == 0 {
+        return from_bcd(byte & 0x3F);
+    }
+    let hour = from_bcd(byte & 0x1F) % 12;
+    if byte & HOURS_PM != 0 { hour + 12 } else { hour }
+}
+
+/// A DS3231 at `address` on `i2c`, keeping UTC.
+pub struct Ds3231<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C, E> Ds3231<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        Ds3231 { i2c, address }
+    }
+
+    pub fn now(&mut self) -> Result<NaiveDateTime, RtcError<E>> {
+        let mut time = [0; 7];
+        self.i2c
+            .write_read(self.address, &[REG_SECONDS], &mut time)
+            .map_err(RtcError::Bus)?;
+        let [seconds, minutes, hour, _weekday, day, month, year] = time;
+        let century = if month & MONTH_CENTURY != 0 { 2100 } else { 2000 };
+        NaiveDate::from_ymd_opt(
+            century + from_bcd(year) as i32,
+            from_bcd(month & 0x1F),
+            from_bcd(day & 0x3F),
+        )
+        .and_then(|date| date.and_hms_opt(hours(hour), from_bcd(minutes & 0x7F), from_bcd(seconds & 0x7F)))
+        .ok_or(RtcError::InvalidTime)
+    }
+
+    /// Sets the clock (in 24 hour mode) and clears the lost-time flag.
+    pub fn set_time(&mut self, at: NaiveDateTime) -> Result<(), RtcError<E>> {
+        let year = at.year() - 2000;
+        if !(0..200).contains(&year) {
+            return Err(RtcError::InvalidTime);
+        }
+        let century = if year >= 100 { MONTH_CENTURY } else { 0 };
+        self.i2c
+            .write(
+                self.address,
+                &[
+                    REG_SECONDS,
+                    bcd(at.second()),
+                    bcd(at.minute()),
+                    bcd(at.hour()),
+                    bcd(at.weekday().number_from_monday()),
+                    bcd(at.day()),
+                    bcd(at.month()) | century,
+                    bcd(year as u32 % 100),
+                ],
+            )
+            .map_err(RtcError::Bus)?;
+        let status = self.read(REG_STATUS)?;
+        self.write(REG_STATUS, status & !STATUS_OSF)
+    }
+
+    /// The oscillator stopped at some point, so the time can't be trusted.
+    pub fn lost_time(&mut self) -> Result<bool, RtcError<E>> {
+        Ok(self.read(REG_STATUS)? & STATUS_OSF != 0)
+    }
+
+    /// Sets alarm 1 for `at`, to the second, and has it pull INT/SQW low.
+    /// Alarm 2 is turned off.
+    pub fn set_alarm(&mut self, at: NaiveDateTime) -> Result<(), RtcError<E>> {
+        // With A1M1 to A1M4 clear, the alarm matches date, hours, minutes and seconds
+        self.i2c
+            .write(
+                self.address,
+                &[REG_ALARM1, bcd(at.second()), bcd(at.minute()), bcd(at.hour()), bcd(at.day())],
+            )
+            .map_err(RtcError::Bus)?;
+        // A flag still set from the last alarm would hold INT/SQW low
+        let status = self.read(REG_STATUS)?;
+        self.write(REG_STATUS, status & !(STATUS_A1F | STATUS_A2F))?;
+        let control = self.read(REG_CONTROL)?;
+        self.write(REG_CONTROL, (control & !CONTROL_A2IE) | CONTROL_INTCN | CONTROL_A1IE)
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn read(&mut self, register: u8) -> Result<u8, RtcError<E>> {
+        let mut value = [0];
+        self.i2c
+            .write_read(self.address, &[register], &mut value)
+            .map_err(RtcError::Bus)?;
+        Ok(value[0])
+    }
+
+    fn write(&mut self, register: u8, value: u8) -> Result<(), RtcError<E>> {
+        self.i2c.write(self.address, &[register, value]).map_err(RtcError::Bus)
+    }
+}
+
+/// Opens the DS3231 on a Linux I2C bus.
+pub fn open(bus: &Path, address: u8) -> io::Result<Ds3231<I2cdev>> {
+    let i2c = I2cdev::new(bus).map_err(|err| io::Error::other(format!("{}: {err}", bus.display())))?;
+    Ok(Ds3231::new(i2c, address))
+}
+
+/// Sets the alarm for `sleep` from now, flushes the filesystems and halts.
+pub fn power_off(config: &RtcWakeConfig, sleep: Duration) -> io::Result<()> {
+    let mut rtc = open(&config.bus, config.address)?;
+    if rtc.lost_time().map_err(io::Error::other)? {
+        eprintln!("The RTC lost its time; setting it from the system clock");
+        rtc.set_time(Utc::now().naive_utc()).map_err(io::Error::other)?;
+    }
+    // Whole seconds, rounded up so a short sleep can't become no sleep at all
+    let sleep = TimeDelta::seconds(sleep.as_secs() as i64 + i64::from(sleep.subsec_nanos() > 0));
+    let wake = rtc.now().map_err(io::Error::other)? + sleep;
+    rtc.set_alarm(wake).map_err(io::Error::other)?;
+    println!("Waking at {wake} UTC, by the RTC");
+    // The frame store and the refresh counters were written moments ago
+    unsafe { libc::sync() };
+    power::halt(&config.halt_command)
+}
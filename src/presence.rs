@@ -0,0 +1,168 @@
+// Whether anyone is home, for switching pages on presence.
+//
+// Two sources, either or both: phones seen on the local network (the kernel's
+// ARP cache, optionally kept fresh by pinging them), and presence states
+// published over MQTT, e.g. Home Assistant `person.*` entities. Someone is
+// home if any source says so.
+
+use std::collections::HashMap;
+use std::fs;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::metrics;
+use crate::mqtt::{self, MqttOptions};
+
+const ARP_TABLE: &str = "/proc/net/arp";
+// ATF_COM: the entry has a resolved hardware address
+const ARP_COMPLETE: u32 = 0x2;
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PresenceConfig {
+    /// MAC addresses of residents' phones, looked up in the ARP cache
+    pub macs: Vec<String>,
+    /// Addresses to ping before each check so that sleeping phones stay in the ARP cache
+    pub ping: Vec<String>,
+    /// Seconds a phone may go unseen before it counts as away; phones drop off Wi-Fi when idle
+    pub away_after: Option<u64>,
+    /// States published over MQTT; needs the top-level `[mqtt]` broker
+    pub mqtt: Option<MqttPresenceConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttPresenceConfig {
+    /// Topics carrying one person's state each; wildcards allowed
+    pub topics: Vec<String>,
+    /// Payload meaning "home"; anything else means away
+    #[serde(default = "default_home_payload")]
+    pub home_payload: String,
+}
+
+fn default_home_payload() -> String {
+    "home".to_string()
+}
+
+/// Required presence for a schedule rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceState {
+    Home,
+    Away,
+}
+
+pub struct Presence {
+    macs: Vec<String>,
+    ping: Vec<String>,
+    away_after: Duration,
+    last_seen: Option<Instant>,
+    // Latest "is home" per MQTT topic, filled in by a background thread
+    mqtt: Option<Arc<Mutex<HashMap<String, bool>>>>,
+}
+
+impl Presence {
+    /// Starts the MQTT subscriber, if configured, in a background thread.
+    pub fn start(config: &PresenceConfig, broker: Option<&MqttOptions>) -> Self {
+        let mqtt = config.mqtt.as_ref().zip(broker).map(|(mqtt, broker)| {
+            let states = Arc::new(Mutex::new(HashMap::new()));
+            let shared = Arc::clone(&states);
+            let (mqtt, broker) = (mqtt.clone(), broker.clone());
+            thread::spawn(move || subscribe(&broker, &mqtt, &shared));
+            states
+        });
+        Presence {
+            macs: config.macs.iter().map(|mac| mac.to_ascii_lowercase()).collect(),
+            ping: config.ping.clone(),
+            away_after: Duration::from_secs(config.away_after.unwrap_or(600)),
+            last_seen: None,
+            mqtt,
+        }
+    }
+
+    pub fn state(&mut self) -> PresenceState {
+        if self.is_home() {
+            PresenceState::Home
+        } else {
+            PresenceState::Away
+        }
+    }
+
+    pub fn is_home(&mut self) -> bool {
+        if let Some(states) = &self.mqtt
+            && states.lock().unwrap_or_else(PoisonError::into_inner).values().any(|home| *home)
+        {
+            return true;
+        }
+        if self.macs.is_empty() {
+            return false;
+        }
+        for host in &self.ping {
+            ping(host);
+        }
+        if self.phone_in_arp_cache() {
+            self.last_seen = Some(Instant::now());
+        }
+        self.last_seen.is_some_and(|seen| seen.elapsed() < self.away_after)
+    }
+
+    fn phone_in_arp_cache(&self) -> bool {
+        let Ok(table) = metrics::timed("presence arp", || fs::read_to_string(ARP_TABLE)) else {
+            return false;
+        };
+        // IP address  HW type  Flags  HW address  Mask  Device
+        table.lines().skip(1).any(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let complete = fields
+                .get(2)
+                .and_then(|flags| u32::from_str_radix(flags.trim_start_matches("0x"), 16).ok())
+                .is_some_and(|flags| flags & ARP_COMPLETE != 0);
+            complete
+                && fields
+                    .get(3)
+                    .is_some_and(|mac| self.macs.iter().any(|wanted| mac.eq_ignore_ascii_case(wanted)))
+        })
+    }
+}
+
+// One ping with a short timeout; we only care that the kernel refreshes its ARP entry
+fn ping(host: &str) {
+    let _ = Command::new("ping")
+        .args(["-c", "1", "-W", "1", host])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+// Runs forever, reconnecting after errors
+fn subscribe(broker: &MqttOptions, config: &MqttPresenceConfig, states: &Mutex<HashMap<String, bool>>) {
+    loop {
+        let connected = metrics::timed("presence mqtt", || {
+            let mut client = mqtt::Client::connect(broker)?;
+            let topics: Vec<&str> = config.topics.iter().map(String::as_str).collect();
+            client.subscribe(&topics)?;
+            Ok::<_, std::io::Error>(client)
+        });
+        let result = connected.and_then(|mut client| -> std::io::Result<()> {
+            loop {
+                if let Some(message) = client.poll()? {
+                    let home = message.payload.trim_ascii() == config.home_payload.as_bytes();
+                    states
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .insert(message.topic, home);
+                }
+            }
+        });
+        if let Err(err) = result {
+            eprintln!("presence: mqtt {}:{}: {err}", broker.host, broker.port);
+            metrics::record("presence mqtt", Instant::now(), &Err::<(), _>(err));
+        }
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
@@ -0,0 +1,171 @@
+// Content pushed over the API, shown instead of the schedule until it expires.
+//
+// Every push has a TTL so that a one-off announcement can't outlive the
+// script that sent it: once it lapses the daemon goes back to its pages.
+// Alerts are the exception: they stay until acknowledged at the panel.
+
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::config::ScreenConfig;
+use crate::http::Request;
+use crate::images::Placement;
+
+/// Body of `POST /push` when sent as JSON. A plain-text body is taken as
+/// `text`, with the TTL in `?ttl=SECS`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PushRequest {
+    pub text: Option<String>,
+    /// Image on the daemon's filesystem
+    pub image: Option<std::path::PathBuf>,
+    /// How `image` is fitted; defaults to the config's `placement`
+    pub placement: Option<Placement>,
+    /// Seconds to keep it up; 0 means until replaced or cleared. Defaults to `push_ttl` in the config.
+    pub ttl: Option<u64>,
+    /// Stays up, with a red border, until someone presses the acknowledge button; `ttl` is ignored
+    pub alert: bool,
+    /// Sent back with the acknowledgement so the sender can tell alerts apart
+    pub id: Option<String>,
+}
+
+impl PushRequest {
+    pub fn parse(request: &Request) -> Result<Self, String> {
+        let is_json = request
+            .header("Content-Type")
+            .is_some_and(|kind| kind.starts_with("application/json"));
+        let mut push = if is_json {
+            serde_json::from_slice(&request.body).map_err(|err| err.to_string())?
+        } else {
+            let text = String::from_utf8(request.body.clone()).map_err(|_| "body is not UTF-8".to_string())?;
+            PushRequest {
+                text: Some(text),
+                ..PushRequest::default()
+            }
+        };
+        if let Some(ttl) = request.query_param("ttl") {
+            push.ttl = Some(ttl.parse().map_err(|_| format!("bad ttl {ttl:?}"))?);
+        }
+        if push.text.as_deref().is_none_or(str::is_empty) && push.image.is_none() {
+            return Err("nothing to show".to_string());
+        }
+        Ok(push)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Push {
+    pub screen: ScreenConfig,
+    /// `None` for pushes without a TTL
+    pub expires: Option<Instant>,
+    /// `Some(id)` for alerts; the id is empty if the sender didn't give one
+    pub alert: Option<String>,
+}
+
+impl Push {
+    pub fn new(request: PushRequest, default_ttl: u64) -> Self {
+        let ttl = if request.alert { 0 } else { request.ttl.unwrap_or(default_ttl) };
+        Push {
+            screen: ScreenConfig {
+                image: request.image,
+                placement: request.placement,
+                text: request.text,
+            },
+            expires: (ttl > 0).then(|| Instant::now() + Duration::from_secs(ttl)),
+            alert: request.alert.then(|| request.id.unwrap_or_default()),
+        }
+    }
+
+    pub fn is_alert(&self) -> bool {
+        self.alert.is_some()
+    }
+
+    /// Time left before it expires, `None` if it never does.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.expires.map(|expires| expires.saturating_duration_since(Instant::now()))
+    }
+
+    fn expired(&self) -> bool {
+        self.expires.is_some_and(|expires| Instant::now() >= expires)
+    }
+}
+
+/// What the API has pushed, shared between the HTTP thread and the refresh loop.
+#[derive(Default)]
+pub struct Inbox {
+    slot: Mutex<Slot>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct Slot {
+    push: Option<Push>,
+    // Bumped on every push and clear
+    generation: u64,
+    // Set by `wake`, cleared by the `wait` it cuts short
+    woken: bool,
+}
+
+impl Inbox {
+    /// Replaces whatever was pushed before, except that only another alert may replace an
+    /// unacknowledged alert. Returns whether `push` was taken.
+    pub fn push(&self, push: Push) -> bool {
+        let alert = push.is_alert();
+        self.update(|current| alert || !current.is_some_and(Push::is_alert), Some(push))
+            .is_ok()
+    }
+
+    /// Dismisses the current alert and hands it back; ordinary pushes are left alone.
+    pub fn acknowledge(&self) -> Option<Push> {
+        self.update(|current| current.is_some_and(Push::is_alert), None)
+            .ok()
+            .flatten()
+    }
+
+    /// Drops the current push, if any; returns whether there was one.
+    pub fn clear(&self) -> bool {
+        matches!(self.update(|_| true, None), Ok(Some(_)))
+    }
+
+    /// The live push, if any, tagged with a generation number that changes whenever it is replaced.
+    pub fn current(&self) -> (u64, Option<Push>) {
+        let mut slot = self.slot.lock().unwrap_or_else(PoisonError::into_inner);
+        if slot.push.as_ref().is_some_and(Push::expired) {
+            slot.push = None;
+        }
+        (slot.generation, slot.push.clone())
+    }
+
+    /// Sleeps for `timeout`, or until the generation moves past `seen` or someone calls `wake`.
+    pub fn wait(&self, seen: u64, timeout: Duration) {
+        let slot = self.slot.lock().unwrap_or_else(PoisonError::into_inner);
+        let (mut slot, _) = self
+            .changed
+            .wait_timeout_while(slot, timeout, |slot| slot.generation == seen && !slot.woken)
+            .unwrap_or_else(PoisonError::into_inner);
+        slot.woken = false;
+    }
+
+    /// Cuts the current (or next) `wait` short without changing what was pushed.
+    pub fn wake(&self) {
+        self.slot.lock().unwrap_or_else(PoisonError::into_inner).woken = true;
+        self.changed.notify_all();
+    }
+
+    // Swaps in `push` if `allow` approves of the live push, returning the one it replaced
+    fn update(&self, allow: impl FnOnce(Option<&Push>) -> bool, push: Option<Push>) -> Result<Option<Push>, ()> {
+        let mut slot = self.slot.lock().unwrap_or_else(PoisonError::into_inner);
+        if slot.push.as_ref().is_some_and(Push::expired) {
+            slot.push = None;
+        }
+        if !allow(slot.push.as_ref()) {
+            return Err(());
+        }
+        slot.generation += 1;
+        let old = std::mem::replace(&mut slot.push, push);
+        self.changed.notify_all();
+        Ok(old)
+    }
+}
@@ -0,0 +1,270 @@
+// Recording every frame sent to the panel, and playing a recording back.
+//
+// A recording is one file: a header with the panel size, then each refresh as
+// its time since the start, whether it was a fast refresh, and both planes.
+// Replaying it on another panel reproduces exactly what a field unit showed,
+// in the same order and at the same pace. Off the device it can be replayed
+// into the terminal simulator, or written out one PNG per frame.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_hal::blocking::delay::DelayMs;
+
+use crate::epd::{ColorCapability, EpdController};
+use crate::framebuffer::{Framebuffer, Rotation};
+use crate::pack::row_bytes;
+
+const MAGIC: &[u8; 8] = b"INKYREC1";
+
+/// Wraps a panel and appends every refresh to a recording, if one is open.
+///
+/// Failing to write the recording never fails the refresh: the error is
+/// printed once and recording stops.
+pub struct Recorder<E> {
+    epd: E,
+    out: Option<BufWriter<File>>,
+    started: Instant,
+    bw: Vec<u8>,
+    red: Vec<u8>,
+}
+
+impl<E: EpdController> Recorder<E> {
+    /// Passes everything straight through to `epd` without recording.
+    pub fn new(epd: E) -> Self {
+        Recorder {
+            epd,
+            out: None,
+            started: Instant::now(),
+            bw: Vec::new(),
+            red: Vec::new(),
+        }
+    }
+
+    /// Records into `path`, replacing whatever was there.
+    pub fn create(epd: E, path: impl AsRef<Path>) -> io::Result<Self> {
+        let (width, height) = epd.dimensions();
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&width.to_le_bytes())?;
+        out.write_all(&height.to_le_bytes())?;
+        out.flush()?;
+        let mut recorder = Recorder::new(epd);
+        recorder.out = Some(out);
+        Ok(recorder)
+    }
+
+    pub fn into_inner(self) -> E {
+        self.epd
+    }
+
+    fn record(&mut self, fast: bool) {
+        let Some(out) = &mut self.out else {
+            return;
+        };
+        let at = self.started.elapsed().as_millis() as u64;
+        // Flush every frame so a crash or power cut keeps everything up to it
+        let written = out
+            .write_all(&at.to_le_bytes())
+            .and_then(|()| out.write_all(&[fast as u8]))
+            .and_then(|()| out.write_all(&self.bw))
+            .and_then(|()| out.write_all(&self.red))
+            .and_then(|()| out.flush());
+        if let Err(err) = written {
+            eprintln!("Recording stopped: {err}");
+            self.out = None;
+        }
+    }
+}
+
+impl<E> Deref for Recorder<E> {
+    type Target = E;
+
+    fn deref(&self) -> &E {
+        &self.epd
+    }
+}
+
+impl<E> DerefMut for Recorder<E> {
+    fn deref_mut(&mut self) -> &mut E {
+        &mut self.epd
+    }
+}
+
+impl<E: EpdController> EpdController for Recorder<E> {
+    type Error = E::Error;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.epd.dimensions()
+    }
+
+    fn colors(&self) -> ColorCapability {
+        self.epd.colors()
+    }
+
+    fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.epd.init(delay)
+    }
+
+    fn write_planes(&mut self, bw: &[u8], red: &[u8]) -> Result<(), Self::Error> {
+        self.epd.write_planes(bw, red)?;
+        if self.out.is_some() {
+            self.bw.clear();
+            self.bw.extend_from_slice(bw);
+            self.red.clear();
+            self.red.extend_from_slice(red);
+        }
+        Ok(())
+    }
+
+    fn refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.epd.refresh(delay)?;
+        self.record(false);
+        Ok(())
+    }
+
+    fn refresh_fast<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.epd.refresh_fast(delay)?;
+        self.record(true);
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        self.epd.sleep()
+    }
+}
+
+/// One refresh out of a recording.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// Time since the recording started
+    pub at: Duration,
+    pub fast: bool,
+    pub bw: Vec<u8>,
+    pub red: Vec<u8>,
+}
+
+/// A recording opened for reading.
+pub struct Recording {
+    input: BufReader<File>,
+    width: u32,
+    height: u32,
+}
+
+impl Recording {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a panel recording"));
+        }
+        let width = read_u32(&mut input)?;
+        let height = read_u32(&mut input)?;
+        Ok(Recording { input, width, height })
+    }
+
+    /// Native size of the panel it was recorded on.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The next frame, or `None` at the end. A frame cut short by a crash
+    /// mid-write also counts as the end.
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut header = [0; 9];
+        match self.input.read_exact(&mut header) {
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        let [at @ .., fast] = header;
+        let len = row_bytes(self.width as usize) * self.height as usize;
+        let mut bw = vec![0; len];
+        let mut red = vec![0; len];
+        match self.input.read_exact(&mut bw).and_then(|()| self.input.read_exact(&mut red)) {
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        Ok(Some(Frame {
+            at: Duration::from_millis(u64::from_le_bytes(at)),
+            fast: fast != 0,
+            bw,
+            red,
+        }))
+    }
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Shows every frame of `recording` on `epd`, keeping the recorded gaps
+/// between them divided by `speed`. Calls `shown` after each frame and
+/// returns how many there were.
+///
+/// `epd` must be the same size as the panel the recording came from.
+pub fn replay<E: EpdController, D: DelayMs<u8>>(
+    epd: &mut E,
+    recording: &mut Recording,
+    delay: &mut D,
+    speed: f32,
+    mut shown: impl FnMut(&Frame),
+) -> io::Result<usize> {
+    if epd.dimensions() != recording.dimensions() {
+        let (width, height) = recording.dimensions();
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("recording is for a {width}x{height} panel"),
+        ));
+    }
+    let started = Instant::now();
+    let mut count = 0;
+    while let Some(frame) = recording.next_frame()? {
+        let due = frame.at.div_f32(speed.max(f32::MIN_POSITIVE));
+        thread::sleep(due.saturating_sub(started.elapsed()));
+        epd.write_planes(&frame.bw, &frame.red).map_err(display_error)?;
+        if frame.fast {
+            epd.refresh_fast(delay).map_err(display_error)?;
+        } else {
+            epd.refresh(delay).map_err(display_error)?;
+        }
+        shown(&frame);
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Writes every frame of `recording` into `dir` as `0001.png`, `0002.png`
+/// and so on, in `rotation` and as the panel would have shown it, without
+/// waiting between them. Calls `written` with each frame and its file and
+/// returns how many there were.
+pub fn replay_to_png(
+    recording: &mut Recording,
+    dir: &Path,
+    rotation: Rotation,
+    mut written: impl FnMut(&Frame, &Path),
+) -> io::Result<usize> {
+    fs::create_dir_all(dir)?;
+    let (width, height) = recording.dimensions();
+    let mut fb = Framebuffer::new(width, height, rotation);
+    let mut count = 0;
+    while let Some(frame) = recording.next_frame()? {
+        count += 1;
+        fb.load_planes(&frame.bw, &frame.red);
+        let path = dir.join(format!("{count:04}.png"));
+        fb.to_png(&path).map_err(io::Error::other)?;
+        written(&frame, &path);
+    }
+    Ok(count)
+}
+
+fn display_error(err: impl std::fmt::Debug) -> io::Error {
+    io::Error::other(format!("display: {err:?}"))
+}
@@ -0,0 +1,227 @@
+// A cap on how often the panel may refresh, whatever is driving it.
+//
+// E-paper wears out if refreshed too often, and fast (partial) refreshes
+// leave ghosting that only a full refresh clears. `Guarded` wraps a panel and
+// holds it to a `RefreshPolicy`, either sleeping until a refresh is allowed or
+// refusing with `InkyError::RateLimited`. The counters live in a small JSON
+// file so the limits still hold across restarts and crash loops.
+
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use embedded_hal::blocking::delay::DelayMs;
+use serde::{Deserialize, Serialize};
+
+use crate::epd::{ColorCapability, EpdController};
+use crate::inky_driver::InkyError;
+
+/// Where the counters are kept unless the policy says otherwise.
+pub const DEFAULT_STATE_PATH: &str = "/var/lib/rust_raspi/refresh.json";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RefreshPolicy {
+    /// Seconds that must pass between full refreshes; 0 for no limit
+    pub min_full_interval: u64,
+    /// Fast refreshes allowed between two full ones; once used up, the next
+    /// fast refresh is done as a full one. `None` for no limit.
+    pub max_partials: Option<u32>,
+    /// Sleep until a refresh is allowed rather than failing with `InkyError::RateLimited`.
+    /// The daemon never sleeps this way, as it would hold the panel from the API
+    /// meanwhile; it puts the refresh off until the `retry_after` instead.
+    pub wait: bool,
+    /// File the counters are kept in; `None` keeps them in memory only
+    pub state: Option<PathBuf>,
+}
+
+impl Default for RefreshPolicy {
+    fn default() -> Self {
+        RefreshPolicy {
+            min_full_interval: 0,
+            max_partials: None,
+            wait: true,
+            state: Some(PathBuf::from(DEFAULT_STATE_PATH)),
+        }
+    }
+}
+
+/// What the panel has been through, as persisted between runs.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RefreshCounters {
+    /// Unix time of the last full refresh
+    pub last_full: Option<u64>,
+    /// Fast refreshes since the last full one
+    pub partials_since_full: u32,
+    pub full_refreshes: u64,
+    pub partial_refreshes: u64,
+}
+
+impl RefreshCounters {
+    /// Reads `path`, starting from zero if it is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes `path` atomically, so a power cut leaves either the old or the new counters.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let partial = path.with_extension("tmp");
+        fs::write(&partial, serde_json::to_vec(self).map_err(io::Error::other)?)?;
+        fs::rename(partial, path)
+    }
+}
+
+/// A panel held to a `RefreshPolicy`.
+pub struct Guarded<E> {
+    epd: E,
+    policy: RefreshPolicy,
+    counters: RefreshCounters,
+    // Only complain about an unwritable state file once
+    save_failed: bool,
+}
+
+impl<E> Guarded<E> {
+    pub fn new(epd: E, policy: RefreshPolicy) -> Self {
+        let counters = policy.state.as_deref().map(RefreshCounters::load).unwrap_or_default();
+        Guarded {
+            epd,
+            policy,
+            counters,
+            save_failed: false,
+        }
+    }
+
+    pub fn counters(&self) -> &RefreshCounters {
+        &self.counters
+    }
+
+    pub fn into_inner(self) -> E {
+        self.epd
+    }
+
+    // How long until a full refresh is allowed
+    fn full_wait(&self) -> Duration {
+        let Some(last) = self.counters.last_full else {
+            return Duration::ZERO;
+        };
+        let min = Duration::from_secs(self.policy.min_full_interval);
+        match SystemTime::now().duration_since(UNIX_EPOCH + Duration::from_secs(last)) {
+            Ok(elapsed) => min.saturating_sub(elapsed),
+            // The clock went backwards (a Pi without an RTC, before NTP); nothing to go on
+            Err(_) => Duration::ZERO,
+        }
+    }
+
+    fn save(&mut self) {
+        let Some(path) = &self.policy.state else {
+            return;
+        };
+        match self.counters.save(path) {
+            Ok(()) => self.save_failed = false,
+            Err(err) if !self.save_failed => {
+                eprintln!("Could not save refresh counters to {}: {err}", path.display());
+                self.save_failed = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+impl<E, SPIE, GPIOE> Guarded<E>
+where
+    E: EpdController<Error = InkyError<SPIE, GPIOE>>,
+    SPIE: Debug,
+    GPIOE: Debug,
+{
+    fn full_refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), E::Error> {
+        let wait = self.full_wait();
+        if !wait.is_zero() {
+            if !self.policy.wait {
+                return Err(InkyError::RateLimited { retry_after: wait });
+            }
+            thread::sleep(wait);
+        }
+        self.epd.refresh(delay)?;
+        self.counters.last_full = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|elapsed| elapsed.as_secs());
+        self.counters.partials_since_full = 0;
+        self.counters.full_refreshes += 1;
+        self.save();
+        Ok(())
+    }
+}
+
+impl<E> Deref for Guarded<E> {
+    type Target = E;
+
+    fn deref(&self) -> &E {
+        &self.epd
+    }
+}
+
+impl<E> DerefMut for Guarded<E> {
+    fn deref_mut(&mut self) -> &mut E {
+        &mut self.epd
+    }
+}
+
+impl<E, SPIE, GPIOE> EpdController for Guarded<E>
+where
+    E: EpdController<Error = InkyError<SPIE, GPIOE>>,
+    SPIE: Debug,
+    GPIOE: Debug,
+{
+    type Error = E::Error;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.epd.dimensions()
+    }
+
+    fn colors(&self) -> ColorCapability {
+        self.epd.colors()
+    }
+
+    fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.epd.init(delay)
+    }
+
+    fn write_planes(&mut self, bw: &[u8], red: &[u8]) -> Result<(), Self::Error> {
+        self.epd.write_planes(bw, red)
+    }
+
+    fn refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.full_refresh(delay)
+    }
+
+    fn refresh_fast<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        if self
+            .policy
+            .max_partials
+            .is_some_and(|max| self.counters.partials_since_full >= max)
+        {
+            return self.full_refresh(delay);
+        }
+        self.epd.refresh_fast(delay)?;
+        self.counters.partials_since_full += 1;
+        self.counters.partial_refreshes += 1;
+        self.save();
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        self.epd.sleep()
+    }
+}
@@ -0,0 +1,245 @@
+// Second chances for a flaky bus.
+//
+// A loose jumper or a noisy supply makes the odd SPI write or GPIO toggle
+// fail, and a daemon that panics on the first one leaves the panel stuck on
+// whatever it last showed. `Retrying` wraps a panel and tries each operation
+// again after a growing pause. Once failures pile up it resets and
+// re-initialises the controller first, and writes the last frame again, in
+// case the controller lost it, before retrying a refresh. Refusals by a
+// refresh policy are passed straight up: trying again won't change its mind.
+//
+// Every retry and re-init is counted in `metrics`, along with the error that
+// caused it; only the failure that gets through is the caller's to record.
+
+use std::fmt::Debug;
+use std::ops::{Deref, DerefMut};
+use std::thread;
+use std::time::Duration;
+
+use embedded_hal::blocking::delay::DelayMs;
+use serde::Deserialize;
+
+use crate::epd::{ColorCapability, EpdController, GrayWriter};
+use crate::inky_driver::InkyError;
+use crate::metrics;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Tries per operation, the first included; 1 turns retrying off
+    pub attempts: u32,
+    /// Milliseconds before the first retry, doubled before each one after it
+    pub backoff_ms: u64,
+    /// Longest pause between tries, in milliseconds
+    pub max_backoff_ms: u64,
+    /// Failures in a row after which the controller is re-initialised before
+    /// the next try; 0 never does
+    pub reinit_after: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 4,
+            backoff_ms: 50,
+            max_backoff_ms: 2000,
+            reinit_after: 2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// No retries: every error goes straight up.
+    pub fn never() -> Self {
+        RetryPolicy {
+            attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    // Pause before try `attempt` (1 for the first retry)
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ms = self.backoff_ms.saturating_mul(1 << (attempt - 1).min(16));
+        Duration::from_millis(ms.min(self.max_backoff_ms))
+    }
+}
+
+/// What `Retrying` has had to do since it was opened.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RetryStats {
+    pub retries: u64,
+    pub reinits: u64,
+    /// Operations that failed every try
+    pub gave_up: u64,
+}
+
+/// A panel whose operations are retried as a `RetryPolicy` says.
+pub struct Retrying<E> {
+    epd: E,
+    policy: RetryPolicy,
+    // The last planes written, to write again after a re-init
+    bw: Vec<u8>,
+    red: Vec<u8>,
+    // Failures since the last operation that worked
+    failures: u32,
+    stats: RetryStats,
+}
+
+// Re-inits happen inside operations that aren't given a delay
+struct Sleep;
+
+impl DelayMs<u8> for Sleep {
+    fn delay_ms(&mut self, ms: u8) {
+        thread::sleep(Duration::from_millis(ms.into()));
+    }
+}
+
+// Which operation is being retried, for what to redo after a re-init
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Init,
+    Write,
+    Refresh,
+    Sleep,
+}
+
+impl<E> Retrying<E> {
+    pub fn new(epd: E, policy: RetryPolicy) -> Self {
+        Retrying {
+            epd,
+            policy,
+            bw: Vec::new(),
+            red: Vec::new(),
+            failures: 0,
+            stats: RetryStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> RetryStats {
+        self.stats
+    }
+
+    pub fn into_inner(self) -> E {
+        self.epd
+    }
+}
+
+impl<E, SPIE, GPIOE> Retrying<E>
+where
+    E: EpdController<Error = InkyError<SPIE, GPIOE>>,
+    SPIE: Debug,
+    GPIOE: Debug,
+{
+    fn retry<T>(
+        &mut self,
+        operation: Operation,
+        mut run: impl FnMut(&mut E) -> Result<T, E::Error>,
+    ) -> Result<T, E::Error> {
+        let mut attempt = 0;
+        loop {
+            let err = match run(&mut self.epd) {
+                Ok(value) => {
+                    self.failures = 0;
+                    return Ok(value);
+                }
+                Err(err @ InkyError::RateLimited { .. }) => return Err(err),
+                Err(err) => err,
+            };
+            self.failures += 1;
+            attempt += 1;
+            if attempt >= self.policy.attempts {
+                self.stats.gave_up += 1;
+                return Err(err);
+            }
+            metrics::record_retry(&err);
+            self.stats.retries += 1;
+            thread::sleep(self.policy.backoff(attempt));
+            if self.policy.reinit_after > 0 && self.failures >= self.policy.reinit_after {
+                // A failed re-init counts as one more failure; the loop carries on either way
+                if let Err(err) = self.reinit(operation) {
+                    eprintln!("Re-init after repeated panel errors failed: {err}");
+                }
+            }
+        }
+    }
+
+    // Reset the controller and bring it back to where `operation` expects it
+    fn reinit(&mut self, operation: Operation) -> Result<(), E::Error> {
+        self.stats.reinits += 1;
+        metrics::record_reinit();
+        self.failures = 0;
+        self.epd.init(&mut Sleep)?;
+        // A refresh needs the frame back in RAM; a write is about to send it anyway
+        if operation == Operation::Refresh && !self.bw.is_empty() {
+            self.epd.write_planes(&self.bw, &self.red)?;
+        }
+        Ok(())
+    }
+}
+
+impl<E> Deref for Retrying<E> {
+    type Target = E;
+
+    fn deref(&self) -> &E {
+        &self.epd
+    }
+}
+
+impl<E> DerefMut for Retrying<E> {
+    fn deref_mut(&mut self) -> &mut E {
+        &mut self.epd
+    }
+}
+
+impl<E, SPIE, GPIOE> EpdController for Retrying<E>
+where
+    E: EpdController<Error = InkyError<SPIE, GPIOE>>,
+    SPIE: Debug,
+    GPIOE: Debug,
+{
+    type Error = E::Error;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.epd.dimensions()
+    }
+
+    fn colors(&self) -> ColorCapability {
+        self.epd.colors()
+    }
+
+    fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.retry(Operation::Init, |epd| epd.init(delay))
+    }
+
+    fn write_planes(&mut self, bw: &[u8], red: &[u8]) -> Result<(), Self::Error> {
+        self.bw.clear();
+        self.bw.extend_from_slice(bw);
+        self.red.clear();
+        self.red.extend_from_slice(red);
+        self.retry(Operation::Write, |epd| epd.write_planes(bw, red))
+    }
+
+    fn refresh<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.retry(Operation::Refresh, |epd| epd.refresh(delay))
+    }
+
+    fn refresh_fast<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Self::Error> {
+        self.retry(Operation::Refresh, |epd| epd.refresh_fast(delay))
+    }
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        self.retry(Operation::Sleep, |epd| epd.sleep())
+    }
+}
+
+impl<E, SPIE, GPIOE> GrayWriter for Retrying<E>
+where
+    E: GrayWriter<Error = InkyError<SPIE, GPIOE>>,
+    SPIE: Debug,
+    GPIOE: Debug,
+{
+    fn show_gray<D: DelayMs<u8>>(&mut self, msb: &[u8], lsb: &[u8], delay: &mut D) -> Result<(), Self::Error> {
+        // Both planes go with every try, so a re-init needs nothing redone
+        self.retry(Operation::Write, |epd| epd.show_gray(msb, lsb, delay))
+    }
+}
@@ -0,0 +1,167 @@
+// Time-of-day policies for the daemon's refresh loop.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate, NaiveTime, TimeDelta, Weekday};
+use serde::Deserialize;
+
+use crate::ical::{self, Event};
+use crate::presence::PresenceState;
+
+/// Night hours: a minimal layout refreshed far less often, or a blank panel.
+///
+/// `start` may be later than `end`, in which case the night spans midnight.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NightConfig {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// Seconds between refreshes at night
+    #[serde(default = "default_night_interval")]
+    pub interval: u64,
+    /// Blank the panel and put it to sleep until `end` instead of showing the night layout
+    #[serde(default)]
+    pub blank: bool,
+}
+
+fn default_night_interval() -> u64 {
+    3600
+}
+
+impl NightConfig {
+    pub fn is_night(&self, time: NaiveTime) -> bool {
+        in_window(self.start, self.end, time)
+    }
+
+    /// How long until night starts or ends, whichever comes next.
+    pub fn until_change(&self, time: NaiveTime) -> Duration {
+        until_boundary(self.start, self.end, time)
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval)
+    }
+}
+
+/// Overrides for days off (weekends and holidays). Unset fields fall back to the normal schedule.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// Seconds between refreshes
+    pub interval: Option<u64>,
+    /// Pages to cycle through, by name
+    pub pages: Option<Vec<String>>,
+}
+
+/// Pages to show instead of the normal schedule while every condition holds,
+/// e.g. transit departures on weekday mornings when someone is home.
+/// Rules are tried in order and the first match wins.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    #[serde(default)]
+    pub days: Days,
+    /// Time window; like the night, it may span midnight. Both or neither must be set.
+    pub from: Option<NaiveTime>,
+    pub to: Option<NaiveTime>,
+    /// Requires a `[presence]` section
+    pub presence: Option<PresenceState>,
+    /// Seconds between refreshes; defaults to the normal interval
+    pub interval: Option<u64>,
+    pub pages: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Days {
+    #[default]
+    All,
+    /// Monday to Friday, except holidays
+    Weekdays,
+    /// Weekends and holidays
+    DaysOff,
+}
+
+impl RuleConfig {
+    /// Everything but presence, which the caller checks only if this returns true.
+    pub fn matches_time(&self, day_off: bool, time: NaiveTime) -> bool {
+        let day = match self.days {
+            Days::All => true,
+            Days::Weekdays => !day_off,
+            Days::DaysOff => day_off,
+        };
+        day && match (self.from, self.to) {
+            (Some(from), Some(to)) => in_window(from, to, time),
+            _ => true,
+        }
+    }
+
+    /// How long until the time window opens or closes, if there is one.
+    pub fn until_change(&self, time: NaiveTime) -> Option<Duration> {
+        Some(until_boundary(self.from?, self.to?, time))
+    }
+}
+
+// Half-open [start, end), wrapping past midnight when start > end
+pub(crate) fn in_window(start: NaiveTime, end: NaiveTime, time: NaiveTime) -> bool {
+    if start <= end {
+        time >= start && time < end
+    } else {
+        time >= start || time < end
+    }
+}
+
+pub(crate) fn until_boundary(start: NaiveTime, end: NaiveTime, time: NaiveTime) -> Duration {
+    let boundary = if in_window(start, end, time) { end } else { start };
+    let mut delta = boundary.signed_duration_since(time);
+    if delta <= TimeDelta::zero() {
+        delta += TimeDelta::days(1);
+    }
+    delta.to_std().unwrap_or_default()
+}
+
+pub fn is_weekend(date: NaiveDate) -> bool {
+    matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// Dates that get the weekend schedule even though they fall on a weekday.
+#[derive(Clone, Debug, Default)]
+pub struct Holidays {
+    dates: HashSet<NaiveDate>,
+    // (month, day) of yearly recurring events
+    yearly: HashSet<(u32, u32)>,
+}
+
+impl Holidays {
+    /// Reads an iCalendar file; every event in it counts as a holiday.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::from_events(&ical::parse(&fs::read_to_string(path)?)))
+    }
+
+    pub fn from_events(events: &[Event]) -> Self {
+        let mut holidays = Holidays::default();
+        for event in events {
+            for date in event.dates() {
+                if event.yearly {
+                    holidays.yearly.insert((date.month(), date.day()));
+                } else {
+                    holidays.dates.insert(date);
+                }
+            }
+        }
+        holidays
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.dates.contains(&date) || self.yearly.contains(&(date.month(), date.day()))
+    }
+
+    /// Weekend or holiday.
+    pub fn is_day_off(&self, date: NaiveDate) -> bool {
+        is_weekend(date) || self.contains(date)
+    }
+}
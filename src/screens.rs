@@ -0,0 +1,136 @@
+// Pages the daemon can put on the panel.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+
+use crate::config::Config;
+use crate::framebuffer::Framebuffer;
+use crate::layout;
+use crate::theme::Theme;
+
+pub mod air_quality;
+pub mod almanac;
+pub mod calendar;
+pub mod chess;
+pub mod clock;
+pub mod daily;
+pub mod diagnostics;
+pub mod docker;
+pub mod door_sign;
+pub mod energy;
+pub mod flights;
+pub mod github;
+pub mod habits;
+pub mod homeassistant;
+#[cfg(feature = "i2c")]
+pub mod indoor_climate;
+pub mod mail;
+pub mod mpd;
+pub mod network;
+pub mod octoprint;
+pub mod plugin;
+pub mod pomodoro;
+pub mod speedtest;
+pub mod ticker;
+pub mod transit;
+pub mod uptime;
+
+/// Looks up a screen by the name used in the config file: a built-in one, or
+/// else one of the config's `[layouts]` or `[plugins]`. Screens with settings of their own
+/// read them from their section of `config`.
+pub fn by_name(name: &str, config: &Config) -> Option<Box<dyn Screen + Send>> {
+    match name {
+        "air_quality" => Some(Box::new(air_quality::AirQuality::new(&config.air_quality))),
+        "almanac" => Some(Box::new(almanac::Almanac::new(&config.almanac))),
+        "calendar" => Some(Box::new(calendar::Calendar::new(&config.calendar))),
+        "chess" => Some(Box::new(chess::Chess::new(&config.chess))),
+        "clock" => Some(Box::new(clock::Clock::new())),
+        "daily" => Some(Box::new(daily::Daily::new(&config.daily))),
+        "diagnostics" => Some(Box::new(diagnostics::Diagnostics)),
+        "docker" => Some(Box::new(docker::Docker::new(&config.docker))),
+        "door_sign" => Some(Box::new(door_sign::DoorSign::new(&config.door_sign, &config.calendar))),
+        "energy" => Some(Box::new(energy::Energy::new(&config.energy, config.mqtt.as_ref()))),
+        "flights" => Some(Box::new(flights::Flights::new(&config.flights))),
+        "github" => Some(Box::new(github::GitHub::new(&config.github))),
+        "habits" => Some(Box::new(habits::Habits::new(&config.habits))),
+        "homeassistant" => Some(Box::new(homeassistant::HomeAssistant::new(&config.homeassistant))),
+        "octoprint" => Some(Box::new(octoprint::OctoPrint::new(&config.octoprint))),
+        #[cfg(feature = "i2c")]
+        "indoor_climate" => Some(Box::new(indoor_climate::IndoorClimate::new(&config.indoor_climate))),
+        "mail" => Some(Box::new(mail::Mail::new(&config.mail))),
+        "mpd" => Some(Box::new(mpd::Mpd::new(&config.mpd))),
+        "network" => Some(Box::new(network::Network::new(&config.network))),
+        "night_clock" => Some(Box::new(clock::NightClock)),
+        "pomodoro" => Some(Box::new(pomodoro::Pomodoro::new(&config.pomodoro))),
+        "segment_clock" => Some(Box::new(clock::SegmentClock)),
+        "speedtest" => Some(Box::new(speedtest::Speedtest::new(&config.speedtest))),
+        "ticker" => Some(Box::new(ticker::Ticker::new(&config.ticker))),
+        "transit" => Some(Box::new(transit::Transit::new(&config.transit))),
+        "uptime" => Some(Box::new(uptime::Uptime::new(&config.uptime))),
+        _ => {
+            if let Some(layout) = config.layouts.get(name) {
+                return Some(Box::new(layout::Layout::new(name, layout)));
+            }
+            config
+                .plugins
+                .get(name)
+                .map(|plugin| Box::new(plugin::Plugin::new(name, plugin)) as Box<dyn Screen + Send>)
+        }
+    }
+}
+
+/// Everything a screen may need to know about the moment it is drawn.
+#[derive(Clone, Debug)]
+pub struct RenderContext {
+    pub now: DateTime<Local>,
+    pub theme: Theme,
+}
+
+impl RenderContext {
+    /// Now, in the default theme.
+    pub fn now() -> Self {
+        RenderContext {
+            now: Local::now(),
+            theme: Theme::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+/// Renders `screen` and then applies the theme to the finished frame, as
+/// everything that puts a page on the panel should.
+pub fn draw<S: Screen + ?Sized>(screen: &mut S, fb: &mut Framebuffer, ctx: &RenderContext) {
+    screen.render(fb, ctx);
+    ctx.theme.apply(fb, ctx.now.time());
+}
+
+pub trait Screen {
+    /// Draws the whole page into `fb`, starting from whatever was there before.
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext);
+
+    /// Moves a page with more than fits on to its next screenful, for the
+    /// `next` button. Returns false (and goes back to the first screenful)
+    /// when there was no more, so that the button turns the page instead.
+    fn scroll(&mut self) -> bool {
+        false
+    }
+
+    /// Offers the page showing a press of the button at `button` in
+    /// `[buttons] pins`, before it turns the page. Returns true if the page
+    /// used it, and is redrawn for it instead.
+    fn press(&mut self, _button: usize) -> bool {
+        false
+    }
+
+    /// How soon the page wants drawing again while it is showing, for one
+    /// that changes more often than pages turn (a countdown, say). Those
+    /// redraws use the panel's fast refresh. `None` keeps to the schedule.
+    fn live(&self) -> Option<Duration> {
+        None
+    }
+}
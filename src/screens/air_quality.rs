@@ -0,0 +1,384 @@
+// The air outside: an air quality index in large figures with its band, the
+// fine and coarse particulates beside it, and pollen counts below. Anything
+// over its threshold is drawn in red.
+//
+// Readings come from an `AirQualityProvider`. Open-Meteo's air quality API is
+// built in: free, keyless and worldwide, though its pollen forecasts only
+// cover Europe, and elsewhere the pollen rows are left out.
+//
+//     [air_quality]
+//     latitude = 52.52
+//     longitude = 13.41
+//     index = "european"
+//
+//     [air_quality.thresholds]
+//     pollen = 30
+
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+use profont::{PROFONT_12_POINT, PROFONT_24_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{self, Alignment, TextBox};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+// Open-Meteo's pollen variables, and what to call them on the panel
+const POLLEN: [(&str, &str); 6] = [
+    ("alder_pollen", "Alder"),
+    ("birch_pollen", "Birch"),
+    ("grass_pollen", "Grass"),
+    ("mugwort_pollen", "Mugwort"),
+    ("olive_pollen", "Olive"),
+    ("ragweed_pollen", "Ragweed"),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    OpenMeteo,
+}
+
+/// Which air quality index to show.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Index {
+    /// The US EPA's, 0 to 500
+    #[default]
+    Us,
+    /// The European Environment Agency's, 0 to 100 and beyond
+    European,
+}
+
+impl Index {
+    /// What the index is called on the panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            Index::Us => "US AQI",
+            Index::European => "EU AQI",
+        }
+    }
+
+    /// The name of the band `value` falls in.
+    pub fn band(self, value: f64) -> &'static str {
+        match self {
+            Index::Us => match value {
+                ..=50.0 => "Good",
+                ..=100.0 => "Moderate",
+                ..=150.0 => "Unhealthy for some",
+                ..=200.0 => "Unhealthy",
+                ..=300.0 => "Very unhealthy",
+                _ => "Hazardous",
+            },
+            Index::European => match value {
+                ..=20.0 => "Good",
+                ..=40.0 => "Fair",
+                ..=60.0 => "Moderate",
+                ..=80.0 => "Poor",
+                ..=100.0 => "Very poor",
+                _ => "Extremely poor",
+            },
+        }
+    }
+
+    // Where the band that is unhealthy for sensitive groups starts
+    fn unhealthy(self) -> f64 {
+        match self {
+            Index::Us => 100.0,
+            Index::European => 60.0,
+        }
+    }
+}
+
+/// Levels above which a reading is drawn in red.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Thresholds {
+    /// The index; unset for the start of the index's "unhealthy for
+    /// sensitive groups" band or its equivalent (100 US, 60 European)
+    pub aqi: Option<f64>,
+    /// PM2.5 in µg/m³
+    pub pm2_5: f64,
+    /// PM10 in µg/m³
+    pub pm10: f64,
+    /// Any one pollen, in grains/m³
+    pub pollen: f64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        // The EU's annual limits for particulates
+        Thresholds {
+            aqi: None,
+            pm2_5: 25.0,
+            pm10: 40.0,
+            pollen: 50.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AirQualityConfig {
+    /// Degrees north
+    pub latitude: Option<f64>,
+    /// Degrees east
+    pub longitude: Option<f64>,
+    pub provider: ProviderKind,
+    pub index: Index,
+    pub thresholds: Thresholds,
+    /// Heading; none if empty
+    pub title: String,
+    /// Seconds between fetches
+    pub refresh: u64,
+}
+
+impl Default for AirQualityConfig {
+    fn default() -> Self {
+        AirQualityConfig {
+            latitude: None,
+            longitude: None,
+            provider: ProviderKind::default(),
+            index: Index::default(),
+            thresholds: Thresholds::default(),
+            title: "Air".to_string(),
+            refresh: 1800,
+        }
+    }
+}
+
+/// The air at one place, as last fetched.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reading {
+    /// When the reading is for, as the provider gives it
+    pub time: String,
+    pub us_aqi: Option<f64>,
+    pub european_aqi: Option<f64>,
+    /// µg/m³
+    pub pm2_5: Option<f64>,
+    /// µg/m³
+    pub pm10: Option<f64>,
+    /// Name and grains/m³ of each pollen the provider has figures for
+    pub pollen: Vec<(String, f64)>,
+}
+
+impl Reading {
+    pub fn aqi(&self, index: Index) -> Option<f64> {
+        match index {
+            Index::Us => self.us_aqi,
+            Index::European => self.european_aqi,
+        }
+    }
+}
+
+/// A source of air quality readings.
+pub trait AirQualityProvider {
+    /// Name for the metrics table, e.g. `air_quality open-meteo`.
+    fn source(&self) -> String;
+
+    fn fetch(&self, latitude: f64, longitude: f64) -> Result<Reading, String>;
+}
+
+/// Open-Meteo's air quality API, current conditions.
+pub struct OpenMeteo {
+    agent: ureq::Agent,
+}
+
+impl OpenMeteo {
+    pub fn new() -> Self {
+        OpenMeteo {
+            agent: ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build(),
+        }
+    }
+
+    /// Makes a `Reading` out of the body of `GET /v1/air-quality?current=...`.
+    pub fn parse(body: &Value) -> Result<Reading, String> {
+        if body["error"].as_bool() == Some(true) {
+            return Err(body["reason"].as_str().unwrap_or("error").to_string());
+        }
+        let current = &body["current"];
+        if !current.is_object() {
+            return Err("no current conditions".to_string());
+        }
+        Ok(Reading {
+            time: current["time"].as_str().unwrap_or_default().to_string(),
+            us_aqi: current["us_aqi"].as_f64(),
+            european_aqi: current["european_aqi"].as_f64(),
+            pm2_5: current["pm2_5"].as_f64(),
+            pm10: current["pm10"].as_f64(),
+            // Null outside Europe
+            pollen: POLLEN
+                .iter()
+                .filter_map(|(variable, name)| Some((name.to_string(), current[*variable].as_f64()?)))
+                .collect(),
+        })
+    }
+}
+
+impl Default for OpenMeteo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AirQualityProvider for OpenMeteo {
+    fn source(&self) -> String {
+        "air_quality open-meteo".to_string()
+    }
+
+    fn fetch(&self, latitude: f64, longitude: f64) -> Result<Reading, String> {
+        let pollen: Vec<&str> = POLLEN.iter().map(|(variable, _)| *variable).collect();
+        let url = format!(
+            "https://air-quality-api.open-meteo.com/v1/air-quality?latitude={latitude}&longitude={longitude}\
+             &current=us_aqi,european_aqi,pm2_5,pm10,{}",
+            pollen.join(",")
+        );
+        let response = match self.agent.get(&url).call() {
+            Ok(response) => response,
+            // A bad request still comes with a reason in the body
+            Err(ureq::Error::Status(_, response)) => response,
+            Err(err) => return Err(err.to_string()),
+        };
+        Self::parse(&response.into_json().map_err(|err| err.to_string())?)
+    }
+}
+
+pub struct AirQuality {
+    config: AirQualityConfig,
+    provider: Box<dyn AirQualityProvider + Send>,
+    reading: Option<Reading>,
+    fetched: Option<Instant>,
+}
+
+impl AirQuality {
+    /// A page fetching from the provider `config` names.
+    pub fn new(config: &AirQualityConfig) -> Self {
+        let provider: Box<dyn AirQualityProvider + Send> = match config.provider {
+            ProviderKind::OpenMeteo => Box::new(OpenMeteo::new()),
+        };
+        Self::with_provider(config, provider)
+    }
+
+    pub fn with_provider(config: &AirQualityConfig, provider: Box<dyn AirQualityProvider + Send>) -> Self {
+        AirQuality {
+            config: config.clone(),
+            provider,
+            reading: None,
+            fetched: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let Some((latitude, longitude)) = self.config.latitude.zip(self.config.longitude) else {
+            return;
+        };
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let source = self.provider.source();
+        match metrics::timed(&source, || self.provider.fetch(latitude, longitude)) {
+            Ok(reading) => self.reading = Some(reading),
+            // Keep showing the last good reading
+            Err(err) => eprintln!("{source}: {err}"),
+        }
+    }
+}
+
+// Red for a figure over its threshold
+fn color(value: Option<f64>, threshold: f64) -> Color {
+    if value.is_some_and(|value| value > threshold) { Color::Red } else { Color::Black }
+}
+
+fn format_figure(value: Option<f64>) -> String {
+    value.map_or("-".to_string(), |value| format!("{value:.0}"))
+}
+
+impl Screen for AirQuality {
+    fn render(&mut self, fb: &mut Framebuffer, _ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let small = [&PROFONT_9_POINT];
+        let medium = [&PROFONT_12_POINT];
+        let line = PROFONT_9_POINT.character_size.height + 2;
+        let big = PROFONT_24_POINT.character_size.height;
+        let [header, hero, rest] = Flex::column()
+            .padding(2)
+            .gap(4)
+            .split(fb.bounding_box(), [Item::fixed(16), Item::fixed(big + line), Item::grow(1)]);
+        if !self.config.title.is_empty() {
+            let Ok(_) = TextBox::new(header, Color::Black).fonts(&medium).draw(&self.config.title, fb);
+        }
+        if self.config.latitude.zip(self.config.longitude).is_none() {
+            let Ok(_) = TextBox::new(hero, Color::Black)
+                .fonts(&small)
+                .draw("Set latitude and longitude under [air_quality]", fb);
+            return;
+        }
+        // Level with the title's lowercase, on the right
+        let corner = Edges::new(2, 0, 0, 0).inset(header);
+        let Some(reading) = &self.reading else {
+            let Ok(_) = TextBox::new(corner, Color::Black)
+                .alignment(Alignment::Right)
+                .fonts(&small)
+                .draw("unavailable", fb);
+            return;
+        };
+        // The provider's time is local, e.g. `2024-05-01T14:00`
+        if let Some((_, time)) = reading.time.split_once('T') {
+            let Ok(_) = TextBox::new(corner, Color::Black)
+                .alignment(Alignment::Right)
+                .fonts(&small)
+                .draw(time, fb);
+        }
+
+        // The index in large figures, its name and band under it
+        let thresholds = &self.config.thresholds;
+        let index = self.config.index;
+        let aqi = reading.aqi(index);
+        let aqi_color = color(aqi, thresholds.aqi.unwrap_or(index.unhealthy()));
+        let figure = format_figure(aqi);
+        let figure_width = text::line_width(&PROFONT_24_POINT, &figure).max(text::line_width(&PROFONT_9_POINT, index.label()));
+        let [left, right] = Flex::row().gap(8).split(hero, [Item::fixed(figure_width), Item::grow(1)]);
+        let [number, label] = Flex::column().split(left, [Item::fixed(big), Item::fixed(line)]);
+        let fonts = [&PROFONT_24_POINT];
+        let Ok(_) = TextBox::new(number, aqi_color).fonts(&fonts).draw(&figure, fb);
+        let Ok(_) = TextBox::new(label, Color::Black).fonts(&small).draw(index.label(), fb);
+        let [band, figures, units] = Flex::column()
+            .padding(Edges::new(4, 0, 0, 0))
+            .split(right, [Item::fixed(17), Item::fixed(line), Item::fixed(line)]);
+        if let Some(aqi) = aqi {
+            let Ok(_) = TextBox::new(band, aqi_color).fonts(&medium).draw(index.band(aqi), fb);
+        }
+        let particulates = [
+            ("PM2.5", reading.pm2_5, thresholds.pm2_5),
+            ("PM10", reading.pm10, thresholds.pm10),
+        ];
+        let halves = Flex::row().split(figures, [Item::grow(1), Item::grow(1)]);
+        for (bounds, (name, value, threshold)) in halves.into_iter().zip(particulates) {
+            let figure = format!("{name} {}", format_figure(value));
+            let Ok(_) = TextBox::new(bounds, color(value, threshold)).fonts(&small).draw(&figure, fb);
+        }
+        let Ok(_) = TextBox::new(units, Color::Black).fonts(&small).draw("µg/m³", fb);
+
+        // Pollen, worst first, three to a row
+        let mut pollen = reading.pollen.clone();
+        pollen.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let cells = Flex::column()
+            .repeat(rest, line)
+            .flat_map(|row| Flex::row().split(row, [Item::grow(1), Item::grow(1), Item::grow(1)]));
+        for (bounds, (name, grains)) in cells.zip(&pollen) {
+            let figure = format!("{name} {grains:.0}");
+            let Ok(_) = TextBox::new(bounds, color(Some(*grains), thresholds.pollen)).fonts(&small).draw(&figure, fb);
+        }
+    }
+}
@@ -0,0 +1,416 @@
+// The day's sun and moon for a place: sunrise, sunset, how long the day is
+// and how that compares with yesterday, and the moon's phase. All of it is
+// worked out locally (see `astro`), so the page works without a network.
+//
+// With `[almanac.tides]` set, the next high and low waters are listed too,
+// from a `TideProvider`. Two are built in: NOAA's CO-OPS predictions for US
+// stations, free and keyless (find the station id on tidesandcurrents.noaa.gov),
+// and WorldTides, which covers anywhere by position but needs an API key.
+//
+//     [almanac]
+//     latitude = 50.37
+//     longitude = -4.14
+//
+//     [almanac.tides]
+//     provider = "worldtides"
+//     key = "..."
+
+use std::f64::consts::TAU;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeDelta, Utc};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use profont::{PROFONT_12_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::astro::Daylight;
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{Alignment, TextBox};
+use crate::widgets::icon::{self, Icon, SYNODIC_MONTH};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+// High and low waters listed, two to a row
+const TIDES_SHOWN: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TideProviderKind {
+    #[default]
+    Noaa,
+    WorldTides,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TidesConfig {
+    pub provider: TideProviderKind,
+    /// NOAA station id, e.g. `9414290` for San Francisco
+    pub station: String,
+    /// WorldTides API key; it looks the tides up by the page's position
+    pub key: String,
+    /// Seconds between fetches; predictions don't change, so hours
+    pub refresh: u64,
+}
+
+impl Default for TidesConfig {
+    fn default() -> Self {
+        TidesConfig {
+            provider: TideProviderKind::default(),
+            station: String::new(),
+            key: String::new(),
+            refresh: 6 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlmanacConfig {
+    /// Degrees north
+    pub latitude: Option<f64>,
+    /// Degrees east
+    pub longitude: Option<f64>,
+    /// Where high and low waters come from; no tides if unset
+    pub tides: Option<TidesConfig>,
+    /// Heading; none if empty
+    pub title: String,
+}
+
+impl Default for AlmanacConfig {
+    fn default() -> Self {
+        AlmanacConfig {
+            latitude: None,
+            longitude: None,
+            tides: None,
+            title: "Almanac".to_string(),
+        }
+    }
+}
+
+/// A predicted high or low water.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tide {
+    pub time: DateTime<Utc>,
+    /// Metres above the provider's datum
+    pub height: f64,
+    pub high: bool,
+}
+
+/// A source of tide predictions.
+pub trait TideProvider {
+    /// Name for the metrics table, e.g. `almanac noaa`.
+    fn source(&self) -> String;
+
+    /// High and low waters for the next couple of days from `from`, in order.
+    fn fetch(&self, from: DateTime<Utc>) -> Result<Vec<Tide>, String>;
+}
+
+fn get_json(agent: &ureq::Agent, url: &str) -> Result<Value, String> {
+    agent
+        .get(url)
+        .call()
+        .map_err(|err| err.to_string())?
+        .into_json()
+        .map_err(|err| err.to_string())
+}
+
+/// NOAA CO-OPS high/low predictions for one station.
+pub struct Noaa {
+    agent: ureq::Agent,
+    station: String,
+}
+
+impl Noaa {
+    pub fn new(station: impl Into<String>) -> Self {
+        Noaa {
+            agent: ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build(),
+            station: station.into(),
+        }
+    }
+
+    /// Reads the body of a `product=predictions&interval=hilo` request made
+    /// with `time_zone=gmt`.
+    pub fn parse(body: &Value) -> Result<Vec<Tide>, String> {
+        let predictions = body["predictions"].as_array().ok_or_else(|| {
+            body["error"]["message"].as_str().unwrap_or("no predictions").to_string()
+        })?;
+        predictions
+            .iter()
+            .map(|prediction| {
+                let time = prediction["t"].as_str().unwrap_or_default();
+                let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M")
+                    .map_err(|err| format!("time {time:?}: {err}"))?
+                    .and_utc();
+                let height = prediction["v"].as_str().and_then(|v| v.trim().parse().ok()).ok_or("no height")?;
+                // `HH` and `LL` for the higher and lower of a day's two
+                let high = prediction["type"].as_str().is_some_and(|kind| kind.starts_with('H'));
+                Ok(Tide { time, height, high })
+            })
+            .collect()
+    }
+}
+
+impl TideProvider for Noaa {
+    fn source(&self) -> String {
+        "almanac noaa".to_string()
+    }
+
+    fn fetch(&self, from: DateTime<Utc>) -> Result<Vec<Tide>, String> {
+        let url = format!(
+            "https://api.tidesandcurrents.noaa.gov/api/prod/datagetter?product=predictions&interval=hilo\
+             &datum=MLLW&units=metric&time_zone=gmt&format=json&application=rust_raspi\
+             &station={}&begin_date={}&range=48",
+            self.station,
+            from.format("%Y%m%d%%20%H:%M")
+        );
+        Self::parse(&get_json(&self.agent, &url)?)
+    }
+}
+
+/// WorldTides' extremes for a position.
+pub struct WorldTides {
+    agent: ureq::Agent,
+    key: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+impl WorldTides {
+    pub fn new(key: impl Into<String>, latitude: f64, longitude: f64) -> Self {
+        WorldTides {
+            agent: ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build(),
+            key: key.into(),
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Reads the body of a v3 `extremes` request.
+    pub fn parse(body: &Value) -> Result<Vec<Tide>, String> {
+        let extremes = body["extremes"]
+            .as_array()
+            .ok_or_else(|| body["error"].as_str().unwrap_or("no extremes").to_string())?;
+        extremes
+            .iter()
+            .map(|extreme| {
+                let time = extreme["dt"].as_i64().and_then(|dt| DateTime::from_timestamp(dt, 0)).ok_or("no time")?;
+                Ok(Tide {
+                    time,
+                    height: extreme["height"].as_f64().ok_or("no height")?,
+                    high: extreme["type"].as_str() == Some("High"),
+                })
+            })
+            .collect()
+    }
+}
+
+impl TideProvider for WorldTides {
+    fn source(&self) -> String {
+        "almanac worldtides".to_string()
+    }
+
+    fn fetch(&self, from: DateTime<Utc>) -> Result<Vec<Tide>, String> {
+        let url = format!(
+            "https://www.worldtides.info/api/v3?extremes&days=2&lat={}&lon={}&start={}&key={}",
+            self.latitude,
+            self.longitude,
+            from.timestamp(),
+            self.key
+        );
+        Self::parse(&get_json(&self.agent, &url)?)
+    }
+}
+
+/// Name of the phase `phase` (a fraction of the cycle since new moon) is
+/// nearest to, matching `Icon::moon`.
+pub fn phase_name(phase: f64) -> &'static str {
+    const NAMES: [&str; 8] = [
+        "New moon",
+        "Waxing crescent",
+        "First quarter",
+        "Waxing gibbous",
+        "Full moon",
+        "Waning gibbous",
+        "Last quarter",
+        "Waning crescent",
+    ];
+    NAMES[(phase.rem_euclid(1.0) * 8.0).round() as usize % 8]
+}
+
+/// Share of the moon's disc that is lit at `phase`, 0 to 1.
+pub fn illumination(phase: f64) -> f64 {
+    (1.0 - (phase * TAU).cos()) / 2.0
+}
+
+// `2h 05m`, or `5m 12s` for the small differences from one day to the next
+fn format_span(span: TimeDelta) -> String {
+    let seconds = span.num_seconds().abs();
+    if seconds >= 3600 {
+        format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60)
+    } else {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    }
+}
+
+pub struct Almanac {
+    config: AlmanacConfig,
+    provider: Option<Box<dyn TideProvider + Send>>,
+    tides: Vec<Tide>,
+    fetched: Option<Instant>,
+}
+
+impl Almanac {
+    /// An almanac fetching tides from the provider `config` names, if any.
+    pub fn new(config: &AlmanacConfig) -> Self {
+        let provider = config.tides.as_ref().and_then(|tides| -> Option<Box<dyn TideProvider + Send>> {
+            match tides.provider {
+                TideProviderKind::Noaa => Some(Box::new(Noaa::new(&tides.station))),
+                TideProviderKind::WorldTides => {
+                    // Only with a position to look the tides up by
+                    let position = config.latitude.zip(config.longitude)?;
+                    Some(Box::new(WorldTides::new(&tides.key, position.0, position.1)))
+                }
+            }
+        });
+        Self::with_provider(config, provider)
+    }
+
+    pub fn with_provider(config: &AlmanacConfig, provider: Option<Box<dyn TideProvider + Send>>) -> Self {
+        Almanac {
+            config: config.clone(),
+            provider,
+            tides: Vec::new(),
+            fetched: None,
+        }
+    }
+
+    fn refresh(&mut self, now: DateTime<Utc>) {
+        let Some(provider) = &self.provider else {
+            return;
+        };
+        let refresh = self.config.tides.as_ref().map_or(0, |tides| tides.refresh);
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let source = provider.source();
+        // From a little before now, so the tide just gone is there to compare with
+        match metrics::timed(&source, || provider.fetch(now - TimeDelta::hours(1))) {
+            Ok(tides) => self.tides = tides,
+            // Keep showing the last good predictions
+            Err(err) => eprintln!("{source}: {err}"),
+        }
+    }
+}
+
+impl Screen for Almanac {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        let now = ctx.now.with_timezone(&Utc);
+        self.refresh(now);
+        fb.clear(Color::White);
+        let small = [&PROFONT_9_POINT];
+        let medium = [&PROFONT_12_POINT];
+        let line = PROFONT_9_POINT.character_size.height + 2;
+        // A row for each of the title, the sun and the moon, with text a little below the top of its row
+        let [header, sun, moon, tides] = Flex::column()
+            .padding(2)
+            .gap(2)
+            .split(fb.bounding_box(), [Item::fixed(18), Item::fixed(18), Item::fixed(18), Item::grow(1)]);
+        if !self.config.title.is_empty() {
+            let Ok(_) = TextBox::new(header, Color::Black).fonts(&medium).draw(&self.config.title, fb);
+        }
+        let date = ctx.now.format("%a %-d %b").to_string();
+        let Ok(_) = TextBox::new(lowered(header, 2), Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&small)
+            .draw(&date, fb);
+
+        let Some((latitude, longitude)) = self.config.latitude.zip(self.config.longitude) else {
+            let Ok(_) = TextBox::new(Rectangle::new(sun.top_left, Size::new(sun.size.width, line * 2)), Color::Black)
+                .fonts(&small)
+                .draw("Set latitude and longitude under [almanac]", fb);
+            return;
+        };
+
+        // Sunrise and sunset, with the day's length and its change since yesterday
+        let today = ctx.now.date_naive();
+        let daylight = Daylight::on(today, latitude, longitude);
+        let yesterday = today.pred_opt().map(|day| Daylight::on(day, latitude, longitude));
+        match daylight {
+            Daylight::RisesAndSets { rise, set } => {
+                let boxes = Flex::row().gap(2).split(
+                    sun,
+                    [Item::fixed(icon::SIZE), Item::fixed(44), Item::fixed(icon::SIZE), Item::fixed(44)],
+                );
+                for (pair, (icon, time)) in boxes.chunks(2).zip([(Icon::Sunrise, rise), (Icon::Sunset, set)]) {
+                    let Ok(_) = icon::draw(icon, pair[0].top_left, Color::Black, fb);
+                    let time = time.with_timezone(&Local).format("%H:%M").to_string();
+                    let Ok(_) = TextBox::new(lowered(pair[1], 2), Color::Black).fonts(&medium).draw(&time, fb);
+                }
+            }
+            Daylight::AlwaysUp | Daylight::AlwaysDown => {
+                let icon = if daylight == Daylight::AlwaysUp { Icon::Sun } else { Icon::MoonNew };
+                let [picture, label] = Flex::row().gap(2).split(sun, [Item::fixed(icon::SIZE), Item::fixed(120)]);
+                let Ok(_) = icon::draw(icon, picture.top_left, Color::Black, fb);
+                let words = if daylight == Daylight::AlwaysUp { "Up all day" } else { "Down all day" };
+                let Ok(_) = TextBox::new(lowered(label, 2), Color::Black).fonts(&medium).draw(words, fb);
+            }
+        }
+        let mut length = format_span(daylight.length());
+        if let Some(yesterday) = yesterday {
+            let change = daylight.length() - yesterday.length();
+            if !change.is_zero() {
+                let sign = if change > TimeDelta::zero() { '+' } else { '-' };
+                length = format!("{length}\n{sign}{}", format_span(change));
+            }
+        }
+        // Two lines, centred on the row
+        let bounds = Rectangle::new(sun.top_left - Point::new(0, 1), Size::new(sun.size.width, line * 2));
+        let Ok(_) = TextBox::new(bounds, Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&small)
+            .draw(&length, fb);
+
+        // The moon, and when it is next full
+        let phase = icon::moon_phase(now.naive_utc());
+        let [picture, label] = Flex::row().gap(2).split(moon, [Item::fixed(icon::SIZE), Item::grow(1)]);
+        let Ok(_) = icon::draw(Icon::moon(phase), picture.top_left, Color::Black, fb);
+        let words = format!("{} {:.0}%", phase_name(phase), illumination(phase) * 100.0);
+        let Ok(_) = TextBox::new(lowered(label, 3), Color::Black).fonts(&small).draw(&words, fb);
+        let to_full = TimeDelta::seconds(((0.5 - phase).rem_euclid(1.0) * SYNODIC_MONTH * 86_400.0) as i64);
+        let full = format!("Full {}", (ctx.now + to_full).format("%-d %b"));
+        let Ok(_) = TextBox::new(lowered(moon, 3), Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&small)
+            .draw(&full, fb);
+
+        // The coming high and low waters, two to a row
+        let upcoming: Vec<&Tide> = self.tides.iter().filter(|tide| tide.time >= now).take(TIDES_SHOWN).collect();
+        if self.provider.is_none() || upcoming.is_empty() {
+            return;
+        }
+        let [picture, list] = Flex::row().gap(4).split(tides, [Item::fixed(icon::SIZE), Item::grow(1)]);
+        let Ok(_) = icon::draw(Icon::Tide, picture.top_left, Color::Black, fb);
+        let cells = Flex::column()
+            .repeat(list, line)
+            .flat_map(|row| Flex::row().split(row, [Item::grow(1), Item::grow(1)]));
+        for (bounds, tide) in cells.zip(upcoming) {
+            let kind = if tide.high { "High" } else { "Low" };
+            let text = format!("{kind} {} {:.1}m", tide.time.with_timezone(&Local).format("%H:%M"), tide.height);
+            let Ok(_) = TextBox::new(bounds, Color::Black).fonts(&small).draw(&text, fb);
+        }
+    }
+}
+
+// `bounds` less `by` pixels off the top
+fn lowered(bounds: Rectangle, by: u32) -> Rectangle {
+    Edges::new(by, 0, 0, 0).inset(bounds)
+}
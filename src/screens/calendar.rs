@@ -0,0 +1,167 @@
+// Today's agenda from one or more iCalendar feeds.
+//
+// Google Calendar, Nextcloud and most other calendars publish a secret ICS
+// address, so reading them needs no OAuth. Feeds are re-fetched every
+// `refresh` seconds; if a fetch fails the events from the last good one stay.
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime};
+use embedded_graphics::prelude::*;
+use profont::PROFONT_9_POINT;
+use serde::Deserialize;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::ical::{self, Event};
+use crate::layout::{Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::TextBox;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalendarConfig {
+    /// `http(s)://` addresses of ICS feeds, or paths to local `.ics` files
+    pub urls: Vec<String>,
+    /// Seconds between fetches
+    pub refresh: u64,
+}
+
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        CalendarConfig {
+            urls: Vec::new(),
+            refresh: 900,
+        }
+    }
+}
+
+/// One line of the agenda.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// `None` for all-day events
+    pub start: Option<NaiveTime>,
+    pub end: Option<NaiveTime>,
+    pub summary: String,
+}
+
+/// The events falling on `date`, all-day ones first, the rest by start time.
+pub fn agenda(events: &[Event], date: NaiveDate) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = events
+        .iter()
+        .filter(|event| {
+            if event.yearly {
+                event
+                    .dates()
+                    .any(|day| (day.month(), day.day()) == (date.month(), date.day()))
+            } else {
+                event.dates().any(|day| day == date)
+            }
+        })
+        .map(|event| {
+            let time_on = |time: NaiveDateTime| (time.date() == date || event.yearly).then(|| time.time());
+            Entry {
+                start: (!event.is_all_day()).then(|| time_on(event.start.naive())).flatten(),
+                end: event.end.filter(|_| !event.is_all_day()).and_then(|end| time_on(end.naive())),
+                summary: event.summary.clone(),
+            }
+        })
+        .collect();
+    entries.sort_by(|a, b| (a.start, &a.summary).cmp(&(b.start, &b.summary)));
+    entries
+}
+
+pub struct Calendar {
+    config: CalendarConfig,
+    events: Vec<Event>,
+    fetched: Option<Instant>,
+}
+
+impl Calendar {
+    pub fn new(config: &CalendarConfig) -> Self {
+        Calendar {
+            config: config.clone(),
+            events: Vec::new(),
+            fetched: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let mut events = Vec::new();
+        for url in &self.config.urls {
+            let source = metrics::source_name("calendar", url);
+            match metrics::timed(&source, || fetch(url)) {
+                Ok(text) => events.extend(ical::parse(&text)),
+                Err(err) => {
+                    // Keep showing the last good copy of every feed rather than a partial agenda
+                    eprintln!("{source}: {err}");
+                    return;
+                }
+            }
+        }
+        self.events = events;
+    }
+}
+
+/// Reads the feed at `url`: over HTTP(S), or from a local file.
+pub fn fetch(url: &str) -> Result<String, String> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        let agent = ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build();
+        agent
+            .get(url)
+            .call()
+            .map_err(|err| err.to_string())?
+            .into_string()
+            .map_err(|err| err.to_string())
+    } else {
+        fs::read_to_string(url).map_err(|err| err.to_string())
+    }
+}
+
+impl Screen for Calendar {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let [header, body] = Flex::column()
+            .padding(2)
+            .gap(4)
+            .split(fb.bounding_box(), [Item::fixed(ctx.theme.title_height()), Item::grow(1)]);
+        let title = ctx.now.format("%A %e %B").to_string();
+        let Ok(()) = ctx.theme.title(&title, header, fb);
+
+        let entries = agenda(&self.events, ctx.now.date_naive());
+        let now = ctx.now.time();
+        // The first timed event that hasn't finished yet
+        let next = entries
+            .iter()
+            .position(|entry| entry.start.is_some() && entry.end.or(entry.start).is_some_and(|end| end > now));
+
+        let fonts = [&PROFONT_9_POINT];
+        let mut rows = Flex::column().repeat(body, PROFONT_9_POINT.character_size.height + 2);
+        if entries.is_empty() {
+            if let Some(bounds) = rows.next() {
+                let Ok(_) = TextBox::new(bounds, Color::Black).fonts(&fonts).draw("Nothing today", fb);
+            }
+            return;
+        }
+        for (index, (bounds, entry)) in rows.zip(&entries).enumerate() {
+            let time = match entry.start {
+                Some(start) => start.format("%H:%M").to_string(),
+                None => "all day".to_string(),
+            };
+            let color = if Some(index) == next { Color::Red } else { Color::Black };
+            let line = format!("{time:<7} {}", entry.summary);
+            let Ok(_) = TextBox::new(bounds, color).fonts(&fonts).draw(&line, fb);
+        }
+    }
+}
@@ -0,0 +1,506 @@
+// A chess puzzle a day: the position drawn as a board, from the side to
+// move's point of view, with whose move it is beside it.
+//
+// Positions are FEN. The `chesscom` provider fetches chess.com's daily
+// puzzle; `file` reads one FEN a line from `path` (blank lines and `#`
+// comments skipped, an optional title after ` ; `) and takes a line a day:
+//
+//     [chess]
+//     provider = "file"
+//     path = "/etc/rust_raspi/puzzles.fen"
+//
+// Pieces are drawn from built-in 12x12 sprites, doubled and so on for
+// squares big enough; white ones are outlines, black ones solid, and dark
+// squares are stippled.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDate;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use profont::{PROFONT_12_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Align, Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::daily::of_the_day;
+use crate::screens::{RenderContext, Screen};
+use crate::text::TextBox;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+// chess.com turns away requests without one
+const USER_AGENT: &str = concat!("rust_raspi/", env!("CARGO_PKG_VERSION"));
+/// Width and height of a piece sprite, in pixels.
+pub const SPRITE: u32 = 12;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    ChessCom,
+    File,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChessConfig {
+    pub provider: ProviderKind,
+    /// The `file` provider's positions
+    pub path: PathBuf,
+    /// Heading; none if empty
+    pub title: String,
+    /// Seconds between fetches; the page also fetches as soon as the day changes
+    pub refresh: u64,
+}
+
+impl Default for ChessConfig {
+    fn default() -> Self {
+        ChessConfig {
+            provider: ProviderKind::default(),
+            path: PathBuf::from("/etc/rust_raspi/puzzles.fen"),
+            title: "Puzzle".to_string(),
+            refresh: 6 * 3600,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    White,
+    Black,
+}
+
+/// In the order of the sprites.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Piece {
+    pub side: Side,
+    pub kind: Kind,
+}
+
+impl Piece {
+    /// The piece a FEN letter stands for: upper case white, lower case black.
+    pub fn from_letter(letter: char) -> Option<Self> {
+        let kind = match letter.to_ascii_lowercase() {
+            'p' => Kind::Pawn,
+            'n' => Kind::Knight,
+            'b' => Kind::Bishop,
+            'r' => Kind::Rook,
+            'q' => Kind::Queen,
+            'k' => Kind::King,
+            _ => return None,
+        };
+        let side = if letter.is_ascii_uppercase() { Side::White } else { Side::Black };
+        Some(Piece { side, kind })
+    }
+}
+
+/// Where the pieces stand and whose move it is; the rest of a FEN (castling,
+/// en passant, clocks) doesn't show on a board.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    // Rank 8 first, file a first in each
+    squares: [[Option<Piece>; 8]; 8],
+    pub to_move: Side,
+}
+
+impl Position {
+    pub fn from_fen(fen: &str) -> Result<Self, String> {
+        let mut fields = fen.split_whitespace();
+        let placement = fields.next().ok_or("empty FEN")?;
+        let mut squares = [[None; 8]; 8];
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!("{} ranks in {placement:?}", ranks.len()));
+        }
+        for (row, rank) in ranks.iter().enumerate() {
+            let mut file = 0;
+            for letter in rank.chars() {
+                if let Some(empty) = letter.to_digit(10) {
+                    file += empty as usize;
+                    continue;
+                }
+                let piece = Piece::from_letter(letter).ok_or_else(|| format!("no piece {letter:?}"))?;
+                *squares[row].get_mut(file).ok_or_else(|| format!("rank {rank:?} is too long"))? = Some(piece);
+                file += 1;
+            }
+            if file != 8 {
+                return Err(format!("rank {rank:?} has {file} squares"));
+            }
+        }
+        let to_move = match fields.next() {
+            Some("w") | None => Side::White,
+            Some("b") => Side::Black,
+            Some(other) => return Err(format!("no side {other:?}")),
+        };
+        Ok(Position { squares, to_move })
+    }
+
+    /// The piece on `file` (0 for a) and `rank` (0 for the first).
+    pub fn at(&self, file: usize, rank: usize) -> Option<Piece> {
+        self.squares[7 - rank][file]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Puzzle {
+    pub position: Position,
+    /// What the provider calls it; may be empty
+    pub title: String,
+}
+
+/// A source of puzzles.
+pub trait PuzzleProvider {
+    /// Name for the metrics table, e.g. `chess chesscom`.
+    fn source(&self) -> String;
+
+    /// The puzzle for `date`.
+    fn fetch(&self, date: NaiveDate) -> Result<Puzzle, String>;
+}
+
+/// chess.com's daily puzzle.
+pub struct ChessCom {
+    agent: ureq::Agent,
+}
+
+impl ChessCom {
+    pub fn new() -> Self {
+        ChessCom {
+            agent: ureq::AgentBuilder::new()
+                .timeout(FETCH_TIMEOUT)
+                .user_agent(USER_AGENT)
+                .build(),
+        }
+    }
+
+    /// Makes a `Puzzle` out of the body of `GET /pub/puzzle`.
+    pub fn parse(body: &Value) -> Result<Puzzle, String> {
+        let fen = body["fen"].as_str().ok_or("no fen")?;
+        Ok(Puzzle {
+            position: Position::from_fen(fen)?,
+            title: body["title"].as_str().unwrap_or_default().trim().to_string(),
+        })
+    }
+}
+
+impl Default for ChessCom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PuzzleProvider for ChessCom {
+    fn source(&self) -> String {
+        "chess chesscom".to_string()
+    }
+
+    fn fetch(&self, _date: NaiveDate) -> Result<Puzzle, String> {
+        let body: Value = self
+            .agent
+            .get("https://api.chess.com/pub/puzzle")
+            .call()
+            .map_err(|err| err.to_string())?
+            .into_json()
+            .map_err(|err| err.to_string())?;
+        Self::parse(&body)
+    }
+}
+
+/// Positions from a local file, one a day in turn.
+pub struct FileProvider {
+    path: PathBuf,
+}
+
+impl FileProvider {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileProvider { path: path.into() }
+    }
+
+    /// Reads every puzzle out of a file's contents. Lines that aren't a
+    /// position are an error, so a typo doesn't quietly skip a day.
+    pub fn parse(contents: &str) -> Result<Vec<Puzzle>, String> {
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (fen, title) = line.split_once(" ; ").unwrap_or((line, ""));
+                Ok(Puzzle {
+                    position: Position::from_fen(fen)?,
+                    title: title.trim().to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl PuzzleProvider for FileProvider {
+    fn source(&self) -> String {
+        let name = self.path.file_name().unwrap_or(self.path.as_os_str());
+        format!("chess {}", Path::new(name).display())
+    }
+
+    fn fetch(&self, date: NaiveDate) -> Result<Puzzle, String> {
+        let contents = fs::read_to_string(&self.path).map_err(|err| format!("{}: {err}", self.path.display()))?;
+        let puzzles = Self::parse(&contents).map_err(|err| format!("{}: {err}", self.path.display()))?;
+        of_the_day(&puzzles, date)
+            .cloned()
+            .ok_or_else(|| format!("{} has no positions", self.path.display()))
+    }
+}
+
+/// Draws `position` as a board with its top-left corner at `origin`, each
+/// square `square` pixels, with `bottom` nearest the viewer. A one-pixel
+/// frame goes round the outside.
+pub fn draw_board<T: DrawTarget<Color = Color>>(
+    position: &Position,
+    origin: Point,
+    square: u32,
+    bottom: Side,
+    target: &mut T,
+) -> Result<(), T::Error> {
+    let frame = Rectangle::new(origin, Size::new(8 * square + 2, 8 * square + 2));
+    frame.into_styled(PrimitiveStyle::with_stroke(Color::Black, 1)).draw(target)?;
+    let scale = (square / SPRITE).max(1);
+    let inset = (square as i32 - (SPRITE * scale) as i32) / 2;
+    for row in 0..8 {
+        for column in 0..8 {
+            // Row 0 is at the top: the eighth rank for white, the first for black
+            let (file, rank) = match bottom {
+                Side::White => (column, 7 - row),
+                Side::Black => (7 - column, row),
+            };
+            let top_left = origin + Point::new(1 + (column * square) as i32, 1 + (row * square) as i32);
+            // a1 is dark
+            if (file + rank) % 2 == 0 {
+                let stipple = (0..square * square)
+                    .map(|i| Point::new((i % square) as i32, (i / square) as i32))
+                    .filter(|p| (p.x + p.y) % 2 == 0)
+                    .map(|p| Pixel(top_left + p, Color::Black));
+                target.draw_iter(stipple)?;
+            }
+            if let Some(piece) = position.at(file as usize, rank as usize) {
+                draw_piece(piece, top_left + Point::new(inset, inset), scale, target)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Draws `piece` `scale` times its sprite size, white ones outlined and
+/// black ones solid, each with a pixel of white round the edge.
+pub fn draw_piece<T: DrawTarget<Color = Color>>(piece: Piece, at: Point, scale: u32, target: &mut T) -> Result<(), T::Error> {
+    let rows = &SPRITES[piece.kind as usize];
+    let set = |x: i32, y: i32| {
+        (0..SPRITE as i32).contains(&x) && (0..SPRITE as i32).contains(&y) && rows[y as usize] & (0x800 >> x) != 0
+    };
+    let size = SPRITE as i32;
+    for (x, y) in (0..size).flat_map(|y| (0..size).map(move |x| (x, y))) {
+        let neighbours = [set(x - 1, y), set(x + 1, y), set(x, y - 1), set(x, y + 1)];
+        let color = match set(x, y) {
+            true if piece.side == Side::Black || neighbours.contains(&false) => Color::Black,
+            true => Color::White,
+            // A pixel of white round the piece keeps it clear of a stippled square
+            false if neighbours.contains(&true) => Color::White,
+            false => continue,
+        };
+        let cell = Rectangle::new(at + Point::new(x, y) * scale as i32, Size::new(scale, scale));
+        target.fill_solid(&cell, color)?;
+    }
+    Ok(())
+}
+
+pub struct Chess {
+    config: ChessConfig,
+    provider: Box<dyn PuzzleProvider + Send>,
+    puzzle: Option<Puzzle>,
+    // When, and for which day, the puzzle was last fetched
+    fetched: Option<(Instant, NaiveDate)>,
+}
+
+impl Chess {
+    /// A page fetching from the provider `config` names.
+    pub fn new(config: &ChessConfig) -> Self {
+        let provider: Box<dyn PuzzleProvider + Send> = match config.provider {
+            ProviderKind::ChessCom => Box::new(ChessCom::new()),
+            ProviderKind::File => Box::new(FileProvider::new(&config.path)),
+        };
+        Self::with_provider(config, provider)
+    }
+
+    pub fn with_provider(config: &ChessConfig, provider: Box<dyn PuzzleProvider + Send>) -> Self {
+        Chess {
+            config: config.clone(),
+            provider,
+            puzzle: None,
+            fetched: None,
+        }
+    }
+
+    fn refresh(&mut self, today: NaiveDate) {
+        let due = self.fetched.is_none_or(|(fetched, day)| {
+            day != today || fetched.elapsed() >= Duration::from_secs(self.config.refresh)
+        });
+        if !due {
+            return;
+        }
+        self.fetched = Some((Instant::now(), today));
+        let source = self.provider.source();
+        match metrics::timed(&source, || self.provider.fetch(today)) {
+            Ok(puzzle) => self.puzzle = Some(puzzle),
+            // Keep showing the last good puzzle, even if it was yesterday's
+            Err(err) => eprintln!("{source}: {err}"),
+        }
+    }
+}
+
+impl Screen for Chess {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh(ctx.now.date_naive());
+        fb.clear(Color::White);
+        let line = PROFONT_9_POINT.character_size.height + 2;
+        let small = [&PROFONT_9_POINT];
+        let medium = [&PROFONT_12_POINT];
+
+        // The board as large as the shorter side allows, on the left, the text level with it
+        let square = (fb.width().min(fb.height()) - 4) / 8;
+        let board = 8 * square + 2;
+        let [board_box, side] = Flex::row()
+            .padding(Edges::axes(0, 2))
+            .gap(4)
+            .align(Align::Center)
+            .split(fb.bounding_box(), [Item::fixed(board).cross(board), Item::grow(1).cross(board)]);
+        // Whose move along the bottom, the puzzle's name in whatever is left above
+        let prompt = 2 * PROFONT_12_POINT.character_size.height;
+        let title = if self.config.title.is_empty() { 0 } else { 18 };
+        let [heading, name, bottom] = Flex::column()
+            .gap(2)
+            .split(side, [Item::fixed(title), Item::grow(1), Item::fixed(prompt)]);
+        if !self.config.title.is_empty() {
+            let Ok(_) = TextBox::new(heading, Color::Black).fonts(&medium).draw(&self.config.title, fb);
+        }
+        let Some(puzzle) = &self.puzzle else {
+            let bounds = Rectangle::new(name.top_left, Size::new(name.size.width, 2 * line));
+            let Ok(_) = TextBox::new(bounds, Color::Black).fonts(&small).draw("no puzzle today", fb);
+            return;
+        };
+        let Ok(_) = draw_board(&puzzle.position, board_box.top_left, square, puzzle.position.to_move, fb);
+
+        let to_move = match puzzle.position.to_move {
+            Side::White => "White\nto move",
+            Side::Black => "Black\nto move",
+        };
+        let Ok(_) = TextBox::new(bottom, Color::Black).fonts(&medium).draw(to_move, fb);
+        if !puzzle.title.is_empty() && name.size.height + 2 >= line {
+            let Ok(_) = TextBox::new(name, Color::Black).fonts(&small).draw(&puzzle.title, fb);
+        }
+    }
+}
+
+// Indexed by `Kind as usize`, one `u16` a row with the leftmost pixel in bit 11
+#[rustfmt::skip]
+const SPRITES: [[u16; SPRITE as usize]; 6] = [
+    // Pawn
+    [
+        0b000000000000,
+        0b000000000000,
+        0b000001100000,
+        0b000011110000,
+        0b000011110000,
+        0b000001100000,
+        0b000001100000,
+        0b000011110000,
+        0b000111111000,
+        0b001111111100,
+        0b001111111100,
+        0b000000000000,
+    ],
+    // Knight
+    [
+        0b000000000000,
+        0b000011010000,
+        0b000111111000,
+        0b001110111100,
+        0b011111111100,
+        0b011110111100,
+        0b000001111100,
+        0b000011111000,
+        0b000111111000,
+        0b001111111100,
+        0b001111111100,
+        0b000000000000,
+    ],
+    // Bishop
+    [
+        0b000000000000,
+        0b000001100000,
+        0b000011110000,
+        0b000111011000,
+        0b000110111000,
+        0b000111111000,
+        0b000011110000,
+        0b000001100000,
+        0b000111111000,
+        0b001111111100,
+        0b001111111100,
+        0b000000000000,
+    ],
+    // Rook
+    [
+        0b000000000000,
+        0b001101101100,
+        0b001111111100,
+        0b001111111100,
+        0b000111111000,
+        0b000111111000,
+        0b000111111000,
+        0b000111111000,
+        0b001111111100,
+        0b011111111110,
+        0b011111111110,
+        0b000000000000,
+    ],
+    // Queen
+    [
+        0b000000000000,
+        0b010001100010,
+        0b011001100110,
+        0b011111111110,
+        0b001111111100,
+        0b001111111100,
+        0b000111111000,
+        0b000011110000,
+        0b000111111000,
+        0b001111111100,
+        0b001111111100,
+        0b000000000000,
+    ],
+    // King
+    [
+        0b000001100000,
+        0b000011110000,
+        0b000001100000,
+        0b001101101100,
+        0b011111111110,
+        0b011111111110,
+        0b001111111100,
+        0b000111111000,
+        0b000011110000,
+        0b000111111000,
+        0b001111111100,
+        0b000000000000,
+    ],
+];
@@ -0,0 +1,106 @@
+// Time and date, the default page.
+
+use embedded_graphics::prelude::*;
+use profont::{PROFONT_12_POINT, PROFONT_24_POINT, PROFONT_9_POINT};
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Align, Flex, Item, Justify};
+use crate::screens::{RenderContext, Screen};
+use crate::splash::hostname;
+use crate::text::{Alignment, TextBox};
+use crate::widgets::seven_segment::{ClockDigits, SevenSegment};
+
+/// Large time with the date and hostname underneath.
+pub struct Clock {
+    hostname: String,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self::with_hostname(hostname())
+    }
+
+    /// A clock that shows `hostname` instead of this machine's, e.g. for snapshots.
+    pub fn with_hostname(hostname: impl Into<String>) -> Self {
+        Clock {
+            hostname: hostname.into(),
+        }
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Screen for Clock {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        fb.clear(Color::White);
+        let time = ctx.now.format("%H:%M").to_string();
+        let date = ctx.now.format("%a %e %b").to_string();
+
+        let lines = [
+            (&PROFONT_24_POINT, time),
+            (&PROFONT_12_POINT, date),
+            (&PROFONT_9_POINT, self.hostname.clone()),
+        ];
+        // Together in the middle of the panel, whatever its size
+        let boxes = Flex::column()
+            .gap(8)
+            .justify(Justify::Center)
+            .layout(fb.bounding_box(), &lines.each_ref().map(|(font, _)| Item::fixed(font.character_size.height)));
+        for (bounds, (font, text)) in boxes.into_iter().zip(lines) {
+            let fonts = [font];
+            let Ok(_) = TextBox::new(bounds, Color::Black)
+                .alignment(Alignment::Center)
+                .fonts(&fonts)
+                .draw(&text, fb);
+        }
+    }
+}
+
+/// Night layout: nothing but the time, as large as it will go, centred.
+#[derive(Default)]
+pub struct NightClock;
+
+impl Screen for NightClock {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        fb.clear(Color::White);
+        let time = ctx.now.format("%H:%M").to_string();
+        let [bounds] = Flex::column()
+            .justify(Justify::Center)
+            .split(fb.bounding_box(), [Item::fixed(PROFONT_24_POINT.character_size.height)]);
+        let fonts = [&PROFONT_24_POINT];
+        let Ok(_) = TextBox::new(bounds, Color::Black)
+            .alignment(Alignment::Center)
+            .fonts(&fonts)
+            .draw(&time, fb);
+    }
+}
+
+/// Seven-segment `HH:MM` across the full width, with the date underneath.
+#[derive(Default)]
+pub struct SegmentClock;
+
+impl Screen for SegmentClock {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        fb.clear(Color::White);
+        // As tall as half the panel, unless four digits wouldn't fit across it
+        let height = (fb.width() * 2 / 5).min(fb.height() / 2);
+        let segments = SevenSegment::new(height);
+        let width = segments.text_width("00:00");
+        let [digits, bounds] = Flex::column().gap(6).justify(Justify::Center).align(Align::Center).split(
+            fb.bounding_box(),
+            [Item::fixed(height).cross(width), Item::fixed(14).align(Align::Stretch)],
+        );
+        let Ok(_) = ClockDigits::new(segments, digits.top_left, false).draw(ctx.now.time(), fb);
+
+        let date = ctx.now.format("%a %e %b").to_string();
+        let fonts = [&PROFONT_12_POINT];
+        let Ok(_) = TextBox::new(bounds, Color::Black)
+            .alignment(Alignment::Center)
+            .fonts(&fonts)
+            .draw(&date, fb);
+    }
+}
@@ -0,0 +1,322 @@
+// Something new every day: a quote, a word and its meaning, a verse, a joke.
+// Whatever it is comes from a `ContentProvider` as a title, a body and an
+// attribution, and the body is set as large as it will fit.
+//
+// Two providers are built in. `file` picks the day's entry from a file of
+// them, going round in order so the same day always gets the same one:
+// either a JSON array of `{ "title", "body", "attribution" }` objects, or
+// plain text with a blank line between entries, where an entry's first line
+// may be a `# title` and its last a `-- attribution`:
+//
+//     # serendipity
+//     The occurrence of events by chance in a happy way.
+//     -- Horace Walpole, 1754
+//
+// `http` fetches `url` and picks the parts out of the JSON with JSON pointers
+// (RFC 6901), like `transit` does. For ZenQuotes' quote of the day, say:
+//
+//     [daily]
+//     provider = "http"
+//     url = "https://zenquotes.io/api/today"
+//     fields = { body = "/0/q", attribution = "/0/a" }
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDate;
+use embedded_graphics::prelude::*;
+use profont::PROFONT_9_POINT;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{Alignment, TextBox};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    File,
+    Http,
+}
+
+/// JSON pointers to the parts of a `Content`; empty for a part not there.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fields {
+    pub title: String,
+    pub body: String,
+    pub attribution: String,
+}
+
+impl Default for Fields {
+    fn default() -> Self {
+        Fields {
+            title: String::new(),
+            body: "/body".to_string(),
+            attribution: "/attribution".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DailyConfig {
+    pub provider: ProviderKind,
+    /// The `file` provider's entries
+    pub path: PathBuf,
+    /// The `http` provider's endpoint
+    pub url: String,
+    /// Extra request headers, e.g. an API key
+    pub headers: BTreeMap<String, String>,
+    /// Where in the `http` provider's JSON each part is
+    pub fields: Fields,
+    /// Heading when the content has no title of its own; none if empty
+    pub title: String,
+    /// Seconds between fetches; the page also fetches as soon as the day changes
+    pub refresh: u64,
+}
+
+impl Default for DailyConfig {
+    fn default() -> Self {
+        DailyConfig {
+            provider: ProviderKind::default(),
+            path: PathBuf::from("/etc/rust_raspi/daily.txt"),
+            url: String::new(),
+            headers: BTreeMap::new(),
+            fields: Fields::default(),
+            title: "Today".to_string(),
+            refresh: 3600,
+        }
+    }
+}
+
+/// One day's entry. Any part may be empty.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Content {
+    pub title: String,
+    pub body: String,
+    pub attribution: String,
+}
+
+/// A source of something to show each day.
+pub trait ContentProvider {
+    /// Name for the metrics table, e.g. `daily quotes.txt`.
+    fn source(&self) -> String;
+
+    /// The entry for `date`.
+    fn fetch(&self, date: NaiveDate) -> Result<Content, String>;
+}
+
+/// The one of `entries` for `date`, going round them a day at a time, so
+/// that every day has its own until they run out.
+pub fn of_the_day<T>(entries: &[T], date: NaiveDate) -> Option<&T> {
+    let day = date.signed_duration_since(NaiveDate::default()).num_days();
+    entries.get(day.rem_euclid(entries.len().max(1) as i64) as usize)
+}
+
+/// Entries from a local file, one a day in turn.
+pub struct FileProvider {
+    path: PathBuf,
+}
+
+impl FileProvider {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileProvider { path: path.into() }
+    }
+
+    /// Reads every entry out of a file's contents: a JSON array if it
+    /// starts with `[`, else blank-line separated text.
+    pub fn parse(contents: &str) -> Result<Vec<Content>, String> {
+        if contents.trim_start().starts_with('[') {
+            return serde_json::from_str(contents).map_err(|err| err.to_string());
+        }
+        let mut entries = Vec::new();
+        let mut lines: Vec<&str> = Vec::new();
+        for line in contents.lines().chain([""]) {
+            let line = line.trim_end();
+            if !line.is_empty() {
+                lines.push(line);
+                continue;
+            }
+            if lines.is_empty() {
+                continue;
+            }
+            let mut content = Content::default();
+            if let Some(title) = lines[0].strip_prefix("# ") {
+                content.title = title.trim().to_string();
+                lines.remove(0);
+            }
+            if let Some(attribution) = lines.last().and_then(|last| last.strip_prefix("-- ")) {
+                content.attribution = attribution.trim().to_string();
+                lines.pop();
+            }
+            content.body = lines.join("\n");
+            entries.push(content);
+            lines.clear();
+        }
+        Ok(entries)
+    }
+}
+
+impl ContentProvider for FileProvider {
+    fn source(&self) -> String {
+        let name = self.path.file_name().unwrap_or(self.path.as_os_str());
+        format!("daily {}", Path::new(name).display())
+    }
+
+    fn fetch(&self, date: NaiveDate) -> Result<Content, String> {
+        let contents = fs::read_to_string(&self.path).map_err(|err| format!("{}: {err}", self.path.display()))?;
+        let entries = Self::parse(&contents)?;
+        of_the_day(&entries, date)
+            .cloned()
+            .ok_or_else(|| format!("{} has no entries", self.path.display()))
+    }
+}
+
+/// Any JSON API, mapped with the pointers in `DailyConfig::fields`.
+pub struct HttpProvider {
+    agent: ureq::Agent,
+    url: String,
+    headers: BTreeMap<String, String>,
+    fields: Fields,
+}
+
+impl HttpProvider {
+    pub fn new(config: &DailyConfig) -> Self {
+        HttpProvider {
+            agent: ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build(),
+            url: config.url.clone(),
+            headers: config.headers.clone(),
+            fields: config.fields.clone(),
+        }
+    }
+
+    /// Picks the parts out of a response body. The body has to be there.
+    pub fn parse(body: &Value, fields: &Fields) -> Result<Content, String> {
+        let text = |pointer: &str| match body.pointer(pointer) {
+            _ if pointer.is_empty() => String::new(),
+            Some(Value::String(text)) => text.trim().to_string(),
+            Some(Value::Null) | None => String::new(),
+            Some(value) => value.to_string(),
+        };
+        let content = Content {
+            title: text(&fields.title),
+            body: text(&fields.body),
+            attribution: text(&fields.attribution),
+        };
+        if content.body.is_empty() {
+            return Err(format!("nothing at {:?}", fields.body));
+        }
+        Ok(content)
+    }
+}
+
+impl ContentProvider for HttpProvider {
+    fn source(&self) -> String {
+        metrics::source_name("daily", &self.url)
+    }
+
+    fn fetch(&self, _date: NaiveDate) -> Result<Content, String> {
+        let mut request = self.agent.get(&self.url);
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        let body: Value = request
+            .call()
+            .map_err(|err| err.to_string())?
+            .into_json()
+            .map_err(|err| err.to_string())?;
+        Self::parse(&body, &self.fields)
+    }
+}
+
+pub struct Daily {
+    config: DailyConfig,
+    provider: Box<dyn ContentProvider + Send>,
+    content: Option<Content>,
+    // When, and for which day, the content was last fetched
+    fetched: Option<(Instant, NaiveDate)>,
+}
+
+impl Daily {
+    /// A page fetching from the provider `config` names.
+    pub fn new(config: &DailyConfig) -> Self {
+        let provider: Box<dyn ContentProvider + Send> = match config.provider {
+            ProviderKind::File => Box::new(FileProvider::new(&config.path)),
+            ProviderKind::Http => Box::new(HttpProvider::new(config)),
+        };
+        Self::with_provider(config, provider)
+    }
+
+    pub fn with_provider(config: &DailyConfig, provider: Box<dyn ContentProvider + Send>) -> Self {
+        Daily {
+            config: config.clone(),
+            provider,
+            content: None,
+            fetched: None,
+        }
+    }
+
+    fn refresh(&mut self, today: NaiveDate) {
+        let due = self.fetched.is_none_or(|(fetched, day)| {
+            day != today || fetched.elapsed() >= Duration::from_secs(self.config.refresh)
+        });
+        if !due {
+            return;
+        }
+        self.fetched = Some((Instant::now(), today));
+        let source = self.provider.source();
+        match metrics::timed(&source, || self.provider.fetch(today)) {
+            Ok(content) => self.content = Some(content),
+            // Keep showing the last good entry, even if it was yesterday's
+            Err(err) => eprintln!("{source}: {err}"),
+        }
+    }
+}
+
+impl Screen for Daily {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh(ctx.now.date_naive());
+        fb.clear(Color::White);
+        let mut page = Edges::all(2).inset(fb.bounding_box());
+        let title = match &self.content {
+            Some(content) if !content.title.is_empty() => &content.title,
+            _ => &self.config.title,
+        };
+        if !title.is_empty() {
+            let [header, rest] = Flex::column()
+                .gap(2)
+                .split(page, [Item::fixed(ctx.theme.title_height() + 2), Item::grow(1)]);
+            let Ok(()) = ctx.theme.title(title, header, fb);
+            page = rest;
+        }
+        let small = [&PROFONT_9_POINT];
+        let line = PROFONT_9_POINT.character_size.height + 2;
+        let Some(content) = &self.content else {
+            let Ok(_) = TextBox::new(page, Color::Black).fonts(&small).draw("nothing for today", fb);
+            return;
+        };
+
+        // The attribution along the bottom, the body as large as fits above it
+        let mut bounds = page;
+        if !content.attribution.is_empty() {
+            let [body, footer] = Flex::column().gap(2).split(page, [Item::grow(1), Item::fixed(line)]);
+            let Ok(_) = TextBox::new(footer, Color::Black)
+                .alignment(Alignment::Right)
+                .fonts(&small)
+                .draw(&format!("-- {}", content.attribution), fb);
+            bounds = body;
+        }
+        // Every ProFont size is tried, largest first
+        let Ok(_) = TextBox::new(bounds, Color::Black).draw(&content.body, fb);
+    }
+}
@@ -0,0 +1,62 @@
+// On-panel view of data-source health, so a flaky integration shows up
+// without logging in.
+
+use chrono::Local;
+use embedded_graphics::prelude::*;
+use profont::PROFONT_7_POINT;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Flex, Item};
+use crate::metrics::{self, SourceStats};
+use crate::screens::{RenderContext, Screen};
+use crate::text::TextBox;
+
+/// One line per data source: name, latency, failures and time since it last
+/// worked. Sources whose latest fetch failed are drawn in red.
+#[derive(Default)]
+pub struct Diagnostics;
+
+impl Screen for Diagnostics {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        fb.clear(Color::White);
+        let [header, body] = Flex::column()
+            .padding(2)
+            .gap(2)
+            .split(fb.bounding_box(), [Item::fixed(ctx.theme.title_height()), Item::grow(1)]);
+        let Ok(()) = ctx.theme.title("Data sources", header, fb);
+
+        let sources = metrics::snapshot();
+        let fonts = [&PROFONT_7_POINT];
+        let mut rows = Flex::column().repeat(body, PROFONT_7_POINT.character_size.height + 1);
+        if sources.is_empty() {
+            if let Some(bounds) = rows.next() {
+                let Ok(_) = TextBox::new(bounds, Color::Black).fonts(&fonts).draw("No fetches yet", fb);
+            }
+            return;
+        }
+        for (bounds, (name, stats)) in rows.zip(&sources) {
+            let color = if stats.is_ok() { Color::Black } else { Color::Red };
+            let line = format!("{name} {}", summary(stats));
+            let Ok(_) = TextBox::new(bounds, color).fonts(&fonts).draw(&line, fb);
+        }
+    }
+}
+
+// "120ms 2/40 ok 5m ago"
+fn summary(stats: &SourceStats) -> String {
+    let latency = stats.last_latency_ms.map_or("-".to_string(), |ms| format!("{ms}ms"));
+    let last_ok = match stats.last_success {
+        Some(time) => format!("ok {} ago", age((Local::now() - time).num_seconds())),
+        None => "never ok".to_string(),
+    };
+    format!("{latency} {}/{} {last_ok}", stats.failures, stats.fetches)
+}
+
+fn age(seconds: i64) -> String {
+    match seconds.max(0) {
+        s if s < 60 => format!("{s}s"),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
@@ -0,0 +1,262 @@
+// The containers on this machine's Docker, one per row with an icon for its
+// state. Exited, dead, restarting and unhealthy ones are drawn in red and
+// listed first, so they stay on the first screenful.
+//
+// Docker is asked over its socket, which takes membership of the `docker`
+// group (or root). With more containers than fit, the `next` button steps
+// through them a screenful at a time before it turns the page.
+//
+//     [docker]
+//     socket = "/var/run/docker.sock"
+//     all = true
+
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use profont::PROFONT_9_POINT;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Align, Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{self, Alignment, TextBox};
+use crate::widgets::icon::{self, Icon};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DockerConfig {
+    /// The Docker daemon's API socket
+    pub socket: PathBuf,
+    /// List stopped containers too, not just running ones
+    pub all: bool,
+    /// Heading; none if empty
+    pub title: String,
+    /// Seconds between fetches
+    pub refresh: u64,
+}
+
+impl Default for DockerConfig {
+    fn default() -> Self {
+        DockerConfig {
+            socket: PathBuf::from("/var/run/docker.sock"),
+            all: true,
+            title: "Containers".to_string(),
+            refresh: 60,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Running,
+    Unhealthy,
+    Paused,
+    Restarting,
+    Created,
+    Exited,
+    Dead,
+}
+
+impl State {
+    pub fn icon(self) -> Icon {
+        match self {
+            State::Running => Icon::Check,
+            State::Unhealthy => Icon::Warning,
+            State::Paused | State::Created => Icon::Pause,
+            State::Restarting => Icon::Sync,
+            State::Exited | State::Dead => Icon::Cross,
+        }
+    }
+
+    pub fn is_failing(self) -> bool {
+        matches!(self, State::Unhealthy | State::Restarting | State::Exited | State::Dead)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Container {
+    pub name: String,
+    pub image: String,
+    pub state: State,
+    /// Docker's own summary, e.g. `Up 2 hours (healthy)`
+    pub status: String,
+}
+
+impl Container {
+    /// Reads the body of `GET /containers/json`, failing ones first and
+    /// otherwise by name.
+    pub fn parse(list: &Value) -> Result<Vec<Container>, String> {
+        let list = list.as_array().ok_or_else(|| message(list))?;
+        let mut containers: Vec<Container> = list
+            .iter()
+            .map(|container| {
+                let status = container["Status"].as_str().unwrap_or_default().to_string();
+                let state = match container["State"].as_str().unwrap_or_default() {
+                    // Health only shows in the status text
+                    "running" if status.contains("(unhealthy)") => State::Unhealthy,
+                    "running" => State::Running,
+                    "paused" => State::Paused,
+                    "restarting" => State::Restarting,
+                    "created" => State::Created,
+                    "dead" | "removing" => State::Dead,
+                    _ => State::Exited,
+                };
+                Container {
+                    name: container["Names"][0].as_str().unwrap_or_default().trim_start_matches('/').to_string(),
+                    image: container["Image"].as_str().unwrap_or_default().to_string(),
+                    state,
+                    status,
+                }
+            })
+            .collect();
+        containers.sort_by(|a, b| (!a.state.is_failing(), &a.name).cmp(&(!b.state.is_failing(), &b.name)));
+        Ok(containers)
+    }
+}
+
+// Docker's explanation of an error body, such as an API version it doesn't speak
+fn message(body: &Value) -> String {
+    body["message"].as_str().unwrap_or("unexpected reply").to_string()
+}
+
+// One request to the API on `socket`. HTTP/1.0, so the reply is neither
+// chunked nor kept alive and ends where the stream does.
+fn get(socket: &Path, path: &str) -> io::Result<Value> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(FETCH_TIMEOUT))?;
+    write!(stream, "GET {path} HTTP/1.0\r\nHost: docker\r\n\r\n")?;
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply)?;
+    let split = reply
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "reply without a body"))?;
+    let (head, body) = (String::from_utf8_lossy(&reply[..split]), &reply[split + 4..]);
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+    let body = serde_json::from_slice(body).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    if status != "200" {
+        return Err(io::Error::other(format!("{status}: {}", message(&body))));
+    }
+    Ok(body)
+}
+
+pub struct Docker {
+    config: DockerConfig,
+    containers: Option<Vec<Container>>,
+    fetched: Option<Instant>,
+    // First container on the screen, and how many the last render fitted
+    offset: usize,
+    per_screen: usize,
+}
+
+impl Docker {
+    pub fn new(config: &DockerConfig) -> Self {
+        Docker {
+            config: config.clone(),
+            containers: None,
+            fetched: None,
+            offset: 0,
+            per_screen: 0,
+        }
+    }
+
+    fn fetch(&self) -> Result<Vec<Container>, String> {
+        let path = format!("/containers/json?all={}", self.config.all);
+        let list = get(&self.config.socket, &path).map_err(|err| err.to_string())?;
+        Container::parse(&list)
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let source = format!("docker {}", self.config.socket.display());
+        match metrics::timed(&source, || self.fetch()) {
+            Ok(containers) => self.containers = Some(containers),
+            // Keep showing the last good list
+            Err(err) => eprintln!("{source}: {err}"),
+        }
+    }
+}
+
+impl Screen for Docker {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let font = &PROFONT_9_POINT;
+        let fonts = [font];
+        let line = font.character_size.height + 2;
+        let row_height = line.max(icon::SIZE + 2);
+        let [header, body] = Flex::column()
+            .padding(2)
+            .gap(2)
+            .split(fb.bounding_box(), [Item::fixed(ctx.theme.title_height() + 2), Item::grow(1)]);
+        if !self.config.title.is_empty() {
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+        }
+        // Level with the title's lowercase, on the right
+        let corner = Edges::new(2, 0, 0, 0).inset(header);
+        let Some(containers) = &self.containers else {
+            let Ok(_) = TextBox::new(corner, Color::Black)
+                .alignment(Alignment::Right)
+                .fonts(&fonts)
+                .draw("unreachable", fb);
+            return;
+        };
+        let rows: Vec<Rectangle> = Flex::column().repeat(body, row_height).collect();
+        let per_screen = rows.len().max(1);
+        self.per_screen = per_screen;
+        if self.offset >= containers.len() {
+            self.offset = 0;
+        }
+        let failing = containers.iter().filter(|container| container.state.is_failing()).count();
+        let mut summary = format!("{}/{} up", containers.len() - failing, containers.len());
+        if containers.len() > per_screen {
+            let screens = containers.len().div_ceil(per_screen);
+            summary = format!("{summary} {}/{screens}", self.offset / per_screen + 1);
+        }
+        let Ok(_) = TextBox::new(corner, Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&fonts)
+            .draw(&summary, fb);
+
+        for (row, container) in rows.into_iter().zip(containers.iter().skip(self.offset)) {
+            let color = if container.state.is_failing() { Color::Red } else { Color::Black };
+            let [picture, rest] = Flex::row().gap(3).split(row, [Item::fixed(icon::SIZE), Item::grow(1)]);
+            let Ok(_) = icon::draw(container.state.icon(), picture.top_left, color, fb);
+            // The status gets its width, the name whatever is left
+            let status_width = text::line_width(font, &container.status).min(rest.size.width / 2);
+            let [name, status] = Flex::row()
+                .gap(4)
+                .align(Align::Center)
+                .split(rest, [Item::grow(1).cross(line), Item::fixed(status_width).cross(line)]);
+            let Ok(_) = TextBox::new(name, color).fonts(&fonts).draw(&container.name, fb);
+            let Ok(_) = TextBox::new(status, color)
+                .alignment(Alignment::Right)
+                .fonts(&fonts)
+                .draw(&container.status, fb);
+        }
+    }
+
+    fn scroll(&mut self) -> bool {
+        let len = self.containers.as_ref().map_or(0, Vec::len);
+        if self.per_screen > 0 && self.offset + self.per_screen < len {
+            self.offset += self.per_screen;
+            return true;
+        }
+        self.offset = 0;
+        false
+    }
+}
@@ -0,0 +1,202 @@
+// A sign for a meeting room or office door: whose it is, a large banner
+// saying whether they're busy (red) or free (black), and the meeting on now
+// or the next one today.
+//
+// Busy means a meeting with a start and end time is on; all-day events
+// don't count. The meetings come from iCalendar feeds as for the `calendar`
+// page, and from the same ones unless `urls` names others. `button` (by its
+// place in `[buttons] pins`, 0 being the first) turns the sign the other way
+// round for `override_minutes` while this page shows, for a meeting that
+// isn't in the calendar or one that finished early; a second press goes back
+// to the calendar.
+//
+//     pages = ["door_sign"]
+//
+//     [door_sign]
+//     name = "Room 4.12"
+//     urls = ["https://calendar.example.org/rooms/4.12.ics"]
+//     button = 0
+
+use std::time::{Duration, Instant};
+
+use chrono::{Local, NaiveTime, TimeDelta};
+use embedded_graphics::prelude::*;
+use profont::{PROFONT_24_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::ical::{self, Event};
+use crate::layout::{Edges, Flex, Item, Justify};
+use crate::metrics;
+use crate::screens::calendar::{self, Entry, agenda};
+use crate::screens::{RenderContext, Screen};
+use crate::text::{Alignment, TextBox};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DoorSignConfig {
+    /// The room or person, across the top
+    pub name: String,
+    /// ICS feeds as for `[calendar] urls`; empty for the same ones
+    pub urls: Vec<String>,
+    /// Button that overrides the calendar; none for no override
+    pub button: Option<usize>,
+    /// How long an override lasts
+    pub override_minutes: u64,
+    /// Seconds between fetches
+    pub refresh: u64,
+}
+
+impl Default for DoorSignConfig {
+    fn default() -> Self {
+        DoorSignConfig {
+            name: String::new(),
+            urls: Vec::new(),
+            button: None,
+            override_minutes: 60,
+            refresh: 300,
+        }
+    }
+}
+
+/// The meeting on at `time`, among one day's `entries`.
+pub fn current(entries: &[Entry], time: NaiveTime) -> Option<&Entry> {
+    entries
+        .iter()
+        .find(|entry| entry.start.is_some_and(|start| start <= time) && entry.end.is_some_and(|end| time < end))
+}
+
+/// The first meeting starting after `time`.
+pub fn next(entries: &[Entry], time: NaiveTime) -> Option<&Entry> {
+    entries.iter().find(|entry| entry.start.is_some_and(|start| start > time))
+}
+
+pub struct DoorSign {
+    config: DoorSignConfig,
+    events: Vec<Event>,
+    fetched: Option<Instant>,
+    // Busy or free whatever the calendar says, until then
+    overridden: Option<(bool, Instant)>,
+}
+
+impl DoorSign {
+    /// A sign reading `config`'s feeds, or `calendar`'s if it names none.
+    pub fn new(config: &DoorSignConfig, calendar: &calendar::CalendarConfig) -> Self {
+        let mut config = config.clone();
+        if config.urls.is_empty() {
+            config.urls = calendar.urls.clone();
+        }
+        DoorSign {
+            config,
+            events: Vec::new(),
+            fetched: None,
+            overridden: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let mut events = Vec::new();
+        for url in &self.config.urls {
+            let source = metrics::source_name("door_sign", url);
+            match metrics::timed(&source, || calendar::fetch(url)) {
+                Ok(text) => events.extend(ical::parse(&text)),
+                Err(err) => {
+                    // Keep the last good copy of every feed rather than miss a meeting
+                    eprintln!("{source}: {err}");
+                    return;
+                }
+            }
+        }
+        self.events = events;
+    }
+
+    // The override, while it lasts
+    fn overridden(&self) -> Option<bool> {
+        self.overridden
+            .filter(|(_, until)| Instant::now() < *until)
+            .map(|(busy, _)| busy)
+    }
+}
+
+impl Screen for DoorSign {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let small = [&PROFONT_9_POINT];
+        let line = PROFONT_9_POINT.character_size.height + 2;
+        let medium = ctx.theme.title_font().character_size.height;
+        let big = PROFONT_24_POINT.character_size.height;
+        // The banner across the whole width, the text either side of it inset
+        let [header, banner, rest] = Flex::column()
+            .padding(Edges::new(2, 0, 0, 0))
+            .gap(4)
+            .split(fb.bounding_box(), [Item::fixed(medium), Item::fixed(big + 12), Item::grow(1)]);
+        let (header, rest) = (Edges::axes(0, 2).inset(header), Edges::axes(0, 2).inset(rest));
+
+        let Ok(()) = ctx.theme.title(&self.config.name, header, fb);
+        let clock = ctx.now.format("%H:%M").to_string();
+        let Ok(_) = TextBox::new(Edges::new(2, 0, 0, 0).inset(header), Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&small)
+            .draw(&clock, fb);
+
+        // The banner, white on red or on black
+        let entries = agenda(&self.events, ctx.now.date_naive());
+        let time = ctx.now.time();
+        let meeting = current(&entries, time);
+        let overridden = self.overridden();
+        let busy = overridden.unwrap_or(meeting.is_some());
+        let Ok(_) = fb.fill_solid(&banner, if busy { Color::Red } else { Color::Black });
+        let fonts = [&PROFONT_24_POINT];
+        let [bounds] = Flex::column().justify(Justify::Center).split(banner, [Item::fixed(big)]);
+        let Ok(_) = TextBox::new(bounds, Color::White)
+            .alignment(Alignment::Center)
+            .fonts(&fonts)
+            .draw(if busy { "BUSY" } else { "FREE" }, fb);
+
+        // What's on now, or next, and any override
+        let mut lines = Vec::new();
+        if let Some(busy) = overridden {
+            let until = self.overridden.map(|(_, until)| until.saturating_duration_since(Instant::now()));
+            let until = ctx.now + TimeDelta::from_std(until.unwrap_or_default()).unwrap_or_default();
+            lines.push(format!("{} until {}", if busy { "busy" } else { "free" }, until.format("%H:%M")));
+        }
+        if let Some(meeting) = meeting {
+            let end = meeting.end.map(|end| end.format("%H:%M").to_string()).unwrap_or_default();
+            lines.push(format!("{} until {end}", meeting.summary));
+        }
+        match next(&entries, time) {
+            Some(entry) => {
+                let start = entry.start.map(|start| start.format("%H:%M").to_string()).unwrap_or_default();
+                lines.push(format!("next {start} {}", entry.summary));
+            }
+            None if meeting.is_none() => lines.push("nothing else today".to_string()),
+            None => {}
+        }
+        for (bounds, text) in Flex::column().repeat(rest, line).zip(lines) {
+            let Ok(_) = TextBox::new(bounds, Color::Black).fonts(&small).draw(&text, fb);
+        }
+    }
+
+    fn press(&mut self, button: usize) -> bool {
+        if self.config.button != Some(button) {
+            return false;
+        }
+        if self.overridden().is_some() {
+            self.overridden = None;
+            return true;
+        }
+        let entries = agenda(&self.events, Local::now().date_naive());
+        let busy = current(&entries, Local::now().time()).is_some();
+        let until = Instant::now() + Duration::from_secs(self.config.override_minutes * 60);
+        self.overridden = Some((!busy, until));
+        true
+    }
+}
@@ -0,0 +1,340 @@
+// What the house is drawing: the live power in large figures, today's
+// energy, and a bar for each of the last 24 hours. The figure turns red over
+// `spike` watts, and so does the bar of any hour that reached it.
+//
+// Readings arrive over MQTT from the top-level `[mqtt]` broker, on a thread
+// that listens whether the page is showing or not: Zigbee2MQTT plugs, Shelly
+// and Tasmota meters all publish power, as a bare number or a field of a JSON
+// payload. The hours come from a running energy total when the meter
+// publishes one, or else from adding up the power readings. Either way they
+// only go back as far as the daemon has been listening.
+//
+//     [energy]
+//     power_topic = "zigbee2mqtt/washing_machine"
+//     power_field = "power"
+//     energy_topic = "zigbee2mqtt/washing_machine"
+//     energy_field = "energy"
+//     spike = 2000
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, TimeDelta, Timelike};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use profont::{PROFONT_24_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::mqtt::{self, MqttOptions};
+use crate::screens::{RenderContext, Screen};
+use crate::text::{self, Alignment, TextBox};
+use crate::widgets::chart::{self, BarChart};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+const HOURS: usize = 24;
+// Power readings further apart than this aren't added up across the gap
+const MAX_GAP: TimeDelta = TimeDelta::minutes(15);
+// A power reading older than this isn't shown as live
+const STALE: TimeDelta = TimeDelta::minutes(10);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnergyConfig {
+    /// Topic the meter publishes its power on, in watts
+    pub power_topic: String,
+    /// Field of a JSON payload holding the power, with dots for nesting
+    /// (`power` for Zigbee2MQTT, `ENERGY.Power` for Tasmota); empty if the
+    /// payload is just the number
+    pub power_field: String,
+    /// Topic with a running total of energy; the power readings are added up
+    /// instead if unset
+    pub energy_topic: Option<String>,
+    /// Field of a JSON payload holding the total, as for `power_field`
+    pub energy_field: String,
+    /// kWh in one unit of the total: 0.001 for a counter in Wh
+    pub energy_scale: f64,
+    /// Watts over which the live figure, and the hours that reached it, are red
+    pub spike: f64,
+    /// Heading; none if empty
+    pub title: String,
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        EnergyConfig {
+            power_topic: String::new(),
+            power_field: String::new(),
+            energy_topic: None,
+            energy_field: String::new(),
+            energy_scale: 1.0,
+            spike: 3000.0,
+            title: "Energy".to_string(),
+        }
+    }
+}
+
+/// The number in `payload`: the whole of it, or the dotted `field` of it as JSON.
+pub fn reading(payload: &[u8], field: &str) -> Option<f64> {
+    if field.is_empty() {
+        return std::str::from_utf8(payload).ok()?.trim().parse().ok();
+    }
+    let body: Value = serde_json::from_slice(payload).ok()?;
+    let value = field.split('.').try_fold(&body, |value, key| value.get(key))?;
+    value.as_f64().or_else(|| value.as_str()?.trim().parse().ok())
+}
+
+/// One hour of the meter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hour {
+    pub start: DateTime<Local>,
+    pub kwh: f64,
+    /// Highest power seen, in watts
+    pub peak: f64,
+}
+
+/// The readings so far: the latest power, and the last 24 hours.
+#[derive(Clone, Debug, Default)]
+pub struct Meter {
+    /// Watts, and when they were read
+    pub power: Option<(f64, DateTime<Local>)>,
+    // The last energy total, in kWh
+    total: Option<f64>,
+    hours: VecDeque<Hour>,
+    // Add up the power readings, for a meter without a total
+    integrate: bool,
+}
+
+fn hour_of(time: DateTime<Local>) -> DateTime<Local> {
+    time.with_minute(0)
+        .and_then(|time| time.with_second(0))
+        .and_then(|time| time.with_nanosecond(0))
+        .unwrap_or(time)
+}
+
+impl Meter {
+    pub fn new(integrate: bool) -> Self {
+        Meter {
+            integrate,
+            ..Meter::default()
+        }
+    }
+
+    pub fn push_power(&mut self, watts: f64, at: DateTime<Local>) {
+        if let Some((previous, since)) = self.power
+            && self.integrate
+        {
+            let gap = at - since;
+            if gap > TimeDelta::zero() && gap <= MAX_GAP {
+                self.hour(at).kwh += previous * gap.num_milliseconds() as f64 / 3_600_000_000.0;
+            }
+        }
+        let hour = self.hour(at);
+        hour.peak = hour.peak.max(watts);
+        self.power = Some((watts, at));
+    }
+
+    /// A new reading of the running total, in kWh.
+    pub fn push_total(&mut self, kwh: f64, at: DateTime<Local>) {
+        // A total that went down was reset, so there's nothing to add
+        if let Some(previous) = self.total.filter(|previous| kwh >= *previous) {
+            self.hour(at).kwh += kwh - previous;
+        }
+        self.total = Some(kwh);
+    }
+
+    // The hour `at` falls in, started if it is new
+    fn hour(&mut self, at: DateTime<Local>) -> &mut Hour {
+        let start = hour_of(at);
+        if self.hours.back().is_none_or(|hour| hour.start < start) {
+            if self.hours.len() == HOURS {
+                self.hours.pop_front();
+            }
+            self.hours.push_back(Hour { start, kwh: 0.0, peak: 0.0 });
+        }
+        // A reading from an earlier hour goes in the latest
+        self.hours.back_mut().expect("an hour was pushed if there were none")
+    }
+
+    /// The 24 hours up to and including the one `now` is in, oldest first;
+    /// `None` for those before the first reading.
+    pub fn last_day(&self, now: DateTime<Local>) -> Vec<Option<Hour>> {
+        let current = hour_of(now);
+        let first = self.hours.front().map(|hour| hour.start);
+        (0..HOURS)
+            .map(|index| {
+                let start = current - TimeDelta::hours((HOURS - 1 - index) as i64);
+                match self.hours.iter().find(|hour| hour.start == start) {
+                    Some(hour) => Some(*hour),
+                    None if first.is_some_and(|first| first <= start) => Some(Hour { start, kwh: 0.0, peak: 0.0 }),
+                    None => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Energy since local midnight, and the day's highest power.
+    pub fn today(&self, now: DateTime<Local>) -> (f64, f64) {
+        self.hours
+            .iter()
+            .filter(|hour| hour.start.date_naive() == now.date_naive())
+            .fold((0.0, 0.0), |(kwh, peak), hour| (kwh + hour.kwh, f64::max(peak, hour.peak)))
+    }
+}
+
+type Shared = Arc<Mutex<Meter>>;
+
+// One subscriber per set of topics, however many profiles show the page
+static METERS: Mutex<Vec<(String, Shared)>> = Mutex::new(Vec::new());
+
+fn meter(config: &EnergyConfig, broker: &MqttOptions) -> Shared {
+    let key = format!("{} {}", config.power_topic, config.energy_topic.as_deref().unwrap_or_default());
+    let mut meters = METERS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((_, meter)) = meters.iter().find(|(listening, _)| *listening == key) {
+        return meter.clone();
+    }
+    let meter = Arc::new(Mutex::new(Meter::new(config.energy_topic.is_none())));
+    meters.push((key, meter.clone()));
+    // A client id of its own: the broker drops whichever connection used one first
+    let mut broker = broker.clone();
+    broker.client_id = format!("{}-energy-{}", broker.client_id, meters.len());
+    let (config, shared) = (config.clone(), meter.clone());
+    thread::spawn(move || subscribe(&broker, &config, &shared));
+    meter
+}
+
+// Runs forever, reconnecting after errors
+fn subscribe(broker: &MqttOptions, config: &EnergyConfig, meter: &Mutex<Meter>) {
+    loop {
+        let connected = metrics::timed("energy mqtt", || {
+            let mut client = mqtt::Client::connect(broker)?;
+            let mut topics = vec![config.power_topic.as_str()];
+            topics.extend(config.energy_topic.as_deref().filter(|topic| *topic != config.power_topic));
+            client.subscribe(&topics)?;
+            Ok::<_, std::io::Error>(client)
+        });
+        let result = connected.and_then(|mut client| -> std::io::Result<()> {
+            loop {
+                let Some(message) = client.poll()? else {
+                    continue;
+                };
+                let now = Local::now();
+                let mut meter = meter.lock().unwrap_or_else(PoisonError::into_inner);
+                // One topic may carry both, as Zigbee2MQTT's do
+                if mqtt::topic_matches(&config.power_topic, &message.topic)
+                    && let Some(watts) = reading(&message.payload, &config.power_field)
+                {
+                    meter.push_power(watts, now);
+                }
+                if let Some(topic) = &config.energy_topic
+                    && mqtt::topic_matches(topic, &message.topic)
+                    && let Some(total) = reading(&message.payload, &config.energy_field)
+                {
+                    meter.push_total(total * config.energy_scale, now);
+                }
+            }
+        });
+        if let Err(err) = result {
+            eprintln!("energy: mqtt {}:{}: {err}", broker.host, broker.port);
+            metrics::record("energy mqtt", Instant::now(), &Err::<(), _>(err));
+        }
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+pub struct Energy {
+    config: EnergyConfig,
+    // `None` without a broker or a topic to listen to
+    meter: Option<Shared>,
+}
+
+impl Energy {
+    /// Starts listening on `broker`, if there is one.
+    pub fn new(config: &EnergyConfig, broker: Option<&MqttOptions>) -> Self {
+        let meter = broker
+            .filter(|_| !config.power_topic.is_empty())
+            .map(|broker| meter(config, broker));
+        Self::with_meter(config, meter)
+    }
+
+    /// A page showing `meter`, however it is fed.
+    pub fn with_meter(config: &EnergyConfig, meter: Option<Arc<Mutex<Meter>>>) -> Self {
+        Energy {
+            config: config.clone(),
+            meter,
+        }
+    }
+}
+
+impl Screen for Energy {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        fb.clear(Color::White);
+        let small = [&PROFONT_9_POINT];
+        let big = PROFONT_24_POINT.character_size.height;
+        let [header, hero, rest] = Flex::column()
+            .padding(2)
+            .gap(4)
+            .split(fb.bounding_box(), [Item::fixed(ctx.theme.title_height()), Item::fixed(big), Item::grow(1)]);
+        if !self.config.title.is_empty() {
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+        }
+        let Some(meter) = &self.meter else {
+            let Ok(_) = TextBox::new(hero, Color::Black)
+                .fonts(&small)
+                .draw("Needs an [mqtt] broker and a power_topic", fb);
+            return;
+        };
+        let meter = meter.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        let now = ctx.now;
+        let (today, peak) = meter.today(now);
+        let summary = format!("today {today:.2} kWh");
+        let Ok(_) = TextBox::new(Edges::new(2, 0, 0, 0).inset(header), Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&small)
+            .draw(&summary, fb);
+
+        // The live figure, with the day's peak and the reading's time beside it
+        let live = meter.power.filter(|(_, at)| now - *at <= STALE);
+        let figure = live.map_or("- W".to_string(), |(watts, _)| format!("{watts:.0} W"));
+        let color = if live.is_some_and(|(watts, _)| watts > self.config.spike) { Color::Red } else { Color::Black };
+        let fonts = [&PROFONT_24_POINT];
+        let figure_width = text::line_width(&PROFONT_24_POINT, &figure);
+        let [number, beside] = Flex::row().gap(4).split(hero, [Item::fixed(figure_width), Item::grow(1)]);
+        let Ok(_) = TextBox::new(number, color).fonts(&fonts).draw(&figure, fb);
+        let mut details = format!("peak {peak:.0} W");
+        match meter.power {
+            Some((_, at)) if live.is_some() => details = format!("{details}\nat {}", at.format("%H:%M")),
+            Some((_, at)) => details = format!("{details}\nnone since {}", at.format("%H:%M")),
+            None => details = format!("{details}\nno reading yet"),
+        }
+        let Ok(_) = TextBox::new(Edges::new(2, 0, 0, 0).inset(beside), Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&small)
+            .draw(&details, fb);
+
+        // The last 24 hours, the ones that reached the spike drawn over in red
+        let hours = meter.last_day(now);
+        let kwh: Vec<f32> = hours.iter().map(|hour| hour.map_or(f32::NAN, |hour| hour.kwh as f32)).collect();
+        let spiked: Vec<f32> = hours
+            .iter()
+            .map(|hour| match hour {
+                Some(hour) if hour.peak > self.config.spike => hour.kwh as f32,
+                _ => f32::NAN,
+            })
+            .collect();
+        let bounds = Rectangle::new(rest.top_left, rest.size.component_max(Size::new(1, 1)));
+        // From zero, and not so tall that a quiet day's bars look like a busy one's
+        let top = chart::range(&kwh).map_or(0.0, |(_, max)| max).max(0.01);
+        let chart = BarChart {
+            range: Some((0.0, top)),
+            ..BarChart::new(bounds)
+        };
+        let Ok(_) = chart.draw(&kwh, fb);
+        let Ok(_) = BarChart { color: Color::Red, ..chart }.draw(&spiked, fb);
+    }
+}
@@ -0,0 +1,276 @@
+// Aircraft overhead, from a dump1090 or readsb receiver on the network: the
+// closest one's callsign, altitude, distance and speed, with a compass
+// needle pointing the way to look, and the next few closest below it.
+//
+// `url` is the decoder's `aircraft.json` (tar1090 serves it under
+// `/tar1090/data/`). Distances are from `latitude`/`longitude`, or from the
+// receiver's own position in the `receiver.json` beside it when those aren't
+// set. Aviation units throughout: feet, nautical miles and knots.
+//
+//     [flights]
+//     url = "http://adsb.local:8080/data/aircraft.json"
+//     radius = 25
+
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+use profont::{PROFONT_12_POINT, PROFONT_18_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{Alignment, TextBox};
+use crate::widgets::compass::Compass;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const EARTH_RADIUS_NM: f64 = 3440.065;
+const COMPASS_DIAMETER: u32 = 40;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlightsConfig {
+    /// The decoder's `aircraft.json`
+    pub url: String,
+    /// Where distances are measured from; the receiver's position if unset
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Aircraft further than this many nautical miles are left out
+    pub radius: f64,
+    /// Seconds since its last position after which an aircraft is left out
+    pub max_age: f64,
+    /// Heading; none if empty
+    pub title: String,
+    /// Seconds between fetches
+    pub refresh: u64,
+}
+
+impl Default for FlightsConfig {
+    fn default() -> Self {
+        FlightsConfig {
+            url: "http://localhost:8080/data/aircraft.json".to_string(),
+            latitude: None,
+            longitude: None,
+            radius: 50.0,
+            max_age: 60.0,
+            title: "Flights".to_string(),
+            refresh: 30,
+        }
+    }
+}
+
+/// An aircraft with a position, as seen from the receiver.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aircraft {
+    /// The callsign it broadcasts, or its ICAO address if it doesn't
+    pub callsign: String,
+    /// Barometric altitude in feet; `Some(0)` on the ground
+    pub altitude: Option<i64>,
+    pub on_ground: bool,
+    /// Ground speed in knots
+    pub speed: Option<f64>,
+    /// Nautical miles away
+    pub distance: f64,
+    /// Degrees clockwise from north, from here to it
+    pub bearing: f64,
+}
+
+impl Aircraft {
+    /// Reads the body of `aircraft.json`, closest first, leaving out those
+    /// without a recent position.
+    pub fn parse(body: &Value, from: (f64, f64), max_age: f64) -> Vec<Aircraft> {
+        let mut aircraft: Vec<Aircraft> = body["aircraft"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|plane| {
+                let position = (plane["lat"].as_f64()?, plane["lon"].as_f64()?);
+                if plane["seen_pos"].as_f64().unwrap_or(0.0) > max_age {
+                    return None;
+                }
+                let callsign = match plane["flight"].as_str().map(str::trim) {
+                    Some(flight) if !flight.is_empty() => flight.to_string(),
+                    _ => plane["hex"].as_str().unwrap_or("?").trim_start_matches('~').to_uppercase(),
+                };
+                // readsb's `alt_baro`, or dump1090-mutability's older `altitude`
+                let altitude = if plane["alt_baro"].is_null() { &plane["altitude"] } else { &plane["alt_baro"] };
+                let on_ground = altitude.as_str() == Some("ground");
+                let (distance, bearing) = distance_and_bearing(from, position);
+                Some(Aircraft {
+                    callsign,
+                    altitude: if on_ground { Some(0) } else { altitude.as_i64() },
+                    on_ground,
+                    speed: plane["gs"].as_f64().or_else(|| plane["speed"].as_f64()),
+                    distance,
+                    bearing,
+                })
+            })
+            .collect();
+        aircraft.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        aircraft
+    }
+
+    pub fn altitude_text(&self) -> String {
+        match self.altitude {
+            _ if self.on_ground => "ground".to_string(),
+            Some(feet) => format!("{feet} ft"),
+            None => "- ft".to_string(),
+        }
+    }
+}
+
+/// Great-circle distance in nautical miles and initial bearing in degrees
+/// from `from` to `to`, both `(latitude, longitude)`.
+pub fn distance_and_bearing(from: (f64, f64), to: (f64, f64)) -> (f64, f64) {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let (dlat, dlon) = (lat2 - lat1, lon2 - lon1);
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    let distance = 2.0 * EARTH_RADIUS_NM * a.sqrt().asin();
+    let y = dlon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+    (distance, y.atan2(x).to_degrees().rem_euclid(360.0))
+}
+
+/// Eight-point compass name of a bearing in degrees.
+pub fn compass_point(bearing: f64) -> &'static str {
+    const POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    POINTS[((bearing.rem_euclid(360.0) + 22.5) / 45.0) as usize % 8]
+}
+
+pub struct Flights {
+    config: FlightsConfig,
+    // Where distances are measured from, once known
+    position: Option<(f64, f64)>,
+    aircraft: Option<Vec<Aircraft>>,
+    fetched: Option<Instant>,
+}
+
+impl Flights {
+    pub fn new(config: &FlightsConfig) -> Self {
+        Flights {
+            config: config.clone(),
+            position: config.latitude.zip(config.longitude),
+            aircraft: None,
+            fetched: None,
+        }
+    }
+
+    fn fetch(&mut self, agent: &ureq::Agent) -> Result<Vec<Aircraft>, String> {
+        let get = |url: &str| -> Result<Value, String> {
+            agent
+                .get(url)
+                .call()
+                .map_err(|err| err.to_string())?
+                .into_json()
+                .map_err(|err| err.to_string())
+        };
+        let position = match self.position {
+            Some(position) => position,
+            None => {
+                let base = self.config.url.rsplit_once('/').map_or("", |(base, _)| base);
+                let receiver = get(&format!("{base}/receiver.json"))?;
+                let position = receiver["lat"]
+                    .as_f64()
+                    .zip(receiver["lon"].as_f64())
+                    .ok_or("the receiver has no position; set latitude and longitude")?;
+                *self.position.insert(position)
+            }
+        };
+        Ok(Aircraft::parse(&get(&self.config.url)?, position, self.config.max_age))
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let source = metrics::source_name("flights", &self.config.url);
+        let agent = ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build();
+        match metrics::timed(&source, || self.fetch(&agent)) {
+            Ok(aircraft) => {
+                let radius = self.config.radius;
+                self.aircraft = Some(aircraft.into_iter().filter(|plane| plane.distance <= radius).collect());
+            }
+            // Keep showing the last good list
+            Err(err) => eprintln!("{source}: {err}"),
+        }
+    }
+}
+
+impl Screen for Flights {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let small = [&PROFONT_9_POINT];
+        let line = PROFONT_9_POINT.character_size.height + 2;
+        let big = PROFONT_18_POINT.character_size.height;
+        let medium = PROFONT_12_POINT.character_size.height;
+        let closest_height = (big + medium + line).max(COMPASS_DIAMETER);
+        let [header, hero, rest] = Flex::column()
+            .padding(2)
+            .gap(2)
+            .split(
+                fb.bounding_box(),
+                [Item::fixed(ctx.theme.title_height() + 2), Item::fixed(closest_height), Item::grow(1)],
+            );
+        if !self.config.title.is_empty() {
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+        }
+        let summary = match &self.aircraft {
+            None => "receiver unreachable".to_string(),
+            Some(aircraft) => format!("{} within {} nm", aircraft.len(), self.config.radius),
+        };
+        let Ok(_) = TextBox::new(Edges::new(2, 0, 0, 0).inset(header), Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&small)
+            .draw(&summary, fb);
+        let Some(aircraft) = &self.aircraft else {
+            return;
+        };
+        let Some(closest) = aircraft.first() else {
+            let fonts = [&PROFONT_12_POINT];
+            let Ok(_) = TextBox::new(hero, Color::Black).fonts(&fonts).draw("Clear skies", fb);
+            return;
+        };
+
+        // The closest: compass on the left, the rest beside it
+        let [dial, beside] = Flex::row().gap(6).split(hero, [Item::fixed(COMPASS_DIAMETER), Item::grow(1)]);
+        let center = dial.top_left + Point::new_equal(COMPASS_DIAMETER as i32 / 2);
+        let Ok(_) = Compass::new(center, COMPASS_DIAMETER).draw(closest.bearing as f32, fb);
+        let [callsign, height, heading] =
+            Flex::column().split(beside, [Item::fixed(big), Item::fixed(medium), Item::fixed(line)]);
+        let fonts = [&PROFONT_18_POINT];
+        let Ok(_) = TextBox::new(callsign, Color::Black).fonts(&fonts).draw(&closest.callsign, fb);
+        let fonts = [&PROFONT_12_POINT];
+        let figures = format!("{}  {:.1} nm", closest.altitude_text(), closest.distance);
+        let Ok(_) = TextBox::new(height, Color::Black).fonts(&fonts).draw(&figures, fb);
+        let mut bearing = format!("{} {:03.0}°", compass_point(closest.bearing), closest.bearing);
+        if let Some(speed) = closest.speed {
+            bearing = format!("{bearing}  {speed:.0} kt");
+        }
+        let Ok(_) = TextBox::new(heading, Color::Black).fonts(&small).draw(&bearing, fb);
+
+        // The next closest, a row each
+        for (bounds, plane) in Flex::column().repeat(rest, line).zip(&aircraft[1..]) {
+            let Ok(_) = TextBox::new(bounds, Color::Black)
+                .fonts(&small)
+                .draw(&plane.callsign, fb);
+            let figures = format!(
+                "{}  {:.1} nm {:>2}",
+                plane.altitude_text(),
+                plane.distance,
+                compass_point(plane.bearing)
+            );
+            let Ok(_) = TextBox::new(bounds, Color::Black)
+                .alignment(Alignment::Right)
+                .fonts(&small)
+                .draw(&figures, fb);
+        }
+    }
+}
@@ -0,0 +1,248 @@
+// What's waiting on GitHub: pull requests whose review has been asked of
+// you, the latest run of each workflow in a few repos, and the unread
+// notification count. Workflows whose latest finished run failed are drawn
+// in red; a repo with none failing gets a single "passing" line.
+//
+// Needs a personal access token. A fine-grained one with read access to
+// Actions, Metadata and Pull requests covers the repos, but notifications
+// are only served to classic tokens with the `notifications` (or `repo`)
+// scope.
+//
+//     [github]
+//     token = "ghp_..."
+//     repos = ["owner/project", "owner/other"]
+//     branch = "main"
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+use profont::PROFONT_9_POINT;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{Alignment, TextBox};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+// GitHub turns away requests without one
+const USER_AGENT: &str = concat!("rust_raspi/", env!("CARGO_PKG_VERSION"));
+// Conclusions of a finished run that count as failing
+const FAILING: [&str; 3] = ["failure", "timed_out", "startup_failure"];
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitHubConfig {
+    /// Personal access token
+    pub token: String,
+    /// `owner/name` of each repo whose workflows are shown
+    pub repos: Vec<String>,
+    /// Only count runs on this branch; every branch if unset
+    pub branch: Option<String>,
+    /// API root; change for GitHub Enterprise, e.g. `https://github.example.com/api/v3`
+    pub api_url: String,
+    /// Heading; none if empty
+    pub title: String,
+    /// Seconds between fetches
+    pub refresh: u64,
+}
+
+impl Default for GitHubConfig {
+    fn default() -> Self {
+        GitHubConfig {
+            token: String::new(),
+            repos: Vec::new(),
+            branch: None,
+            api_url: "https://api.github.com".to_string(),
+            title: "GitHub".to_string(),
+            refresh: 300,
+        }
+    }
+}
+
+/// An open pull request waiting on a review from you.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReviewRequest {
+    /// `owner/name`
+    pub repo: String,
+    pub number: u64,
+    pub title: String,
+}
+
+impl ReviewRequest {
+    /// Picks the pull requests out of the body of `GET /search/issues`.
+    pub fn parse(search: &Value) -> Result<Vec<ReviewRequest>, String> {
+        let items = search["items"].as_array().ok_or_else(|| message(search))?;
+        Ok(items
+            .iter()
+            .filter_map(|item| {
+                // Only the API URL of the repo comes with a search result
+                let (_, repo) = item["repository_url"].as_str()?.split_once("/repos/")?;
+                Some(ReviewRequest {
+                    repo: repo.to_string(),
+                    number: item["number"].as_u64()?,
+                    title: item["title"].as_str().unwrap_or_default().to_string(),
+                })
+            })
+            .collect())
+    }
+}
+
+/// The latest finished run of one workflow.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkflowRun {
+    pub workflow: String,
+    pub branch: String,
+    pub failing: bool,
+}
+
+impl WorkflowRun {
+    /// The latest finished run of each workflow in the body of
+    /// `GET /repos/<owner>/<name>/actions/runs`, which lists newest first.
+    pub fn parse(runs: &Value) -> Result<Vec<WorkflowRun>, String> {
+        let list = runs["workflow_runs"].as_array().ok_or_else(|| message(runs))?;
+        let mut seen = HashSet::new();
+        Ok(list
+            .iter()
+            .filter(|run| run["status"].as_str() == Some("completed"))
+            .filter(|run| seen.insert(run["workflow_id"].as_u64()))
+            .map(|run| WorkflowRun {
+                workflow: run["name"].as_str().unwrap_or_default().to_string(),
+                branch: run["head_branch"].as_str().unwrap_or_default().to_string(),
+                failing: run["conclusion"].as_str().is_some_and(|conclusion| FAILING.contains(&conclusion)),
+            })
+            .collect())
+    }
+}
+
+// GitHub's explanation of an error body, such as a bad token
+fn message(body: &Value) -> String {
+    body["message"].as_str().unwrap_or("unexpected reply").to_string()
+}
+
+/// Everything on the board, as last fetched.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Board {
+    pub reviews: Vec<ReviewRequest>,
+    /// Runs per configured repo, in the same order
+    pub runs: Vec<Vec<WorkflowRun>>,
+    pub unread: usize,
+}
+
+pub struct GitHub {
+    config: GitHubConfig,
+    board: Option<Board>,
+    fetched: Option<Instant>,
+}
+
+impl GitHub {
+    pub fn new(config: &GitHubConfig) -> Self {
+        GitHub {
+            config: config.clone(),
+            board: None,
+            fetched: None,
+        }
+    }
+
+    fn fetch(&self, agent: &ureq::Agent) -> Result<Board, String> {
+        let base = self.config.api_url.trim_end_matches('/');
+        let get = |path: &str| {
+            agent
+                .get(&format!("{base}{path}"))
+                .set("Authorization", &format!("Bearer {}", self.config.token))
+                .set("Accept", "application/vnd.github+json")
+                .set("X-GitHub-Api-Version", "2022-11-28")
+                .call()
+                .map_err(|err| err.to_string())?
+                .into_json::<Value>()
+                .map_err(|err| err.to_string())
+        };
+        let reviews = ReviewRequest::parse(&get("/search/issues?q=is:open+is:pr+review-requested:@me&per_page=20")?)?;
+        let branch = self.config.branch.as_ref().map_or(String::new(), |branch| format!("&branch={branch}"));
+        let runs = self
+            .config
+            .repos
+            .iter()
+            .map(|repo| WorkflowRun::parse(&get(&format!("/repos/{repo}/actions/runs?per_page=30{branch}"))?))
+            .collect::<Result<_, _>>()?;
+        // Only unread ones are listed unless `all=true` is asked for
+        let unread = get("/notifications?per_page=50")?
+            .as_array()
+            .map_or(0, Vec::len);
+        Ok(Board { reviews, runs, unread })
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let source = metrics::source_name("github", &self.config.api_url);
+        let agent = ureq::AgentBuilder::new()
+            .timeout(FETCH_TIMEOUT)
+            .user_agent(USER_AGENT)
+            .build();
+        match metrics::timed(&source, || self.fetch(&agent)) {
+            Ok(board) => self.board = Some(board),
+            // Keep showing the last good board
+            Err(err) => eprintln!("{source}: {err}"),
+        }
+    }
+}
+
+impl Screen for GitHub {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let font = &PROFONT_9_POINT;
+        let fonts = [font];
+        let [header, body] = Flex::column()
+            .padding(2)
+            .gap(4)
+            .split(fb.bounding_box(), [Item::fixed(ctx.theme.title_height()), Item::grow(1)]);
+        if !self.config.title.is_empty() {
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+        }
+        // Level with the title's lowercase, on the right
+        let corner = Edges::new(2, 0, 0, 0).inset(header);
+        let Some(board) = &self.board else {
+            let Ok(_) = TextBox::new(corner, Color::Black)
+                .alignment(Alignment::Right)
+                .fonts(&fonts)
+                .draw("unreachable", fb);
+            return;
+        };
+        let unread = format!("{} unread", board.unread);
+        let Ok(_) = TextBox::new(corner, Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&fonts)
+            .draw(&unread, fb);
+
+        let mut rows = Flex::column().repeat(body, font.character_size.height + 2);
+        let mut line = |text: &str, color: Color, fb: &mut Framebuffer| {
+            if let Some(bounds) = rows.next() {
+                let Ok(_) = TextBox::new(bounds, color).fonts(&fonts).draw(text, fb);
+            }
+        };
+        // Checks first, so a long review queue can't push a failure off the bottom
+        for (repo, runs) in self.config.repos.iter().zip(&board.runs) {
+            let failing: Vec<&WorkflowRun> = runs.iter().filter(|run| run.failing).collect();
+            if failing.is_empty() {
+                line(&format!("{repo}: passing"), Color::Black, fb);
+            }
+            for run in failing {
+                line(&format!("{repo}: {} failed on {}", run.workflow, run.branch), Color::Red, fb);
+            }
+        }
+        line(&format!("Reviews requested: {}", board.reviews.len()), Color::Black, fb);
+        for review in &board.reviews {
+            line(&format!(" {}#{} {}", review.repo, review.number, review.title), Color::Black, fb);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+// Habits kept day by day, each as a grid of weeks like GitHub's contribution
+// graph: a column a week, Monday at the top, this week on the right, with a
+// filled square for every day it was done and today outlined in red.
+//
+// A habit's `button` (by its place in `[buttons] pins`, 0 being the first)
+// marks today done while this page shows, and a second press takes the mark
+// back off; the other buttons turn pages as usual. The marks are kept in
+// `state` so they outlive restarts.
+//
+//     [[habits.habits]]
+//     name = "Run"
+//     button = 0
+//
+//     [[habits.habits]]
+//     name = "Read"
+//     button = 2
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, Local, NaiveDate, TimeDelta};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use profont::PROFONT_9_POINT;
+use serde::{Deserialize, Serialize};
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::screens::{RenderContext, Screen};
+use crate::text::{Alignment, TextBox};
+
+/// Where the marks are kept unless the config says otherwise.
+pub const DEFAULT_STATE_PATH: &str = "/var/lib/rust_raspi/habits.json";
+
+// Cells never get smaller or larger than this, whatever room there is
+const MIN_CELL: u32 = 3;
+const MAX_CELL: u32 = 10;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HabitConfig {
+    pub name: String,
+    /// Button that marks today done; none to keep the habit out of reach of the buttons
+    #[serde(default)]
+    pub button: Option<usize>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HabitsConfig {
+    pub habits: Vec<HabitConfig>,
+    /// Weeks of history to show at most; fewer if they don't fit across
+    pub weeks: u32,
+    /// File the marks are kept in
+    pub state: PathBuf,
+    /// Heading; none if empty
+    pub title: String,
+}
+
+impl Default for HabitsConfig {
+    fn default() -> Self {
+        HabitsConfig {
+            habits: Vec::new(),
+            weeks: 52,
+            state: PathBuf::from(DEFAULT_STATE_PATH),
+            title: "Habits".to_string(),
+        }
+    }
+}
+
+/// The days each habit was done, by name, as kept in the state file.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HabitLog {
+    pub done: BTreeMap<String, BTreeSet<NaiveDate>>,
+}
+
+impl HabitLog {
+    /// Reads `path`, starting empty if it is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes `path` atomically, so a power cut leaves either the old or the new marks.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let partial = path.with_extension("tmp");
+        fs::write(&partial, serde_json::to_vec(self).map_err(io::Error::other)?)?;
+        fs::rename(partial, path)
+    }
+
+    pub fn is_done(&self, habit: &str, date: NaiveDate) -> bool {
+        self.done.get(habit).is_some_and(|days| days.contains(&date))
+    }
+
+    /// Marks `habit` done on `date`, or takes the mark off if it was already.
+    /// Returns whether it is done now.
+    pub fn toggle(&mut self, habit: &str, date: NaiveDate) -> bool {
+        let days = self.done.entry(habit.to_string()).or_default();
+        if days.remove(&date) {
+            return false;
+        }
+        days.insert(date)
+    }
+
+    /// Days in a row `habit` was done up to `today`, or up to yesterday while
+    /// today isn't done yet.
+    pub fn streak(&self, habit: &str, today: NaiveDate) -> u32 {
+        let mut day = if self.is_done(habit, today) { today } else { today - TimeDelta::days(1) };
+        let mut streak = 0;
+        while self.is_done(habit, day) {
+            streak += 1;
+            day -= TimeDelta::days(1);
+        }
+        streak
+    }
+}
+
+pub struct Habits {
+    config: HabitsConfig,
+    log: HabitLog,
+}
+
+impl Habits {
+    pub fn new(config: &HabitsConfig) -> Self {
+        Habits {
+            config: config.clone(),
+            log: HabitLog::load(&config.state),
+        }
+    }
+
+    // One habit's grid, ending with the week of `today`, in the cells of `bounds`
+    fn draw_grid(&self, habit: &str, today: NaiveDate, bounds: Rectangle, cell: u32, fb: &mut Framebuffer) {
+        let pitch = cell + 1;
+        let weeks = ((bounds.size.width + 1) / pitch).min(self.config.weeks.max(1));
+        // Right-aligned, so this week's column sits against the edge
+        let left = bounds.top_left.x + (bounds.size.width + 1 - weeks * pitch) as i32;
+        let monday = today - TimeDelta::days(i64::from(today.weekday().num_days_from_monday()));
+        for week in 0..weeks {
+            let start = monday - TimeDelta::weeks(i64::from(weeks - 1 - week));
+            for weekday in 0..7 {
+                let date = start + TimeDelta::days(i64::from(weekday));
+                if date > today {
+                    break;
+                }
+                let square = Rectangle::new(
+                    Point::new(left + (week * pitch) as i32, bounds.top_left.y + (weekday * pitch) as i32),
+                    Size::new(cell, cell),
+                );
+                let done = self.log.is_done(habit, date);
+                let color = if date == today { Color::Red } else { Color::Black };
+                let style = if done { PrimitiveStyle::with_fill(color) } else { PrimitiveStyle::with_stroke(color, 1) };
+                // Too small for an outline to read: a dot in the middle instead
+                if !done && cell < 4 && date != today {
+                    let Ok(_) = Pixel(square.center(), color).draw(fb);
+                    continue;
+                }
+                let Ok(_) = square.into_styled(style).draw(fb);
+            }
+        }
+    }
+}
+
+impl Screen for Habits {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        fb.clear(Color::White);
+        let small = [&PROFONT_9_POINT];
+        let line = PROFONT_9_POINT.character_size.height + 2;
+        let mut page = Edges::new(2, 2, 0, 2).inset(fb.bounding_box());
+        if !self.config.title.is_empty() {
+            let [header, rest] = Flex::column()
+                .gap(4)
+                .split(page, [Item::fixed(ctx.theme.title_height()), Item::grow(1)]);
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+            page = rest;
+        }
+        if self.config.habits.is_empty() {
+            let Ok(_) = TextBox::new(page, Color::Black).fonts(&small).draw("no habits configured", fb);
+            return;
+        }
+
+        // Every habit gets an equal band: its name and streak, then seven rows of cells
+        let today = ctx.now.date_naive();
+        let bands = Flex::column().layout(page, &vec![Item::grow(1); self.config.habits.len()]);
+        let band = bands.iter().map(|band| band.size.height).min().unwrap_or(0);
+        let cell = (band.saturating_sub(line + 2) / 7).saturating_sub(1).clamp(MIN_CELL, MAX_CELL);
+        let bottom = page.top_left.y + page.size.height as i32;
+        for (band, habit) in bands.into_iter().zip(&self.config.habits) {
+            let [row, grid] = Flex::column().split(band, [Item::fixed(line), Item::fixed(7 * (cell + 1))]);
+            // Cells too big for the band may run into the next, but not off the panel
+            if grid.top_left.y + grid.size.height as i32 > bottom {
+                break;
+            }
+            let Ok(_) = TextBox::new(row, Color::Black).fonts(&small).draw(&habit.name, fb);
+            let streak = match self.log.streak(&habit.name, today) {
+                1 => "1 day".to_string(),
+                days => format!("{days} days"),
+            };
+            let Ok(_) = TextBox::new(row, Color::Black)
+                .alignment(Alignment::Right)
+                .fonts(&small)
+                .draw(&streak, fb);
+            self.draw_grid(&habit.name, today, grid, cell, fb);
+        }
+    }
+
+    fn press(&mut self, button: usize) -> bool {
+        let Some(habit) = self.config.habits.iter().find(|habit| habit.button == Some(button)) else {
+            return false;
+        };
+        self.log.toggle(&habit.name, Local::now().date_naive());
+        if let Err(err) = self.log.save(&self.config.state) {
+            eprintln!("Could not save {}: {err}", self.config.state.display());
+        }
+        true
+    }
+}
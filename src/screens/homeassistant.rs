@@ -0,0 +1,192 @@
+// States of Home Assistant entities, one per line.
+//
+// Entities are read from HA's REST API with a long-lived access token, every
+// `refresh` seconds. An entity whose state is one of its `alert_states`, or
+// whose value is outside `above`/`below`, is drawn in red: an open door, a
+// freezer warming up, a house drawing more power than it should.
+
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+use profont::PROFONT_9_POINT;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{self, Alignment, TextBox};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HomeAssistantConfig {
+    /// Base address, e.g. `http://homeassistant.local:8123`
+    pub url: String,
+    /// Long-lived access token (Profile → Security in HA)
+    pub token: String,
+    /// Heading; none if empty
+    pub title: String,
+    pub entities: Vec<EntityConfig>,
+    /// Seconds between fetches
+    pub refresh: u64,
+}
+
+impl Default for HomeAssistantConfig {
+    fn default() -> Self {
+        HomeAssistantConfig {
+            url: "http://homeassistant.local:8123".to_string(),
+            token: String::new(),
+            title: "Home".to_string(),
+            entities: Vec::new(),
+            refresh: 60,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EntityConfig {
+    /// Entity ID, e.g. `binary_sensor.front_door`
+    pub id: String,
+    /// Label; defaults to the entity's friendly name
+    pub name: Option<String>,
+    /// Show this attribute instead of the state, e.g. `current_temperature` on a thermostat
+    pub attribute: Option<String>,
+    /// States that count as an alert, e.g. `["on", "open", "unavailable"]`
+    pub alert_states: Vec<String>,
+    /// A numeric value above this is an alert
+    pub above: Option<f64>,
+    /// A numeric value below this is an alert
+    pub below: Option<f64>,
+}
+
+/// One entity as last fetched.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reading {
+    pub name: String,
+    /// Value with its unit, if HA gives one
+    pub value: String,
+    pub alert: bool,
+}
+
+impl EntityConfig {
+    /// Makes a `Reading` out of the body of `GET /api/states/<id>`.
+    pub fn reading(&self, state: &Value) -> Reading {
+        let attributes = &state["attributes"];
+        let raw = match &self.attribute {
+            Some(attribute) => &attributes[attribute.as_str()],
+            None => &state["state"],
+        };
+        let value = match raw {
+            Value::String(value) => value.clone(),
+            Value::Null => "?".to_string(),
+            value => value.to_string(),
+        };
+        let number = raw.as_f64().or_else(|| value.parse().ok());
+        let alert = self.alert_states.contains(&value)
+            || number.is_some_and(|number| {
+                self.above.is_some_and(|above| number > above) || self.below.is_some_and(|below| number < below)
+            });
+        // The unit belongs to the state; attributes such as temperatures don't carry one
+        let unit = attributes["unit_of_measurement"].as_str().filter(|_| self.attribute.is_none());
+        let name = self
+            .name
+            .clone()
+            .or_else(|| attributes["friendly_name"].as_str().map(str::to_string))
+            .unwrap_or_else(|| self.id.clone());
+        Reading {
+            name,
+            value: match unit {
+                Some(unit) => format!("{value} {unit}"),
+                None => value,
+            },
+            alert,
+        }
+    }
+}
+
+pub struct HomeAssistant {
+    config: HomeAssistantConfig,
+    readings: Vec<Option<Reading>>,
+    fetched: Option<Instant>,
+}
+
+impl HomeAssistant {
+    pub fn new(config: &HomeAssistantConfig) -> Self {
+        HomeAssistant {
+            config: config.clone(),
+            readings: vec![None; config.entities.len()],
+            fetched: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let source = metrics::source_name("homeassistant", &self.config.url);
+        let agent = ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build();
+        let base = self.config.url.trim_end_matches('/');
+        for (entity, reading) in self.config.entities.iter().zip(&mut self.readings) {
+            let url = format!("{base}/api/states/{}", entity.id);
+            let fetched = metrics::timed(&source, || {
+                agent
+                    .get(&url)
+                    .set("Authorization", &format!("Bearer {}", self.config.token))
+                    .call()
+                    .map_err(|err| err.to_string())?
+                    .into_json::<Value>()
+                    .map_err(|err| err.to_string())
+            });
+            match fetched {
+                Ok(state) => *reading = Some(entity.reading(&state)),
+                // Keep showing the last good reading
+                Err(err) => eprintln!("{source} {}: {err}", entity.id),
+            }
+        }
+    }
+}
+
+impl Screen for HomeAssistant {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let mut page = Edges::new(2, 2, 0, 2).inset(fb.bounding_box());
+        if !self.config.title.is_empty() {
+            let [header, rest] = Flex::column()
+                .gap(4)
+                .split(page, [Item::fixed(ctx.theme.title_height()), Item::grow(1)]);
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+            page = rest;
+        }
+
+        let font = &PROFONT_9_POINT;
+        let fonts = [font];
+        let rows = Flex::column().repeat(page, font.character_size.height + 2);
+        for (bounds, (entity, reading)) in rows.zip(self.config.entities.iter().zip(&self.readings)) {
+            let (name, value, color) = match reading {
+                Some(reading) => {
+                    let color = if reading.alert { Color::Red } else { Color::Black };
+                    (reading.name.as_str(), reading.value.as_str(), color)
+                }
+                None => (entity.name.as_deref().unwrap_or(&entity.id), "-", Color::Black),
+            };
+            // The name gets whatever the value leaves, less a space
+            let [name_bounds, value_bounds] = Flex::row()
+                .gap(font.character_size.width)
+                .split(bounds, [Item::grow(1), Item::flexible(text::line_width(font, value))]);
+            let Ok(_) = TextBox::new(value_bounds, color)
+                .alignment(Alignment::Right)
+                .fonts(&fonts)
+                .draw(value, fb);
+            let Ok(_) = TextBox::new(name_bounds, color).fonts(&fonts).draw(name, fb);
+        }
+    }
+}
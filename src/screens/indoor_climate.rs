@@ -0,0 +1,153 @@
+// Temperature, humidity and pressure from a BME280 on the Pi's I2C bus, each
+// next to a sparkline of the last `history` readings.
+//
+// A thread reads the sensor every `interval` seconds whether the page is
+// showing or not, so the trend has no gaps from the time spent on other
+// pages. The history is only kept in memory and starts over on restart.
+//
+//     [indoor_climate]
+//     address = 0x77
+//     interval = 300
+//     history = 288    # a day's worth
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use embedded_graphics::prelude::*;
+use profont::{PROFONT_12_POINT, PROFONT_14_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::sensors::bme280::{self, History, Reading};
+use crate::text::TextBox;
+use crate::widgets::chart::Sparkline;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IndoorClimateConfig {
+    /// I2C bus device
+    pub bus: PathBuf,
+    pub address: u8,
+    /// Seconds between readings
+    pub interval: u64,
+    /// Readings kept for the sparklines
+    pub history: usize,
+    /// Heading; none if empty
+    pub title: String,
+}
+
+impl Default for IndoorClimateConfig {
+    fn default() -> Self {
+        IndoorClimateConfig {
+            bus: PathBuf::from(bme280::DEFAULT_BUS),
+            address: bme280::DEFAULT_ADDRESS,
+            interval: 300,
+            history: 288,
+            title: "Indoors".to_string(),
+        }
+    }
+}
+
+type Shared = Arc<Mutex<History>>;
+
+// One sampling thread per sensor, however many profiles show the page
+static SAMPLERS: Mutex<Vec<((PathBuf, u8), Shared)>> = Mutex::new(Vec::new());
+
+fn sampler(config: &IndoorClimateConfig) -> Shared {
+    let mut samplers = SAMPLERS.lock().unwrap_or_else(PoisonError::into_inner);
+    let key = (config.bus.clone(), config.address);
+    if let Some((_, history)) = samplers.iter().find(|(sensor, _)| *sensor == key) {
+        return history.clone();
+    }
+    let history = Arc::new(Mutex::new(History::new(config.history)));
+    samplers.push((key, history.clone()));
+    let (bus, address) = (config.bus.clone(), config.address);
+    let interval = Duration::from_secs(config.interval.max(1));
+    let shared = history.clone();
+    thread::spawn(move || {
+        let source = format!("bme280 {}@{address:#04x}", bus.display());
+        let mut sensor = None;
+        loop {
+            let read = metrics::timed(&source, || {
+                // Opened again after a failure, in case the sensor was unplugged and back
+                let bme280 = match &mut sensor {
+                    Some(bme280) => bme280,
+                    None => sensor.insert(bme280::open(&bus, address)?),
+                };
+                bme280.read(&mut linux_embedded_hal::Delay).map_err(|err| err.to_string())
+            });
+            match read {
+                Ok(reading) => shared.lock().unwrap_or_else(PoisonError::into_inner).push(reading),
+                Err(err) => {
+                    eprintln!("{source}: {err}");
+                    sensor = None;
+                }
+            }
+            thread::sleep(interval);
+        }
+    });
+    history
+}
+
+pub struct IndoorClimate {
+    config: IndoorClimateConfig,
+    history: Shared,
+}
+
+impl IndoorClimate {
+    pub fn new(config: &IndoorClimateConfig) -> Self {
+        IndoorClimate {
+            config: config.clone(),
+            history: sampler(config),
+        }
+    }
+}
+
+impl Screen for IndoorClimate {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        fb.clear(Color::White);
+        let mut page = Edges::new(2, 2, 0, 2).inset(fb.bounding_box());
+        if !self.config.title.is_empty() {
+            let [header, rest] = Flex::column()
+                .gap(4)
+                .split(page, [Item::fixed(ctx.theme.title_height()), Item::grow(1)]);
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+            page = rest;
+        }
+        let history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(latest) = history.latest() else {
+            let fonts = [&PROFONT_9_POINT];
+            let Ok(_) = TextBox::new(page, Color::Black).fonts(&fonts).draw("Waiting for the sensor", fb);
+            return;
+        };
+
+        type Field = fn(&Reading) -> Option<f32>;
+        let mut rows: Vec<(&str, String, Field)> = vec![(
+            "Temperature",
+            format!("{:.1}°C", latest.temperature),
+            |reading| Some(reading.temperature),
+        )];
+        if let Some(humidity) = latest.humidity {
+            rows.push(("Humidity", format!("{humidity:.0}%"), |reading| reading.humidity));
+        }
+        rows.push(("Pressure", format!("{:.0} hPa", latest.pressure), |reading| Some(reading.pressure)));
+
+        // Figures on the left half, trends on the right
+        let bands = Flex::column().layout(page, &vec![Item::grow(1); rows.len()]);
+        let label_height = PROFONT_9_POINT.character_size.height + 1;
+        for (band, (label, value, field)) in bands.into_iter().zip(rows) {
+            let [figures, trend] = Flex::row().gap(4).split(band, [Item::grow(1), Item::grow(1)]);
+            let [label_bounds, value_bounds] = Flex::column().split(figures, [Item::fixed(label_height), Item::grow(1)]);
+            let fonts = [&PROFONT_9_POINT];
+            let Ok(_) = TextBox::new(label_bounds, Color::Black).fonts(&fonts).draw(label, fb);
+            let fonts = [&PROFONT_14_POINT, &PROFONT_12_POINT, &PROFONT_9_POINT];
+            let Ok(_) = TextBox::new(value_bounds, Color::Black).fonts(&fonts).draw(&value, fb);
+            let Ok(_) = Sparkline::new(Edges::new(2, 0, 4, 0).inset(trend)).draw(&history.series(field), fb);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+// Unread mail, counted over IMAP: one row per account, with the count in a
+// badge that turns red once it passes the account's threshold.
+//
+// Each fetch logs in, asks for the mailbox's `STATUS (UNSEEN)` and logs out
+// again; nothing is downloaded or marked read. Accounts use implicit TLS on
+// port 993 unless `tls = false`, which needs the crate's `tls` feature; a
+// build without it can only reach servers in plain text, on port 143.
+//
+//     [[mail.accounts]]
+//     name = "Work"
+//     host = "imap.example.com"
+//     username = "me@example.com"
+//     password = "app password"
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+use profont::{PROFONT_12_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{self, Alignment, TextBox};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    pub accounts: Vec<AccountConfig>,
+    /// Unread count above which a badge is red, for accounts without their own
+    pub threshold: u32,
+    /// Heading; none if empty
+    pub title: String,
+    /// Seconds between fetches
+    pub refresh: u64,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        MailConfig {
+            accounts: Vec::new(),
+            threshold: 10,
+            title: "Mail".to_string(),
+            refresh: 300,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccountConfig {
+    /// Label; defaults to the username
+    pub name: Option<String>,
+    pub host: String,
+    /// Defaults to 993 with TLS and 143 without
+    pub port: Option<u16>,
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+    pub username: String,
+    pub password: String,
+    #[serde(default = "default_mailbox")]
+    pub mailbox: String,
+    pub threshold: Option<u32>,
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+impl AccountConfig {
+    fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.username)
+    }
+}
+
+trait Stream: Read + Write {}
+impl<S: Read + Write> Stream for S {}
+
+/// A logged-in IMAP session.
+pub struct Session {
+    stream: BufReader<Box<dyn Stream + Send>>,
+    tag: u32,
+}
+
+impl Session {
+    /// Connects to `account`'s server and logs in.
+    pub fn login(account: &AccountConfig) -> io::Result<Self> {
+        let port = account.port.unwrap_or(if account.tls { 993 } else { 143 });
+        let tcp = TcpStream::connect((account.host.as_str(), port))?;
+        tcp.set_read_timeout(Some(TIMEOUT))?;
+        tcp.set_write_timeout(Some(TIMEOUT))?;
+        let stream: Box<dyn Stream + Send> = if account.tls { tls::wrap(&account.host, tcp)? } else { Box::new(tcp) };
+        let mut session = Session {
+            stream: BufReader::new(stream),
+            tag: 0,
+        };
+        let greeting = session.line()?;
+        if !greeting.starts_with("* OK") {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("not IMAP: {greeting:?}")));
+        }
+        session.command(&format!("LOGIN {} {}", quote(&account.username), quote(&account.password)))?;
+        Ok(session)
+    }
+
+    /// Sends `command` and returns the untagged lines of the reply, without
+    /// their leading `* `.
+    pub fn command(&mut self, command: &str) -> io::Result<Vec<String>> {
+        self.tag += 1;
+        let tag = format!("a{}", self.tag);
+        let stream = self.stream.get_mut();
+        stream.write_all(format!("{tag} {command}\r\n").as_bytes())?;
+        stream.flush()?;
+        let mut untagged = Vec::new();
+        loop {
+            let line = self.line()?;
+            if let Some(status) = line.strip_prefix(&tag).and_then(|rest| rest.strip_prefix(' ')) {
+                return match status.strip_prefix("OK") {
+                    Some(_) => Ok(untagged),
+                    // NO or BAD, with the server's reason
+                    None => Err(io::Error::other(status.to_string())),
+                };
+            }
+            if let Some(data) = line.strip_prefix("* ") {
+                untagged.push(data.to_string());
+            }
+        }
+    }
+
+    /// Messages in `mailbox` without the `\Seen` flag.
+    pub fn unseen(&mut self, mailbox: &str) -> io::Result<u32> {
+        let untagged = self.command(&format!("STATUS {} (UNSEEN)", quote(mailbox)))?;
+        untagged
+            .iter()
+            .filter(|line| line.starts_with("STATUS "))
+            .find_map(|line| {
+                let items = line.rsplit_once('(')?.1;
+                let mut words = items.trim_end_matches(')').split_whitespace();
+                while let Some(word) = words.next() {
+                    if word.eq_ignore_ascii_case("UNSEEN") {
+                        return words.next()?.parse().ok();
+                    }
+                }
+                None
+            })
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no UNSEEN in STATUS reply"))
+    }
+
+    pub fn logout(mut self) -> io::Result<()> {
+        self.command("LOGOUT").map(|_| ())
+    }
+
+    fn line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+// A quoted string, with backslashes before quotes and backslashes
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(feature = "tls")]
+mod tls {
+    use std::io;
+    use std::net::TcpStream;
+    use std::sync::{Arc, OnceLock};
+
+    use rustls::pki_types::ServerName;
+    use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+    use super::Stream;
+
+    pub fn wrap(host: &str, tcp: TcpStream) -> io::Result<Box<dyn Stream + Send>> {
+        static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+        let config = CONFIG.get_or_init(|| {
+            let roots = RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let config = ClientConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .expect("ring supports the default protocol versions")
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            Arc::new(config)
+        });
+        let name = ServerName::try_from(host.to_string()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let connection = ClientConnection::new(config.clone(), name).map_err(io::Error::other)?;
+        Ok(Box::new(StreamOwned::new(connection, tcp)))
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+mod tls {
+    use std::io;
+    use std::net::TcpStream;
+
+    use super::Stream;
+
+    pub fn wrap(_host: &str, _tcp: TcpStream) -> io::Result<Box<dyn Stream + Send>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "built without the tls feature; set tls = false to connect in plain text",
+        ))
+    }
+}
+
+pub struct Mail {
+    config: MailConfig,
+    unseen: Vec<Option<u32>>,
+    fetched: Option<Instant>,
+}
+
+impl Mail {
+    pub fn new(config: &MailConfig) -> Self {
+        Mail {
+            config: config.clone(),
+            unseen: vec![None; config.accounts.len()],
+            fetched: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        for (account, unseen) in self.config.accounts.iter().zip(&mut self.unseen) {
+            let source = format!("mail {}", account.host);
+            let counted = metrics::timed(&source, || {
+                let mut session = Session::login(account)?;
+                let count = session.unseen(&account.mailbox)?;
+                session.logout()?;
+                Ok::<_, io::Error>(count)
+            });
+            match counted {
+                Ok(count) => *unseen = Some(count),
+                // Keep showing the last good count
+                Err(err) => eprintln!("{source} {}: {err}", account.label()),
+            }
+        }
+    }
+}
+
+impl Screen for Mail {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let mut page = Edges::new(2, 2, 0, 2).inset(fb.bounding_box());
+        if !self.config.title.is_empty() {
+            let [header, rest] = Flex::column()
+                .gap(4)
+                .split(page, [Item::fixed(ctx.theme.title_height()), Item::grow(1)]);
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+            page = rest;
+        }
+
+        let font = &PROFONT_12_POINT;
+        let fonts = [font, &PROFONT_9_POINT];
+        let rows = Flex::column().gap(2).repeat(page, font.character_size.height + 4);
+        for (row, (account, unseen)) in rows.zip(self.config.accounts.iter().zip(&self.unseen)) {
+            let count = unseen.map_or("-".to_string(), |count| count.to_string());
+            // The badge is the count in white on a block of black, or red past the threshold
+            let badge_width = text::line_width(font, &count) + 8;
+            let [name, badge] = Flex::row().gap(4).split(row, [Item::grow(1), Item::fixed(badge_width)]);
+            let threshold = account.threshold.unwrap_or(self.config.threshold);
+            let color = if unseen.is_some_and(|count| count > threshold) { Color::Red } else { Color::Black };
+            let Ok(_) = fb.fill_solid(&badge, color);
+            let Ok(_) = TextBox::new(badge.offset(-2), Color::White)
+                .alignment(Alignment::Center)
+                .fonts(&[font])
+                .draw(&count, fb);
+            let Ok(_) = TextBox::new(Edges::new(2, 0, 0, 0).inset(name), Color::Black)
+                .fonts(&fonts)
+                .draw(account.label(), fb);
+        }
+    }
+}
@@ -0,0 +1,314 @@
+// What MPD is playing: title, artist and album, and how far into the track
+// it is.
+//
+// The page only changes when the track does (or playback starts, pauses or
+// stops): the elapsed time is the one seen when it did, not a running count.
+// The same frame comes out of every other render, and `FrameStore` skips
+// refreshing to a frame that is already showing, so the panel isn't flashed
+// every few seconds just to move the progress bar along.
+//
+//     [mpd]
+//     socket = "/run/mpd/socket"
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+use profont::{PROFONT_10_POINT, PROFONT_12_POINT, PROFONT_14_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{Alignment, TextBox};
+use crate::widgets::progress::ProgressBar;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MpdConfig {
+    pub host: String,
+    pub port: u16,
+    /// Unix socket to use instead of `host` and `port`
+    pub socket: Option<PathBuf>,
+    pub password: Option<String>,
+    /// Seconds between polls
+    pub refresh: u64,
+}
+
+impl Default for MpdConfig {
+    fn default() -> Self {
+        MpdConfig {
+            host: "localhost".to_string(),
+            port: 6600,
+            socket: None,
+            password: None,
+            refresh: 5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PlayState {
+    Play,
+    Pause,
+    #[default]
+    Stop,
+}
+
+/// The current song, from `currentsong`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Song {
+    /// MPD's id for this entry in the queue
+    pub id: Option<u64>,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+}
+
+/// Playback as last polled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NowPlaying {
+    pub state: PlayState,
+    pub song: Option<Song>,
+    pub elapsed: Duration,
+    pub duration: Option<Duration>,
+}
+
+impl NowPlaying {
+    /// Makes a `NowPlaying` out of the replies to `currentsong` and `status`.
+    pub fn parse(song: &HashMap<String, String>, status: &HashMap<String, String>) -> Self {
+        let state = match status.get("state").map(String::as_str) {
+            Some("play") => PlayState::Play,
+            Some("pause") => PlayState::Pause,
+            _ => PlayState::Stop,
+        };
+        let seconds = |value: &str| value.parse::<f64>().ok().map(|secs| Duration::from_secs_f64(secs.max(0.0)));
+        // `time` is "elapsed:total" in whole seconds, from before MPD 0.20
+        let time = status.get("time").and_then(|time| time.split_once(':'));
+        let elapsed = status
+            .get("elapsed")
+            .and_then(|elapsed| seconds(elapsed))
+            .or_else(|| time.and_then(|(elapsed, _)| seconds(elapsed)))
+            .unwrap_or_default();
+        let duration = status
+            .get("duration")
+            .or_else(|| song.get("duration"))
+            .and_then(|duration| seconds(duration))
+            .or_else(|| time.and_then(|(_, total)| seconds(total)))
+            .filter(|duration| !duration.is_zero());
+        let song = song.get("file").map(|file| Song {
+            id: song.get("Id").and_then(|id| id.parse().ok()),
+            // Untagged files go by their name
+            title: song
+                .get("Title")
+                .or_else(|| song.get("Name"))
+                .cloned()
+                .unwrap_or_else(|| file.rsplit('/').next().unwrap_or(file).to_string()),
+            artist: song.get("Artist").or_else(|| song.get("AlbumArtist")).cloned().unwrap_or_default(),
+            album: song.get("Album").cloned().unwrap_or_default(),
+        });
+        NowPlaying {
+            state,
+            song,
+            elapsed,
+            duration,
+        }
+    }
+
+    // What has to change before the page is redrawn
+    fn track(&self) -> (PlayState, Option<u64>, Option<&str>) {
+        let song = self.song.as_ref();
+        (self.state, song.and_then(|song| song.id), song.map(|song| song.title.as_str()))
+    }
+}
+
+trait Stream: Read + Write {}
+impl<S: Read + Write> Stream for S {}
+
+/// A connection to MPD, speaking its line-based protocol.
+pub struct Client {
+    stream: BufReader<Box<dyn Stream + Send>>,
+}
+
+impl Client {
+    pub fn connect(config: &MpdConfig) -> io::Result<Self> {
+        let stream: Box<dyn Stream + Send> = match &config.socket {
+            Some(path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                Box::new(stream)
+            }
+            None => {
+                let stream = TcpStream::connect((config.host.as_str(), config.port))?;
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                Box::new(stream)
+            }
+        };
+        let mut client = Client {
+            stream: BufReader::new(stream),
+        };
+        let greeting = client.line()?;
+        if !greeting.starts_with("OK MPD ") {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("not MPD: {greeting:?}")));
+        }
+        if let Some(password) = &config.password {
+            client.command(&format!("password {}", quote(password)))?;
+        }
+        Ok(client)
+    }
+
+    /// Sends `command` and collects the `key: value` pairs of the reply. A
+    /// key that comes more than once keeps its first value.
+    pub fn command(&mut self, command: &str) -> io::Result<HashMap<String, String>> {
+        let stream = self.stream.get_mut();
+        stream.write_all(command.as_bytes())?;
+        stream.write_all(b"\n")?;
+        let mut pairs = HashMap::new();
+        loop {
+            let line = self.line()?;
+            if line == "OK" {
+                return Ok(pairs);
+            }
+            if let Some(error) = line.strip_prefix("ACK ") {
+                return Err(io::Error::other(error.to_string()));
+            }
+            if let Some((key, value)) = line.split_once(": ") {
+                pairs.entry(key.to_string()).or_insert_with(|| value.to_string());
+            }
+        }
+    }
+
+    pub fn now_playing(&mut self) -> io::Result<NowPlaying> {
+        let song = self.command("currentsong")?;
+        let status = self.command("status")?;
+        Ok(NowPlaying::parse(&song, &status))
+    }
+
+    fn line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(line.trim_end_matches('\n').to_string())
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.stream.get_mut().write_all(b"close\n");
+    }
+}
+
+// Arguments are double-quoted, with backslashes before quotes and backslashes
+fn quote(argument: &str) -> String {
+    format!("\"{}\"", argument.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// "3:07", or "1:02:45" past the hour
+fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    match secs / 3600 {
+        0 => format!("{}:{:02}", secs / 60, secs % 60),
+        hours => format!("{hours}:{:02}:{:02}", secs / 60 % 60, secs % 60),
+    }
+}
+
+pub struct Mpd {
+    config: MpdConfig,
+    playing: Option<NowPlaying>,
+    polled: Option<Instant>,
+}
+
+impl Mpd {
+    pub fn new(config: &MpdConfig) -> Self {
+        Mpd {
+            config: config.clone(),
+            playing: None,
+            polled: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .polled
+            .is_none_or(|polled| polled.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.polled = Some(Instant::now());
+        let source = match &self.config.socket {
+            Some(path) => format!("mpd {}", path.display()),
+            None => format!("mpd {}", self.config.host),
+        };
+        let polled = metrics::timed(&source, || Client::connect(&self.config)?.now_playing());
+        match polled {
+            // Only a new track (or play, pause or stop) replaces what's drawn
+            Ok(playing) => {
+                if self.playing.as_ref().is_none_or(|shown| shown.track() != playing.track()) {
+                    self.playing = Some(playing);
+                }
+            }
+            // Keep showing the last track
+            Err(err) => eprintln!("{source}: {err}"),
+        }
+    }
+}
+
+impl Screen for Mpd {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let line = PROFONT_9_POINT.character_size.height + 2;
+        let [title, names, bar, times] = Flex::column().padding(2).gap(2).split(
+            fb.bounding_box(),
+            [Item::fixed(36), Item::fixed(2 * line + 2), Item::fixed(8), Item::fixed(line)],
+        );
+        let heading = |text: &str, fb: &mut Framebuffer| {
+            let fonts = [&PROFONT_12_POINT];
+            let Ok(_) = TextBox::new(title, Color::Black).fonts(&fonts).draw(text, fb);
+        };
+        let Some(playing) = &self.playing else {
+            heading("MPD unreachable", fb);
+            return;
+        };
+        let Some(song) = playing.song.as_ref().filter(|_| playing.state != PlayState::Stop) else {
+            heading("Stopped", fb);
+            return;
+        };
+
+        // Long titles drop a size or two before they wrap onto a second line
+        let fonts = [&PROFONT_14_POINT, &PROFONT_12_POINT, &PROFONT_10_POINT];
+        let Ok(_) = TextBox::new(title, Color::Black).fonts(&fonts).draw(&song.title, fb);
+
+        let fonts = [&PROFONT_9_POINT];
+        let rows = Flex::column().repeat(names, line);
+        for (row, (text, color)) in rows.zip([(&song.artist, Color::Red), (&song.album, Color::Black)]) {
+            let Ok(_) = TextBox::new(row, color).fonts(&fonts).draw(text, fb);
+        }
+
+        let Some(duration) = playing.duration else {
+            return;
+        };
+        let done = (playing.elapsed.as_secs_f64() / duration.as_secs_f64()) as f32;
+        let Ok(_) = ProgressBar::new(bar).themed(&ctx.theme).draw(done, fb);
+
+        let elapsed = match playing.state {
+            PlayState::Pause => format!("{} paused", format_time(playing.elapsed)),
+            _ => format_time(playing.elapsed),
+        };
+        let Ok(_) = TextBox::new(times, Color::Black).fonts(&fonts).draw(&elapsed, fb);
+        let Ok(_) = TextBox::new(times, Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&fonts)
+            .draw(&format_time(duration), fb);
+    }
+}
@@ -0,0 +1,313 @@
+// How the Pi is connected: the Wi-Fi network and its signal, the addresses
+// it has, and a sparkline of ping times to the gateway or another host.
+//
+// The signal comes from `/proc/net/wireless`, the network name from `iw`,
+// the addresses from `ip` and the default gateway from `/proc/net/route`. A
+// thread pings every `ping_interval` seconds whether the page is showing or
+// not, so the sparkline has no gaps from the time spent on other pages;
+// pings that get no reply leave a gap in it instead.
+//
+//     [network]
+//     interface = "wlan0"
+//     ping = "1.1.1.1"    # the gateway if unset
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use profont::PROFONT_9_POINT;
+use serde::Deserialize;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{Alignment, TextBox};
+use crate::widgets::chart::Sparkline;
+use crate::widgets::icon::{self, Icon};
+
+const WIRELESS: &str = "/proc/net/wireless";
+const ROUTES: &str = "/proc/net/route";
+// Seconds a ping waits for its reply
+const PING_TIMEOUT: &str = "2";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Wireless interface whose signal is shown
+    pub interface: String,
+    /// Host to ping; the default gateway if unset
+    pub ping: Option<String>,
+    /// Seconds between pings
+    pub ping_interval: u64,
+    /// Pings kept for the sparkline
+    pub history: usize,
+    /// Heading; none if empty
+    pub title: String,
+    /// Seconds between looks at the interface and addresses
+    pub refresh: u64,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            interface: "wlan0".to_string(),
+            ping: None,
+            ping_interval: 60,
+            history: 60,
+            title: "Network".to_string(),
+            refresh: 60,
+        }
+    }
+}
+
+/// The signal level of `interface` in dBm, from the body of
+/// `/proc/net/wireless`; `None` if it isn't associated.
+pub fn parse_signal(wireless: &str, interface: &str) -> Option<i32> {
+    // Two header lines, then: wlan0: 0000   57.  -53.  -256  ...
+    wireless.lines().skip(2).find_map(|line| {
+        let (name, fields) = line.split_once(':')?;
+        if name.trim() != interface {
+            return None;
+        }
+        let level = fields.split_whitespace().nth(2)?;
+        level.trim_end_matches('.').parse().ok()
+    })
+}
+
+/// The network name in the output of `iw dev <interface> link`.
+pub fn parse_ssid(link: &str) -> Option<String> {
+    link.lines()
+        .find_map(|line| line.trim().strip_prefix("SSID: "))
+        .map(str::to_string)
+}
+
+/// The addresses in the output of `ip -o addr show`, as `(interface,
+/// address)`, leaving out loopback and link-local ones.
+pub fn parse_addresses(ip: &str) -> Vec<(String, String)> {
+    // 3: wlan0    inet 192.168.1.23/24 brd 192.168.1.255 scope global dynamic wlan0\ ...
+    ip.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (interface, family, address) = (fields.get(1)?, fields.get(2)?, fields.get(3)?);
+            let scope = fields.iter().position(|field| *field == "scope").and_then(|at| fields.get(at + 1));
+            let wanted = matches!(*family, "inet" | "inet6") && *interface != "lo" && scope != Some(&"link");
+            wanted.then(|| {
+                let address = address.split_once('/').map_or(*address, |(address, _)| address);
+                (interface.to_string(), address.to_string())
+            })
+        })
+        .collect()
+}
+
+/// The default gateway in the body of `/proc/net/route`.
+pub fn parse_gateway(routes: &str) -> Option<String> {
+    // Iface  Destination  Gateway  Flags ..., addresses in little-endian hex
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        let [a, b, c, d] = gateway.to_le_bytes();
+        Some(format!("{a}.{b}.{c}.{d}"))
+    })
+}
+
+/// The round trip in milliseconds in the output of `ping -c 1`.
+pub fn parse_ping(output: &str) -> Option<f32> {
+    // 64 bytes from 192.168.1.1: icmp_seq=1 ttl=64 time=3.21 ms
+    let (_, time) = output.split_once("time=")?;
+    time.split_whitespace().next()?.parse().ok()
+}
+
+// Runs `program` and returns what it printed, failing if it did
+fn output(program: &str, args: &[&str]) -> io::Result<String> {
+    let output = Command::new(program).args(args).stderr(Stdio::null()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("{program} failed: {}", output.status)));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The interface's state, as last looked at.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Link {
+    pub ssid: Option<String>,
+    /// dBm
+    pub signal: Option<i32>,
+    pub addresses: Vec<(String, String)>,
+    pub gateway: Option<String>,
+}
+
+impl Link {
+    fn read(interface: &str) -> io::Result<Link> {
+        // No wireless file or no `iw` just means a wired Pi
+        let signal = fs::read_to_string(WIRELESS)
+            .ok()
+            .and_then(|wireless| parse_signal(&wireless, interface));
+        let ssid = output("iw", &["dev", interface, "link"])
+            .ok()
+            .and_then(|link| parse_ssid(&link));
+        let addresses = parse_addresses(&output("ip", &["-o", "addr", "show"])?);
+        let gateway = fs::read_to_string(ROUTES)?;
+        Ok(Link {
+            ssid,
+            signal,
+            addresses,
+            gateway: parse_gateway(&gateway),
+        })
+    }
+}
+
+// Round trips in milliseconds, NaN for a ping that got no reply
+type Pings = Arc<Mutex<VecDeque<f32>>>;
+
+// One pinging thread per host, however many profiles show the page
+static PINGERS: Mutex<Vec<(Option<String>, Pings)>> = Mutex::new(Vec::new());
+
+fn pinger(config: &NetworkConfig) -> Pings {
+    let mut pingers = PINGERS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((_, pings)) = pingers.iter().find(|(host, _)| *host == config.ping) {
+        return pings.clone();
+    }
+    let pings = Arc::new(Mutex::new(VecDeque::with_capacity(config.history)));
+    pingers.push((config.ping.clone(), pings.clone()));
+    let (host, history) = (config.ping.clone(), config.history.max(1));
+    let interval = Duration::from_secs(config.ping_interval.max(1));
+    let shared = pings.clone();
+    thread::spawn(move || {
+        loop {
+            // Looked up every time, since the gateway changes with the network
+            let target = host
+                .clone()
+                .or_else(|| fs::read_to_string(ROUTES).ok().and_then(|routes| parse_gateway(&routes)));
+            if let Some(target) = target {
+                let ping = metrics::timed("network ping", || {
+                    output("ping", &["-c", "1", "-W", PING_TIMEOUT, &target])
+                });
+                let millis = ping.ok().and_then(|output| parse_ping(&output)).unwrap_or(f32::NAN);
+                let mut pings = shared.lock().unwrap_or_else(PoisonError::into_inner);
+                if pings.len() == history {
+                    pings.pop_front();
+                }
+                pings.push_back(millis);
+            }
+            thread::sleep(interval);
+        }
+    });
+    pings
+}
+
+pub struct Network {
+    config: NetworkConfig,
+    link: Option<Link>,
+    fetched: Option<Instant>,
+    pings: Pings,
+}
+
+impl Network {
+    pub fn new(config: &NetworkConfig) -> Self {
+        Network {
+            config: config.clone(),
+            link: None,
+            fetched: None,
+            pings: pinger(config),
+        }
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let source = format!("network {}", self.config.interface);
+        match metrics::timed(&source, || Link::read(&self.config.interface)) {
+            Ok(link) => self.link = Some(link),
+            // Keep showing the last good state
+            Err(err) => eprintln!("{source}: {err}"),
+        }
+    }
+}
+
+impl Screen for Network {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let font = &PROFONT_9_POINT;
+        let fonts = [font];
+        let row_height = font.character_size.height + 2;
+        let mut page = Edges::all(2).inset(fb.bounding_box());
+        if !self.config.title.is_empty() {
+            let [header, rest] = Flex::column()
+                .gap(4)
+                .split(page, [Item::fixed(ctx.theme.title_height()), Item::grow(1)]);
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+            page = rest;
+        }
+        let Some(link) = &self.link else {
+            let Ok(_) = TextBox::new(page, Color::Black).fonts(&fonts).draw("No network information", fb);
+            return;
+        };
+
+        // Bars, then the network name and the signal in dBm beside them
+        let [wifi, rest] = Flex::column().gap(4).split(page, [Item::fixed(icon::SIZE), Item::grow(1)]);
+        let [bars, name_bounds] = Flex::row().gap(4).split(wifi, [Item::fixed(icon::SIZE), Item::grow(1)]);
+        let icon = link.signal.map_or(Icon::WifiOff, Icon::wifi_signal);
+        let Ok(_) = icon::draw(icon, bars.top_left, Color::Black, fb);
+        let name = match (&link.ssid, link.signal) {
+            (Some(ssid), Some(dbm)) => format!("{ssid} ({dbm} dBm)"),
+            (Some(ssid), None) => ssid.clone(),
+            (None, Some(dbm)) => format!("{dbm} dBm"),
+            (None, None) => "Not on Wi-Fi".to_string(),
+        };
+        let name_bounds = Rectangle::new(name_bounds.top_left + Point::new(0, 3), Size::new(name_bounds.size.width, row_height));
+        let Ok(_) = TextBox::new(name_bounds, Color::Black).fonts(&fonts).draw(&name, fb);
+
+        let mut lines = Vec::new();
+        if link.addresses.is_empty() {
+            lines.push(("No address".to_string(), Color::Red));
+        }
+        for (interface, address) in &link.addresses {
+            lines.push((format!("{interface} {address}"), Color::Black));
+        }
+        let gateway = link.gateway.as_deref().unwrap_or("none");
+        lines.push((format!("Gateway {gateway}"), Color::Black));
+        // As many as leave room below for the ping line
+        let room = Flex::column().repeat(rest, row_height).count().saturating_sub(1);
+        lines.truncate(room);
+        let [list, ping, chart] = Flex::column().split(
+            rest,
+            [Item::fixed(lines.len() as u32 * row_height), Item::fixed(row_height), Item::grow(1)],
+        );
+        for (bounds, (text, color)) in Flex::column().repeat(list, row_height).zip(lines) {
+            let Ok(_) = TextBox::new(bounds, color).fonts(&fonts).draw(&text, fb);
+        }
+
+        // Latest round trip over the sparkline of the rest, down to the bottom
+        let pings: Vec<f32> = self.pings.lock().unwrap_or_else(PoisonError::into_inner).iter().copied().collect();
+        let target = self.config.ping.as_deref().unwrap_or("gateway");
+        let (latest, color) = match pings.last() {
+            Some(millis) if millis.is_finite() => (format!("{millis:.0} ms"), Color::Black),
+            Some(_) => ("no reply".to_string(), Color::Red),
+            None => ("-".to_string(), Color::Black),
+        };
+        let Ok(_) = TextBox::new(ping, Color::Black).fonts(&fonts).draw(&format!("Ping {target}"), fb);
+        let Ok(_) = TextBox::new(ping, color)
+            .alignment(Alignment::Right)
+            .fonts(&fonts)
+            .draw(&latest, fb);
+        if chart.size.height >= 4 {
+            let Ok(_) = Sparkline::new(chart).draw(&pings, fb);
+        }
+    }
+}
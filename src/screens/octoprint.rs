@@ -0,0 +1,217 @@
+// What the 3D printer is up to, from OctoPrint's REST API: the job, how far
+// through it is, the hotend and bed temperatures, and when it should be done.
+// The progress bar turns red when the printer reports an error.
+//
+// The API key is under Settings → API (or an application key) in OctoPrint.
+
+use std::time::{Duration, Instant};
+
+use chrono::TimeDelta;
+use embedded_graphics::prelude::*;
+use profont::{PROFONT_12_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{self, Alignment, TextBox};
+use crate::widgets::progress::ProgressBar;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+// OctoPrint answers /api/printer with this when no printer is connected
+const NOT_CONNECTED: u16 = 409;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OctoPrintConfig {
+    /// Base address, e.g. `http://octopi.local`
+    pub url: String,
+    pub api_key: String,
+    /// Seconds between fetches
+    pub refresh: u64,
+}
+
+impl Default for OctoPrintConfig {
+    fn default() -> Self {
+        OctoPrintConfig {
+            url: "http://octopi.local".to_string(),
+            api_key: String::new(),
+            refresh: 30,
+        }
+    }
+}
+
+/// A heater's reading and what it is heading for, in °C.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Temperature {
+    pub actual: f64,
+    pub target: f64,
+}
+
+impl Temperature {
+    fn parse(value: &Value) -> Option<Self> {
+        Some(Temperature {
+            actual: value["actual"].as_f64()?,
+            target: value["target"].as_f64().unwrap_or(0.0),
+        })
+    }
+}
+
+/// The printer as last fetched.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PrinterStatus {
+    /// OctoPrint's own words: `Printing`, `Operational`, `Offline`...
+    pub state: String,
+    pub error: bool,
+    pub job: Option<String>,
+    /// Percent done
+    pub completion: Option<f64>,
+    pub time_left: Option<Duration>,
+    pub hotend: Option<Temperature>,
+    pub bed: Option<Temperature>,
+}
+
+impl PrinterStatus {
+    /// Makes a `PrinterStatus` out of the bodies of `GET /api/job` and, when
+    /// a printer is connected, `GET /api/printer`.
+    pub fn parse(job: &Value, printer: Option<&Value>) -> Self {
+        let state = job["state"].as_str().unwrap_or("Unknown").to_string();
+        let flagged = printer.is_some_and(|printer| printer["state"]["flags"]["error"].as_bool() == Some(true));
+        let file = &job["job"]["file"];
+        let temperatures = printer.map(|printer| &printer["temperature"]);
+        PrinterStatus {
+            error: flagged || state.starts_with("Error") || state.starts_with("Offline after error"),
+            job: file["display"].as_str().or_else(|| file["name"].as_str()).map(str::to_string),
+            completion: job["progress"]["completion"].as_f64(),
+            time_left: job["progress"]["printTimeLeft"]
+                .as_f64()
+                .map(|secs| Duration::from_secs_f64(secs.max(0.0))),
+            hotend: temperatures.and_then(|temperatures| Temperature::parse(&temperatures["tool0"])),
+            bed: temperatures.and_then(|temperatures| Temperature::parse(&temperatures["bed"])),
+            state,
+        }
+    }
+}
+
+// "1h 12m", or "4m" under the hour
+fn format_left(left: Duration) -> String {
+    let minutes = left.as_secs().div_ceil(60);
+    match minutes / 60 {
+        0 => format!("{minutes}m"),
+        hours => format!("{hours}h {}m", minutes % 60),
+    }
+}
+
+fn format_temperature(name: &str, temperature: Option<Temperature>) -> String {
+    match temperature {
+        Some(Temperature { actual, target }) if target > 0.0 => format!("{name} {actual:.0}/{target:.0}°C"),
+        Some(Temperature { actual, .. }) => format!("{name} {actual:.0}°C"),
+        None => format!("{name} -"),
+    }
+}
+
+pub struct OctoPrint {
+    config: OctoPrintConfig,
+    status: Option<PrinterStatus>,
+    fetched: Option<Instant>,
+}
+
+impl OctoPrint {
+    pub fn new(config: &OctoPrintConfig) -> Self {
+        OctoPrint {
+            config: config.clone(),
+            status: None,
+            fetched: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let source = metrics::source_name("octoprint", &self.config.url);
+        let agent = ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build();
+        let base = self.config.url.trim_end_matches('/');
+        // `None` for a printer that isn't connected: the job still says "Offline"
+        let get = |path: &str| match agent.get(&format!("{base}{path}")).set("X-Api-Key", &self.config.api_key).call() {
+            Ok(response) => response.into_json::<Value>().map(Some).map_err(|err| err.to_string()),
+            Err(ureq::Error::Status(NOT_CONNECTED, _)) => Ok(None),
+            Err(err) => Err(err.to_string()),
+        };
+        let fetched = metrics::timed(&source, || {
+            let job = get("/api/job")?.ok_or("no job")?;
+            let printer = get("/api/printer")?;
+            Ok::<_, String>(PrinterStatus::parse(&job, printer.as_ref()))
+        });
+        match fetched {
+            Ok(status) => self.status = Some(status),
+            // Keep showing the last good status
+            Err(err) => eprintln!("{source}: {err}"),
+        }
+    }
+}
+
+impl Screen for OctoPrint {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let line = PROFONT_9_POINT.character_size.height + 2;
+        let [header, job_bounds, progress, details] = Flex::column().padding(2).gap(2).split(
+            fb.bounding_box(),
+            [Item::fixed(16), Item::fixed(line * 2), Item::fixed(16), Item::fixed(line * 2)],
+        );
+        let Some(status) = &self.status else {
+            let fonts = [&PROFONT_12_POINT];
+            let Ok(_) = TextBox::new(header, Color::Black).fonts(&fonts).draw("Printer unreachable", fb);
+            return;
+        };
+        let state_color = if status.error { Color::Red } else { Color::Black };
+        let fonts = [&PROFONT_12_POINT];
+        let Ok(_) = TextBox::new(header, state_color).fonts(&fonts).draw(&status.state, fb);
+
+        let fonts = [&PROFONT_9_POINT];
+        let job = status.job.as_deref().unwrap_or("No job");
+        let Ok(_) = TextBox::new(job_bounds, Color::Black).fonts(&fonts).draw(job, fb);
+
+        // Progress bar with the percentage to its right
+        let percent = status.completion.unwrap_or(0.0).clamp(0.0, 100.0);
+        let label = format!("{percent:.0}%");
+        let label_width = text::line_width(&PROFONT_9_POINT, &label);
+        let [bar_bounds, label_bounds] = Flex::row()
+            .gap(PROFONT_9_POINT.character_size.width)
+            .split(progress, [Item::grow(1).cross(14), Item::fixed(label_width)]);
+        let bar = ProgressBar {
+            color: if status.error { Color::Red } else { Color::Black },
+            ..ProgressBar::new(bar_bounds).themed(&ctx.theme)
+        };
+        let Ok(_) = bar.draw((percent / 100.0) as f32, fb);
+        let Ok(_) = TextBox::new(Edges::new(2, 0, 0, 0).inset(label_bounds), Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&fonts)
+            .draw(&label, fb);
+
+        let mut rows = Flex::column().repeat(details, line);
+        let temperatures = format!(
+            "{}  {}",
+            format_temperature("Hotend", status.hotend),
+            format_temperature("Bed", status.bed)
+        );
+        if let Some(bounds) = rows.next() {
+            let Ok(_) = TextBox::new(bounds, Color::Black).fonts(&fonts).draw(&temperatures, fb);
+        }
+
+        if let Some(left) = status.time_left.filter(|_| status.completion.is_some_and(|done| done < 100.0)) {
+            let done_at = ctx.now + TimeDelta::from_std(left).unwrap_or_default();
+            let eta = format!("ETA {}, {} left", done_at.format("%H:%M"), format_left(left));
+            if let Some(bounds) = rows.next() {
+                let Ok(_) = TextBox::new(bounds, Color::Black).fonts(&fonts).draw(&eta, fb);
+            }
+        }
+    }
+}
@@ -0,0 +1,297 @@
+// Screens drawn by third-party WebAssembly modules.
+//
+// No WebAssembly runtime is built in: a plugin is a WASI command module that
+// the daemon runs in a separate process under a runtime installed on the Pi,
+// `wasmtime run` from PATH by default. The sandbox is external too. The
+// daemon doesn't confine the module itself; what the module may reach is
+// whatever that binary, run with the arguments in `runtime`, allows.
+// wasmtime gives a module no filesystem or network access unless told to,
+// but a different program on PATH under that name could give it anything.
+// The runtime is asked for `--version` when the config is loaded, so a
+// missing one stops the daemon starting rather than breaking the page later.
+// Each render the daemon writes one JSON object to the module's stdin:
+//
+//     {"width": 212, "height": 104, "now": "2024-05-01T12:00:00+01:00", "data": {...}}
+//
+// where `data` is the plugin's `data` table from the config, and reads back a
+// JSON array of draw commands on stdout, e.g.
+//
+//     [{"op": "clear", "color": "white"},
+//      {"op": "text", "x": 2, "y": 2, "text": "Hello", "color": "red", "size": 24},
+//      {"op": "icon", "x": 190, "y": 2, "icon": "wifi3", "color": "black"}]
+//
+// A module that fails, times out or prints something unreadable leaves an
+// error message on the page instead.
+
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command as Process, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{Circle, Line, PrimitiveStyle, Rectangle};
+use profont::PROFONT_9_POINT;
+use serde::Deserialize;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::Edges;
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{self, Alignment, TextBox};
+use crate::widgets::icon::{self, Icon};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PluginConfig {
+    /// The `.wasm` module
+    pub module: PathBuf,
+    /// Runtime command, looked up on PATH, that the module path is appended
+    /// to; the module is as sandboxed as this makes it
+    pub runtime: Vec<String>,
+    /// Seconds the module may take before it is killed
+    pub timeout: u64,
+    /// Passed to the module as `data`
+    pub data: serde_json::Value,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        PluginConfig {
+            module: PathBuf::new(),
+            runtime: vec!["wasmtime".to_string(), "run".to_string()],
+            timeout: 10,
+            data: serde_json::Value::Object(Default::default()),
+        }
+    }
+}
+
+/// One thing for the daemon to draw on a plugin's behalf. Coordinates are in
+/// pixels from the top left of the rotated panel.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Command {
+    Clear {
+        color: Color,
+    },
+    Line {
+        from: [i32; 2],
+        to: [i32; 2],
+        color: Color,
+        #[serde(default = "one")]
+        width: u32,
+    },
+    Rect {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: Color,
+        #[serde(default)]
+        fill: bool,
+    },
+    Circle {
+        x: i32,
+        y: i32,
+        diameter: u32,
+        color: Color,
+        #[serde(default)]
+        fill: bool,
+    },
+    /// Wrapped text in a box reaching to the panel edges unless `width` and
+    /// `height` say otherwise; `size` is a ProFont point size, the largest that
+    /// fits if left out
+    Text {
+        x: i32,
+        y: i32,
+        width: Option<u32>,
+        height: Option<u32>,
+        text: String,
+        color: Color,
+        size: Option<u32>,
+        #[serde(default)]
+        align: Alignment,
+    },
+    Icon {
+        x: i32,
+        y: i32,
+        icon: Icon,
+        color: Color,
+    },
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl Command {
+    pub fn draw(&self, fb: &mut Framebuffer) {
+        let style = |color, fill: bool| {
+            if fill {
+                PrimitiveStyle::with_fill(color)
+            } else {
+                PrimitiveStyle::with_stroke(color, 1)
+            }
+        };
+        // Drawing into the framebuffer can't fail
+        let Ok(()) = match self {
+            Command::Clear { color } => {
+                fb.clear(*color);
+                Ok(())
+            }
+            Command::Line { from, to, color, width } => {
+                Line::new(Point::from(*from), Point::from(*to))
+                    .into_styled(PrimitiveStyle::with_stroke(*color, *width))
+                    .draw(fb)
+            }
+            Command::Rect {
+                x,
+                y,
+                width,
+                height,
+                color,
+                fill,
+            } => Rectangle::new(Point::new(*x, *y), Size::new(*width, *height))
+                .into_styled(style(*color, *fill))
+                .draw(fb),
+            Command::Circle {
+                x,
+                y,
+                diameter,
+                color,
+                fill,
+            } => Circle::new(Point::new(*x, *y), *diameter)
+                .into_styled(style(*color, *fill))
+                .draw(fb),
+            Command::Text {
+                x,
+                y,
+                width,
+                height,
+                text,
+                color,
+                size,
+                align,
+            } => {
+                let width = width.unwrap_or_else(|| fb.width().saturating_sub(*x as u32));
+                let height = height.unwrap_or_else(|| fb.height().saturating_sub(*y as u32));
+                let font = size.and_then(text::profont);
+                let single = font.as_slice();
+                let mut text_box = TextBox::new(Rectangle::new(Point::new(*x, *y), Size::new(width, height)), *color)
+                    .alignment(*align);
+                if font.is_some() {
+                    text_box = text_box.fonts(single);
+                }
+                text_box.draw(text, fb).map(|_| ())
+            }
+            Command::Icon { x, y, icon, color } => icon::draw(*icon, Point::new(*x, *y), *color, fb),
+        };
+    }
+}
+
+impl PluginConfig {
+    /// Whether the module is there and the runtime runs, by asking it for its version.
+    pub fn check(&self) -> Result<(), String> {
+        if !self.module.is_file() {
+            return Err(format!("no module at {}", self.module.display()));
+        }
+        let program = self.runtime.first().ok_or("no runtime configured")?;
+        let output = Process::new(program)
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|err| format!("runtime {program}: {err}"))?;
+        if !output.status.success() || output.stdout.trim_ascii().is_empty() {
+            return Err(format!("runtime {program} doesn't report a version"));
+        }
+        Ok(())
+    }
+}
+
+pub struct Plugin {
+    name: String,
+    config: PluginConfig,
+}
+
+impl Plugin {
+    pub fn new(name: &str, config: &PluginConfig) -> Self {
+        Plugin {
+            name: name.to_string(),
+            config: config.clone(),
+        }
+    }
+
+    // Runs the module once and parses what it printed
+    fn run(&self, input: &[u8]) -> Result<Vec<Command>, String> {
+        let (program, args) = self.config.runtime.split_first().ok_or("no runtime configured")?;
+        let mut child = Process::new(program)
+            .args(args)
+            .arg(&self.config.module)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|err| format!("{program}: {err}"))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // A module that doesn't read its input closes the pipe early; that's its business
+            let _ = stdin.write_all(input);
+        }
+        // Read on another thread so a chatty module can't fill the pipe and stall
+        let mut stdout = child.stdout.take().ok_or("no stdout")?;
+        let reader = thread::spawn(move || {
+            let mut output = Vec::new();
+            stdout.read_to_end(&mut output).map(|_| output)
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(self.config.timeout);
+        let status = loop {
+            match child.try_wait().map_err(|err| err.to_string())? {
+                Some(status) => break status,
+                None if Instant::now() >= deadline => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(format!("timed out after {}s", self.config.timeout));
+                }
+                None => thread::sleep(Duration::from_millis(20)),
+            }
+        };
+        let output = reader
+            .join()
+            .map_err(|_| "reader panicked".to_string())?
+            .map_err(|err| err.to_string())?;
+        if !status.success() {
+            return Err(format!("exited with {status}"));
+        }
+        serde_json::from_slice(&output).map_err(|err| format!("bad draw commands: {err}"))
+    }
+}
+
+impl Screen for Plugin {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        let input = serde_json::json!({
+            "width": fb.width(),
+            "height": fb.height(),
+            "now": ctx.now.to_rfc3339(),
+            "data": self.config.data,
+        })
+        .to_string();
+        let source = format!("plugin {}", self.name);
+        match metrics::timed(&source, || self.run(input.as_bytes())) {
+            Ok(commands) => {
+                fb.clear(Color::White);
+                for command in &commands {
+                    command.draw(fb);
+                }
+            }
+            Err(err) => {
+                eprintln!("{source}: {err}");
+                fb.clear(Color::White);
+                let fonts = [&PROFONT_9_POINT];
+                let Ok(_) = TextBox::new(Edges::all(2).inset(fb.bounding_box()), Color::Red)
+                    .fonts(&fonts)
+                    .draw(&format!("{source}: {err}"), fb);
+            }
+        }
+    }
+}
@@ -0,0 +1,301 @@
+// A Pomodoro timer run from the HAT's buttons: work for a while, take a
+// short break, and a long one after every few rounds. The time left is in
+// large seven-segment digits, redrawn with the panel's fast refresh while it
+// runs; breaks put a red banner across the top.
+//
+// `start` and `reset` are buttons by their place in `[buttons] pins` (0 is
+// the first). While this page shows they start, pause and reset the timer
+// instead of turning pages; the other buttons turn pages as usual. A break
+// follows a round of work straight away, but the next round waits for
+// `start`. The timer keeps time whichever page is showing.
+//
+//     pages = ["clock", "pomodoro"]
+//
+//     [pomodoro]
+//     work = 50
+//     short_break = 10
+
+use std::time::{Duration, Instant};
+
+use chrono::TimeDelta;
+use embedded_graphics::prelude::*;
+use profont::{PROFONT_12_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Align, Edges, Flex, Item};
+use crate::screens::{RenderContext, Screen};
+use crate::text::{Alignment, TextBox};
+use crate::widgets::seven_segment::SevenSegment;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PomodoroConfig {
+    /// Minutes in a round of work
+    pub work: u64,
+    /// Minutes in the break after most rounds
+    pub short_break: u64,
+    /// Minutes in the break after every `rounds`th round
+    pub long_break: u64,
+    pub rounds: u32,
+    /// Button that starts and pauses the timer
+    pub start: usize,
+    /// Button that goes back to the first round, stopped
+    pub reset: usize,
+    /// Seconds between redraws while the timer runs; the time left is shown to the nearest of these
+    pub tick: u64,
+    /// Heading; none if empty
+    pub title: String,
+}
+
+impl Default for PomodoroConfig {
+    fn default() -> Self {
+        PomodoroConfig {
+            work: 25,
+            short_break: 5,
+            long_break: 15,
+            rounds: 4,
+            start: 0,
+            reset: 2,
+            tick: 60,
+            title: "Pomodoro".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Work,
+    ShortBreak,
+    LongBreak,
+}
+
+impl Phase {
+    pub fn is_break(self) -> bool {
+        self != Phase::Work
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Stopped { left: Duration },
+    Running { ends: Instant },
+}
+
+/// The timer itself, apart from the page that shows it.
+#[derive(Clone, Debug)]
+pub struct Timer {
+    work: Duration,
+    short_break: Duration,
+    long_break: Duration,
+    rounds: u32,
+    phase: Phase,
+    // Rounds of work finished since the last long break
+    done: u32,
+    state: State,
+}
+
+impl Timer {
+    /// Stopped at the start of the first round.
+    pub fn new(config: &PomodoroConfig) -> Self {
+        let work = Duration::from_secs(config.work * 60);
+        Timer {
+            work,
+            short_break: Duration::from_secs(config.short_break * 60),
+            long_break: Duration::from_secs(config.long_break * 60),
+            rounds: config.rounds.max(1),
+            phase: Phase::Work,
+            done: 0,
+            state: State::Stopped { left: work },
+        }
+    }
+
+    fn length(&self, phase: Phase) -> Duration {
+        match phase {
+            Phase::Work => self.work,
+            Phase::ShortBreak => self.short_break,
+            Phase::LongBreak => self.long_break,
+        }
+    }
+
+    /// Moves on past any phases that ended by `now`.
+    pub fn update(&mut self, now: Instant) {
+        while let State::Running { ends } = self.state
+            && ends <= now
+        {
+            self.phase = match self.phase {
+                Phase::Work => {
+                    self.done += 1;
+                    if self.done >= self.rounds { Phase::LongBreak } else { Phase::ShortBreak }
+                }
+                Phase::LongBreak => {
+                    self.done = 0;
+                    Phase::Work
+                }
+                Phase::ShortBreak => Phase::Work,
+            };
+            let length = self.length(self.phase);
+            // Breaks start by themselves, from when the work ended; work waits to be started
+            self.state = match self.phase {
+                Phase::Work => State::Stopped { left: length },
+                _ => State::Running { ends: ends + length },
+            };
+        }
+    }
+
+    pub fn start_pause(&mut self, now: Instant) {
+        self.update(now);
+        self.state = match self.state {
+            State::Stopped { left } => State::Running { ends: now + left },
+            State::Running { ends } => State::Stopped { left: ends - now },
+        };
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = Phase::Work;
+        self.done = 0;
+        self.state = State::Stopped { left: self.work };
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Rounds of work finished since the last long break.
+    pub fn done(&self) -> u32 {
+        self.done
+    }
+
+    pub fn rounds(&self) -> u32 {
+        self.rounds
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self.state, State::Running { .. })
+    }
+
+    /// Whether this phase has yet to start.
+    pub fn is_fresh(&self) -> bool {
+        self.state == State::Stopped { left: self.length(self.phase) }
+    }
+
+    /// Time left in the phase as of `now`, which `update` should have seen.
+    pub fn left(&self, now: Instant) -> Duration {
+        match self.state {
+            State::Stopped { left } => left,
+            State::Running { ends } => ends.saturating_duration_since(now),
+        }
+    }
+}
+
+pub struct Pomodoro {
+    config: PomodoroConfig,
+    timer: Timer,
+}
+
+impl Pomodoro {
+    pub fn new(config: &PomodoroConfig) -> Self {
+        Pomodoro {
+            config: config.clone(),
+            timer: Timer::new(config),
+        }
+    }
+
+    fn tick(&self) -> u128 {
+        Duration::from_secs(self.config.tick).as_millis().max(1)
+    }
+
+    // Time left, rounded up to a tick while running so that every redraw lands on one
+    fn shown(&self, now: Instant) -> Duration {
+        let left = self.timer.left(now);
+        if !self.timer.is_running() {
+            return left;
+        }
+        let tick = self.tick();
+        Duration::from_millis((left.as_millis().div_ceil(tick) * tick) as u64)
+    }
+}
+
+impl Screen for Pomodoro {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        let now = Instant::now();
+        self.timer.update(now);
+        fb.clear(Color::White);
+        let small = [&PROFONT_9_POINT];
+        let line = PROFONT_9_POINT.character_size.height + 2;
+        let title = PROFONT_12_POINT.character_size.height;
+        let fonts = [&PROFONT_12_POINT];
+        let [banner, body] = Flex::column()
+            .gap(4)
+            .split(fb.bounding_box(), [Item::fixed(title + 4), Item::grow(1)]);
+
+        // Heading, or the banner on a break
+        let phase = self.timer.phase();
+        let heading = Edges::axes(2, 2).inset(banner);
+        if phase.is_break() {
+            let Ok(_) = fb.fill_solid(&banner, Color::Red);
+            let text = if phase == Phase::LongBreak { "LONG BREAK" } else { "BREAK" };
+            let Ok(_) = TextBox::new(heading, Color::White)
+                .alignment(Alignment::Center)
+                .fonts(&fonts)
+                .draw(text, fb);
+        } else if !self.config.title.is_empty() {
+            let Ok(_) = TextBox::new(heading, Color::Black).fonts(&fonts).draw(&self.config.title, fb);
+        }
+        if !phase.is_break() {
+            let round = format!("round {} of {}", self.timer.done() + 1, self.timer.rounds());
+            let Ok(_) = TextBox::new(Edges::new(4, 2, 0, 2).inset(banner), Color::Black)
+                .alignment(Alignment::Right)
+                .fonts(&small)
+                .draw(&round, fb);
+        }
+
+        // The time left, as large as fits between the heading and the status line
+        let shown = self.shown(now).as_secs();
+        let digits = format!("{:02}:{:02}", shown / 60 % 100, shown % 60);
+        let room = body.size.height.saturating_sub(line + 6);
+        let segments = SevenSegment::new((body.size.width * 2 / 5).min(room));
+        let [figures, below] = Flex::column().gap(4).align(Align::Center).split(
+            body,
+            [Item::fixed(segments.digit.height).cross(segments.text_width(&digits)), Item::fixed(line)],
+        );
+        let Ok(_) = segments.draw(&digits, figures.top_left, fb);
+
+        let status = if self.timer.is_running() {
+            let ends = ctx.now + TimeDelta::from_std(self.timer.left(now)).unwrap_or_default();
+            format!("until {}", ends.format("%H:%M"))
+        } else if self.timer.is_fresh() {
+            "ready".to_string()
+        } else {
+            "paused".to_string()
+        };
+        let Ok(_) = TextBox::new(Edges::axes(0, 2).inset(below), Color::Black)
+            .alignment(Alignment::Center)
+            .fonts(&small)
+            .draw(&status, fb);
+    }
+
+    fn press(&mut self, button: usize) -> bool {
+        if button == self.config.start {
+            self.timer.start_pause(Instant::now());
+        } else if button == self.config.reset {
+            self.timer.reset();
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn live(&self) -> Option<Duration> {
+        if !self.timer.is_running() {
+            return None;
+        }
+        // Until the digits next change: the time left comes down to the tick below, or the phase ends
+        let now = Instant::now();
+        let left = self.timer.left(now);
+        if left.is_zero() {
+            return Some(Duration::ZERO);
+        }
+        Some((left + Duration::from_millis(self.tick() as u64)).saturating_sub(self.shown(now)))
+    }
+}
@@ -0,0 +1,343 @@
+// Internet speed: download, upload and latency from the latest test, over a
+// chart of the tests before it (download in black, upload in red).
+//
+// A test moves tens of megabytes and takes a while, so it runs on a thread
+// of its own every `interval` seconds, however often the page is drawn. The
+// history is only kept in memory and starts over on restart.
+//
+// Tests come from a `SpeedtestBackend`. Two are built in: Cloudflare's
+// speed test endpoints, which need nothing installed, and a command that
+// prints JSON, which understands Ookla's `speedtest --format=json` and
+// `speedtest-cli --json`.
+//
+//     [speedtest]
+//     backend = "command"
+//     command = ["speedtest-cli", "--json"]
+//     interval = 21600
+
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use embedded_graphics::prelude::*;
+use profont::{PROFONT_12_POINT, PROFONT_14_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{Alignment, TextBox};
+use crate::widgets::chart::{self, LineChart};
+
+// Longer than the other pages' fetches: this one is meant to take a while
+const TEST_TIMEOUT: Duration = Duration::from_secs(60);
+const CLOUDFLARE: &str = "https://speed.cloudflare.com";
+// Empty downloads timed for the latency, of which the median is taken
+const LATENCY_PROBES: usize = 5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Cloudflare,
+    Command,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeedtestConfig {
+    pub backend: BackendKind,
+    /// Program and arguments for the `command` backend
+    pub command: Vec<String>,
+    /// Bytes the `cloudflare` backend downloads per test
+    pub download_bytes: u64,
+    /// Bytes the `cloudflare` backend uploads per test
+    pub upload_bytes: usize,
+    /// Seconds between tests
+    pub interval: u64,
+    /// Tests kept for the chart
+    pub history: usize,
+    /// Heading; none if empty
+    pub title: String,
+}
+
+impl Default for SpeedtestConfig {
+    fn default() -> Self {
+        SpeedtestConfig {
+            backend: BackendKind::default(),
+            command: vec!["speedtest".to_string(), "--format=json".to_string(), "--accept-license".to_string()],
+            download_bytes: 25_000_000,
+            upload_bytes: 5_000_000,
+            interval: 4 * 60 * 60,
+            history: 42,
+            title: "Speed".to_string(),
+        }
+    }
+}
+
+/// One test's results.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    /// Megabits per second
+    pub download: f32,
+    pub upload: f32,
+    /// Milliseconds
+    pub latency: f32,
+    pub at: DateTime<Local>,
+}
+
+impl Measurement {
+    /// Reads the JSON that Ookla's `speedtest --format=json` or
+    /// `speedtest-cli --json` prints.
+    pub fn parse(output: &Value) -> Result<Measurement, String> {
+        let (download, upload, latency) = if output["download"].is_object() {
+            // Ookla: bytes per second
+            let mbps = |direction: &str| output[direction]["bandwidth"].as_f64().map(|bytes| bytes * 8.0 / 1e6);
+            (mbps("download"), mbps("upload"), output["ping"]["latency"].as_f64())
+        } else {
+            // speedtest-cli: bits per second
+            let mbps = |direction: &str| output[direction].as_f64().map(|bits| bits / 1e6);
+            (mbps("download"), mbps("upload"), output["ping"].as_f64())
+        };
+        let (Some(download), Some(upload), Some(latency)) = (download, upload, latency) else {
+            let error = output["error"].as_str().unwrap_or("unexpected output");
+            return Err(error.to_string());
+        };
+        Ok(Measurement {
+            download: download as f32,
+            upload: upload as f32,
+            latency: latency as f32,
+            at: Local::now(),
+        })
+    }
+}
+
+/// A way of measuring the connection.
+pub trait SpeedtestBackend {
+    /// Name for the metrics table, e.g. `speedtest cloudflare`.
+    fn source(&self) -> String;
+
+    fn run(&self) -> Result<Measurement, String>;
+}
+
+/// Cloudflare's speed test: timed downloads from `__down` and uploads to `__up`.
+pub struct Cloudflare {
+    agent: ureq::Agent,
+    download_bytes: u64,
+    upload_bytes: usize,
+}
+
+impl Cloudflare {
+    pub fn new(download_bytes: u64, upload_bytes: usize) -> Self {
+        Cloudflare {
+            agent: ureq::AgentBuilder::new().timeout(TEST_TIMEOUT).build(),
+            download_bytes,
+            upload_bytes,
+        }
+    }
+
+    // Seconds to download `bytes`, reading the whole body
+    fn download(&self, bytes: u64) -> Result<f64, String> {
+        let started = Instant::now();
+        let response = self
+            .agent
+            .get(&format!("{CLOUDFLARE}/__down?bytes={bytes}"))
+            .call()
+            .map_err(|err| err.to_string())?;
+        io::copy(&mut response.into_reader().take(bytes), &mut io::sink()).map_err(|err| err.to_string())?;
+        Ok(started.elapsed().as_secs_f64())
+    }
+}
+
+impl SpeedtestBackend for Cloudflare {
+    fn source(&self) -> String {
+        "speedtest cloudflare".to_string()
+    }
+
+    fn run(&self) -> Result<Measurement, String> {
+        // Time to the response, near enough a round trip once the connection is reused
+        let mut probes = (0..LATENCY_PROBES)
+            .map(|_| self.download(0))
+            .collect::<Result<Vec<_>, _>>()?;
+        probes.sort_by(f64::total_cmp);
+        let latency = probes[probes.len() / 2] * 1000.0;
+
+        let download = self.download_bytes as f64 * 8.0 / 1e6 / self.download(self.download_bytes)?;
+
+        let body = vec![0; self.upload_bytes];
+        let started = Instant::now();
+        self.agent
+            .post(&format!("{CLOUDFLARE}/__up"))
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(&body)
+            .map_err(|err| err.to_string())?;
+        let upload = self.upload_bytes as f64 * 8.0 / 1e6 / started.elapsed().as_secs_f64();
+
+        Ok(Measurement {
+            download: download as f32,
+            upload: upload as f32,
+            latency: latency as f32,
+            at: Local::now(),
+        })
+    }
+}
+
+/// A speed test program that prints its results as JSON.
+pub struct TestCommand {
+    command: Vec<String>,
+}
+
+impl TestCommand {
+    pub fn new(command: Vec<String>) -> Self {
+        TestCommand { command }
+    }
+}
+
+impl SpeedtestBackend for TestCommand {
+    fn source(&self) -> String {
+        format!("speedtest {}", self.command.first().map_or("", String::as_str))
+    }
+
+    fn run(&self) -> Result<Measurement, String> {
+        let [program, args @ ..] = &self.command[..] else {
+            return Err("no command configured".to_string());
+        };
+        let output = Command::new(program)
+            .args(args)
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|err| format!("{program}: {err}"))?;
+        // Ookla's prints a JSON error and fails, so look at the output either way
+        match serde_json::from_slice::<Value>(&output.stdout) {
+            Ok(json) => Measurement::parse(&json),
+            Err(_) if !output.status.success() => Err(format!("{program} failed: {}", output.status)),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+type Shared = Arc<Mutex<VecDeque<Measurement>>>;
+
+// One testing thread per backend, however many profiles show the page
+static RUNNERS: Mutex<Vec<(String, Shared)>> = Mutex::new(Vec::new());
+
+fn runner(config: &SpeedtestConfig, backend: Box<dyn SpeedtestBackend + Send>) -> Shared {
+    let mut runners = RUNNERS.lock().unwrap_or_else(PoisonError::into_inner);
+    let source = backend.source();
+    if let Some((_, results)) = runners.iter().find(|(name, _)| *name == source) {
+        return results.clone();
+    }
+    let results = Arc::new(Mutex::new(VecDeque::with_capacity(config.history)));
+    runners.push((source.clone(), results.clone()));
+    let history = config.history.max(1);
+    let interval = Duration::from_secs(config.interval.max(1));
+    let shared = results.clone();
+    thread::spawn(move || {
+        loop {
+            match metrics::timed(&source, || backend.run()) {
+                Ok(measurement) => {
+                    let mut results = shared.lock().unwrap_or_else(PoisonError::into_inner);
+                    if results.len() == history {
+                        results.pop_front();
+                    }
+                    results.push_back(measurement);
+                }
+                Err(err) => eprintln!("{source}: {err}"),
+            }
+            thread::sleep(interval);
+        }
+    });
+    results
+}
+
+pub struct Speedtest {
+    config: SpeedtestConfig,
+    results: Shared,
+}
+
+impl Speedtest {
+    /// A page testing with the backend `config` names.
+    pub fn new(config: &SpeedtestConfig) -> Self {
+        let backend: Box<dyn SpeedtestBackend + Send> = match config.backend {
+            BackendKind::Cloudflare => Box::new(Cloudflare::new(config.download_bytes, config.upload_bytes)),
+            BackendKind::Command => Box::new(TestCommand::new(config.command.clone())),
+        };
+        Self::with_backend(config, backend)
+    }
+
+    pub fn with_backend(config: &SpeedtestConfig, backend: Box<dyn SpeedtestBackend + Send>) -> Self {
+        Speedtest {
+            config: config.clone(),
+            results: runner(config, backend),
+        }
+    }
+}
+
+impl Screen for Speedtest {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        fb.clear(Color::White);
+        let font = &PROFONT_9_POINT;
+        let fonts = [font];
+        let row_height = font.character_size.height + 2;
+        let figure_height = PROFONT_14_POINT.character_size.height + 2;
+        let [header, figures_row, rest] = Flex::column()
+            .padding(2)
+            .gap(2)
+            .split(
+                fb.bounding_box(),
+                [Item::fixed(ctx.theme.title_height() + 2), Item::fixed(row_height + figure_height), Item::grow(1)],
+            );
+        let results = self.results.lock().unwrap_or_else(PoisonError::into_inner);
+        if !self.config.title.is_empty() {
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+        }
+        let Some(latest) = results.back() else {
+            let Ok(_) = TextBox::new(figures_row, Color::Black)
+                .fonts(&fonts)
+                .draw("Waiting for the first test", fb);
+            return;
+        };
+        let tested = latest.at.format("%a %H:%M").to_string();
+        let Ok(_) = TextBox::new(Edges::new(2, 0, 0, 0).inset(header), Color::Black)
+            .alignment(Alignment::Right)
+            .fonts(&fonts)
+            .draw(&tested, fb);
+
+        // Three columns: label above, figure below
+        let figures = [
+            ("Down Mbps", format!("{:.0}", latest.download), Color::Black),
+            ("Up Mbps", format!("{:.0}", latest.upload), Color::Red),
+            ("Ping ms", format!("{:.0}", latest.latency), Color::Black),
+        ];
+        let big = [&PROFONT_14_POINT, &PROFONT_12_POINT, &PROFONT_9_POINT];
+        let columns = Flex::row().split(figures_row, [Item::grow(1), Item::grow(1), Item::grow(1)]);
+        for (column, (label, figure, color)) in columns.into_iter().zip(&figures) {
+            let [label_bounds, bounds] = Flex::column().split(column, [Item::fixed(row_height), Item::fixed(figure_height)]);
+            let Ok(_) = TextBox::new(label_bounds, Color::Black).fonts(&fonts).draw(label, fb);
+            let Ok(_) = TextBox::new(bounds, *color).fonts(&big).draw(figure, fb);
+        }
+
+        // Both directions on one scale, so the lines compare
+        if results.len() < 2 || rest.size.height < 8 {
+            return;
+        }
+        let downloads: Vec<f32> = results.iter().map(|result| result.download).collect();
+        let uploads: Vec<f32> = results.iter().map(|result| result.upload).collect();
+        let all: Vec<f32> = downloads.iter().chain(&uploads).copied().collect();
+        let range = chart::range(&all).map(|(_, max)| (0.0, max));
+        let Ok(_) = LineChart { range, ..LineChart::new(rest) }.draw(&downloads, fb);
+        let Ok(_) = LineChart {
+            range,
+            color: Color::Red,
+            thickness: 1,
+            ..LineChart::new(rest)
+        }
+        .draw(&uploads, fb);
+    }
+}
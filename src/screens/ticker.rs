@@ -0,0 +1,324 @@
+// Prices of a few stocks or coins, one per row: the symbol, a sparkline of
+// the day so far, the price and its change. Anything down on the day is
+// drawn in red.
+//
+// Prices come from a `PriceProvider`. Two free ones are built in, neither
+// needing a key: Yahoo Finance's chart API (stock tickers such as `AAPL`, and
+// coins as `BTC-USD`) and CoinGecko (coins by id, such as `bitcoin`).
+//
+//     [ticker]
+//     symbols = ["AAPL", "MSFT", "BTC-USD"]
+//     refresh = 600
+
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+use profont::PROFONT_9_POINT;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Align, Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{self, Alignment, TextBox};
+use crate::widgets::chart::Sparkline;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+// Yahoo turns away requests without one
+const USER_AGENT: &str = concat!("rust_raspi/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    Yahoo,
+    CoinGecko,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TickerConfig {
+    /// Tickers for Yahoo (`AAPL`, `BTC-USD`) or coin ids for CoinGecko (`bitcoin`)
+    pub symbols: Vec<String>,
+    pub provider: ProviderKind,
+    /// Currency CoinGecko prices are in; Yahoo quotes each symbol in its own
+    pub currency: String,
+    /// Heading; none if empty
+    pub title: String,
+    /// Seconds between fetches
+    pub refresh: u64,
+}
+
+impl Default for TickerConfig {
+    fn default() -> Self {
+        TickerConfig {
+            symbols: Vec::new(),
+            provider: ProviderKind::default(),
+            currency: "usd".to_string(),
+            title: "Markets".to_string(),
+            refresh: 300,
+        }
+    }
+}
+
+/// One symbol as last fetched.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Quote {
+    pub price: f64,
+    /// Change since the previous close (Yahoo) or over the last 24 hours (CoinGecko)
+    pub change: f64,
+    /// Prices through the day, oldest first, for the sparkline
+    pub history: Vec<f32>,
+}
+
+impl Quote {
+    /// The change as a percentage of the price it started from.
+    pub fn change_percent(&self) -> f64 {
+        let before = self.price - self.change;
+        if before == 0.0 { 0.0 } else { self.change / before * 100.0 }
+    }
+}
+
+/// A source of prices.
+pub trait PriceProvider {
+    /// Name for the metrics table, e.g. `ticker yahoo`.
+    fn source(&self) -> String;
+
+    fn fetch(&self, symbol: &str) -> Result<Quote, String>;
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent(USER_AGENT)
+        .build()
+}
+
+fn get_json(agent: &ureq::Agent, url: &str) -> Result<Value, String> {
+    agent
+        .get(url)
+        .call()
+        .map_err(|err| err.to_string())?
+        .into_json()
+        .map_err(|err| err.to_string())
+}
+
+/// Yahoo Finance's chart API: the day's prices at 15-minute intervals.
+pub struct Yahoo {
+    agent: ureq::Agent,
+}
+
+impl Yahoo {
+    pub fn new() -> Self {
+        Yahoo { agent: agent() }
+    }
+
+    /// Makes a `Quote` out of the body of `GET /v8/finance/chart/<symbol>`.
+    pub fn parse(chart: &Value) -> Result<Quote, String> {
+        let result = &chart["chart"]["result"][0];
+        if result.is_null() {
+            let error = chart["chart"]["error"]["description"].as_str().unwrap_or("no data");
+            return Err(error.to_string());
+        }
+        let meta = &result["meta"];
+        let price = meta["regularMarketPrice"].as_f64().ok_or("no price")?;
+        let previous = meta["chartPreviousClose"].as_f64().or_else(|| meta["previousClose"].as_f64());
+        let history = result["indicators"]["quote"][0]["close"]
+            .as_array()
+            .map(|closes| {
+                // Intervals without trades are null, and stay gaps
+                closes.iter().map(|close| close.as_f64().map_or(f32::NAN, |close| close as f32)).collect()
+            })
+            .unwrap_or_default();
+        Ok(Quote {
+            price,
+            change: previous.map_or(0.0, |previous| price - previous),
+            history,
+        })
+    }
+}
+
+impl Default for Yahoo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceProvider for Yahoo {
+    fn source(&self) -> String {
+        "ticker yahoo".to_string()
+    }
+
+    fn fetch(&self, symbol: &str) -> Result<Quote, String> {
+        let url = format!("https://query1.finance.yahoo.com/v8/finance/chart/{symbol}?range=1d&interval=15m");
+        Self::parse(&get_json(&self.agent, &url)?)
+    }
+}
+
+/// CoinGecko's market chart: the last 24 hours of prices in `currency`.
+pub struct CoinGecko {
+    agent: ureq::Agent,
+    currency: String,
+}
+
+impl CoinGecko {
+    pub fn new(currency: impl Into<String>) -> Self {
+        CoinGecko {
+            agent: agent(),
+            currency: currency.into(),
+        }
+    }
+
+    /// Makes a `Quote` out of the body of `GET /api/v3/coins/<id>/market_chart`.
+    pub fn parse(chart: &Value) -> Result<Quote, String> {
+        let prices: Vec<f64> = chart["prices"]
+            .as_array()
+            .ok_or_else(|| chart["error"].as_str().unwrap_or("no prices").to_string())?
+            .iter()
+            .filter_map(|point| point[1].as_f64())
+            .collect();
+        let (Some(first), Some(last)) = (prices.first(), prices.last()) else {
+            return Err("no prices".to_string());
+        };
+        Ok(Quote {
+            price: *last,
+            change: last - first,
+            history: prices.iter().map(|&price| price as f32).collect(),
+        })
+    }
+}
+
+impl PriceProvider for CoinGecko {
+    fn source(&self) -> String {
+        "ticker coingecko".to_string()
+    }
+
+    fn fetch(&self, symbol: &str) -> Result<Quote, String> {
+        let url = format!(
+            "https://api.coingecko.com/api/v3/coins/{symbol}/market_chart?vs_currency={}&days=1",
+            self.currency
+        );
+        Self::parse(&get_json(&self.agent, &url)?)
+    }
+}
+
+// Enough digits to see a move in, whatever the price
+fn format_price(price: f64) -> String {
+    match price.abs() {
+        p if p >= 1000.0 => format!("{price:.0}"),
+        p if p >= 1.0 => format!("{price:.2}"),
+        _ => format!("{price:.4}"),
+    }
+}
+
+pub struct Ticker {
+    config: TickerConfig,
+    provider: Box<dyn PriceProvider + Send>,
+    quotes: Vec<Option<Quote>>,
+    fetched: Option<Instant>,
+}
+
+impl Ticker {
+    /// A ticker fetching from the provider `config` names.
+    pub fn new(config: &TickerConfig) -> Self {
+        let provider: Box<dyn PriceProvider + Send> = match config.provider {
+            ProviderKind::Yahoo => Box::new(Yahoo::new()),
+            ProviderKind::CoinGecko => Box::new(CoinGecko::new(&config.currency)),
+        };
+        Self::with_provider(config, provider)
+    }
+
+    pub fn with_provider(config: &TickerConfig, provider: Box<dyn PriceProvider + Send>) -> Self {
+        Ticker {
+            config: config.clone(),
+            provider,
+            quotes: vec![None; config.symbols.len()],
+            fetched: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let source = self.provider.source();
+        for (symbol, quote) in self.config.symbols.iter().zip(&mut self.quotes) {
+            match metrics::timed(&source, || self.provider.fetch(symbol)) {
+                Ok(fetched) => *quote = Some(fetched),
+                // Keep showing the last good price
+                Err(err) => eprintln!("{source} {symbol}: {err}"),
+            }
+        }
+    }
+}
+
+impl Screen for Ticker {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let mut page = Edges::new(2, 2, 0, 2).inset(fb.bounding_box());
+        if !self.config.title.is_empty() {
+            let [header, rest] = Flex::column()
+                .gap(4)
+                .split(page, [Item::fixed(ctx.theme.title_height()), Item::grow(1)]);
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+            page = rest;
+        }
+        if self.config.symbols.is_empty() {
+            return;
+        }
+
+        let font = &PROFONT_9_POINT;
+        let fonts = [font];
+        // Rows share the height left, but never get smaller than a line of text
+        let line = font.character_size.height + 2;
+        let row_height = (page.size.height / self.config.symbols.len() as u32).max(line);
+        // Symbols get a column as wide as the longest
+        let symbol_width = self
+            .config
+            .symbols
+            .iter()
+            .map(|symbol| text::line_width(font, symbol))
+            .max()
+            .unwrap_or(0);
+        let rows = Flex::column().repeat(page, row_height);
+        for (row, (symbol, quote)) in rows.zip(self.config.symbols.iter().zip(&self.quotes)) {
+            let (figures, color) = match quote {
+                Some(quote) => (
+                    format!("{} {:+.1}%", format_price(quote.price), quote.change_percent()),
+                    if quote.change < 0.0 { Color::Red } else { Color::Black },
+                ),
+                None => ("-".to_string(), Color::Black),
+            };
+            // The sparkline gets whatever the symbol and figures leave
+            let [name, trend, figure_bounds] = Flex::row().gap(4).align(Align::Center).split(
+                row,
+                [
+                    Item::fixed(symbol_width).cross(line),
+                    Item::grow(1),
+                    Item::fixed(text::line_width(font, &figures)).cross(line),
+                ],
+            );
+            let Ok(_) = TextBox::new(name, Color::Black).fonts(&fonts).draw(symbol, fb);
+            let Ok(_) = TextBox::new(figure_bounds, color)
+                .alignment(Alignment::Right)
+                .fonts(&fonts)
+                .draw(&figures, fb);
+            if let Some(quote) = quote
+                && trend.size.width > 8
+            {
+                let sparkline = Sparkline {
+                    color,
+                    last: None,
+                    ..Sparkline::new(Edges::axes(1, 0).inset(trend))
+                };
+                let Ok(_) = sparkline.draw(&quote.history, fb);
+            }
+        }
+    }
+}
@@ -0,0 +1,301 @@
+// Next departures from a few stops, with minutes to go counted down from
+// the scheduled (or live) time. Those leaving within `imminent` minutes are
+// drawn in red: time to put your shoes on.
+//
+// Departures come from a `DepartureProvider`. The built-in one, `JsonRest`,
+// fits most agencies' REST APIs without code: it fetches `url` with `{stop}`
+// replaced by each stop's id, and picks the departures out of the JSON with
+// JSON pointers (RFC 6901). For the VBB/BVG API in Berlin, say:
+//
+//     [transit]
+//     url = "https://v6.bvg.transport.rest/stops/{stop}/departures?duration=30"
+//     list = "/departures"
+//     route = "/line/name"
+//     destination = "/direction"
+//     time = "/when"
+//     stops = [{ id = "900100003", name = "Alexanderplatz" }]
+//
+// Times may be Unix seconds or milliseconds, or RFC 3339 strings.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, TimeZone};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use profont::PROFONT_9_POINT;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{Alignment, TextBox};
+use crate::widgets::table::{Cell, Column, Table};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransitConfig {
+    /// Departures endpoint; `{stop}` is replaced by each stop's id
+    pub url: String,
+    /// Extra request headers, e.g. an API key
+    pub headers: BTreeMap<String, String>,
+    /// JSON pointer to the array of departures; empty if the body is the array
+    pub list: String,
+    /// JSON pointers into each departure
+    pub route: String,
+    pub destination: String,
+    pub time: String,
+    pub stops: Vec<StopConfig>,
+    /// Departures shown per stop
+    pub per_stop: usize,
+    /// Minutes to go at or below which a departure is drawn in red
+    pub imminent: i64,
+    /// Heading; none if empty
+    pub title: String,
+    /// Seconds between fetches
+    pub refresh: u64,
+}
+
+impl Default for TransitConfig {
+    fn default() -> Self {
+        TransitConfig {
+            url: String::new(),
+            headers: BTreeMap::new(),
+            list: "/departures".to_string(),
+            route: "/route".to_string(),
+            destination: "/destination".to_string(),
+            time: "/time".to_string(),
+            stops: Vec::new(),
+            per_stop: 4,
+            imminent: 5,
+            title: String::new(),
+            refresh: 60,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StopConfig {
+    /// What the provider knows the stop as
+    pub id: String,
+    /// Heading; defaults to the id
+    pub name: Option<String>,
+}
+
+/// One departure from a stop.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Departure {
+    pub route: String,
+    pub destination: String,
+    pub at: DateTime<Local>,
+}
+
+impl Departure {
+    /// Whole minutes from `now` until it leaves, rounded down; negative once gone.
+    pub fn minutes(&self, now: DateTime<Local>) -> i64 {
+        (self.at - now).num_seconds().div_euclid(60)
+    }
+}
+
+/// A source of departures.
+pub trait DepartureProvider {
+    /// Name for the metrics table, e.g. `transit example.org`.
+    fn source(&self) -> String;
+
+    /// Upcoming departures from `stop`, in any order.
+    fn departures(&self, stop: &str) -> Result<Vec<Departure>, String>;
+}
+
+/// Any JSON API, mapped with the pointers in a `TransitConfig`.
+pub struct JsonRest {
+    agent: ureq::Agent,
+    config: TransitConfig,
+}
+
+impl JsonRest {
+    pub fn new(config: &TransitConfig) -> Self {
+        JsonRest {
+            agent: ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build(),
+            config: config.clone(),
+        }
+    }
+
+    /// Picks the departures out of a response body. Entries without a time
+    /// that can be read are skipped; a missing route or destination is blank.
+    pub fn parse(&self, body: &Value) -> Result<Vec<Departure>, String> {
+        let list = if self.config.list.is_empty() { Some(body) } else { body.pointer(&self.config.list) };
+        let list = list
+            .and_then(Value::as_array)
+            .ok_or_else(|| format!("no array at {:?}", self.config.list))?;
+        let text = |entry: &Value, pointer: &str| match entry.pointer(pointer) {
+            Some(Value::String(text)) => text.clone(),
+            Some(Value::Null) | None => String::new(),
+            Some(value) => value.to_string(),
+        };
+        Ok(list
+            .iter()
+            .filter_map(|entry| {
+                Some(Departure {
+                    route: text(entry, &self.config.route),
+                    destination: text(entry, &self.config.destination),
+                    at: parse_time(entry.pointer(&self.config.time)?)?,
+                })
+            })
+            .collect())
+    }
+}
+
+impl DepartureProvider for JsonRest {
+    fn source(&self) -> String {
+        metrics::source_name("transit", &self.config.url)
+    }
+
+    fn departures(&self, stop: &str) -> Result<Vec<Departure>, String> {
+        let mut request = self.agent.get(&self.config.url.replace("{stop}", stop));
+        for (name, value) in &self.config.headers {
+            request = request.set(name, value);
+        }
+        let body = request
+            .call()
+            .map_err(|err| err.to_string())?
+            .into_json::<Value>()
+            .map_err(|err| err.to_string())?;
+        self.parse(&body)
+    }
+}
+
+// Unix seconds or milliseconds (as a number or a string of digits), or RFC 3339
+fn parse_time(value: &Value) -> Option<DateTime<Local>> {
+    let number = match value {
+        Value::Number(number) => number.as_i64(),
+        Value::String(text) => text.parse().ok(),
+        _ => None,
+    };
+    match number {
+        // Seconds won't reach 10^12 for another thirty thousand years
+        Some(ms) if ms > 1_000_000_000_000 => Local.timestamp_millis_opt(ms).single(),
+        Some(secs) => Local.timestamp_opt(secs, 0).single(),
+        None => DateTime::parse_from_rfc3339(value.as_str()?).ok().map(|at| at.with_timezone(&Local)),
+    }
+}
+
+// "now" for anything leaving within the minute
+fn format_minutes(minutes: i64) -> String {
+    if minutes < 1 { "now".to_string() } else { format!("{minutes} min") }
+}
+
+pub struct Transit {
+    config: TransitConfig,
+    provider: Box<dyn DepartureProvider + Send>,
+    departures: Vec<Vec<Departure>>,
+    fetched: Option<Instant>,
+}
+
+impl Transit {
+    pub fn new(config: &TransitConfig) -> Self {
+        Self::with_provider(config, Box::new(JsonRest::new(config)))
+    }
+
+    pub fn with_provider(config: &TransitConfig, provider: Box<dyn DepartureProvider + Send>) -> Self {
+        Transit {
+            config: config.clone(),
+            provider,
+            departures: vec![Vec::new(); config.stops.len()],
+            fetched: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let due = self
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= Duration::from_secs(self.config.refresh));
+        if !due {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        let source = self.provider.source();
+        for (stop, departures) in self.config.stops.iter().zip(&mut self.departures) {
+            match metrics::timed(&source, || self.provider.departures(&stop.id)) {
+                Ok(mut fetched) => {
+                    fetched.sort_by_key(|departure| departure.at);
+                    *departures = fetched;
+                }
+                // Keep counting down the last good ones
+                Err(err) => eprintln!("{source} {}: {err}", stop.id),
+            }
+        }
+    }
+}
+
+impl Screen for Transit {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        self.refresh();
+        fb.clear(Color::White);
+        let mut page = Edges::new(2, 2, 0, 2).inset(fb.bounding_box());
+        if !self.config.title.is_empty() {
+            let [header, rest] = Flex::column()
+                .gap(4)
+                .split(page, [Item::fixed(ctx.theme.title_height()), Item::grow(1)]);
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+            page = rest;
+        }
+
+        let font = &PROFONT_9_POINT;
+        let fonts = [font];
+        let row_height = font.character_size.height + 2;
+        for (stop, departures) in self.config.stops.iter().zip(&self.departures) {
+            if page.size.height < row_height {
+                break;
+            }
+            // Gone ones are dropped here rather than at fetch time, so they leave between fetches
+            let upcoming: Vec<&Departure> = departures
+                .iter()
+                .filter(|departure| departure.minutes(ctx.now) >= 0)
+                .take(self.config.per_stop)
+                .collect();
+            // The stop's name over its departures, cut short at the bottom of the panel
+            let lines = upcoming.len().max(1) as u32;
+            let [section, rest] = Flex::column()
+                .gap(2)
+                .split(page, [Item::flexible((1 + lines) * row_height), Item::grow(1)]);
+            page = rest;
+            let [heading, list] = Flex::column().split(section, [Item::fixed(row_height), Item::grow(1)]);
+            let name = stop.name.as_deref().unwrap_or(&stop.id);
+            let Ok(_) = TextBox::new(heading, ctx.theme.heading()).fonts(&fonts).draw(name, fb);
+            let rule = Rectangle::new(Point::new(heading.top_left.x, heading.top_left.y + row_height as i32 - 1), Size::new(heading.size.width, 1));
+            let Ok(_) = fb.fill_solid(&rule, ctx.theme.heading());
+
+            if upcoming.is_empty() {
+                let Ok(_) = TextBox::new(list, Color::Black).fonts(&fonts).draw("no departures", fb);
+                continue;
+            }
+            // Routes as wide as the longest shown, the destination whatever the minutes leave
+            let columns = [
+                Column::new(""),
+                Column::new("").grow(),
+                Column::new("").alignment(Alignment::Right),
+            ];
+            let rows: Vec<Vec<Cell>> = upcoming
+                .iter()
+                .map(|departure| {
+                    let minutes = departure.minutes(ctx.now);
+                    let color = if minutes <= self.config.imminent { Color::Red } else { Color::Black };
+                    vec![
+                        Cell::from(departure.route.as_str()),
+                        Cell::from(departure.destination.as_str()),
+                        Cell::colored(format_minutes(minutes), color),
+                    ]
+                })
+                .collect();
+            let Ok(shown) = Table::new(list, &columns, font).themed(&ctx.theme).draw(&rows, fb);
+            if shown < rows.len() {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,308 @@
+// Whether the machines on the network are up: one row per check with its
+// response time, the share of recent probes that got an answer, and a strip
+// of those probes (black up, red down, newest on the right).
+//
+// Each check probes its `target` every `interval` seconds on a thread of its
+// own, whether the page is showing or not. A target says how to probe:
+//
+//     https://nas.local:5001/    an HTTP(S) GET; any reply short of a 5xx is up
+//     ssh://router.lan           a TCP connection that answers with an SSH banner
+//     tcp://printer.lan:9100     a TCP connection, nothing more
+//     pi-hole.lan                a ping
+//
+//     [[uptime.checks]]
+//     name = "NAS"
+//     target = "https://nas.local:5001/"
+
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+use profont::PROFONT_9_POINT;
+use serde::Deserialize;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::layout::{Edges, Flex, Item};
+use crate::metrics;
+use crate::screens::network::parse_ping;
+use crate::screens::{RenderContext, Screen};
+use crate::text::{self, Alignment, TextBox};
+use crate::widgets::chart::BarChart;
+
+const SSH_PORT: u16 = 22;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckConfig {
+    /// Shown on the row; the target if empty
+    pub name: String,
+    pub target: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UptimeConfig {
+    pub checks: Vec<CheckConfig>,
+    /// Seconds between probes
+    pub interval: u64,
+    /// Seconds a probe waits for an answer
+    pub timeout: u64,
+    /// Probes kept per check, for the strip and the percentage
+    pub history: usize,
+    /// Heading; none if empty
+    pub title: String,
+}
+
+impl Default for UptimeConfig {
+    fn default() -> Self {
+        UptimeConfig {
+            checks: Vec::new(),
+            interval: 60,
+            timeout: 5,
+            history: 60,
+            title: "Uptime".to_string(),
+        }
+    }
+}
+
+/// How a target is probed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Probe {
+    Http(String),
+    Ssh(String),
+    Tcp(String),
+    Ping(String),
+}
+
+impl Probe {
+    /// Reads a target as the page's doc comment describes.
+    pub fn parse(target: &str) -> Result<Probe, String> {
+        if target.starts_with("http://") || target.starts_with("https://") {
+            return Ok(Probe::Http(target.to_string()));
+        }
+        if let Some(host) = target.strip_prefix("ssh://") {
+            let host = host.trim_end_matches('/');
+            return Ok(Probe::Ssh(if has_port(host) { host.to_string() } else { format!("{host}:{SSH_PORT}") }));
+        }
+        if let Some(address) = target.strip_prefix("tcp://") {
+            let address = address.trim_end_matches('/');
+            if !has_port(address) {
+                return Err(format!("{target:?} needs a port"));
+            }
+            return Ok(Probe::Tcp(address.to_string()));
+        }
+        if target.is_empty() || target.contains("://") {
+            return Err(format!("can't probe {target:?}"));
+        }
+        Ok(Probe::Ping(target.to_string()))
+    }
+
+    /// Probes once, returning the response time if the target answered.
+    pub fn run(&self, agent: &ureq::Agent, timeout: Duration) -> Result<Duration, String> {
+        let started = Instant::now();
+        match self {
+            Probe::Http(url) => match agent.get(url).call() {
+                // Turned away is still up
+                Ok(_) => {}
+                Err(ureq::Error::Status(status, _)) if status < 500 => {}
+                Err(err) => return Err(err.to_string()),
+            },
+            Probe::Ssh(address) => {
+                let mut stream = connect(address, timeout)?;
+                stream.set_read_timeout(Some(timeout)).map_err(|err| err.to_string())?;
+                let mut banner = [0; 4];
+                stream.read_exact(&mut banner).map_err(|err| err.to_string())?;
+                if &banner != b"SSH-" {
+                    return Err("no SSH banner".to_string());
+                }
+            }
+            Probe::Tcp(address) => {
+                connect(address, timeout)?;
+            }
+            Probe::Ping(host) => {
+                let output = Command::new("ping")
+                    .args(["-c", "1", "-W", &timeout.as_secs().max(1).to_string(), host])
+                    .stderr(Stdio::null())
+                    .output()
+                    .map_err(|err| format!("ping: {err}"))?;
+                // ping's own figure leaves out starting the process
+                return match parse_ping(&String::from_utf8_lossy(&output.stdout)) {
+                    Some(millis) if output.status.success() => Ok(Duration::from_secs_f32(millis / 1000.0)),
+                    _ => Err("no reply".to_string()),
+                };
+            }
+        }
+        Ok(started.elapsed())
+    }
+}
+
+// Whether `address` ends in a port, rather than in the last group of a bare IPv6 address
+fn has_port(address: &str) -> bool {
+    address
+        .rsplit_once(':')
+        .is_some_and(|(host, port)| port.parse::<u16>().is_ok() && (!host.contains(':') || host.ends_with(']')))
+}
+
+fn connect(address: &str, timeout: Duration) -> Result<TcpStream, String> {
+    let addresses = address.to_socket_addrs().map_err(|err| err.to_string())?;
+    let mut last = io::Error::new(io::ErrorKind::NotFound, "no addresses");
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last = err,
+        }
+    }
+    Err(last.to_string())
+}
+
+/// The recent probes of one check, oldest first: the response time of each
+/// that got an answer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    probes: VecDeque<Option<Duration>>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            probes: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn push(&mut self, probe: Option<Duration>) {
+        if self.probes.len() == self.capacity {
+            self.probes.pop_front();
+        }
+        self.probes.push_back(probe);
+    }
+
+    pub fn latest(&self) -> Option<Option<Duration>> {
+        self.probes.back().copied()
+    }
+
+    /// Share of the probes that got an answer, 0 to 100.
+    pub fn availability(&self) -> Option<f32> {
+        if self.probes.is_empty() {
+            return None;
+        }
+        let up = self.probes.iter().filter(|probe| probe.is_some()).count();
+        Some(up as f32 * 100.0 / self.probes.len() as f32)
+    }
+
+    /// One value per probe for a bar chart: 1 up, -1 down.
+    pub fn strip(&self) -> Vec<f32> {
+        self.probes.iter().map(|probe| if probe.is_some() { 1.0 } else { -1.0 }).collect()
+    }
+}
+
+type Shared = Arc<Mutex<History>>;
+
+// One probing thread per target, however many profiles show the page
+static PROBERS: Mutex<Vec<(String, Shared)>> = Mutex::new(Vec::new());
+
+fn prober(config: &UptimeConfig, target: &str) -> Shared {
+    let mut probers = PROBERS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((_, history)) = probers.iter().find(|(probed, _)| probed == target) {
+        return history.clone();
+    }
+    let history = Arc::new(Mutex::new(History::new(config.history)));
+    probers.push((target.to_string(), history.clone()));
+    let probe = match Probe::parse(target) {
+        Ok(probe) => probe,
+        Err(err) => {
+            // Nothing to probe, so the row stays empty
+            eprintln!("uptime: {err}");
+            return history;
+        }
+    };
+    let source = format!("uptime {target}");
+    let timeout = Duration::from_secs(config.timeout.max(1));
+    let interval = Duration::from_secs(config.interval.max(1));
+    let shared = history.clone();
+    thread::spawn(move || {
+        let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+        loop {
+            let probed = metrics::timed(&source, || probe.run(&agent, timeout));
+            if let Err(err) = &probed {
+                eprintln!("{source}: {err}");
+            }
+            shared.lock().unwrap_or_else(PoisonError::into_inner).push(probed.ok());
+            thread::sleep(interval);
+        }
+    });
+    history
+}
+
+pub struct Uptime {
+    config: UptimeConfig,
+    histories: Vec<Shared>,
+}
+
+impl Uptime {
+    pub fn new(config: &UptimeConfig) -> Self {
+        Uptime {
+            config: config.clone(),
+            histories: config.checks.iter().map(|check| prober(config, &check.target)).collect(),
+        }
+    }
+}
+
+impl Screen for Uptime {
+    fn render(&mut self, fb: &mut Framebuffer, ctx: &RenderContext) {
+        fb.clear(Color::White);
+        let mut page = Edges::new(2, 2, 0, 2).inset(fb.bounding_box());
+        if !self.config.title.is_empty() {
+            let [header, rest] = Flex::column()
+                .gap(4)
+                .split(page, [Item::fixed(ctx.theme.title_height()), Item::grow(1)]);
+            let Ok(()) = ctx.theme.title(&self.config.title, header, fb);
+            page = rest;
+        }
+        if self.config.checks.is_empty() {
+            return;
+        }
+
+        let font = &PROFONT_9_POINT;
+        let fonts = [font];
+        let line = font.character_size.height + 2;
+        let names: Vec<&str> = self
+            .config
+            .checks
+            .iter()
+            .map(|check| if check.name.is_empty() { check.target.as_str() } else { check.name.as_str() })
+            .collect();
+        // Names get a column as wide as the longest, up to a third of the panel
+        let name_width = names.iter().map(|name| text::line_width(font, name)).max().unwrap_or(0).min(fb.width() / 3);
+        // Room for "9999ms 100%" on the right
+        let figures_width = text::line_width(font, "9999ms 100%");
+        let rows = Flex::column().repeat(page, line);
+        for (row, (name, history)) in rows.zip(names.iter().zip(&self.histories)) {
+            let history = history.lock().unwrap_or_else(PoisonError::into_inner);
+            let (figures, color) = match (history.latest(), history.availability()) {
+                (Some(Some(time)), Some(share)) => (format!("{}ms {share:.0}%", time.as_millis()), Color::Black),
+                (Some(None), Some(share)) => (format!("down {share:.0}%"), Color::Red),
+                _ => ("-".to_string(), Color::Black),
+            };
+            // The strip gets whatever the name and figures leave
+            let [name_bounds, strip, figure_bounds] = Flex::row()
+                .gap(4)
+                .split(row, [Item::fixed(name_width), Item::grow(1), Item::fixed(figures_width)]);
+            let Ok(_) = TextBox::new(name_bounds, color).fonts(&fonts).draw(name, fb);
+            let Ok(_) = TextBox::new(figure_bounds, color)
+                .alignment(Alignment::Right)
+                .fonts(&fonts)
+                .draw(&figures, fb);
+            if strip.size.width > 8 {
+                let bounds = Edges::new(1, 0, 2, 0).inset(strip);
+                let Ok(_) = BarChart { gap: 0, ..BarChart::new(bounds) }.draw(&history.strip(), fb);
+            }
+        }
+    }
+}
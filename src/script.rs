@@ -0,0 +1,419 @@
+// Scripts: demo sequences and burn-in tests without writing Rust.
+//
+// A script is one command per line. The drawing commands are the plugin ops
+// (see `screens::plugin::Command`) with their fields written as `key=value`;
+// the rest control the panel and the flow:
+//
+//     # Walk a box across the panel, ten times over
+//     repeat 10 as round
+//         clear color=white
+//         text x=2 y=2 text="Round $round" color=black size=12
+//         repeat 8 as step
+//             let x = $step * 24
+//             rect x=$x y=40 width=20 height=20 color=red fill=true
+//             partial x=$x y=40 width=20 height=20
+//         end
+//         image /home/pi/test-card.png cover
+//         show
+//         wait 30
+//     end
+//
+// `show` does a full refresh and `partial` a fast one, of the whole panel or
+// only of the region given. `$name` (or `$$` for a dollar sign) is replaced
+// anywhere on a line, quoted text included; `let` sets a variable to one
+// word or to `a + b` (also `-`, `*`, `/`, `%`), and `repeat forever` never
+// stops.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use embedded_hal::blocking::delay::DelayMs;
+use serde_json::{Map, Value};
+
+use crate::epd::EpdController;
+use crate::framebuffer::{Color, Framebuffer};
+use crate::images::{self, Placement};
+use crate::screens::plugin::Command;
+
+// Drawing commands handed to `Command`
+const DRAW_OPS: &[&str] = &["clear", "line", "rect", "circle", "text", "icon"];
+
+/// What went wrong, and on which line.
+#[derive(Debug)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+// A word as written, and whether any of it was in quotes
+#[derive(Clone, Debug)]
+struct Word {
+    text: String,
+    quoted: bool,
+}
+
+#[derive(Debug)]
+struct Statement {
+    line: usize,
+    op: Op,
+}
+
+#[derive(Debug)]
+enum Op {
+    Let { name: String, expr: Vec<Word> },
+    Repeat { count: Word, var: Option<String>, body: Vec<Statement> },
+    Draw { op: String, args: Vec<Word> },
+    Image { path: Word, placement: Option<Word> },
+    Show,
+    Partial { args: Vec<Word> },
+    Wait { secs: Word },
+}
+
+pub struct Script {
+    statements: Vec<Statement>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, ScriptError> {
+        let text = fs::read_to_string(path).map_err(|err| ScriptError {
+            line: 0,
+            message: format!("{}: {err}", path.display()),
+        })?;
+        Script::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ScriptError> {
+        let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line));
+        let (statements, end) = parse_block(&mut lines)?;
+        if let Some(line) = end {
+            return Err(ScriptError {
+                line,
+                message: "`end` without `repeat`".to_string(),
+            });
+        }
+        Ok(Script { statements })
+    }
+
+    /// Runs the script on `epd`, drawing into `fb`, and returns how many
+    /// refreshes it did.
+    pub fn run<E, D>(&self, epd: &mut E, fb: &mut Framebuffer, delay: &mut D) -> Result<u32, ScriptError>
+    where
+        E: EpdController,
+        E::Error: fmt::Display,
+        D: DelayMs<u8>,
+    {
+        let mut run = Run {
+            epd,
+            delay,
+            shown: None,
+            vars: BTreeMap::new(),
+            refreshes: 0,
+        };
+        run.block(&self.statements, fb)?;
+        Ok(run.refreshes)
+    }
+}
+
+// Statements up to the end of the text or an `end`, and the line of the `end`
+fn parse_block<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+) -> Result<(Vec<Statement>, Option<usize>), ScriptError> {
+    let mut statements = Vec::new();
+    while let Some((line, text)) = lines.next() {
+        let fail = |message: String| ScriptError { line, message };
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let mut words = split(text).map_err(fail)?;
+        let command = words.remove(0).text;
+        let op = match command.as_str() {
+            "end" if words.is_empty() => return Ok((statements, Some(line))),
+            "let" => match words.as_slice() {
+                [name, eq, expr @ ..] if eq.text == "=" && (expr.len() == 1 || expr.len() == 3) => Op::Let {
+                    name: name.text.clone(),
+                    expr: expr.to_vec(),
+                },
+                _ => return Err(fail("expected `let NAME = VALUE` or `let NAME = A OP B`".to_string())),
+            },
+            "repeat" => {
+                let (count, var) = match words.as_slice() {
+                    [count] => (count.clone(), None),
+                    [count, as_, var] if as_.text == "as" => (count.clone(), Some(var.text.clone())),
+                    _ => return Err(fail("expected `repeat COUNT [as NAME]`".to_string())),
+                };
+                let (body, end) = parse_block(lines)?;
+                if end.is_none() {
+                    return Err(fail("`repeat` without `end`".to_string()));
+                }
+                Op::Repeat { count, var, body }
+            }
+            "image" => match words.as_slice() {
+                [path] => Op::Image {
+                    path: path.clone(),
+                    placement: None,
+                },
+                [path, placement] => Op::Image {
+                    path: path.clone(),
+                    placement: Some(placement.clone()),
+                },
+                _ => return Err(fail("expected `image PATH [PLACEMENT]`".to_string())),
+            },
+            "show" if words.is_empty() => Op::Show,
+            "partial" => Op::Partial { args: words },
+            "wait" if words.len() == 1 => Op::Wait { secs: words.remove(0) },
+            op if DRAW_OPS.contains(&op) => Op::Draw {
+                op: op.to_string(),
+                args: words,
+            },
+            "end" | "show" | "wait" => return Err(fail(format!("wrong arguments to `{command}`"))),
+            _ => return Err(fail(format!("unknown command {command:?}"))),
+        };
+        statements.push(Statement { line, op });
+    }
+    Ok((statements, None))
+}
+
+// Splits a line into words at spaces outside double quotes
+fn split(text: &str) -> Result<Vec<Word>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '"' => {
+                let word = word.get_or_insert_with(|| Word {
+                    text: String::new(),
+                    quoted: false,
+                });
+                word.quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.text.extend(chars.next()),
+                        Some(c) => word.text.push(c),
+                        None => return Err("unterminated quote".to_string()),
+                    }
+                }
+            }
+            c => word
+                .get_or_insert_with(|| Word {
+                    text: String::new(),
+                    quoted: false,
+                })
+                .text
+                .push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+struct Run<'a, E, D> {
+    epd: &'a mut E,
+    delay: &'a mut D,
+    // What the panel shows, once the script has refreshed it
+    shown: Option<Framebuffer>,
+    vars: BTreeMap<String, String>,
+    refreshes: u32,
+}
+
+impl<E, D> Run<'_, E, D>
+where
+    E: EpdController,
+    E::Error: fmt::Display,
+    D: DelayMs<u8>,
+{
+    fn block(&mut self, statements: &[Statement], fb: &mut Framebuffer) -> Result<(), ScriptError> {
+        for statement in statements {
+            let fail = |message| ScriptError {
+                line: statement.line,
+                message,
+            };
+            match &statement.op {
+                Op::Repeat { count, var, body } => {
+                    let count = self.expand(count).map_err(fail)?;
+                    let count = match count.as_str() {
+                        "forever" => u64::MAX,
+                        count => count.parse().map_err(|_| fail(format!("bad repeat count {count:?}")))?,
+                    };
+                    for i in 0..count {
+                        if let Some(var) = var {
+                            self.vars.insert(var.clone(), i.to_string());
+                        }
+                        self.block(body, fb)?;
+                    }
+                }
+                _ => self.statement(statement, fb).map_err(fail)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement, fb: &mut Framebuffer) -> Result<(), String> {
+        match &statement.op {
+            Op::Let { name, expr } => {
+                let value = match expr.as_slice() {
+                    [value] => self.expand(value)?,
+                    [a, op, b] => {
+                        let (a, b) = (self.number(a)?, self.number(b)?);
+                        let value = match op.text.as_str() {
+                            "+" => a + b,
+                            "-" => a - b,
+                            "*" => a * b,
+                            "/" => a / b,
+                            "%" => a % b,
+                            op => return Err(format!("unknown operator {op:?}")),
+                        };
+                        // Whole numbers without a trailing `.0`, so they can be coordinates
+                        value.to_string()
+                    }
+                    _ => unreachable!(),
+                };
+                self.vars.insert(name.clone(), value);
+            }
+            // Run by `block`, so errors inside the loop point at their own line
+            Op::Repeat { .. } => unreachable!(),
+            Op::Draw { op, args } => {
+                let mut fields = Map::new();
+                fields.insert("op".to_string(), Value::from(op.as_str()));
+                for arg in args {
+                    let text = self.expand(arg)?;
+                    let Some((key, value)) = text.split_once('=') else {
+                        return Err(format!("expected key=value, not {text:?}"));
+                    };
+                    fields.insert(key.to_string(), value_of(value, arg.quoted));
+                }
+                let command: Command = serde_json::from_value(Value::Object(fields)).map_err(|err| err.to_string())?;
+                command.draw(fb);
+            }
+            Op::Image { path, placement } => {
+                let path = self.expand(path)?;
+                let placement = match placement {
+                    Some(placement) => self.expand(placement)?.parse()?,
+                    None => Placement::default(),
+                };
+                let image = images::load(Path::new(&path)).map_err(|err| format!("{path}: {err}"))?;
+                images::draw_placed(fb, &image, &placement);
+            }
+            Op::Show => {
+                self.epd.show(fb, self.delay).map_err(|err| err.to_string())?;
+                self.shown = Some(fb.clone());
+                self.refreshes += 1;
+            }
+            Op::Partial { args } => {
+                let frame = match args.as_slice() {
+                    [] => fb.clone(),
+                    args => {
+                        let region = self.region(args)?;
+                        // Only the region changes; the rest stays as the panel shows it
+                        let mut frame = self.shown.take().unwrap_or_else(|| {
+                            let mut blank = fb.clone();
+                            blank.clear(Color::White);
+                            blank
+                        });
+                        for y in region[1]..(region[1] + region[3]).min(fb.height()) {
+                            for x in region[0]..(region[0] + region[2]).min(fb.width()) {
+                                frame.set_pixel(x, y, fb.get_pixel(x, y).unwrap_or(Color::White));
+                            }
+                        }
+                        frame
+                    }
+                };
+                self.epd
+                    .write_planes(frame.bw_plane(), frame.red_plane())
+                    .and_then(|()| self.epd.refresh_fast(self.delay))
+                    .map_err(|err| err.to_string())?;
+                self.shown = Some(frame);
+                self.refreshes += 1;
+            }
+            Op::Wait { secs } => {
+                let secs = self.number(secs)?;
+                thread::sleep(Duration::try_from_secs_f64(secs).map_err(|_| format!("bad wait {secs}"))?);
+            }
+        }
+        Ok(())
+    }
+
+    // `x= y= width= height=` in any order
+    fn region(&self, args: &[Word]) -> Result<[u32; 4], String> {
+        let mut region = [None; 4];
+        for arg in args {
+            let text = self.expand(arg)?;
+            let (key, value) = text.split_once('=').ok_or_else(|| format!("expected key=value, not {text:?}"))?;
+            let index = ["x", "y", "width", "height"]
+                .iter()
+                .position(|&name| name == key)
+                .ok_or_else(|| format!("unknown region field {key:?}"))?;
+            region[index] = Some(value.parse().map_err(|_| format!("bad {key} {value:?}"))?);
+        }
+        match region {
+            [Some(x), Some(y), Some(width), Some(height)] => Ok([x, y, width, height]),
+            _ => Err("a region needs x, y, width and height".to_string()),
+        }
+    }
+
+    fn number(&self, word: &Word) -> Result<f64, String> {
+        let text = self.expand(word)?;
+        text.parse().map_err(|_| format!("{text:?} is not a number"))
+    }
+
+    // The word with its variables filled in
+    fn expand(&self, word: &Word) -> Result<String, String> {
+        let mut out = String::with_capacity(word.text.len());
+        let mut rest = word.text.as_str();
+        while let Some(start) = rest.find('$') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            if let Some(after) = after.strip_prefix('$') {
+                out.push('$');
+                rest = after;
+                continue;
+            }
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            let name = &after[..end];
+            match self.vars.get(name) {
+                Some(value) => out.push_str(value),
+                None => return Err(format!("unknown variable ${name}")),
+            }
+            rest = &after[end..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+// A field value for `Command`: quoted text is always a string, otherwise
+// numbers, booleans and `x,y` pairs are what they look like
+fn value_of(text: &str, quoted: bool) -> Value {
+    if quoted {
+        return Value::from(text);
+    }
+    if let Ok(value) = text.parse::<bool>() {
+        return Value::from(value);
+    }
+    if let Ok(value) = text.parse::<i64>() {
+        return Value::from(value);
+    }
+    if text.contains(',')
+        && let Ok(values) = text.split(',').map(str::parse::<i64>).collect::<Result<Vec<_>, _>>()
+    {
+        return Value::from(values);
+    }
+    Value::from(text)
+}
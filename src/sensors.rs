@@ -0,0 +1,5 @@
+// Sensors on the Pi's buses, read by pages and background monitors.
+
+#[cfg(feature = "i2c")]
+pub mod bme280;
+pub mod mcp3008;
@@ -0,0 +1,271 @@
+// Room conditions from a Bosch BME280 on I2C: temperature, humidity and
+// pressure. A BMP280, the same chip without the humidity sensor, works too.
+//
+// The sensor is run in forced mode, one conversion per `read` and asleep in
+// between, with 1x oversampling and no filter: that is Bosch's suggested
+// setting for weather monitoring, and keeps the chip from warming itself.
+// On the Pi the bus is `/dev/i2c-1` (enable it with `dtparam=i2c_arm=on`);
+// breakout boards answer at 0x76 or, with SDO pulled high, 0x77.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::Path;
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use linux_embedded_hal::I2cdev;
+
+pub const DEFAULT_BUS: &str = "/dev/i2c-1";
+pub const DEFAULT_ADDRESS: u8 = 0x76;
+
+const REG_CALIB_00: u8 = 0x88;
+const REG_CHIP_ID: u8 = 0xD0;
+const REG_RESET: u8 = 0xE0;
+const REG_CALIB_26: u8 = 0xE1;
+const REG_CTRL_HUM: u8 = 0xF2;
+const REG_STATUS: u8 = 0xF3;
+const REG_CTRL_MEAS: u8 = 0xF4;
+const REG_DATA: u8 = 0xF7;
+
+const CHIP_BME280: u8 = 0x60;
+const CHIP_BMP280: u8 = 0x58;
+const RESET: u8 = 0xB6;
+// osrs_t and osrs_p at 1x, forced mode
+const MEASURE_FORCED: u8 = (1 << 5) | (1 << 2) | 0b01;
+const STATUS_MEASURING: u8 = 1 << 3;
+const STATUS_IM_UPDATE: u8 = 1 << 0;
+// A 1x conversion of all three takes under 10ms; give up well after
+const MAX_POLLS: u32 = 50;
+
+#[derive(Debug)]
+pub enum SensorError<E> {
+    Bus(E),
+    /// Something other than a BME280 or BMP280 answered
+    UnknownChip(u8),
+    /// The conversion didn't finish
+    Timeout,
+}
+
+impl<E: fmt::Debug> fmt::Display for SensorError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SensorError::Bus(error) => write!(f, "I2C failed: {error:?}"),
+            SensorError::UnknownChip(id) => write!(f, "chip id {id:#04x} is not a BME280 or BMP280"),
+            SensorError::Timeout => write!(f, "measurement didn't finish"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for SensorError<E> {}
+
+/// One reading.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Reading {
+    /// Degrees C
+    pub temperature: f32,
+    /// Relative, in percent; `None` on a BMP280
+    pub humidity: Option<f32>,
+    /// hPa
+    pub pressure: f32,
+}
+
+// Trimming parameters burnt in at the factory, named as in the datasheet
+#[derive(Clone, Copy, Debug, Default)]
+struct Calibration {
+    t1: f64,
+    t2: f64,
+    t3: f64,
+    p: [f64; 9],
+    h1: f64,
+    h2: f64,
+    h3: f64,
+    h4: f64,
+    h5: f64,
+    h6: f64,
+}
+
+impl Calibration {
+    fn parse(low: &[u8; 26], high: &[u8; 7]) -> Self {
+        let u16_at = |i: usize| f64::from(u16::from_le_bytes([low[i], low[i + 1]]));
+        let i16_at = |i: usize| f64::from(i16::from_le_bytes([low[i], low[i + 1]]));
+        let mut p = [u16_at(6); 9];
+        for (n, p) in p.iter_mut().enumerate().skip(1) {
+            *p = i16_at(6 + 2 * n);
+        }
+        Calibration {
+            t1: u16_at(0),
+            t2: i16_at(2),
+            t3: i16_at(4),
+            p,
+            h1: f64::from(low[25]),
+            h2: f64::from(i16::from_le_bytes([high[0], high[1]])),
+            h3: f64::from(high[2]),
+            // H4 and H5 are 12 bits each, sharing the nibbles of 0xE5
+            h4: f64::from((i16::from(high[3] as i8) << 4) | i16::from(high[4] & 0x0F)),
+            h5: f64::from((i16::from(high[5] as i8) << 4) | i16::from(high[4] >> 4)),
+            h6: f64::from(high[6] as i8),
+        }
+    }
+
+    // The floating-point compensation formulas from section 8.1 of the datasheet
+    fn compensate(&self, adc_t: f64, adc_p: f64, adc_h: Option<f64>) -> Reading {
+        let var1 = (adc_t / 16384.0 - self.t1 / 1024.0) * self.t2;
+        let var2 = (adc_t / 131072.0 - self.t1 / 8192.0).powi(2) * self.t3;
+        let t_fine = var1 + var2;
+
+        let p = &self.p;
+        let mut var1 = t_fine / 2.0 - 64000.0;
+        let mut var2 = var1 * var1 * p[5] / 32768.0;
+        var2 += var1 * p[4] * 2.0;
+        var2 = var2 / 4.0 + p[3] * 65536.0;
+        var1 = (p[2] * var1 * var1 / 524288.0 + p[1] * var1) / 524288.0;
+        var1 = (1.0 + var1 / 32768.0) * p[0];
+        let pressure = if var1 == 0.0 {
+            0.0
+        } else {
+            let pressure = (1048576.0 - adc_p - var2 / 4096.0) * 6250.0 / var1;
+            let var1 = p[8] * pressure * pressure / 2147483648.0;
+            let var2 = pressure * p[7] / 32768.0;
+            pressure + (var1 + var2 + p[6]) / 16.0
+        };
+
+        let humidity = adc_h.map(|adc_h| {
+            let h = t_fine - 76800.0;
+            let h = (adc_h - (self.h4 * 64.0 + self.h5 / 16384.0 * h))
+                * (self.h2 / 65536.0 * (1.0 + self.h6 / 67108864.0 * h * (1.0 + self.h3 / 67108864.0 * h)));
+            (h * (1.0 - self.h1 * h / 524288.0)).clamp(0.0, 100.0) as f32
+        });
+
+        Reading {
+            temperature: (t_fine / 5120.0) as f32,
+            humidity,
+            pressure: (pressure / 100.0) as f32,
+        }
+    }
+}
+
+/// A BME280 (or BMP280) at `address` on `i2c`.
+pub struct Bme280<I2C> {
+    i2c: I2C,
+    address: u8,
+    calibration: Calibration,
+    humidity: bool,
+}
+
+impl<I2C, E> Bme280<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Resets the chip and reads its calibration.
+    pub fn new<D: DelayMs<u8>>(i2c: I2C, address: u8, delay: &mut D) -> Result<Self, SensorError<E>> {
+        let mut sensor = Bme280 {
+            i2c,
+            address,
+            calibration: Calibration::default(),
+            humidity: false,
+        };
+        let mut id = [0];
+        sensor.read_registers(REG_CHIP_ID, &mut id)?;
+        sensor.humidity = match id[0] {
+            CHIP_BME280 => true,
+            CHIP_BMP280 => false,
+            other => return Err(SensorError::UnknownChip(other)),
+        };
+        sensor.write_register(REG_RESET, RESET)?;
+        delay.delay_ms(2);
+        // The calibration is copied out of NVM after a reset
+        sensor.wait_while(STATUS_IM_UPDATE, delay)?;
+        let mut low = [0; 26];
+        sensor.read_registers(REG_CALIB_00, &mut low)?;
+        let mut high = [0; 7];
+        if sensor.humidity {
+            sensor.read_registers(REG_CALIB_26, &mut high)?;
+        }
+        sensor.calibration = Calibration::parse(&low, &high);
+        Ok(sensor)
+    }
+
+    /// Takes a reading, waiting for the conversion.
+    pub fn read<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<Reading, SensorError<E>> {
+        if self.humidity {
+            // Only takes effect with the write to ctrl_meas that follows
+            self.write_register(REG_CTRL_HUM, 0b001)?;
+        }
+        self.write_register(REG_CTRL_MEAS, MEASURE_FORCED)?;
+        delay.delay_ms(8);
+        self.wait_while(STATUS_MEASURING, delay)?;
+        let mut data = [0; 8];
+        self.read_registers(REG_DATA, &mut data)?;
+        let adc_20 = |i: usize| f64::from((u32::from(data[i]) << 12) | (u32::from(data[i + 1]) << 4) | (u32::from(data[i + 2]) >> 4));
+        let adc_h = f64::from(u16::from_be_bytes([data[6], data[7]]));
+        Ok(self
+            .calibration
+            .compensate(adc_20(3), adc_20(0), self.humidity.then_some(adc_h)))
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn wait_while<D: DelayMs<u8>>(&mut self, bit: u8, delay: &mut D) -> Result<(), SensorError<E>> {
+        for _ in 0..MAX_POLLS {
+            let mut status = [0];
+            self.read_registers(REG_STATUS, &mut status)?;
+            if status[0] & bit == 0 {
+                return Ok(());
+            }
+            delay.delay_ms(1);
+        }
+        Err(SensorError::Timeout)
+    }
+
+    fn read_registers(&mut self, register: u8, buffer: &mut [u8]) -> Result<(), SensorError<E>> {
+        self.i2c.write_read(self.address, &[register], buffer).map_err(SensorError::Bus)
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), SensorError<E>> {
+        self.i2c.write(self.address, &[register, value]).map_err(SensorError::Bus)
+    }
+}
+
+/// Opens a sensor on a Linux I2C bus such as [`DEFAULT_BUS`].
+pub fn open(bus: &Path, address: u8) -> Result<Bme280<I2cdev>, String> {
+    let i2c = I2cdev::new(bus).map_err(|err| format!("{}: {err}", bus.display()))?;
+    Bme280::new(i2c, address, &mut linux_embedded_hal::Delay).map_err(|err| err.to_string())
+}
+
+/// The last `capacity` readings, oldest first, for drawing trends.
+#[derive(Clone, Debug)]
+pub struct History {
+    readings: VecDeque<Reading>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            readings: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds a reading, dropping the oldest once full.
+    pub fn push(&mut self, reading: Reading) {
+        if self.readings.len() == self.capacity {
+            self.readings.pop_front();
+        }
+        if self.capacity > 0 {
+            self.readings.push_back(reading);
+        }
+    }
+
+    pub fn latest(&self) -> Option<&Reading> {
+        self.readings.back()
+    }
+
+    /// One field of every reading, oldest first; missing values are NaN,
+    /// which charts leave as gaps.
+    pub fn series(&self, field: impl Fn(&Reading) -> Option<f32>) -> Vec<f32> {
+        self.readings.iter().map(|reading| field(reading).unwrap_or(f32::NAN)).collect()
+    }
+}
@@ -0,0 +1,220 @@
+// Battery voltage through an MCP3008, a 10-bit, 8-channel SPI ADC.
+//
+// Neither of SPI0's chip selects is free for it. The daemon drives the panel
+// through `/dev/spidev0.1`, whose CE1 (BCM 7) the kernel asserts round every
+// panel transfer, and selects the panel itself on BCM 8, which is CE0: a
+// device on `/dev/spidev0.0` would be selected along with the panel. So the
+// ADC defaults to the second SPI controller, `/dev/spidev1.0` (enabled with
+// `dtoverlay=spi1-1cs`; CS on BCM 18, clock, MOSI and MISO on BCM 21, 20 and
+// 19), and the daemon refuses an SPI0 device whose CE pin the panel uses.
+// On SPI0 anyway (a panel wired with its CS elsewhere), `SPI_BUS` keeps the
+// ADC's reads out of the panel's transfers. The battery reaches a channel
+// through a resistor divider, since even a single Li-ion cell goes above the
+// ADC's 3.3V reference: `divider` is how many volts of battery make one at
+// the pin (2.0 for two equal resistors). To calibrate, measure the battery
+// with a multimeter and nudge `divider` until the logged voltage agrees.
+//
+// The voltage is turned into a percentage along `curve`, which defaults to
+// the resting discharge curve of a one-cell LiPo.
+//
+//     [battery_adc]
+//     channel = 0
+//     divider = 2.0
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use embedded_hal::blocking::spi::Transfer;
+use linux_embedded_hal::Spidev;
+use linux_embedded_hal::spidev::{SpiModeFlags, SpidevOptions};
+use serde::Deserialize;
+
+use crate::linux::SPI_BUS;
+use crate::metrics;
+use crate::power::{self, Battery};
+
+/// Full-scale reading of the 10-bit converter.
+pub const MAX_READING: u16 = 1023;
+// Longest an ADC read waits for the panel to let go of the bus
+const BUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatteryAdcConfig {
+    /// spidev device the MCP3008 is selected by
+    pub spi: PathBuf,
+    /// SPI clock; the MCP3008 is rated to 1.35 MHz at 2.7V
+    pub speed: u32,
+    /// Input the divider is wired to, 0 to 7
+    pub channel: u8,
+    /// Volts at full scale: the VREF pin, normally tied to 3.3V
+    pub reference: f32,
+    /// Battery volts per volt at the input
+    pub divider: f32,
+    /// `[volts, percent]` points, in rising order; in between is interpolated
+    pub curve: Vec<[f32; 2]>,
+    /// Conversions averaged per reading, to smooth out noise
+    pub samples: u32,
+    /// Seconds between readings
+    pub interval: u64,
+}
+
+impl Default for BatteryAdcConfig {
+    fn default() -> Self {
+        BatteryAdcConfig {
+            spi: PathBuf::from("/dev/spidev1.0"),
+            speed: 1_000_000,
+            channel: 0,
+            reference: 3.3,
+            divider: 2.0,
+            curve: vec![
+                [3.30, 0.0],
+                [3.50, 5.0],
+                [3.60, 10.0],
+                [3.70, 30.0],
+                [3.75, 45.0],
+                [3.80, 55.0],
+                [3.85, 65.0],
+                [3.90, 72.0],
+                [4.00, 85.0],
+                [4.10, 95.0],
+                [4.20, 100.0],
+            ],
+            samples: 8,
+            interval: 60,
+        }
+    }
+}
+
+impl BatteryAdcConfig {
+    /// The battery voltage for a reading off the ADC.
+    pub fn volts(&self, reading: f32) -> f32 {
+        reading / f32::from(MAX_READING) * self.reference * self.divider
+    }
+
+    /// Charge at `volts`, read off `curve`; flat beyond its ends.
+    pub fn percent(&self, volts: f32) -> f32 {
+        let (Some(first), Some(last)) = (self.curve.first(), self.curve.last()) else {
+            return 0.0;
+        };
+        if volts <= first[0] {
+            return first[1];
+        }
+        for pair in self.curve.windows(2) {
+            let ([v0, p0], [v1, p1]) = (pair[0], pair[1]);
+            if volts <= v1 {
+                if v1 <= v0 {
+                    return p1;
+                }
+                return p0 + (p1 - p0) * (volts - v0) / (v1 - v0);
+            }
+        }
+        last[1]
+    }
+}
+
+/// An MCP3008 on `spi`, with its chip select handled by the bus.
+pub struct Mcp3008<SPI> {
+    spi: SPI,
+}
+
+impl<SPI: Transfer<u8>> Mcp3008<SPI> {
+    pub fn new(spi: SPI) -> Self {
+        Mcp3008 { spi }
+    }
+
+    /// One single-ended conversion of `channel`, 0 to `MAX_READING`.
+    pub fn read(&mut self, channel: u8) -> Result<u16, SPI::Error> {
+        // Start bit, then single-ended and the channel; the result comes back
+        // in the last ten bits of the three bytes
+        let mut frame = [0x01, (0x08 | (channel & 0x07)) << 4, 0x00];
+        let reply = self.spi.transfer(&mut frame)?;
+        Ok((u16::from(reply[1] & 0x03) << 8) | u16::from(reply[2]))
+    }
+
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+}
+
+impl BatteryAdcConfig {
+    /// Whether the ADC is kept apart from a panel on `panel_spi` selected by
+    /// the GPIO `panel_cs`.
+    pub fn check(&self, panel_spi: &Path, panel_cs: u64) -> Result<(), String> {
+        if self.spi == panel_spi {
+            return Err(format!("[battery_adc] spi can't be the panel's {}", panel_spi.display()));
+        }
+        if spi0_ce_pin(&self.spi) == Some(panel_cs) {
+            return Err(format!(
+                "[battery_adc] {} is selected by BCM {panel_cs}, the panel's chip select",
+                self.spi.display()
+            ));
+        }
+        Ok(())
+    }
+}
+
+// The pin spidev asserts for a device on SPI0, which the panel shares
+fn spi0_ce_pin(spi: &Path) -> Option<u64> {
+    match spi.to_str()? {
+        "/dev/spidev0.0" => Some(8),
+        "/dev/spidev0.1" => Some(7),
+        _ => None,
+    }
+}
+
+/// Opens the ADC in `config`.
+pub fn open(config: &BatteryAdcConfig) -> io::Result<Mcp3008<Spidev>> {
+    let mut spi = Spidev::open(&config.spi)?;
+    let options = SpidevOptions::new()
+        .bits_per_word(8)
+        .max_speed_hz(config.speed)
+        .mode(SpiModeFlags::SPI_MODE_0)
+        .build();
+    spi.configure(&options)?;
+    Ok(Mcp3008::new(spi))
+}
+
+/// Reads the battery every `interval` seconds, on a thread of its own, and
+/// publishes it to `power`.
+pub fn monitor(config: &BatteryAdcConfig) -> io::Result<()> {
+    if config.channel > 7 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "[battery_adc] channel must be 0 to 7"));
+    }
+    let mut adc = open(config)?;
+    let config = config.clone();
+    let source = format!("mcp3008 {}", config.spi.display());
+    let shared = spi0_ce_pin(&config.spi).is_some();
+    thread::spawn(move || {
+        loop {
+            let read = metrics::timed(&source, || {
+                let mut total = 0;
+                for _ in 0..config.samples.max(1) {
+                    let _bus = if shared {
+                        Some(SPI_BUS.hold(BUS_TIMEOUT).ok_or_else(|| "SPI bus busy".to_string())?)
+                    } else {
+                        None
+                    };
+                    total += u32::from(adc.read(config.channel).map_err(|err| err.to_string())?);
+                }
+                Ok::<_, String>(total as f32 / config.samples.max(1) as f32)
+            });
+            match read {
+                Ok(reading) => {
+                    let volts = config.volts(reading);
+                    power::publish(Battery {
+                        percent: config.percent(volts),
+                        voltage: Some(volts),
+                        charging: None,
+                        faults: Vec::new(),
+                    });
+                }
+                Err(err) => eprintln!("{source}: {err}"),
+            }
+            thread::sleep(Duration::from_secs(config.interval.max(1)));
+        }
+    });
+    Ok(())
+}
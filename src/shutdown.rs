@@ -0,0 +1,86 @@
+// Stopping cleanly on SIGTERM and SIGINT.
+//
+// Killed outright, the process can leave the panel half way through a
+// waveform with its charge pumps still driving the glass. With the handlers
+// installed the first signal only sets a flag: long-running commands notice
+// it between refreshes, let a refresh already under way finish, and sleep the
+// controller before exiting. A second signal exits straight away, for when
+// the panel itself is what's stuck.
+//
+// The handler does nothing but an atomic swap and a write(2) to a socket; a
+// thread of our own reads that and does the rest, so none of it runs in
+// signal context. SA_RESTART keeps SPI transfers and BUSY polls that the
+// signal lands in from failing with EINTR.
+
+use std::io::{self, Read};
+use std::os::fd::IntoRawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+// Write end of the socket the handler wakes the watcher thread through
+static WAKE_FD: AtomicI32 = AtomicI32::new(-1);
+// Lets `wait` be cut short
+static STOPPING: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+
+extern "C" fn handle(signal: libc::c_int) {
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        // Second time of asking
+        unsafe { libc::_exit(128 + signal) };
+    }
+    let fd = WAKE_FD.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let byte = signal as u8;
+        unsafe { libc::write(fd, (&byte as *const u8).cast(), 1) };
+    }
+}
+
+/// Installs the handlers. `on_signal` is called once, on a thread of its
+/// own, after the first signal, to wake whatever should notice `requested`.
+pub fn install(on_signal: impl FnOnce() + Send + 'static) -> io::Result<()> {
+    let (mut reader, writer) = UnixStream::pair()?;
+    // Kept open for the life of the process
+    WAKE_FD.store(writer.into_raw_fd(), Ordering::SeqCst);
+    thread::spawn(move || {
+        let mut signal = [0];
+        if reader.read_exact(&mut signal).is_err() {
+            return;
+        }
+        let name = if i32::from(signal[0]) == libc::SIGINT { "SIGINT" } else { "SIGTERM" };
+        eprintln!("Caught {name}, shutting down (again to exit at once)");
+        *STOPPING.0.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        STOPPING.1.notify_all();
+        on_signal();
+    });
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Whether a signal has asked the process to stop.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Sleeps for `duration`, or until shutdown is asked for; returns whether it was.
+pub fn wait(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    let mut stopping = STOPPING.0.lock().unwrap_or_else(PoisonError::into_inner);
+    while !*stopping {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        stopping = STOPPING.1.wait_timeout(stopping, left).unwrap_or_else(PoisonError::into_inner).0;
+    }
+    requested()
+}
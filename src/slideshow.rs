@@ -0,0 +1,155 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::images::Placement;
+
+/// File extensions picked up from the slideshow directory (case-insensitive).
+pub const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg"];
+
+#[derive(Clone, Debug)]
+pub struct SlideshowOptions {
+    /// Time between slides
+    pub interval: Duration,
+    /// Visit images in a random order, reshuffled after every pass
+    pub shuffle: bool,
+    /// Hard floor on time between refreshes, whatever `interval` says.
+    /// Refreshing e-paper too often wears the panel out.
+    pub min_refresh_interval: Duration,
+    /// How images that don't match the panel's shape are fitted, unless they
+    /// have a sidecar file (see [`placement_for`])
+    pub placement: Placement,
+    /// Show images in four grey levels rather than black, white and red
+    pub gray: bool,
+}
+
+impl Default for SlideshowOptions {
+    fn default() -> Self {
+        SlideshowOptions {
+            interval: Duration::from_secs(300),
+            shuffle: false,
+            min_refresh_interval: Duration::from_secs(180),
+            placement: Placement::default(),
+            gray: false,
+        }
+    }
+}
+
+/// Cycles through the images in a directory.
+///
+/// The directory is rescanned at the start of every pass, so photos can be
+/// added or removed while the slideshow runs.
+pub struct Slideshow {
+    dir: PathBuf,
+    options: SlideshowOptions,
+    images: Vec<PathBuf>,
+    position: usize,
+    rng: u64,
+    last_refresh: Option<Instant>,
+}
+
+impl Slideshow {
+    pub fn new(dir: impl Into<PathBuf>, options: SlideshowOptions) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(0);
+        Slideshow {
+            dir: dir.into(),
+            options,
+            images: Vec::new(),
+            position: 0,
+            // xorshift must not start at zero
+            rng: seed | 1,
+            last_refresh: None,
+        }
+    }
+
+    /// Placement for `image`: its sidecar file if it has one, otherwise the slideshow's.
+    pub fn placement(&self, image: &Path) -> Placement {
+        placement_for(image).unwrap_or(self.options.placement)
+    }
+
+    /// Time the next slide is actually shown after the previous one.
+    pub fn effective_interval(&self) -> Duration {
+        self.options.interval.max(self.options.min_refresh_interval)
+    }
+
+    /// Returns the next image to show, rescanning the directory when a pass completes.
+    /// `Ok(None)` means the directory has no images.
+    pub fn next_image(&mut self) -> io::Result<Option<PathBuf>> {
+        if self.position >= self.images.len() {
+            self.images = collect_images(&self.dir)?;
+            if self.options.shuffle {
+                self.shuffle();
+            }
+            self.position = 0;
+        }
+        let image = self.images.get(self.position).cloned();
+        self.position += 1;
+        Ok(image)
+    }
+
+    /// Sleeps until the next slide is due. Returns immediately before the first refresh.
+    pub fn wait_for_next(&self) {
+        thread::sleep(self.until_next());
+    }
+
+    /// How long until the next slide is due; zero before the first refresh.
+    pub fn until_next(&self) -> Duration {
+        self.last_refresh.map_or(Duration::ZERO, |last| {
+            (last + self.effective_interval()).saturating_duration_since(Instant::now())
+        })
+    }
+
+    /// Records that the panel was just refreshed.
+    pub fn mark_refreshed(&mut self) {
+        self.last_refresh = Some(Instant::now());
+    }
+
+    // Fisher-Yates shuffle driven by xorshift64; good enough for picking photos
+    fn shuffle(&mut self) {
+        for i in (1..self.images.len()).rev() {
+            self.rng ^= self.rng << 13;
+            self.rng ^= self.rng >> 7;
+            self.rng ^= self.rng << 17;
+            let j = (self.rng % (i as u64 + 1)) as usize;
+            self.images.swap(i, j);
+        }
+    }
+}
+
+/// Lists the image files directly inside `dir`, sorted by name.
+pub fn collect_images(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_image = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| IMAGE_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(ext)));
+        if is_image && path.is_file() {
+            images.push(path);
+        }
+    }
+    images.sort();
+    Ok(images)
+}
+
+/// Reads the per-image placement from `<image>.toml` (e.g. `photo.jpg.toml`)
+/// next to the image, with the same `fit`, `anchor` and `background` keys as
+/// the config file. `None` if there is no such file or it doesn't parse.
+pub fn placement_for(image: &Path) -> Option<Placement> {
+    let mut sidecar = image.as_os_str().to_owned();
+    sidecar.push(".toml");
+    let text = fs::read_to_string(sidecar).ok()?;
+    match toml::from_str(&text) {
+        Ok(placement) => Some(placement),
+        Err(err) => {
+            eprintln!("{}.toml: {err}", image.display());
+            None
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// Start-up and shutdown screens.
+
+use std::fs;
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+use crate::config::ScreenConfig;
+use crate::framebuffer::{Color, Framebuffer};
+use crate::images::{self, ImageError, Placement};
+use crate::text::{Alignment, TextBox};
+
+/// Replaces `{name}` placeholders with values from `vars`. Unknown names are left as-is.
+pub fn expand(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open..];
+        let value = after.find('}').and_then(|close| {
+            let name = &after[1..close];
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (*value, close + 1))
+        });
+        match value {
+            Some((value, consumed)) => {
+                out.push_str(value);
+                rest = &after[consumed..];
+            }
+            None => {
+                out.push('{');
+                rest = &after[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// This machine's hostname, or "unknown".
+pub fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Draws `screen` into `fb`: the image (if any) first, then the text centred on top.
+/// The image is placed as `screen.placement` says, or else as `placement` does.
+pub fn render(fb: &mut Framebuffer, screen: &ScreenConfig, placement: &Placement) -> Result<(), ImageError> {
+    fb.clear(Color::White);
+    if let Some(path) = &screen.image {
+        images::draw_placed(fb, &images::load(path)?, screen.placement.as_ref().unwrap_or(placement));
+    }
+    if let Some(template) = &screen.text {
+        let hostname = hostname();
+        let text = expand(
+            template,
+            &[("version", env!("CARGO_PKG_VERSION")), ("hostname", &hostname)],
+        );
+        let bounds = Rectangle::new(Point::zero(), fb.size());
+        let Ok(_) = TextBox::new(bounds, Color::Black)
+            .alignment(Alignment::Center)
+            .draw(&text, fb);
+    }
+    Ok(())
+}
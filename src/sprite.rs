@@ -0,0 +1,207 @@
+// One-bit bitmaps for `Framebuffer::blit`, and the `include_sprite!` macro
+// that embeds them from XBM files or raw bitmaps.
+//
+// Rows are packed MSB first (the leftmost pixel in the top bit) and padded to
+// a whole byte, as in the panel's own planes. XBM puts the leftmost pixel in
+// the lowest bit instead, so its bytes are turned round as they are read.
+// Reading happens in const fns, so a malformed file fails the build rather
+// than a render.
+
+use crate::pack::row_bytes;
+
+/// A one-bit image, with an optional mask and red plane of the same size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sprite<'a> {
+    pub width: u32,
+    pub height: u32,
+    /// Set pixels, drawn in the foreground colour
+    pub bits: &'a [u8],
+    /// Pixels drawn at all; `None` for just the set pixels of `bits` and `red`
+    pub mask: Option<&'a [u8]>,
+    /// Pixels drawn in red rather than the foreground colour
+    pub red: Option<&'a [u8]>,
+}
+
+impl<'a> Sprite<'a> {
+    /// Panics, or fails the build in a const, if `bits` is too short for the size.
+    pub const fn new(width: u32, height: u32, bits: &'a [u8]) -> Self {
+        assert!(bits.len() >= row_bytes(width as usize) * height as usize, "sprite bitmap is too short");
+        Sprite {
+            width,
+            height,
+            bits,
+            mask: None,
+            red: None,
+        }
+    }
+
+    pub const fn with_mask(mut self, mask: &'a [u8]) -> Self {
+        assert!(mask.len() >= self.bits.len(), "sprite mask is too short");
+        self.mask = Some(mask);
+        self
+    }
+
+    pub const fn with_red(mut self, red: &'a [u8]) -> Self {
+        assert!(red.len() >= self.bits.len(), "sprite red plane is too short");
+        self.red = Some(red);
+        self
+    }
+
+    pub fn is_set(&self, x: u32, y: u32) -> bool {
+        self.get(self.bits, x, y)
+    }
+
+    pub fn is_red(&self, x: u32, y: u32) -> bool {
+        self.red.is_some_and(|red| self.get(red, x, y))
+    }
+
+    /// Whether the pixel is drawn at all: inside the mask, or set without one.
+    pub fn is_masked(&self, x: u32, y: u32) -> bool {
+        match self.mask {
+            Some(mask) => self.get(mask, x, y),
+            None => self.is_set(x, y) || self.is_red(x, y),
+        }
+    }
+
+    fn get(&self, plane: &[u8], x: u32, y: u32) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        let index = y as usize * row_bytes(self.width as usize) + x as usize / 8;
+        plane[index] & (0x80 >> (x % 8)) != 0
+    }
+}
+
+/// The `<name>_width` or `<name>_height` (`suffix`) an XBM file defines.
+pub const fn xbm_define(text: &str, suffix: &str) -> u32 {
+    let (text, suffix) = (text.as_bytes(), suffix.as_bytes());
+    let mut i = 0;
+    while i + suffix.len() < text.len() {
+        // The suffix, ending an identifier, followed by the number
+        let mut matched = true;
+        let mut j = 0;
+        while j < suffix.len() {
+            if text[i + j] != suffix[j] {
+                matched = false;
+                break;
+            }
+            j += 1;
+        }
+        let mut k = i + suffix.len();
+        if matched && (text[k] == b' ' || text[k] == b'\t') {
+            while k < text.len() && (text[k] == b' ' || text[k] == b'\t') {
+                k += 1;
+            }
+            let mut value = 0;
+            let mut digits = 0;
+            while k < text.len() && text[k].is_ascii_digit() {
+                value = value * 10 + (text[k] - b'0') as u32;
+                digits += 1;
+                k += 1;
+            }
+            if digits > 0 {
+                return value;
+            }
+        }
+        i += 1;
+    }
+    panic!("XBM file has no #define for its size")
+}
+
+/// How many bytes of bitmap an XBM file holds.
+pub const fn xbm_len(text: &str) -> usize {
+    let text = text.as_bytes();
+    let mut i = bitmap_start(text);
+    let mut count = 0;
+    while i + 1 < text.len() && text[i] != b'}' {
+        if text[i] == b'0' && (text[i + 1] == b'x' || text[i + 1] == b'X') {
+            count += 1;
+            i += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+/// The bitmap of an XBM file, in `Sprite`'s bit order. `N` must be its `xbm_len`.
+pub const fn parse_xbm<const N: usize>(text: &str) -> [u8; N] {
+    let text = text.as_bytes();
+    let mut bits = [0; N];
+    let mut i = bitmap_start(text);
+    let mut count = 0;
+    while i + 1 < text.len() && text[i] != b'}' {
+        if text[i] == b'0' && (text[i + 1] == b'x' || text[i + 1] == b'X') {
+            i += 2;
+            let mut value: u32 = 0;
+            while i < text.len() && hex_digit(text[i]) < 16 {
+                value = value * 16 + hex_digit(text[i]) as u32;
+                i += 1;
+            }
+            assert!(value <= 0xFF, "XBM bitmap has a value wider than a byte");
+            assert!(count < N, "XBM bitmap has more bytes than expected");
+            bits[count] = (value as u8).reverse_bits();
+            count += 1;
+            continue;
+        }
+        i += 1;
+    }
+    assert!(count == N, "XBM bitmap has fewer bytes than expected");
+    bits
+}
+
+// Just past the `{` that opens the bitmap
+const fn bitmap_start(text: &[u8]) -> usize {
+    let mut i = 0;
+    while i < text.len() {
+        if text[i] == b'{' {
+            return i + 1;
+        }
+        i += 1;
+    }
+    panic!("XBM file has no bitmap")
+}
+
+// 16 for anything that isn't a hex digit
+const fn hex_digit(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        b'A'..=b'F' => c - b'A' + 10,
+        _ => 16,
+    }
+}
+
+/// Embeds a `Sprite<'static>` in the binary, checked at compile time.
+///
+/// `include_sprite!("icon.xbm")` reads an XBM file, taking its size from its
+/// `#define`s; `include_sprite!("icon.raw", 16, 16)` reads raw rows packed as
+/// in `Sprite`. Either takes `mask = "..."` and `red = "..."` after it, naming
+/// files of the same kind for those planes. Paths are relative to the file the
+/// macro is used in, as for `include_bytes!`.
+#[macro_export]
+macro_rules! include_sprite {
+    ($path:literal $(, mask = $mask:literal)? $(, red = $red:literal)? $(,)?) => {{
+        const TEXT: &str = include_str!($path);
+        const BITS: [u8; $crate::sprite::xbm_len(TEXT)] = $crate::sprite::parse_xbm(TEXT);
+        $crate::sprite::Sprite::new(
+            $crate::sprite::xbm_define(TEXT, "_width"),
+            $crate::sprite::xbm_define(TEXT, "_height"),
+            &BITS,
+        )
+        $(.with_mask({
+            const TEXT: &str = include_str!($mask);
+            const MASK: [u8; $crate::sprite::xbm_len(TEXT)] = $crate::sprite::parse_xbm(TEXT);
+            &MASK
+        }))?
+        $(.with_red({
+            const TEXT: &str = include_str!($red);
+            const RED: [u8; $crate::sprite::xbm_len(TEXT)] = $crate::sprite::parse_xbm(TEXT);
+            &RED
+        }))?
+    }};
+    ($path:literal, $width:expr, $height:expr $(, mask = $mask:literal)? $(, red = $red:literal)? $(,)?) => {
+        $crate::sprite::Sprite::new($width, $height, include_bytes!($path))
+            $(.with_mask(include_bytes!($mask)))?
+            $(.with_red(include_bytes!($red)))?
+    };
+}
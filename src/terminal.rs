@@ -0,0 +1,116 @@
+// A stand-in panel that draws into the terminal.
+//
+// Each character cell shows two pixels with the upper half block `▀`: its
+// foreground colour is the upper pixel and its background the lower one, in
+// the terminal's white, black and red. Over SSH that is a preview of a page
+// in well under a second, rather than the panel's fifteen-second refresh.
+
+use std::io::{self, Write};
+
+use embedded_hal::blocking::delay::DelayMs;
+
+use crate::epd::EpdController;
+use crate::framebuffer::{Color, Framebuffer, Rotation};
+
+/// Writes `fb`, in its logical orientation, to `out` as half-block characters.
+pub fn render(fb: &Framebuffer, out: &mut impl Write) -> io::Result<()> {
+    let mut text = String::new();
+    for y in (0..fb.height()).step_by(2) {
+        // Colours only change at the edges of shapes, so most cells need no escape
+        let mut colors = None;
+        for x in 0..fb.width() {
+            let cell = (fb.get_pixel(x, y).unwrap_or(Color::White), fb.get_pixel(x, y + 1));
+            if colors != Some(cell) {
+                match cell {
+                    (upper, Some(lower)) => text.push_str(&format!("\x1b[{};{}m", foreground(upper), foreground(lower) + 10)),
+                    // An odd last row gets the terminal's own background below it
+                    (upper, None) => text.push_str(&format!("\x1b[0;{}m", foreground(upper))),
+                }
+                colors = Some(cell);
+            }
+            text.push('▀');
+        }
+        text.push_str("\x1b[0m\n");
+    }
+    out.write_all(text.as_bytes())?;
+    out.flush()
+}
+
+// SGR code for the colour as a foreground; add ten for the background
+fn foreground(color: Color) -> u8 {
+    match color {
+        Color::White => 97,
+        Color::Black => 30,
+        Color::Red => 31,
+    }
+}
+
+/// Pretends to be a panel of the given native size, printing every refresh
+/// to stdout in `rotation`.
+pub struct TerminalPanel {
+    fb: Framebuffer,
+    refreshes: u32,
+}
+
+impl TerminalPanel {
+    pub fn new(width: u32, height: u32, rotation: Rotation) -> Self {
+        TerminalPanel {
+            fb: Framebuffer::new(width, height, rotation),
+            refreshes: 0,
+        }
+    }
+
+    /// The same size as `epd`, so a page lays out as it would on the real panel.
+    pub fn like<E: EpdController>(epd: &E, rotation: Rotation) -> Self {
+        let (width, height) = epd.dimensions();
+        TerminalPanel::new(width, height, rotation)
+    }
+
+    /// Like the Inky pHAT.
+    pub fn inky_phat(rotation: Rotation) -> Self {
+        TerminalPanel {
+            fb: Framebuffer::inky_phat(rotation),
+            refreshes: 0,
+        }
+    }
+
+    fn print(&mut self, kind: &str) -> io::Result<()> {
+        self.refreshes += 1;
+        let mut out = io::stdout().lock();
+        writeln!(out, "--- refresh {} ({kind}) ---", self.refreshes)?;
+        render(&self.fb, &mut out)
+    }
+}
+
+impl EpdController for TerminalPanel {
+    type Error = io::Error;
+
+    fn dimensions(&self) -> (u32, u32) {
+        match self.fb.rotation() {
+            Rotation::Rotate0 | Rotation::Rotate180 => (self.fb.width(), self.fb.height()),
+            Rotation::Rotate90 | Rotation::Rotate270 => (self.fb.height(), self.fb.width()),
+        }
+    }
+
+    fn init<D: DelayMs<u8>>(&mut self, _delay: &mut D) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn write_planes(&mut self, bw: &[u8], red: &[u8]) -> Result<(), Self::Error> {
+        self.fb.load_planes(bw, red);
+        Ok(())
+    }
+
+    fn refresh<D: DelayMs<u8>>(&mut self, _delay: &mut D) -> Result<(), Self::Error> {
+        self.print("full")
+    }
+
+    fn refresh_fast<D: DelayMs<u8>>(&mut self, _delay: &mut D) -> Result<(), Self::Error> {
+        self.print("fast")
+    }
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
@@ -0,0 +1,505 @@
+// Multi-line text layout inside a bounding box.
+//
+// Everything is measured in whole character cells since all the fonts we use
+// are monospaced. Lines are borrowed slices of the input, so laying out text
+// does not allocate.
+//
+// Characters from East Asian scripts take two cells, and lines may break
+// between them as well as at spaces, so Japanese, Chinese and Korean wrap
+// without any. ProFont has no glyphs for them; with the `ttf` feature a
+// fallback TrueType font can be set to draw whatever ProFont lacks instead
+// of its `?`. Common emoji have glyphs of their own (see `emoji`).
+//
+// Text can also be turned: a quarter turn at a time through `Rotated`, which
+// makes part of a target look like a turned surface that anything can draw
+// on, or by any angle with `draw_at_angle`, which resamples one line's glyphs.
+
+#[cfg(feature = "ttf")]
+use std::sync::{Arc, PoisonError, RwLock};
+
+use embedded_graphics::mono_font::{MonoFont, MonoTextStyle};
+use embedded_graphics::pixelcolor::{BinaryColor, PixelColor};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::{Baseline, Text};
+use profont::{
+    PROFONT_10_POINT, PROFONT_12_POINT, PROFONT_14_POINT, PROFONT_18_POINT, PROFONT_24_POINT,
+    PROFONT_7_POINT, PROFONT_9_POINT,
+};
+
+use crate::emoji;
+use crate::framebuffer::Rotation;
+#[cfg(feature = "ttf")]
+use crate::ttf;
+
+/// Every ProFont size, largest first. The default fallback order for [`TextBox`].
+pub const PROFONT_SIZES: &[&MonoFont<'static>] = &[
+    &PROFONT_24_POINT,
+    &PROFONT_18_POINT,
+    &PROFONT_14_POINT,
+    &PROFONT_12_POINT,
+    &PROFONT_10_POINT,
+    &PROFONT_9_POINT,
+    &PROFONT_7_POINT,
+];
+
+/// The ProFont of the given point size, if there is one.
+pub fn profont(points: u32) -> Option<&'static MonoFont<'static>> {
+    match points {
+        7 => Some(&PROFONT_7_POINT),
+        9 => Some(&PROFONT_9_POINT),
+        10 => Some(&PROFONT_10_POINT),
+        12 => Some(&PROFONT_12_POINT),
+        14 => Some(&PROFONT_14_POINT),
+        18 => Some(&PROFONT_18_POINT),
+        24 => Some(&PROFONT_24_POINT),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Alignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Wrapped, aligned text inside `bounds`.
+///
+/// The largest font in `fonts` whose wrapped text fits the box is used; if none
+/// fit, the last (smallest) one is used and lines past the bottom are dropped.
+#[derive(Clone, Copy, Debug)]
+pub struct TextBox<'a, C> {
+    pub bounds: Rectangle,
+    pub color: C,
+    pub alignment: Alignment,
+    /// Candidate fonts, largest first
+    pub fonts: &'a [&'a MonoFont<'a>],
+    /// Extra pixels between lines
+    pub line_spacing: u32,
+}
+
+impl<'a, C: PixelColor> TextBox<'a, C> {
+    pub fn new(bounds: Rectangle, color: C) -> Self {
+        TextBox {
+            bounds,
+            color,
+            alignment: Alignment::Left,
+            fonts: PROFONT_SIZES,
+            line_spacing: 0,
+        }
+    }
+
+    pub fn alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn fonts(mut self, fonts: &'a [&'a MonoFont<'a>]) -> Self {
+        self.fonts = fonts;
+        self
+    }
+
+    pub fn line_spacing(mut self, line_spacing: u32) -> Self {
+        self.line_spacing = line_spacing;
+        self
+    }
+
+    /// Picks the font `draw` would use for `text`.
+    pub fn pick_font(&self, text: &str) -> Option<&'a MonoFont<'a>> {
+        self.fonts
+            .iter()
+            .copied()
+            .find(|font| self.fits(text, font))
+            .or_else(|| self.fonts.last().copied())
+    }
+
+    /// True when `text` wraps into the box with `font` without losing lines.
+    pub fn fits(&self, text: &str, font: &MonoFont) -> bool {
+        let columns = columns(font, self.bounds.size.width);
+        if columns == 0 {
+            return false;
+        }
+        let lines = wrap(text, columns).count() as u32;
+        lines * self.line_height(font) <= self.bounds.size.height + self.line_spacing
+    }
+
+    /// Lays out and draws `text`, returning the font that was used.
+    pub fn draw<D>(&self, text: &str, target: &mut D) -> Result<Option<&'a MonoFont<'a>>, D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let Some(font) = self.pick_font(text) else {
+            return Ok(None);
+        };
+        let line_height = self.line_height(font);
+        let bottom = self.bounds.top_left.y + self.bounds.size.height as i32;
+
+        let mut y = self.bounds.top_left.y;
+        for line in wrap(text, columns(font, self.bounds.size.width)) {
+            if y + font.character_size.height as i32 > bottom {
+                break;
+            }
+            let slack = self.bounds.size.width.saturating_sub(line_width(font, line)) as i32;
+            let x = self.bounds.top_left.x
+                + match self.alignment {
+                    Alignment::Left => 0,
+                    Alignment::Center => slack / 2,
+                    Alignment::Right => slack,
+                };
+            draw_line(line, font, self.color, Point::new(x, y), target)?;
+            y += line_height as i32;
+        }
+        Ok(Some(font))
+    }
+
+    /// Like `draw`, but with the text turned by `rotation` inside `bounds`:
+    /// `Rotate90` reads top to bottom, `Rotate270` bottom to top. Lines wrap
+    /// to the box's height rather than its width when it is turned sideways.
+    pub fn draw_rotated<D>(&self, text: &str, rotation: Rotation, target: &mut D) -> Result<Option<&'a MonoFont<'a>>, D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let mut turned = Rotated::new(target, self.bounds, rotation);
+        TextBox {
+            bounds: turned.bounding_box(),
+            ..*self
+        }
+        .draw(text, &mut turned)
+    }
+
+    fn line_height(&self, font: &MonoFont) -> u32 {
+        font.character_size.height + self.line_spacing
+    }
+}
+
+/// How many characters of `font` fit in `width` pixels.
+pub fn columns(font: &MonoFont, width: u32) -> usize {
+    let advance = font.character_size.width + font.character_spacing;
+    ((width + font.character_spacing) / advance) as usize
+}
+
+/// Pixel width of `line` drawn in `font`.
+pub fn line_width(font: &MonoFont, line: &str) -> u32 {
+    let cells = str_columns(line) as u32;
+    if cells == 0 {
+        return 0;
+    }
+    cells * font.character_size.width + (cells - 1) * font.character_spacing
+}
+
+/// Whether `c` is one of the East Asian characters drawn two cells wide.
+pub fn is_wide(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x115F // Hangul initial consonants
+        | 0x2E80..=0x303E | 0x3041..=0x33FF // CJK radicals and punctuation, kana, compatibility
+        | 0x3400..=0x4DBF | 0x4E00..=0x9FFF // CJK ideographs
+        | 0xA000..=0xA4CF // Yi
+        | 0xAC00..=0xD7A3 // Hangul syllables
+        | 0xF900..=0xFAFF // CJK compatibility ideographs
+        | 0xFE30..=0xFE4F // CJK compatibility forms
+        | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 // fullwidth forms
+        | 0x20000..=0x3FFFD // more ideographs
+    )
+}
+
+/// Cells `c` takes: none for combining marks and zero-width characters, two
+/// for wide ones, one for the rest.
+pub fn char_columns(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0x20D0..=0x20FF | 0xFE00..=0xFE0F => 0,
+        _ if is_wide(c) || emoji::glyph(c).is_some() => 2,
+        _ => 1,
+    }
+}
+
+/// Cells `text` takes on one line.
+pub fn str_columns(text: &str) -> usize {
+    text.chars().map(char_columns).sum()
+}
+
+/// Whether a line may break between `before` and `after` without a space:
+/// next to a wide character, unless that would start a line with closing
+/// punctuation or end one with opening punctuation.
+pub fn can_break_between(before: char, after: char) -> bool {
+    (is_wide(before) || is_wide(after))
+        && !"、。，．・：；？！）」』】〉》〕｝ーぁぃぅぇぉっゃゅょゎァィゥェォッャュョヮヵヶ…,.!?:;)".contains(after)
+        && !"（「『【〈《〔｛(".contains(before)
+}
+
+/// Whether `font` has a glyph of its own for `c`, rather than its stand-in.
+pub fn has_glyph(font: &MonoFont, c: char) -> bool {
+    c == '?' || font.glyph_mapping.index(c) != font.glyph_mapping.index('?')
+}
+
+#[cfg(feature = "ttf")]
+static FALLBACK: RwLock<Option<Arc<ttf::Font>>> = RwLock::new(None);
+
+/// Sets the font characters ProFont lacks are drawn in, sized to its cells.
+#[cfg(feature = "ttf")]
+pub fn set_fallback(font: Option<Arc<ttf::Font>>) {
+    *FALLBACK.write().unwrap_or_else(PoisonError::into_inner) = font;
+}
+
+/// Draws one line of text with its top-left corner at `top_left`, giving wide
+/// characters two cells. Those `font` has no glyph for are drawn as emoji if
+/// they are ones `emoji` covers, else in the fallback font if one is set.
+pub fn draw_line<C, D>(line: &str, font: &MonoFont, color: C, top_left: Point, target: &mut D) -> Result<(), D::Error>
+where
+    C: PixelColor,
+    D: DrawTarget<Color = C>,
+{
+    let style = MonoTextStyle::new(font, color);
+    if line.chars().all(|c| has_glyph(font, c)) {
+        Text::with_baseline(line, top_left, style, Baseline::Top).draw(target)?;
+        return Ok(());
+    }
+    #[cfg(feature = "ttf")]
+    let fallback = FALLBACK.read().unwrap_or_else(PoisonError::into_inner).clone();
+    let advance = (font.character_size.width + font.character_spacing) as i32;
+    let mut x = top_left.x;
+    // Runs of characters with glyphs go out as one string
+    let mut run = None;
+    for (index, c) in line.char_indices().chain([(line.len(), '\0')]) {
+        let end = index == line.len();
+        if !end && has_glyph(font, c) {
+            run.get_or_insert((index, x));
+            x += advance;
+            continue;
+        }
+        if let Some((start, left)) = run.take() {
+            Text::with_baseline(&line[start..index], Point::new(left, top_left.y), style, Baseline::Top).draw(target)?;
+        }
+        if end {
+            break;
+        }
+        let cells = char_columns(c) as i32;
+        if let Some(rows) = emoji::glyph(c) {
+            let room = cells * advance;
+            let size = font.character_size.height.min(room as u32);
+            let at = Point::new(x + (room - size as i32) / 2, top_left.y + (font.character_size.height - size) as i32 / 2);
+            emoji::draw(rows, at, size, color, target)?;
+            x += room;
+            continue;
+        }
+        #[cfg(feature = "ttf")]
+        if let Some(fallback) = fallback.as_ref().filter(|fallback| fallback.has_glyph(c)) {
+            // As tall as the cells, or narrower if need be, centred on the same baseline;
+            // marks stay where the pen is
+            let room = cells * advance;
+            let mut size = font.character_size.height;
+            let width = fallback.advance(c, size);
+            if cells > 0 && width > room as f32 {
+                size = (size as f32 * room as f32 / width) as u32;
+            }
+            let slack = (room - fallback.advance(c, size).round() as i32).max(0) / 2;
+            let top = top_left.y + font.baseline as i32 - fallback.ascent(size) as i32;
+            let mut buffer = [0; 4];
+            fallback.draw_line(c.encode_utf8(&mut buffer), size, Point::new(x + slack, top), color, target)?;
+            x += cells * advance;
+            continue;
+        }
+        if cells > 0 {
+            Text::with_baseline("?", Point::new(x + (cells - 1) * advance / 2, top_left.y), style, Baseline::Top).draw(target)?;
+        }
+        x += cells * advance;
+    }
+    Ok(())
+}
+
+/// Greedy word wrap to at most `max_columns` cells per line.
+///
+/// Honours explicit newlines, breaks at spaces or between wide characters
+/// where possible and splits words that are longer than a whole line.
+pub fn wrap(text: &str, max_columns: usize) -> Wrap<'_> {
+    Wrap {
+        rest: (max_columns > 0).then_some(text),
+        max_columns,
+    }
+}
+
+pub struct Wrap<'a> {
+    rest: Option<&'a str>,
+    max_columns: usize,
+}
+
+impl<'a> Iterator for Wrap<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.rest?;
+        let (paragraph, after) = match rest.find('\n') {
+            Some(newline) => (&rest[..newline], Some(&rest[newline + 1..])),
+            None => (rest, None),
+        };
+
+        // Find where the line overflows, and where to break it
+        let mut last_break = None;
+        let mut split = None;
+        let mut column = 0;
+        let mut previous = None;
+        for (index, ch) in paragraph.char_indices() {
+            if previous.is_some_and(|previous| can_break_between(previous, ch)) {
+                last_break = Some(index);
+            }
+            column += char_columns(ch);
+            if column > self.max_columns {
+                split = Some(match last_break {
+                    _ if ch == ' ' => index,
+                    Some(last_break) => last_break,
+                    // Even a character wider than the line has to go somewhere
+                    None if index == 0 => ch.len_utf8(),
+                    None => index,
+                });
+                break;
+            }
+            if ch == ' ' {
+                last_break = Some(index);
+            }
+            previous = Some(ch);
+        }
+
+        let Some(split) = split else {
+            self.rest = after;
+            return Some(paragraph.trim_end());
+        };
+        let remainder = rest[split..].trim_start_matches(' ');
+        // A break that lands right before a newline or the end shouldn't add an empty line
+        let remainder = remainder.strip_prefix('\n').unwrap_or(remainder);
+        self.rest = (!remainder.is_empty()).then_some(remainder);
+        Some(paragraph[..split].trim_end())
+    }
+}
+
+/// `area` of a target as if it were turned by `rotation`, clockwise. Its own
+/// top-left corner lands on the top-right of `area` for `Rotate90`, the
+/// bottom-right for `Rotate180` and the bottom-left for `Rotate270`, and
+/// pixels outside `area` are dropped.
+pub struct Rotated<'a, D> {
+    target: &'a mut D,
+    area: Rectangle,
+    rotation: Rotation,
+}
+
+impl<'a, D> Rotated<'a, D> {
+    pub fn new(target: &'a mut D, area: Rectangle, rotation: Rotation) -> Self {
+        Rotated { target, area, rotation }
+    }
+
+    // Where a point on the turned surface is on the target
+    fn place(&self, point: Point) -> Point {
+        let (left, top) = (self.area.top_left.x, self.area.top_left.y);
+        let (width, height) = (self.area.size.width as i32, self.area.size.height as i32);
+        match self.rotation {
+            Rotation::Rotate0 => Point::new(left + point.x, top + point.y),
+            Rotation::Rotate90 => Point::new(left + width - 1 - point.y, top + point.x),
+            Rotation::Rotate180 => Point::new(left + width - 1 - point.x, top + height - 1 - point.y),
+            Rotation::Rotate270 => Point::new(left + point.y, top + height - 1 - point.x),
+        }
+    }
+}
+
+impl<D: DrawTarget> Dimensions for Rotated<'_, D> {
+    fn bounding_box(&self) -> Rectangle {
+        let size = match self.rotation {
+            Rotation::Rotate0 | Rotation::Rotate180 => self.area.size,
+            Rotation::Rotate90 | Rotation::Rotate270 => Size::new(self.area.size.height, self.area.size.width),
+        };
+        Rectangle::new(Point::zero(), size)
+    }
+}
+
+impl<D: DrawTarget> DrawTarget for Rotated<'_, D> {
+    type Color = D::Color;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let visible = self.bounding_box();
+        let placed: Vec<_> = pixels
+            .into_iter()
+            .filter(|Pixel(point, _)| visible.contains(*point))
+            .map(|Pixel(point, color)| Pixel(self.place(point), color))
+            .collect();
+        self.target.draw_iter(placed)
+    }
+}
+
+/// Draws one line of `text` turned `degrees` clockwise about `center`, the
+/// middle of the line, for labels at odd angles. The glyphs are resampled
+/// without smoothing, so below about 10pt anything but a quarter turn
+/// starts to look ragged; `Rotated` is exact for those.
+pub fn draw_at_angle<C, D>(text: &str, font: &MonoFont, color: C, center: Point, degrees: f32, target: &mut D) -> Result<(), D::Error>
+where
+    C: PixelColor,
+    D: DrawTarget<Color = C>,
+{
+    let size = Size::new(line_width(font, text), font.character_size.height);
+    if size.width == 0 {
+        return Ok(());
+    }
+    let mut glyphs = Mask {
+        size,
+        bits: vec![false; (size.width * size.height) as usize],
+    };
+    let Ok(_) = Text::with_baseline(text, Point::zero(), MonoTextStyle::new(font, BinaryColor::On), Baseline::Top).draw(&mut glyphs);
+
+    // Every target pixel the turned line could cover, looked up in the unturned one
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (half_width, half_height) = (size.width as f32 / 2.0, size.height as f32 / 2.0);
+    let reach = (half_width * half_width + half_height * half_height).sqrt().ceil() as i32;
+    let mut pixels = Vec::new();
+    for dy in -reach..=reach {
+        for dx in -reach..=reach {
+            let (x, y) = (dx as f32 + 0.5, dy as f32 + 0.5);
+            let along = x * cos + y * sin + half_width;
+            let across = -x * sin + y * cos + half_height;
+            if along < 0.0 || across < 0.0 {
+                continue;
+            }
+            if glyphs.get(along as u32, across as u32) {
+                pixels.push(Pixel(center + Point::new(dx, dy), color));
+            }
+        }
+    }
+    target.draw_iter(pixels)
+}
+
+// One line of text rendered off screen, to be sampled
+struct Mask {
+    size: Size,
+    bits: Vec<bool>,
+}
+
+impl Mask {
+    fn get(&self, x: u32, y: u32) -> bool {
+        x < self.size.width && y < self.size.height && self.bits[(y * self.size.width + x) as usize]
+    }
+}
+
+impl OriginDimensions for Mask {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for Mask {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<BinaryColor>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 && (point.x as u32) < self.size.width && (point.y as u32) < self.size.height {
+                self.bits[(point.y as u32 * self.size.width + point.x as u32) as usize] = color.is_on();
+            }
+        }
+        Ok(())
+    }
+}
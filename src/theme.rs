@@ -0,0 +1,239 @@
+// How pages look, set once in `[theme]` instead of in every page and widget.
+//
+// Pages draw in three colours: `Black` for ink, `White` for paper and `Red`
+// for the accent. The theme decides what each of those is on the panel once
+// the frame is finished (see `screens::draw`), so a dashboard can be turned
+// white on black, or kept off the third colour, without changing any page.
+// Night mode inverts the frame between two times of day, which is easier on
+// the eyes in a dark bedroom than a white panel.
+//
+// Widgets read the rest from the theme the page is drawn with (see
+// `RenderContext`): the frame round bars and dials, the title font, and
+// whether titles and table headings are in the accent.
+//
+//     [theme]
+//     accent = "headings"
+//     border = { width = 1, style = "rounded" }
+//     font = 10
+//     title_font = 14
+//     night = { start = "21:00", end = "07:00" }
+
+use std::time::Duration;
+
+use chrono::NaiveTime;
+use embedded_graphics::mono_font::MonoFont;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyleBuilder, Rectangle, RoundedRectangle, StrokeAlignment};
+use profont::{PROFONT_12_POINT, PROFONT_9_POINT};
+use serde::Deserialize;
+
+use crate::framebuffer::{Color, Framebuffer};
+use crate::schedule;
+use crate::text::{self, TextBox};
+
+/// Where the third colour goes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Accent {
+    /// Only where pages draw it: warnings, late trains, the latest point on a chart
+    #[default]
+    Alerts,
+    /// Page titles and table headings as well
+    Headings,
+    /// Nowhere; drawn in ink instead, for a plain black and white panel
+    Off,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BorderStyle {
+    #[default]
+    Solid,
+    /// Three pixels on, three off
+    Dashed,
+    /// Solid, with corners rounded three times the width
+    Rounded,
+}
+
+/// The frame round progress bars, dials and the like.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Border {
+    /// Pixels; 0 for no frame
+    pub width: u32,
+    pub style: BorderStyle,
+}
+
+impl Default for Border {
+    fn default() -> Self {
+        Border {
+            width: 1,
+            style: BorderStyle::Solid,
+        }
+    }
+}
+
+impl Border {
+    /// Draws the frame just inside `bounds`.
+    pub fn draw<T: DrawTarget<Color = Color>>(&self, bounds: Rectangle, color: Color, target: &mut T) -> Result<(), T::Error> {
+        if self.width == 0 {
+            return Ok(());
+        }
+        let style = PrimitiveStyleBuilder::new()
+            .stroke_color(color)
+            .stroke_width(self.width)
+            .stroke_alignment(StrokeAlignment::Inside)
+            .build();
+        match self.style {
+            BorderStyle::Solid | BorderStyle::Dashed => bounds.into_styled(style).draw(&mut self.stroke(target)),
+            BorderStyle::Rounded => {
+                let radius = (self.width * 3).min(bounds.size.width.min(bounds.size.height) / 2);
+                RoundedRectangle::with_equal_corners(bounds, Size::new_equal(radius))
+                    .into_styled(style)
+                    .draw(target)
+            }
+        }
+    }
+
+    /// `target` with only the pixels this style keeps, for outlines that
+    /// aren't rectangles, like a dial's.
+    pub fn stroke<'a, T>(&self, target: &'a mut T) -> Stroke<'a, T> {
+        Stroke {
+            target,
+            dashed: self.style == BorderStyle::Dashed,
+        }
+    }
+}
+
+/// Hours during which the panel is white on black. Like `[night]`, `start`
+/// may be later than `end`, in which case they span midnight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NightMode {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub accent: Accent,
+    pub border: Border,
+    /// ProFont point size of body text where a page or node doesn't choose one
+    pub font: u32,
+    /// ProFont point size of page titles
+    pub title_font: u32,
+    /// White on black all day
+    pub inverted: bool,
+    /// White on black between these times only
+    pub night: Option<NightMode>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            accent: Accent::Alerts,
+            border: Border::default(),
+            font: 9,
+            title_font: 12,
+            inverted: false,
+            night: None,
+        }
+    }
+}
+
+impl Theme {
+    /// Whether the fonts named are ones there are.
+    pub fn check(&self) -> Result<(), String> {
+        for (name, points) in [("font", self.font), ("title_font", self.title_font)] {
+            if text::profont(points).is_none() {
+                return Err(format!("no {points}pt font for [theme] {name}"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn font(&self) -> &'static MonoFont<'static> {
+        text::profont(self.font).unwrap_or(&PROFONT_9_POINT)
+    }
+
+    pub fn title_font(&self) -> &'static MonoFont<'static> {
+        text::profont(self.title_font).unwrap_or(&PROFONT_12_POINT)
+    }
+
+    /// Room a title takes, with a pixel below it.
+    pub fn title_height(&self) -> u32 {
+        self.title_font().character_size.height + 1
+    }
+
+    /// Colour of titles and table headings.
+    pub fn heading(&self) -> Color {
+        match self.accent {
+            Accent::Headings => Color::ACCENT,
+            Accent::Alerts | Accent::Off => Color::Black,
+        }
+    }
+
+    /// Draws a page title into `bounds`.
+    pub fn title<T: DrawTarget<Color = Color>>(&self, title: &str, bounds: Rectangle, target: &mut T) -> Result<(), T::Error> {
+        let fonts = [self.title_font()];
+        TextBox::new(bounds, self.heading()).fonts(&fonts).draw(title, target)?;
+        Ok(())
+    }
+
+    pub fn is_inverted(&self, time: NaiveTime) -> bool {
+        self.inverted || self.night.is_some_and(|night| schedule::in_window(night.start, night.end, time))
+    }
+
+    /// How long until night mode starts or ends, if there is one.
+    pub fn until_change(&self, time: NaiveTime) -> Option<Duration> {
+        let night = self.night.filter(|_| !self.inverted)?;
+        Some(schedule::until_boundary(night.start, night.end, time))
+    }
+
+    /// Turns a finished frame into what the panel shows at `time`.
+    pub fn apply(&self, fb: &mut Framebuffer, time: NaiveTime) {
+        let inverted = self.is_inverted(time);
+        if !inverted && self.accent != Accent::Off {
+            return;
+        }
+        let (ink, paper) = if inverted { (Color::White, Color::Black) } else { (Color::Black, Color::White) };
+        let accent = if self.accent == Accent::Off { ink } else { Color::Red };
+        fb.recolor(|color| match color {
+            Color::Black => ink,
+            Color::White => paper,
+            Color::Red => accent,
+        });
+    }
+}
+
+/// What `Border::stroke` draws through.
+pub struct Stroke<'a, T> {
+    target: &'a mut T,
+    dashed: bool,
+}
+
+impl<T: DrawTarget<Color = Color>> DrawTarget for Stroke<'_, T> {
+    type Color = Color;
+    type Error = T::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Color>>,
+    {
+        let dashed = self.dashed;
+        self.target
+            .draw_iter(pixels.into_iter().filter(|Pixel(point, _)| !dashed || is_dash(*point)))
+    }
+}
+
+impl<T: DrawTarget<Color = Color>> Dimensions for Stroke<'_, T> {
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+
+// In every other run of three pixels along any straight edge
+fn is_dash(point: Point) -> bool {
+    (point.x + point.y).rem_euclid(6) < 3
+}
@@ -0,0 +1,131 @@
+// Refreshing less often when the Pi or the panel runs hot.
+//
+// In a sealed enclosure in the sun both the SoC and the panel can sit well
+// above what e-paper is rated for, and every refresh adds heat of its own.
+// `Throttle` stretches the schedule's waits the further either temperature is
+// over its limit, and says so in the log when throttling starts and stops.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Where Raspberry Pi OS exposes the SoC temperature, in millidegrees C.
+pub const DEFAULT_SOC_SENSOR: &str = "/sys/class/thermal/thermal_zone0/temp";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThermalConfig {
+    pub enabled: bool,
+    /// sysfs file with the SoC temperature in millidegrees C
+    pub soc_sensor: PathBuf,
+    /// Degrees C above which the SoC counts as hot
+    pub soc_limit: f32,
+    /// Also read the panel's own sensor (needs 3-wire SPI)
+    pub panel_sensor: bool,
+    /// Degrees C above which the panel counts as hot; most are rated to 40
+    pub panel_limit: f32,
+    /// Each this many degrees over a limit doubles the time between refreshes
+    pub doubling: f32,
+    /// Longest the schedule is stretched, as a multiple of the normal wait
+    pub max_stretch: f32,
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        ThermalConfig {
+            enabled: true,
+            soc_sensor: PathBuf::from(DEFAULT_SOC_SENSOR),
+            soc_limit: 70.0,
+            panel_sensor: true,
+            panel_limit: 40.0,
+            doubling: 5.0,
+            max_stretch: 8.0,
+        }
+    }
+}
+
+/// Reads a sysfs thermal zone, in degrees C.
+pub fn soc_temperature(sensor: &Path) -> io::Result<f32> {
+    let text = fs::read_to_string(sensor)?;
+    let millidegrees: i64 = text
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("bad temperature {:?}", text.trim())))?;
+    Ok(millidegrees as f32 / 1000.0)
+}
+
+/// Latest readings, either of which may be missing.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Temperatures {
+    pub soc: Option<f32>,
+    pub panel: Option<f32>,
+}
+
+pub struct Throttle {
+    config: ThermalConfig,
+    // Stretch factor last applied, to log only the changes
+    stretch: f32,
+}
+
+impl Throttle {
+    pub fn new(config: &ThermalConfig) -> Self {
+        Throttle {
+            config: config.clone(),
+            stretch: 1.0,
+        }
+    }
+
+    /// Reads the SoC sensor, if enabled; a missing sensor (not a Pi) reads as nothing.
+    pub fn read_soc(&self) -> Option<f32> {
+        if !self.config.enabled {
+            return None;
+        }
+        soc_temperature(&self.config.soc_sensor).ok()
+    }
+
+    /// Whether the caller should read the panel's sensor for `stretch`.
+    pub fn wants_panel(&self) -> bool {
+        self.config.enabled && self.config.panel_sensor
+    }
+
+    /// `wait`, lengthened for however hot things are.
+    pub fn stretch(&mut self, wait: Duration, temperatures: Temperatures) -> Duration {
+        let stretch = if self.config.enabled { self.factor(temperatures) } else { 1.0 };
+        if stretch != self.stretch {
+            let readings = describe(temperatures);
+            if stretch > 1.0 {
+                println!("Thermal throttling: {readings}; refreshing {stretch:.1}x less often");
+            } else {
+                println!("Thermal throttling off: {readings}");
+            }
+            self.stretch = stretch;
+        }
+        wait.mul_f32(stretch)
+    }
+
+    fn factor(&self, temperatures: Temperatures) -> f32 {
+        let over_soc = temperatures.soc.map_or(0.0, |soc| soc - self.config.soc_limit);
+        let over_panel = temperatures.panel.map_or(0.0, |panel| panel - self.config.panel_limit);
+        let over = over_soc.max(over_panel);
+        if over <= 0.0 {
+            return 1.0;
+        }
+        // Whole steps only, so the factor doesn't wobble (and log) with every tenth of a degree
+        let doublings = (over / self.config.doubling.max(0.1)).ceil();
+        2f32.powf(doublings).min(self.config.max_stretch.max(1.0))
+    }
+}
+
+fn describe(temperatures: Temperatures) -> String {
+    let soc = temperatures.soc.map(|soc| format!("SoC {soc:.1}°C"));
+    let panel = temperatures.panel.map(|panel| format!("panel {panel:.0}°C"));
+    let readings: Vec<String> = soc.into_iter().chain(panel).collect();
+    if readings.is_empty() {
+        "no readings".to_string()
+    } else {
+        readings.join(", ")
+    }
+}
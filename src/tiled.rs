@@ -0,0 +1,199 @@
+// Rendering a frame in bands, for panels too big to hold a whole frame of.
+//
+// Instead of one `Framebuffer` the size of the panel there is one `Band` a
+// few native rows tall. The page is drawn into it once per band, with
+// everything outside the band clipped away, and each band is sent to the
+// controller as soon as it is drawn. Peak memory is one band, whatever the
+// panel size; the price is drawing the page once per band.
+//
+//     tiled::show(&mut epd, Rotation::Rotate90, 64, &mut delay, |band| {
+//         band.clear(Color::White);
+//         let Ok(_) = TextBox::new(bounds, Color::Black).draw("Hello", band);
+//     })?;
+
+use std::convert::Infallible;
+
+use embedded_graphics::prelude::{DrawTarget, OriginDimensions, Pixel, Size};
+use embedded_hal::blocking::delay::DelayMs;
+
+use crate::epd::RowWriter;
+use crate::framebuffer::{Color, Rotation, RotationMap};
+use crate::pack::row_bytes;
+
+/// A strip of native rows of a frame, drawn on in the frame's logical
+/// (rotated) coordinates. Its size is the whole frame's, so pages lay out as
+/// they would in a `Framebuffer`; only pixels inside the strip are kept.
+pub struct Band {
+    native_width: u32,
+    native_height: u32,
+    rotation: Rotation,
+    map: RotationMap,
+    stride: usize,
+    // Native row the band starts at, and the most it holds
+    first_row: u32,
+    capacity: u32,
+    bw: Vec<u8>,
+    red: Vec<u8>,
+}
+
+impl Band {
+    /// The first `rows` native rows of a `native_width` by `native_height` frame.
+    pub fn new(native_width: u32, native_height: u32, rotation: Rotation, rows: u32) -> Self {
+        let stride = row_bytes(native_width as usize);
+        let capacity = rows.clamp(1, native_height.max(1));
+        let mut band = Band {
+            native_width,
+            native_height,
+            rotation,
+            map: RotationMap::new(native_width, native_height, rotation),
+            stride,
+            first_row: 0,
+            capacity,
+            bw: vec![0xFF; stride * capacity as usize],
+            red: vec![0x00; stride * capacity as usize],
+        };
+        band.resize();
+        band
+    }
+
+    /// First native row in the band.
+    pub fn first_row(&self) -> u32 {
+        self.first_row
+    }
+
+    /// Native rows in the band; the last band may be shorter than the rest.
+    pub fn rows(&self) -> u32 {
+        self.capacity.min(self.native_height - self.first_row)
+    }
+
+    /// Moves on to the next rows, blank white. Returns false past the last band.
+    pub fn advance(&mut self) -> bool {
+        let next = self.first_row + self.capacity;
+        if next >= self.native_height {
+            return false;
+        }
+        self.first_row = next;
+        self.resize();
+        self.clear(Color::White);
+        true
+    }
+
+    /// Logical width of the whole frame, after rotation.
+    pub fn width(&self) -> u32 {
+        match self.rotation {
+            Rotation::Rotate0 | Rotation::Rotate180 => self.native_width,
+            Rotation::Rotate90 | Rotation::Rotate270 => self.native_height,
+        }
+    }
+
+    /// Logical height of the whole frame, after rotation.
+    pub fn height(&self) -> u32 {
+        match self.rotation {
+            Rotation::Rotate0 | Rotation::Rotate180 => self.native_height,
+            Rotation::Rotate90 | Rotation::Rotate270 => self.native_width,
+        }
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        let (bw, red) = match color {
+            Color::White => (0xFF, 0x00),
+            Color::Black => (0x00, 0x00),
+            Color::Red => (0xFF, 0xFF),
+        };
+        self.bw.fill(bw);
+        self.red.fill(red);
+    }
+
+    /// Sets a pixel in logical coordinates; points outside the band are ignored.
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        let Some((index, mask)) = self.locate(x, y) else {
+            return;
+        };
+        match color {
+            Color::White => {
+                self.bw[index] |= mask;
+                self.red[index] &= !mask;
+            }
+            Color::Black => {
+                self.bw[index] &= !mask;
+                self.red[index] &= !mask;
+            }
+            Color::Red => {
+                self.bw[index] |= mask;
+                self.red[index] |= mask;
+            }
+        }
+    }
+
+    pub fn bw_plane(&self) -> &[u8] {
+        &self.bw
+    }
+
+    pub fn red_plane(&self) -> &[u8] {
+        &self.red
+    }
+
+    // The planes hold exactly the band's rows
+    fn resize(&mut self) {
+        let len = self.stride * self.rows() as usize;
+        self.bw.truncate(len);
+        self.red.truncate(len);
+    }
+
+    // Index into the band's planes, if the point lands inside it
+    fn locate(&self, x: u32, y: u32) -> Option<(usize, u8)> {
+        let (col_offset, col_mask) = *self.map.cols.get(x as usize)?;
+        let (row_offset, row_mask) = *self.map.rows.get(y as usize)?;
+        let index = (col_offset + row_offset).checked_sub(self.first_row as usize * self.stride)?;
+        (index < self.bw.len()).then_some((index, col_mask | row_mask))
+    }
+}
+
+impl OriginDimensions for Band {
+    fn size(&self) -> Size {
+        Size::new(self.width(), self.height())
+    }
+}
+
+impl DrawTarget for Band {
+    type Color = Color;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 {
+                self.set_pixel(point.x as u32, point.y as u32, color);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draws a frame band by band with `draw`, which is called once per band and
+/// should draw the whole page each time, streams each band to `epd`, then
+/// refreshes. Bands are `rows` native rows tall.
+pub fn show<E, D>(
+    epd: &mut E,
+    rotation: Rotation,
+    rows: u32,
+    delay: &mut D,
+    mut draw: impl FnMut(&mut Band),
+) -> Result<(), E::Error>
+where
+    E: RowWriter,
+    D: DelayMs<u8>,
+{
+    let (width, height) = epd.dimensions();
+    let mut band = Band::new(width, height, rotation, rows);
+    loop {
+        draw(&mut band);
+        epd.write_rows(band.first_row(), band.bw_plane(), band.red_plane())?;
+        if !band.advance() {
+            break;
+        }
+    }
+    epd.refresh(delay)
+}
@@ -0,0 +1,830 @@
+// TrueType fonts at any size, for headlines too big or too particular for
+// ProFont.
+//
+// This reads the outlines straight from the font file (`.ttf`, `.ttc`, or an
+// `.otf` with TrueType outlines; CFF outlines are refused), fills each glyph
+// sampled 4×4 to a pixel, and sets the pixels whose coverage reaches the
+// font's threshold. There is no grey on the panel, so there is no
+// anti-aliasing either: a lower threshold makes thin strokes bolder, a higher
+// one keeps counters open at small sizes. Rasterized glyphs are cached per
+// font and size, so redrawing a page costs little.
+//
+// Emoji the font has no glyphs for come from `emoji`, as in ProFont text.
+//
+// Hinting and kerning are left out; both matter less at the sizes this is for.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use embedded_graphics::pixelcolor::PixelColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+use crate::emoji;
+use crate::text::{Alignment, can_break_between};
+
+/// Smallest size `draw_box` will shrink text to.
+pub const MIN_SIZE: u32 = 6;
+
+// Subsamples per pixel along each axis
+const SAMPLES: i32 = 4;
+// Composite glyphs made of composite glyphs, at most this deep
+const MAX_DEPTH: u32 = 8;
+
+/// One rasterized glyph, top-left relative to the pen on the baseline.
+#[derive(Clone, Debug)]
+pub struct Glyph {
+    pub left: i32,
+    pub top: i32,
+    pub width: u32,
+    pub height: u32,
+    bits: Vec<bool>,
+}
+
+impl Glyph {
+    pub fn is_set(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.bits[(y * self.width + x) as usize]
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum CharMap {
+    // Segment mapping to delta values, the BMP only
+    Segments(usize),
+    // Segmented coverage, all of Unicode
+    Groups(usize),
+}
+
+pub struct Font {
+    data: Vec<u8>,
+    units_per_em: f32,
+    ascent: f32,
+    descent: f32,
+    line_gap: f32,
+    long_offsets: bool,
+    glyph_count: u16,
+    metric_count: u16,
+    hmtx: usize,
+    loca: usize,
+    glyf: usize,
+    char_map: CharMap,
+    // Fraction of a pixel a glyph has to cover for it to be set
+    threshold: f32,
+    cache: Mutex<HashMap<(u16, u32), Arc<Glyph>>>,
+}
+
+impl Font {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Self::parse(data).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    /// Reads the tables a font needs; the first font of a collection.
+    pub fn parse(data: Vec<u8>) -> Result<Self, String> {
+        let start = if data.get(..4) == Some(b"ttcf") {
+            read_u32(&data, 12).ok_or("truncated font collection")? as usize
+        } else {
+            0
+        };
+        let table = |tag: &[u8; 4]| -> Option<usize> {
+            let count = read_u16(&data, start + 4)? as usize;
+            (0..count)
+                .map(|i| start + 12 + i * 16)
+                .find(|&record| data.get(record..record + 4) == Some(tag))
+                .and_then(|record| read_u32(&data, record + 8))
+                .map(|offset| offset as usize)
+        };
+        if table(b"glyf").is_none() && table(b"CFF ").is_some() {
+            return Err("CFF outlines are not supported, only TrueType ones".to_string());
+        }
+        let missing = |tag: &str| format!("no {tag} table");
+        let head = table(b"head").ok_or_else(|| missing("head"))?;
+        let hhea = table(b"hhea").ok_or_else(|| missing("hhea"))?;
+        let maxp = table(b"maxp").ok_or_else(|| missing("maxp"))?;
+        let cmap = table(b"cmap").ok_or_else(|| missing("cmap"))?;
+        let font = Font {
+            units_per_em: read_u16(&data, head + 18).filter(|&units| units > 0).ok_or("bad head table")? as f32,
+            long_offsets: read_i16(&data, head + 50).ok_or("bad head table")? != 0,
+            ascent: read_i16(&data, hhea + 4).ok_or("bad hhea table")? as f32,
+            descent: read_i16(&data, hhea + 6).ok_or("bad hhea table")? as f32,
+            line_gap: read_i16(&data, hhea + 8).ok_or("bad hhea table")? as f32,
+            metric_count: read_u16(&data, hhea + 34).filter(|&count| count > 0).ok_or("bad hhea table")?,
+            glyph_count: read_u16(&data, maxp + 4).ok_or("bad maxp table")?,
+            hmtx: table(b"hmtx").ok_or_else(|| missing("hmtx"))?,
+            loca: table(b"loca").ok_or_else(|| missing("loca"))?,
+            glyf: table(b"glyf").ok_or_else(|| missing("glyf"))?,
+            char_map: char_map(&data, cmap).ok_or("no Unicode cmap")?,
+            threshold: 0.5,
+            cache: Mutex::new(HashMap::new()),
+            data,
+        };
+        Ok(font)
+    }
+
+    /// Sets the fraction of a pixel a glyph has to cover for it to be set, 0.5
+    /// unless changed. Glyphs already in the cache keep their old one.
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    fn scale(&self, size: u32) -> f32 {
+        size as f32 / self.units_per_em
+    }
+
+    /// Pixels from the top of a line to its baseline at `size` pixels per em.
+    pub fn ascent(&self, size: u32) -> u32 {
+        (self.ascent * self.scale(size)).round() as u32
+    }
+
+    /// Pixels from one line's top to the next's.
+    pub fn line_height(&self, size: u32) -> u32 {
+        ((self.ascent - self.descent + self.line_gap) * self.scale(size)).round() as u32
+    }
+
+    /// The glyph `c` is drawn with, 0 (the missing-glyph box) if there is none.
+    pub fn glyph_id(&self, c: char) -> u16 {
+        let found = match self.char_map {
+            CharMap::Segments(table) => segment_glyph(&self.data, table, c as u32),
+            CharMap::Groups(table) => group_glyph(&self.data, table, c as u32),
+        };
+        found.filter(|&glyph| glyph < self.glyph_count).unwrap_or(0)
+    }
+
+    /// Whether the font has a glyph of its own for `c`.
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.glyph_id(c) != 0
+    }
+
+    fn advance_units(&self, glyph: u16) -> f32 {
+        let index = glyph.min(self.metric_count - 1) as usize;
+        read_u16(&self.data, self.hmtx + index * 4).unwrap_or(0) as f32
+    }
+
+    /// Width of `c` in pixels at `size`, before rounding.
+    pub fn advance(&self, c: char, size: u32) -> f32 {
+        match self.glyph_id(c) {
+            // Drawn from `emoji` instead, an em square
+            0 if emoji::glyph(c).is_some() => size as f32,
+            glyph => self.advance_units(glyph) * self.scale(size),
+        }
+    }
+
+    /// Pixel width of one line of `text` at `size`.
+    pub fn line_width(&self, text: &str, size: u32) -> u32 {
+        text.chars().map(|c| self.advance(c, size)).sum::<f32>().ceil() as u32
+    }
+
+    /// The glyph for `c` at `size`, rasterized on first use.
+    pub fn glyph(&self, c: char, size: u32) -> Arc<Glyph> {
+        let id = self.glyph_id(c);
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        cache
+            .entry((id, size))
+            .or_insert_with(|| Arc::new(self.rasterize(id, size)))
+            .clone()
+    }
+
+    fn rasterize(&self, glyph: u16, size: u32) -> Glyph {
+        let scale = self.scale(size);
+        let mut edges = Vec::new();
+        for contour in self.outline(glyph, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], 0) {
+            // Into pixels, y down
+            let points: Vec<(f32, f32, bool)> = contour.iter().map(|&(x, y, on)| (x * scale, -y * scale, on)).collect();
+            flatten(&points, &mut edges);
+        }
+        fill(&edges, self.threshold)
+    }
+
+    // The contours of `glyph` in font units, through `transform` ([a, b, c, d, e, f])
+    fn outline(&self, glyph: u16, transform: [f32; 6], depth: u32) -> Vec<Vec<(f32, f32, bool)>> {
+        self.try_outline(glyph, transform, depth).unwrap_or_default()
+    }
+
+    fn try_outline(&self, glyph: u16, [a, b, c, d, e, f]: [f32; 6], depth: u32) -> Option<Vec<Vec<(f32, f32, bool)>>> {
+        let data = &self.data;
+        let (start, end) = if self.long_offsets {
+            let loca = self.loca + glyph as usize * 4;
+            (read_u32(data, loca)? as usize, read_u32(data, loca + 4)? as usize)
+        } else {
+            let loca = self.loca + glyph as usize * 2;
+            (read_u16(data, loca)? as usize * 2, read_u16(data, loca + 2)? as usize * 2)
+        };
+        if end <= start {
+            // Nothing to draw, like a space
+            return Some(Vec::new());
+        }
+        let at = self.glyf + start;
+        let contours = read_i16(data, at)?;
+        let place = |x: f32, y: f32| (a * x + c * y + e, b * x + d * y + f);
+        if contours >= 0 {
+            let contours = contours as usize;
+            let ends: Vec<usize> = (0..contours)
+                .map(|i| read_u16(data, at + 10 + i * 2).map(usize::from))
+                .collect::<Option<_>>()?;
+            let count = ends.last().map_or(0, |&last| last + 1);
+            let instructions = read_u16(data, at + 10 + contours * 2)? as usize;
+            let mut offset = at + 12 + contours * 2 + instructions;
+            let mut flags = Vec::with_capacity(count);
+            while flags.len() < count {
+                let flag = *data.get(offset)?;
+                offset += 1;
+                flags.push(flag);
+                if flag & 0x08 != 0 {
+                    let repeat = *data.get(offset)?;
+                    offset += 1;
+                    flags.extend((0..repeat).map(|_| flag));
+                }
+            }
+            flags.truncate(count);
+            let mut coordinate = |short: u8, same: u8| -> Option<Vec<f32>> {
+                let mut value = 0i32;
+                let mut values = Vec::with_capacity(count);
+                for &flag in &flags {
+                    if flag & short != 0 {
+                        let delta = *data.get(offset)? as i32;
+                        offset += 1;
+                        value += if flag & same != 0 { delta } else { -delta };
+                    } else if flag & same == 0 {
+                        value += read_i16(data, offset)? as i32;
+                        offset += 2;
+                    }
+                    values.push(value as f32);
+                }
+                Some(values)
+            };
+            let xs = coordinate(0x02, 0x10)?;
+            let ys = coordinate(0x04, 0x20)?;
+            let mut outline = Vec::with_capacity(contours);
+            let mut first = 0;
+            for end in ends {
+                let contour = (first..=end.min(count - 1))
+                    .map(|i| {
+                        let (x, y) = place(xs[i], ys[i]);
+                        (x, y, flags[i] & 0x01 != 0)
+                    })
+                    .collect();
+                outline.push(contour);
+                first = end + 1;
+            }
+            return Some(outline);
+        }
+
+        // A composite: other glyphs, each moved and scaled into place
+        if depth >= MAX_DEPTH {
+            return None;
+        }
+        let mut outline = Vec::new();
+        let mut offset = at + 10;
+        loop {
+            let flags = read_u16(data, offset)?;
+            let part = read_u16(data, offset + 2)?;
+            offset += 4;
+            let (dx, dy) = if flags & 0x0001 != 0 {
+                offset += 4;
+                (read_i16(data, offset - 4)? as f32, read_i16(data, offset - 2)? as f32)
+            } else {
+                offset += 2;
+                (*data.get(offset - 2)? as i8 as f32, *data.get(offset - 1)? as i8 as f32)
+            };
+            // Parts placed by matching points are rare enough to leave where they are
+            let (dx, dy) = if flags & 0x0002 != 0 { (dx, dy) } else { (0.0, 0.0) };
+            let f2dot14 = |offset: usize| read_i16(data, offset).map(|value| value as f32 / 16384.0);
+            let (pa, pb, pc, pd) = if flags & 0x0008 != 0 {
+                offset += 2;
+                let scale = f2dot14(offset - 2)?;
+                (scale, 0.0, 0.0, scale)
+            } else if flags & 0x0040 != 0 {
+                offset += 4;
+                (f2dot14(offset - 4)?, 0.0, 0.0, f2dot14(offset - 2)?)
+            } else if flags & 0x0080 != 0 {
+                offset += 8;
+                (f2dot14(offset - 8)?, f2dot14(offset - 6)?, f2dot14(offset - 4)?, f2dot14(offset - 2)?)
+            } else {
+                (1.0, 0.0, 0.0, 1.0)
+            };
+            // The part's own transform, then this glyph's
+            let transform = [
+                a * pa + c * pb,
+                b * pa + d * pb,
+                a * pc + c * pd,
+                b * pc + d * pd,
+                a * dx + c * dy + e,
+                b * dx + d * dy + f,
+            ];
+            outline.extend(self.outline(part, transform, depth + 1));
+            if flags & 0x0020 == 0 {
+                break;
+            }
+        }
+        Some(outline)
+    }
+
+    /// Draws one line of `text` with its top-left corner at `top_left`.
+    pub fn draw_line<C, D>(&self, text: &str, size: u32, top_left: Point, color: C, target: &mut D) -> Result<(), D::Error>
+    where
+        C: PixelColor,
+        D: DrawTarget<Color = C>,
+    {
+        let baseline = top_left.y + self.ascent(size) as i32;
+        let mut pen = top_left.x as f32;
+        let mut pixels = Vec::new();
+        for c in text.chars() {
+            if let Some(rows) = emoji::glyph(c).filter(|_| !self.has_glyph(c)) {
+                // Standing on the baseline, at most the font's ascent tall
+                let side = size.min(self.ascent(size));
+                let at = Point::new(pen.round() as i32 + (size - side) as i32 / 2, baseline - side as i32);
+                emoji::draw(rows, at, side, color, target)?;
+                pen += self.advance(c, size);
+                continue;
+            }
+            let glyph = self.glyph(c, size);
+            let origin = Point::new(pen.round() as i32 + glyph.left, baseline + glyph.top);
+            for y in 0..glyph.height {
+                for x in 0..glyph.width {
+                    if glyph.is_set(x, y) {
+                        pixels.push(Pixel(origin + Point::new(x as i32, y as i32), color));
+                    }
+                }
+            }
+            pen += self.advance(c, size);
+        }
+        target.draw_iter(pixels)
+    }
+
+    /// Greedy word wrap to lines no wider than `width` at `size`, breaking at
+    /// spaces or between wide characters as `text::wrap` does, and splitting
+    /// words that are wider than a whole line.
+    pub fn wrap<'t>(&self, text: &'t str, size: u32, width: u32) -> Vec<&'t str> {
+        let width = width as f32;
+        let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let mut start = 0;
+            let mut used = 0.0;
+            let mut last_break = None;
+            let mut previous = None;
+            for (index, c) in paragraph.char_indices() {
+                if previous.is_some_and(|previous| can_break_between(previous, c)) {
+                    last_break = Some(index);
+                }
+                let advance = self.advance(c, size);
+                if used + advance > width && index > start {
+                    let split = match last_break {
+                        Some(split) if split > start => split,
+                        _ => index,
+                    };
+                    lines.push(paragraph[start..split].trim_end());
+                    if split == index && c == ' ' {
+                        // The space that ran over goes with the break
+                        start = index + 1;
+                        used = 0.0;
+                        last_break = None;
+                        previous = Some(c);
+                        continue;
+                    }
+                    start = split + usize::from(paragraph[split..].starts_with(' '));
+                    used = paragraph[start..index].chars().map(|c| self.advance(c, size)).sum();
+                    last_break = None;
+                }
+                if c == ' ' {
+                    last_break = Some(index);
+                }
+                used += advance;
+                previous = Some(c);
+            }
+            lines.push(paragraph[start..].trim_end());
+        }
+        lines
+    }
+
+    /// The largest size, no larger than `max`, at which `text` wraps into `bounds`.
+    pub fn fit(&self, text: &str, bounds: Rectangle, max: u32) -> u32 {
+        (MIN_SIZE..=max.max(MIN_SIZE))
+            .rev()
+            .find(|&size| {
+                let lines = self.wrap(text, size, bounds.size.width);
+                lines.len() as u32 * self.line_height(size) <= bounds.size.height
+                    && lines.iter().all(|line| self.line_width(line, size) <= bounds.size.width)
+            })
+            .unwrap_or(MIN_SIZE)
+    }
+
+    /// Wraps and draws `text` inside `bounds` at `size` pixels per em, or as
+    /// large as fits without one, dropping lines past the bottom. Returns the
+    /// size used.
+    pub fn draw_box<C, D>(
+        &self,
+        text: &str,
+        bounds: Rectangle,
+        size: Option<u32>,
+        alignment: Alignment,
+        color: C,
+        target: &mut D,
+    ) -> Result<u32, D::Error>
+    where
+        C: PixelColor,
+        D: DrawTarget<Color = C>,
+    {
+        let size = size.unwrap_or_else(|| self.fit(text, bounds, bounds.size.height));
+        let bottom = bounds.top_left.y + bounds.size.height as i32;
+        let mut y = bounds.top_left.y;
+        for line in self.wrap(text, size, bounds.size.width) {
+            if y + self.line_height(size) as i32 > bottom {
+                break;
+            }
+            let slack = bounds.size.width.saturating_sub(self.line_width(line, size)) as i32;
+            let x = bounds.top_left.x
+                + match alignment {
+                    Alignment::Left => 0,
+                    Alignment::Center => slack / 2,
+                    Alignment::Right => slack,
+                };
+            self.draw_line(line, size, Point::new(x, y), color, target)?;
+            y += self.line_height(size) as i32;
+        }
+        Ok(size)
+    }
+}
+
+static LOADED: Mutex<Vec<(PathBuf, Arc<Font>)>> = Mutex::new(Vec::new());
+
+/// The font at `path`, read once and shared along with its glyph cache.
+pub fn load_cached(path: &Path) -> Result<Arc<Font>, String> {
+    let mut loaded = LOADED.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((_, font)) = loaded.iter().find(|(loaded, _)| loaded == path) {
+        return Ok(font.clone());
+    }
+    let font = Arc::new(Font::load(path)?);
+    loaded.push((path.to_path_buf(), font.clone()));
+    Ok(font)
+}
+
+// The best Unicode subtable: full coverage if there is one, else the BMP
+fn char_map(data: &[u8], cmap: usize) -> Option<CharMap> {
+    let count = read_u16(data, cmap + 2)? as usize;
+    let mut best = None;
+    for record in (0..count).map(|i| cmap + 4 + i * 8) {
+        let (platform, encoding) = (read_u16(data, record)?, read_u16(data, record + 2)?);
+        let table = cmap + read_u32(data, record + 4)? as usize;
+        let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+        match read_u16(data, table)? {
+            12 if unicode => return Some(CharMap::Groups(table)),
+            4 if unicode => best = Some(CharMap::Segments(table)),
+            _ => {}
+        }
+    }
+    best
+}
+
+fn segment_glyph(data: &[u8], table: usize, c: u32) -> Option<u16> {
+    let segments = read_u16(data, table + 6)? as usize / 2;
+    let ends = table + 14;
+    let starts = ends + segments * 2 + 2;
+    let deltas = starts + segments * 2;
+    let ranges = deltas + segments * 2;
+    let segment = (0..segments).find(|&i| read_u16(data, ends + i * 2).is_some_and(|end| end as u32 >= c))?;
+    let start = read_u16(data, starts + segment * 2)? as u32;
+    if start > c {
+        return None;
+    }
+    let delta = read_u16(data, deltas + segment * 2)?;
+    let range = read_u16(data, ranges + segment * 2)? as usize;
+    if range == 0 {
+        return Some((c as u16).wrapping_add(delta));
+    }
+    // An offset from where the offset itself is kept, into the glyph array after it
+    let glyph = read_u16(data, ranges + segment * 2 + range + (c - start) as usize * 2)?;
+    (glyph != 0).then(|| glyph.wrapping_add(delta))
+}
+
+fn group_glyph(data: &[u8], table: usize, c: u32) -> Option<u16> {
+    let groups = read_u32(data, table + 12)? as usize;
+    (0..groups).map(|i| table + 16 + i * 12).find_map(|group| {
+        let (start, end) = (read_u32(data, group)?, read_u32(data, group + 4)?);
+        let first = read_u32(data, group + 8)?;
+        (start..=end).contains(&c).then(|| (first + c - start) as u16)
+    })
+}
+
+// Lines between the points of a contour, quadratic curves cut into short ones
+fn flatten(points: &[(f32, f32, bool)], edges: &mut Vec<[f32; 4]>) {
+    let Some(&last) = points.last() else {
+        return;
+    };
+    let midpoint = |(ax, ay, _): (f32, f32, bool), (bx, by, _): (f32, f32, bool)| ((ax + bx) / 2.0, (ay + by) / 2.0, true);
+    // Start on a point on the curve, implied between two off it if need be
+    let start = match points.iter().position(|point| point.2) {
+        Some(i) => points[i],
+        None => midpoint(points[0], last),
+    };
+    let begin = points.iter().position(|point| point.2).unwrap_or(0);
+    let mut pen = start;
+    let mut control: Option<(f32, f32, bool)> = None;
+    let curve = |pen: (f32, f32, bool), control: (f32, f32, bool), to: (f32, f32, bool), edges: &mut Vec<[f32; 4]>| {
+        // Enough pieces that each is about two pixels long at most
+        let length = (control.0 - pen.0).hypot(control.1 - pen.1) + (to.0 - control.0).hypot(to.1 - control.1);
+        let pieces = (length / 2.0).ceil().clamp(1.0, 32.0) as u32;
+        let mut from = (pen.0, pen.1);
+        for i in 1..=pieces {
+            let t = i as f32 / pieces as f32;
+            let u = 1.0 - t;
+            let x = u * u * pen.0 + 2.0 * u * t * control.0 + t * t * to.0;
+            let y = u * u * pen.1 + 2.0 * u * t * control.1 + t * t * to.1;
+            edges.push([from.0, from.1, x, y]);
+            from = (x, y);
+        }
+    };
+    let skip = if points[begin].2 { 1 } else { 0 };
+    for &point in points.iter().cycle().skip(begin + skip).take(points.len() - skip) {
+        match (point.2, control) {
+            (true, None) => {
+                edges.push([pen.0, pen.1, point.0, point.1]);
+                pen = point;
+            }
+            (true, Some(off)) => {
+                curve(pen, off, point, edges);
+                pen = point;
+                control = None;
+            }
+            (false, None) => control = Some(point),
+            (false, Some(off)) => {
+                let between = midpoint(off, point);
+                curve(pen, off, between, edges);
+                pen = between;
+                control = Some(point);
+            }
+        }
+    }
+    match control {
+        Some(off) => curve(pen, off, start, edges),
+        None => edges.push([pen.0, pen.1, start.0, start.1]),
+    }
+}
+
+// Fills the outline by the non-zero rule, keeping pixels covered enough
+fn fill(edges: &[[f32; 4]], threshold: f32) -> Glyph {
+    let empty = Glyph {
+        left: 0,
+        top: 0,
+        width: 0,
+        height: 0,
+        bits: Vec::new(),
+    };
+    let bounds = edges.iter().fold(None, |bounds: Option<[f32; 4]>, &[x0, y0, x1, y1]| {
+        let [left, top, right, bottom] = bounds.unwrap_or([x0, y0, x0, y0]);
+        Some([left.min(x0).min(x1), top.min(y0).min(y1), right.max(x0).max(x1), bottom.max(y0).max(y1)])
+    });
+    let Some([left, top, right, bottom]) = bounds else {
+        return empty;
+    };
+    let (left, top) = (left.floor() as i32, top.floor() as i32);
+    let (width, height) = ((right.ceil() as i32 - left).max(0) as u32, (bottom.ceil() as i32 - top).max(0) as u32);
+    if width == 0 || height == 0 {
+        return empty;
+    }
+    let mut coverage = vec![0u8; (width * height) as usize];
+    let mut crossings: Vec<(f32, i32)> = Vec::new();
+    for row in 0..height as i32 * SAMPLES {
+        let y = top as f32 + (row as f32 + 0.5) / SAMPLES as f32;
+        crossings.clear();
+        for &[x0, y0, x1, y1] in edges {
+            if (y0 <= y) == (y1 <= y) {
+                continue;
+            }
+            let x = x0 + (y - y0) / (y1 - y0) * (x1 - x0);
+            crossings.push((x, if y1 > y0 { 1 } else { -1 }));
+        }
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut winding = 0;
+        for pair in crossings.windows(2) {
+            winding += pair[0].1;
+            if winding == 0 {
+                continue;
+            }
+            // Subsamples whose centres fall in the span
+            let first = ((pair[0].0 - left as f32) * SAMPLES as f32 - 0.5).ceil().max(0.0) as i32;
+            let last = ((pair[1].0 - left as f32) * SAMPLES as f32 - 0.5).ceil() as i32;
+            let line = (row / SAMPLES) as u32 * width;
+            for sample in first..last.min(width as i32 * SAMPLES) {
+                coverage[(line + (sample / SAMPLES) as u32) as usize] += 1;
+            }
+        }
+    }
+    let needed = ((threshold * (SAMPLES * SAMPLES) as f32).ceil() as u8).max(1);
+    Glyph {
+        left,
+        top,
+        width,
+        height,
+        bits: coverage.into_iter().map(|count| count >= needed).collect(),
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    read_u16(data, offset).map(|value| value as i16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A font at 1000 units to the em: a 500-unit square for every lowercase
+    // letter, an empty space, and for `B` and `C` a composite of the square
+    // with a half-size copy 600 units to its right. `B` is mapped by delta and
+    // `C` through the glyph array; with `groups` the characters are mapped by
+    // a format 12 table instead, which also covers U+10000.
+    const SPACE: i32 = 27;
+    const COMPOSITE: i32 = 28;
+
+    fn font(groups: bool) -> Font {
+        let be16 = |value: i32| (value as u16).to_be_bytes();
+        let mut square = Vec::new();
+        for value in [1, 0, 0, 500, 500, 3, 0] {
+            square.extend(be16(value));
+        }
+        square.extend([0x01; 4]);
+        for value in [0, 500, 0, -500, 0, 0, 500, 0] {
+            square.extend(be16(value));
+        }
+        let mut composite = Vec::new();
+        for value in [-1, 0, 0, 850, 500, 0x0023, 1, 0, 0, 0x000B, 1, 600, 0, 8192] {
+            composite.extend(be16(value));
+        }
+        // Glyph 0 is empty, then a square for each letter, the space, and the composite
+        let mut glyf = Vec::new();
+        let mut loca = [be16(0), be16(0)].concat();
+        for _ in 1..SPACE {
+            glyf.extend(&square);
+            loca.extend(be16(glyf.len() as i32 / 2));
+        }
+        loca.extend(be16(glyf.len() as i32 / 2));
+        glyf.extend(&composite);
+        loca.extend(be16(glyf.len() as i32 / 2));
+        let mut hmtx = Vec::new();
+        for glyph in 0..=COMPOSITE {
+            let advance = match glyph {
+                0 => 500,
+                SPACE => 250,
+                COMPOSITE => 1200,
+                _ => 600,
+            };
+            hmtx.extend(be16(advance));
+            hmtx.extend(be16(0));
+        }
+        let mut head = vec![0; 54];
+        head[18..20].copy_from_slice(&be16(1000));
+        let mut hhea = vec![0; 36];
+        hhea[4..6].copy_from_slice(&be16(800));
+        hhea[6..8].copy_from_slice(&be16(-200));
+        hhea[34..36].copy_from_slice(&be16(COMPOSITE + 1));
+        let mut maxp = vec![0; 6];
+        maxp[4..6].copy_from_slice(&be16(COMPOSITE + 1));
+
+        let mut subtable = Vec::new();
+        if groups {
+            let (space, composite) = (SPACE as u32, COMPOSITE as u32);
+            let map = [(0x20, 0x20, space), (0x42, 0x42, composite), (0x43, 0x43, composite), (0x61, 0x7A, 1), (0x10000, 0x10000, 1)];
+            for value in [12u32 << 16, 16 + 12 * map.len() as u32, 0, map.len() as u32] {
+                subtable.extend(value.to_be_bytes());
+            }
+            for (start, end, glyph) in map {
+                for value in [start, end, glyph] {
+                    subtable.extend(u32::to_be_bytes(value));
+                }
+            }
+        } else {
+            // Ends, starts, deltas and range offsets of five segments, then the glyph array
+            let segments = [
+                (0x20, 0x20, SPACE - 0x20, 0),
+                (0x42, 0x42, COMPOSITE - 0x42, 0),
+                // Three segments on from its own offset, to the first of the glyph array
+                (0x43, 0x43, 0, 6),
+                (0x61, 0x7A, 1 - 0x61, 0),
+                (0xFFFF, 0xFFFF, 1, 0),
+            ];
+            let length = 16 + segments.len() * 8 + 2;
+            for value in [4, length as i32, 0, segments.len() as i32 * 2, 0, 0, 0] {
+                subtable.extend(be16(value));
+            }
+            segments.iter().for_each(|segment| subtable.extend(be16(segment.1)));
+            subtable.extend(be16(0));
+            segments.iter().for_each(|segment| subtable.extend(be16(segment.0)));
+            segments.iter().for_each(|segment| subtable.extend(be16(segment.2)));
+            segments.iter().for_each(|segment| subtable.extend(be16(segment.3)));
+            subtable.extend(be16(COMPOSITE));
+        }
+        let mut cmap = Vec::new();
+        for value in [0, 1, 3, if groups { 10 } else { 1 }] {
+            cmap.extend(be16(value));
+        }
+        cmap.extend(12u32.to_be_bytes());
+        cmap.extend(subtable);
+
+        let tables: [(&[u8; 4], Vec<u8>); 7] =
+            [(b"cmap", cmap), (b"glyf", glyf), (b"head", head), (b"hhea", hhea), (b"hmtx", hmtx), (b"loca", loca), (b"maxp", maxp)];
+        let mut data = vec![0, 1, 0, 0];
+        data.extend(be16(tables.len() as i32));
+        data.extend([0; 6]);
+        let mut offset = 12 + tables.len() * 16;
+        for (tag, table) in &tables {
+            data.extend(*tag);
+            data.extend([0; 4]);
+            data.extend((offset as u32).to_be_bytes());
+            data.extend((table.len() as u32).to_be_bytes());
+            offset += table.len().next_multiple_of(4);
+        }
+        for (_, table) in &tables {
+            data.extend(table);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+        Font::parse(data).unwrap()
+    }
+
+    #[test]
+    fn maps_characters_through_either_kind_of_cmap() {
+        for groups in [false, true] {
+            let font = font(groups);
+            assert_eq!(font.glyph_id('a'), 1);
+            assert_eq!(font.glyph_id('z'), 26);
+            assert_eq!(font.glyph_id(' '), SPACE as u16);
+            assert_eq!(font.glyph_id('B'), COMPOSITE as u16);
+            assert_eq!(font.glyph_id('C'), COMPOSITE as u16);
+            assert_eq!(font.glyph_id('A'), 0);
+            assert!(!font.has_glyph('{'));
+        }
+        assert!(!font(false).has_glyph('\u{10000}'));
+        assert!(font(true).has_glyph('\u{10000}'));
+    }
+
+    #[test]
+    fn measures_from_the_metrics() {
+        let font = font(false);
+        assert_eq!(font.ascent(10), 8);
+        assert_eq!(font.line_height(10), 10);
+        assert_eq!(font.advance('a', 10), 6.0);
+        assert_eq!(font.advance(' ', 10), 2.5);
+        assert_eq!(font.line_width("ab b", 10), 21);
+    }
+
+    #[test]
+    fn rasterizes_simple_and_composite_glyphs() {
+        let font = font(false);
+        let square = font.glyph('a', 10);
+        assert_eq!((square.left, square.top, square.width, square.height), (0, -5, 5, 5));
+        assert!((0..5).all(|y| (0..5).all(|x| square.is_set(x, y))));
+        assert_eq!(font.glyph(' ', 10).width, 0);
+
+        let composite = font.glyph('B', 10);
+        assert_eq!((composite.left, composite.top, composite.width, composite.height), (0, -5, 9, 5));
+        // The square, a gap, then the half-size square along the baseline
+        assert!(composite.is_set(4, 0));
+        assert!(!composite.is_set(5, 4));
+        assert!(composite.is_set(7, 4));
+        assert!(!composite.is_set(7, 0));
+    }
+
+    #[test]
+    fn wraps_at_spaces_and_inside_long_words() {
+        let font = font(false);
+        let width = font.line_width("abcdef", 10);
+        // The space is what runs over, and goes with the break
+        assert_eq!(font.wrap("abcdef ghi", 10, width), ["abcdef", "ghi"]);
+        assert_eq!(font.wrap("abc def ghi", 10, width), ["abc", "def", "ghi"]);
+        assert_eq!(font.wrap("abcdefgh", 10, 20), ["abc", "def", "gh"]);
+        assert_eq!(font.wrap("ab\ncd", 10, 100), ["ab", "cd"]);
+    }
+
+    #[test]
+    fn wraps_any_text_at_any_size_and_width() {
+        let font = font(false);
+        for text in ["abcdef ghi", "a b c d e f", "ab  cd", "abc def ", " ab cd efgh ijklmn"] {
+            for width in 1..120 {
+                for size in MIN_SIZE..40 {
+                    for line in font.wrap(text, size, width) {
+                        assert!(line.chars().count() <= 1 || font.line_width(line, size) <= width, "{text:?} {size} {width}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fits_the_largest_size_that_wraps_into_the_box() {
+        let font = font(false);
+        // On one line 3.85 em wide, or on two each 1.8 em wide
+        assert_eq!(font.fit("abc def", Rectangle::new(Point::zero(), Size::new(66, 20)), 40), 17);
+        assert_eq!(font.fit("abc def", Rectangle::new(Point::zero(), Size::new(40, 40)), 40), 20);
+        assert_eq!(font.fit("abc", Rectangle::new(Point::zero(), Size::new(1000, 1000)), 40), 40);
+        assert_eq!(font.fit("abc", Rectangle::new(Point::zero(), Size::new(1, 1)), 40), MIN_SIZE);
+    }
+}
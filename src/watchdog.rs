@@ -0,0 +1,174 @@
+// Telling systemd the daemon is alive, for as long as it really is.
+//
+// With `Type=notify` and `WatchdogSec=` in the unit, systemd restarts the
+// daemon if it stops sending `WATCHDOG=1` in time:
+//
+//     [Service]
+//     Type=notify
+//     WatchdogSec=60
+//     Restart=on-failure
+//
+// A thread sending pings on a timer would carry on while the refresh loop
+// sat forever on a BUSY line or a hung SPI transfer, so `watch` keeps an eye
+// on the loop's `Heartbeat` as well. Once a cycle has run longer than it
+// allows, the panel's RESET line is pulsed, which makes the controller let go
+// of BUSY, and the loop re-initialises the controller when the cycle ends.
+// Only if that doesn't help (the re-init fails, or the cycle is still stuck
+// another stall later) do the pings stop, leaving it to systemd to restart
+// the daemon. A stuck BUSY line is normally caught sooner, by the driver's
+// busy timeout and the re-init in `retry`; this is for everything those miss.
+
+use std::env;
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A way to send systemd notifications (see sd_notify(3)).
+pub struct Notifier {
+    socket: UnixDatagram,
+    address: SocketAddr,
+    // How often systemd wants pings, if it wants them at all
+    interval: Option<Duration>,
+}
+
+impl Notifier {
+    /// The notification socket systemd handed this process, or `None` when
+    /// it wasn't started by systemd (or not as `Type=notify`).
+    pub fn from_env() -> io::Result<Option<Notifier>> {
+        let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+            return Ok(None);
+        };
+        let path = path.to_string_lossy();
+        // A leading @ is a socket in the abstract namespace
+        let address = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(path.as_ref())?,
+        };
+        // WATCHDOG_PID names the process the pings are wanted from, when set
+        let ours = env::var("WATCHDOG_PID").map_or(true, |pid| pid == std::process::id().to_string());
+        let interval = env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse().ok())
+            .filter(|_| ours)
+            .map(Duration::from_micros);
+        Ok(Some(Notifier {
+            socket: UnixDatagram::unbound()?,
+            address,
+            interval,
+        }))
+    }
+
+    /// Sends `state`, newline-separated `KEY=value` assignments.
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.socket.send_to_addr(state.as_bytes(), &self.address).map(|_| ())
+    }
+
+    /// How often systemd expects `WATCHDOG=1`; `None` when the unit has no watchdog.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+}
+
+/// Looks after `heartbeat` on a thread of its own, for ever: once a cycle has
+/// run past `stall`, calls `reset` to pulse the panel's RESET line and asks
+/// the loop for a re-init (see `Heartbeat::reinit_wanted`). Meanwhile pings
+/// systemd at twice the rate it asks for, if `notifier` says it wants pings,
+/// until the recovery has failed.
+pub fn watch<F>(notifier: Option<Notifier>, heartbeat: Arc<Heartbeat>, stall: Duration, mut reset: F)
+where
+    F: FnMut() -> io::Result<()> + Send + 'static,
+{
+    let interval = notifier.as_ref().and_then(Notifier::interval);
+    let tick = interval.map_or(stall, |interval| interval.min(stall)) / 2;
+    let tick = tick.max(Duration::from_millis(100));
+    thread::spawn(move || {
+        loop {
+            thread::sleep(tick);
+            let busy = heartbeat.busy_for().unwrap_or_default();
+            match heartbeat.recovery() {
+                Recovery::Failed => continue,
+                Recovery::Healthy if busy > stall => {
+                    eprintln!("Refresh stuck for {}s; resetting the panel", busy.as_secs());
+                    let state = match reset() {
+                        Ok(()) => Recovery::Wanted { since: Instant::now() },
+                        Err(err) => {
+                            eprintln!("Panel reset failed: {err}; leaving it to the watchdog");
+                            Recovery::Failed
+                        }
+                    };
+                    heartbeat.set_recovery(state);
+                }
+                Recovery::Wanted { since } if busy > stall && since.elapsed() > stall => {
+                    eprintln!("Refresh still stuck after a reset; leaving it to the watchdog");
+                    heartbeat.set_recovery(Recovery::Failed);
+                    continue;
+                }
+                _ => {}
+            }
+            if let (Some(notifier), Some(_)) = (&notifier, interval)
+                && let Err(err) = notifier.notify("WATCHDOG=1")
+            {
+                eprintln!("Watchdog ping failed: {err}");
+            }
+        }
+    });
+}
+
+// Where getting a stuck refresh going again has got to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Recovery {
+    #[default]
+    Healthy,
+    /// The panel was reset; the loop is to re-init it
+    Wanted { since: Instant },
+    /// Nothing more to try
+    Failed,
+}
+
+/// Whether, and since when, the refresh loop is in the middle of a cycle.
+#[derive(Default)]
+pub struct Heartbeat {
+    busy_since: Mutex<Option<Instant>>,
+    recovery: Mutex<Recovery>,
+}
+
+impl Heartbeat {
+    /// Marks the start of a cycle; the time between cycles doesn't count.
+    pub fn begin(&self) {
+        *self.busy_since.lock().unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+    }
+
+    pub fn end(&self) {
+        *self.busy_since.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    /// How long the current cycle has been going, if one is.
+    pub fn busy_for(&self) -> Option<Duration> {
+        self.busy_since
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .map(|since| since.elapsed())
+    }
+
+    /// Whether `watch` reset the panel under a stuck cycle, so the loop
+    /// should re-init it before the next one.
+    pub fn reinit_wanted(&self) -> bool {
+        matches!(self.recovery(), Recovery::Wanted { .. })
+    }
+
+    /// Reports how the re-init went; after a failed one the pings stop.
+    pub fn reinit_done(&self, succeeded: bool) {
+        self.set_recovery(if succeeded { Recovery::Healthy } else { Recovery::Failed });
+    }
+
+    fn recovery(&self) -> Recovery {
+        *self.recovery.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn set_recovery(&self, state: Recovery) {
+        *self.recovery.lock().unwrap_or_else(PoisonError::into_inner) = state;
+    }
+}
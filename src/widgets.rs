@@ -0,0 +1,13 @@
+// Reusable drawing pieces that screens are built from.
+
+pub mod chart;
+pub mod compass;
+pub mod icon;
+pub mod placeholder;
+pub mod progress;
+pub mod seven_segment;
+pub mod split_flap;
+pub mod table;
+
+pub use progress::{Gauge, ProgressBar};
+pub use table::Table;
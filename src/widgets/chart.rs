@@ -0,0 +1,214 @@
+// Small charts for time series: sparklines, bars and plain line charts.
+//
+// Each draws into an arbitrary bounding box and scales the data to fill it.
+// Values that are NaN are treated as missing and leave a gap.
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{Line, PrimitiveStyle, Rectangle};
+
+use crate::framebuffer::Color;
+
+/// Smallest and largest finite value, or `None` if there are none.
+pub fn range(values: &[f32]) -> Option<(f32, f32)> {
+    values
+        .iter()
+        .copied()
+        .filter(|value| value.is_finite())
+        .fold(None, |range, value| match range {
+            None => Some((value, value)),
+            Some((min, max)) => Some((min.min(value), max.max(value))),
+        })
+}
+
+// Maps value indices and values to pixel positions inside `bounds`
+struct Scale {
+    bounds: Rectangle,
+    count: usize,
+    min: f32,
+    span: f32,
+}
+
+impl Scale {
+    fn new(bounds: Rectangle, count: usize, (min, max): (f32, f32)) -> Self {
+        // A flat series sits in the middle rather than dividing by zero
+        let (min, span) = if max > min { (min, max - min) } else { (min - 1.0, 2.0) };
+        Scale {
+            bounds,
+            count,
+            min,
+            span,
+        }
+    }
+
+    fn x(&self, index: usize) -> i32 {
+        let width = self.bounds.size.width.saturating_sub(1) as i64;
+        let steps = self.count.saturating_sub(1).max(1) as i64;
+        self.bounds.top_left.x + (index as i64 * width / steps) as i32
+    }
+
+    fn y(&self, value: f32) -> i32 {
+        let height = self.bounds.size.height.saturating_sub(1) as f32;
+        let fraction = ((value - self.min) / self.span).clamp(0.0, 1.0);
+        self.bounds.top_left.y + (height - fraction * height).round() as i32
+    }
+
+    fn bottom(&self) -> i32 {
+        self.bounds.top_left.y + self.bounds.size.height as i32 - 1
+    }
+}
+
+/// A one-pixel line with the latest value marked, for squeezing a trend next to a number.
+#[derive(Clone, Copy, Debug)]
+pub struct Sparkline {
+    pub bounds: Rectangle,
+    pub color: Color,
+    /// Colour of the dot on the last value; `None` for no dot
+    pub last: Option<Color>,
+}
+
+impl Sparkline {
+    pub fn new(bounds: Rectangle) -> Self {
+        Sparkline {
+            bounds,
+            color: Color::Black,
+            last: Some(Color::Red),
+        }
+    }
+
+    pub fn draw<T: DrawTarget<Color = Color>>(&self, values: &[f32], target: &mut T) -> Result<(), T::Error> {
+        let Some(range) = range(values) else {
+            return Ok(());
+        };
+        let scale = Scale::new(self.bounds, values.len(), range);
+        draw_polyline(&scale, values, self.color, 1, target)?;
+        if let (Some(color), Some((index, value))) = (self.last, last_finite(values)) {
+            let dot = Rectangle::with_center(Point::new(scale.x(index), scale.y(value)), Size::new(3, 3));
+            target.fill_solid(&dot.intersection(&self.bounds), color)?;
+        }
+        Ok(())
+    }
+}
+
+/// Line chart without axes, optionally filled down to the bottom edge.
+#[derive(Clone, Copy, Debug)]
+pub struct LineChart {
+    pub bounds: Rectangle,
+    pub color: Color,
+    pub thickness: u32,
+    /// Fixed `(min, max)` instead of fitting the data, so charts drawn on
+    /// different days compare
+    pub range: Option<(f32, f32)>,
+    pub fill: bool,
+}
+
+impl LineChart {
+    pub fn new(bounds: Rectangle) -> Self {
+        LineChart {
+            bounds,
+            color: Color::Black,
+            thickness: 2,
+            range: None,
+            fill: false,
+        }
+    }
+
+    pub fn draw<T: DrawTarget<Color = Color>>(&self, values: &[f32], target: &mut T) -> Result<(), T::Error> {
+        let Some(range) = self.range.or_else(|| range(values)) else {
+            return Ok(());
+        };
+        let scale = Scale::new(self.bounds, values.len(), range);
+        if self.fill {
+            for (index, value) in values.iter().enumerate().filter(|(_, value)| value.is_finite()) {
+                let top = Point::new(scale.x(index), scale.y(*value));
+                let bottom = Point::new(top.x, scale.bottom());
+                Line::new(top, bottom)
+                    .into_styled(PrimitiveStyle::with_stroke(self.color, 1))
+                    .draw(target)?;
+            }
+        }
+        draw_polyline(&scale, values, self.color, self.thickness, target)
+    }
+}
+
+/// Vertical bars, one per value, growing from zero, or with `from_zero` off
+/// from the bottom edge, which then stands for the smallest value.
+#[derive(Clone, Copy, Debug)]
+pub struct BarChart {
+    pub bounds: Rectangle,
+    pub color: Color,
+    /// Colour for negative values
+    pub negative: Color,
+    /// Blank columns between bars
+    pub gap: u32,
+    pub from_zero: bool,
+    /// Fixed `(min, max)` instead of fitting the data, so a second chart of
+    /// some of the bars can be drawn over the first in another colour
+    pub range: Option<(f32, f32)>,
+}
+
+impl BarChart {
+    pub fn new(bounds: Rectangle) -> Self {
+        BarChart {
+            bounds,
+            color: Color::Black,
+            negative: Color::Red,
+            gap: 1,
+            from_zero: true,
+            range: None,
+        }
+    }
+
+    pub fn draw<T: DrawTarget<Color = Color>>(&self, values: &[f32], target: &mut T) -> Result<(), T::Error> {
+        let Some((min, max)) = self.range.or_else(|| range(values)) else {
+            return Ok(());
+        };
+        let (min, max) = if self.from_zero { (min.min(0.0), max.max(0.0)) } else { (min, max) };
+        let scale = Scale::new(self.bounds, values.len(), (min, max));
+        let base = scale.y(if self.from_zero { 0.0 } else { min });
+
+        let count = values.len() as u32;
+        let slot = self.bounds.size.width / count.max(1);
+        let bar_width = slot.saturating_sub(self.gap).max(1);
+        for (index, value) in values.iter().enumerate().filter(|(_, value)| value.is_finite()) {
+            let y = scale.y(*value);
+            let (top, bottom) = (y.min(base), y.max(base));
+            let left = self.bounds.top_left.x + (index as u32 * slot) as i32;
+            let bar = Rectangle::new(Point::new(left, top), Size::new(bar_width, (bottom - top + 1) as u32));
+            let color = if *value < 0.0 { self.negative } else { self.color };
+            target.fill_solid(&bar.intersection(&self.bounds), color)?;
+        }
+        Ok(())
+    }
+}
+
+fn last_finite(values: &[f32]) -> Option<(usize, f32)> {
+    values
+        .iter()
+        .copied()
+        .enumerate()
+        .rev()
+        .find(|(_, value)| value.is_finite())
+}
+
+// Joins consecutive finite values; a NaN breaks the line
+fn draw_polyline<T: DrawTarget<Color = Color>>(
+    scale: &Scale,
+    values: &[f32],
+    color: Color,
+    thickness: u32,
+    target: &mut T,
+) -> Result<(), T::Error> {
+    let style = PrimitiveStyle::with_stroke(color, thickness);
+    let mut previous: Option<Point> = None;
+    for (index, value) in values.iter().enumerate() {
+        if !value.is_finite() {
+            previous = None;
+            continue;
+        }
+        let point = Point::new(scale.x(index), scale.y(*value));
+        let start = previous.unwrap_or(point);
+        Line::new(start, point).into_styled(style).draw(target)?;
+        previous = Some(point);
+    }
+    Ok(())
+}
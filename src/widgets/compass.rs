@@ -0,0 +1,55 @@
+// A small compass rose with a needle, for showing which way something lies
+// from where the Pi is.
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{Circle, Line, PrimitiveStyle, Rectangle};
+
+use crate::framebuffer::Color;
+
+/// A ring with north marked at the top, heavier than the other three points,
+/// and a needle from the middle along a bearing.
+#[derive(Clone, Copy, Debug)]
+pub struct Compass {
+    pub center: Point,
+    pub diameter: u32,
+    pub color: Color,
+    pub needle: Color,
+}
+
+impl Compass {
+    pub fn new(center: Point, diameter: u32) -> Self {
+        Compass {
+            center,
+            diameter: diameter.max(9),
+            color: Color::Black,
+            needle: Color::Red,
+        }
+    }
+
+    /// Draws the ring and, unless `bearing` is NaN, the needle pointing
+    /// `bearing` degrees clockwise from north.
+    pub fn draw<T: DrawTarget<Color = Color>>(&self, bearing: f32, target: &mut T) -> Result<(), T::Error> {
+        Circle::with_center(self.center, self.diameter)
+            .into_styled(PrimitiveStyle::with_stroke(self.color, 1))
+            .draw(target)?;
+        let radius = self.diameter as f32 / 2.0;
+        let tick = (radius / 3.0).max(2.0);
+        for (point, width) in [(0.0, 2), (90.0, 1), (180.0, 1), (270.0, 1)] {
+            Line::new(self.along(point, radius), self.along(point, radius - tick))
+                .into_styled(PrimitiveStyle::with_stroke(self.color, width))
+                .draw(target)?;
+        }
+        if bearing.is_finite() {
+            Line::new(self.center, self.along(bearing, radius - tick))
+                .into_styled(PrimitiveStyle::with_stroke(self.needle, 2))
+                .draw(target)?;
+        }
+        target.fill_solid(&Rectangle::with_center(self.center, Size::new(3, 3)), self.color)
+    }
+
+    // The point `distance` pixels from the middle, `bearing` degrees clockwise from up
+    fn along(&self, bearing: f32, distance: f32) -> Point {
+        let (sin, cos) = bearing.to_radians().sin_cos();
+        self.center + Point::new((sin * distance).round() as i32, -(cos * distance).round() as i32)
+    }
+}
@@ -0,0 +1,838 @@
+// A built-in set of 16x16 one-bit icons: battery levels, Wi-Fi bars, status
+// glyphs, moon phases, weather, and the sun and tides.
+//
+// Icons are stored as one `u16` per row, most significant bit on the left, and
+// only their set pixels are drawn, so they can go over any background.
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use embedded_graphics::prelude::*;
+
+use crate::framebuffer::Color;
+
+/// Width and height of every icon, in pixels.
+pub const SIZE: u32 = 16;
+
+/// Length of the synodic month, new moon to new moon, in days.
+pub const SYNODIC_MONTH: f64 = 29.530_588_853;
+
+/// Named in configs and plugins in snake_case: `battery_full`, `wifi3`, `moon_new`, ...
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Icon {
+    BatteryEmpty,
+    Battery25,
+    Battery50,
+    Battery75,
+    BatteryFull,
+    /// Empty battery with a lightning bolt
+    BatteryCharging,
+    /// Wi-Fi fan struck through: not associated
+    WifiOff,
+    /// One bar: signal is barely there
+    Wifi1,
+    Wifi2,
+    Wifi3,
+    /// Every bar lit
+    Wifi4,
+    /// Exclamation mark in a triangle
+    Warning,
+    /// Two arrows chasing each other round a circle
+    Sync,
+    /// Tick: all well
+    Check,
+    /// Diagonal cross: stopped or failed
+    Cross,
+    /// Two upright bars
+    Pause,
+    MoonNew,
+    MoonWaxingCrescent,
+    MoonFirstQuarter,
+    MoonWaxingGibbous,
+    MoonFull,
+    MoonWaningGibbous,
+    MoonLastQuarter,
+    MoonWaningCrescent,
+    Sun,
+    PartlyCloudy,
+    Cloud,
+    Rain,
+    Snow,
+    Thunder,
+    Fog,
+    /// Half a sun on the horizon, arrow up
+    Sunrise,
+    /// Half a sun on the horizon, arrow down
+    Sunset,
+    /// Three rows of waves
+    Tide,
+}
+
+impl Icon {
+    /// Every icon, in declaration order.
+    pub const ALL: [Icon; 34] = [
+        Icon::BatteryEmpty,
+        Icon::Battery25,
+        Icon::Battery50,
+        Icon::Battery75,
+        Icon::BatteryFull,
+        Icon::BatteryCharging,
+        Icon::WifiOff,
+        Icon::Wifi1,
+        Icon::Wifi2,
+        Icon::Wifi3,
+        Icon::Wifi4,
+        Icon::Warning,
+        Icon::Sync,
+        Icon::Check,
+        Icon::Cross,
+        Icon::Pause,
+        Icon::MoonNew,
+        Icon::MoonWaxingCrescent,
+        Icon::MoonFirstQuarter,
+        Icon::MoonWaxingGibbous,
+        Icon::MoonFull,
+        Icon::MoonWaningGibbous,
+        Icon::MoonLastQuarter,
+        Icon::MoonWaningCrescent,
+        Icon::Sun,
+        Icon::PartlyCloudy,
+        Icon::Cloud,
+        Icon::Rain,
+        Icon::Snow,
+        Icon::Thunder,
+        Icon::Fog,
+        Icon::Sunrise,
+        Icon::Sunset,
+        Icon::Tide,
+    ];
+
+    /// The battery icon for a charge of `percent`, rounded to the nearest quarter.
+    pub fn battery(percent: u8) -> Self {
+        match percent.min(100) {
+            0..=12 => Icon::BatteryEmpty,
+            13..=37 => Icon::Battery25,
+            38..=62 => Icon::Battery50,
+            63..=87 => Icon::Battery75,
+            _ => Icon::BatteryFull,
+        }
+    }
+
+    /// The Wi-Fi icon with `bars` of four lit; zero means no connection.
+    pub fn wifi(bars: u8) -> Self {
+        match bars {
+            0 => Icon::WifiOff,
+            1 => Icon::Wifi1,
+            2 => Icon::Wifi2,
+            3 => Icon::Wifi3,
+            _ => Icon::Wifi4,
+        }
+    }
+
+    /// The Wi-Fi icon for a signal level in dBm, as `iw` and `/proc/net/wireless` report it.
+    pub fn wifi_signal(dbm: i32) -> Self {
+        Icon::wifi(match dbm {
+            -55.. => 4,
+            -67..=-56 => 3,
+            -78..=-68 => 2,
+            _ => 1,
+        })
+    }
+
+    /// The moon icon for `phase`, the fraction of the lunar cycle since new moon.
+    pub fn moon(phase: f64) -> Self {
+        const PHASES: [Icon; 8] = [
+            Icon::MoonNew,
+            Icon::MoonWaxingCrescent,
+            Icon::MoonFirstQuarter,
+            Icon::MoonWaxingGibbous,
+            Icon::MoonFull,
+            Icon::MoonWaningGibbous,
+            Icon::MoonLastQuarter,
+            Icon::MoonWaningCrescent,
+        ];
+        let eighth = (phase.rem_euclid(1.0) * 8.0).round() as usize % 8;
+        PHASES[eighth]
+    }
+
+    /// The moon icon for midday on `date`.
+    pub fn moon_on(date: NaiveDate) -> Self {
+        Icon::moon(moon_phase(date.and_time(NaiveTime::from_hms_opt(12, 0, 0).unwrap_or_default())))
+    }
+}
+
+/// Fraction of the lunar cycle since the last new moon at `time` (UTC), from
+/// the mean synodic month; good to within a day, which is all an icon needs.
+pub fn moon_phase(time: NaiveDateTime) -> f64 {
+    // A new moon: 2000-01-06 18:14 UTC
+    let reference = NaiveDate::from_ymd_opt(2000, 1, 6)
+        .and_then(|date| date.and_hms_opt(18, 14, 0))
+        .unwrap_or_default();
+    let days = (time - reference).num_seconds() as f64 / 86_400.0;
+    (days / SYNODIC_MONTH).rem_euclid(1.0)
+}
+
+/// The icon's rows, most significant bit on the left.
+pub fn rows(icon: Icon) -> &'static [u16; 16] {
+    &ATLAS[icon as usize]
+}
+
+/// Draws `icon` with its top-left corner at `point`, setting only the icon's own pixels.
+pub fn draw<T: DrawTarget<Color = Color>>(icon: Icon, point: Point, color: Color, target: &mut T) -> Result<(), T::Error> {
+    let rows = rows(icon);
+    target.draw_iter(rows.iter().enumerate().flat_map(|(y, row)| {
+        (0..SIZE as usize)
+            .filter(move |x| row & (0x8000 >> x) != 0)
+            .map(move |x| Pixel(point + Point::new(x as i32, y as i32), color))
+    }))
+}
+
+// Indexed by `Icon as usize`, so it must stay in the same order as the enum
+#[rustfmt::skip]
+const ATLAS: [[u16; 16]; 34] = [
+    // BatteryEmpty
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b1111111111111100,
+        0b1000000000000100,
+        0b1000000000000111,
+        0b1000000000000111,
+        0b1000000000000111,
+        0b1000000000000111,
+        0b1000000000000100,
+        0b1111111111111100,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Battery25
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b1111111111111100,
+        0b1000000000000100,
+        0b1011100000000111,
+        0b1011100000000111,
+        0b1011100000000111,
+        0b1011100000000111,
+        0b1000000000000100,
+        0b1111111111111100,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Battery50
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b1111111111111100,
+        0b1000000000000100,
+        0b1011111100000111,
+        0b1011111100000111,
+        0b1011111100000111,
+        0b1011111100000111,
+        0b1000000000000100,
+        0b1111111111111100,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Battery75
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b1111111111111100,
+        0b1000000000000100,
+        0b1011111111100111,
+        0b1011111111100111,
+        0b1011111111100111,
+        0b1011111111100111,
+        0b1000000000000100,
+        0b1111111111111100,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // BatteryFull
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b1111111111111100,
+        0b1000000000000100,
+        0b1011111111111111,
+        0b1011111111111111,
+        0b1011111111111111,
+        0b1011111111111111,
+        0b1000000000000100,
+        0b1111111111111100,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // BatteryCharging
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b1111111111111100,
+        0b1000000010000100,
+        0b1000000100000111,
+        0b1000001111000111,
+        0b1000000110000111,
+        0b1000000100000111,
+        0b1000001000000100,
+        0b1111111111111100,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // WifiOff
+    [
+        0b0000111111110000,
+        0b0100111111111100,
+        0b0010000000011111,
+        0b1001000000000011,
+        0b1000100111100001,
+        0b0000010011111000,
+        0b0111001000011110,
+        0b0110000100000110,
+        0b0000000010000000,
+        0b0000111001000000,
+        0b0000110000100000,
+        0b0000000000010000,
+        0b0000001111001000,
+        0b0000001111000100,
+        0b0000001111000010,
+        0b0000001111000000,
+    ],
+    // Wifi1
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+    ],
+    // Wifi2
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000001111000000,
+        0b0000111111110000,
+        0b0000110000110000,
+        0b0000000000000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+    ],
+    // Wifi3
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000011111100000,
+        0b0001111111111000,
+        0b0111100000011110,
+        0b0110000000000110,
+        0b0000001111000000,
+        0b0000111111110000,
+        0b0000110000110000,
+        0b0000000000000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+    ],
+    // Wifi4
+    [
+        0b0000111111110000,
+        0b0011111111111100,
+        0b1111100000011111,
+        0b1100000000000011,
+        0b1000011111100001,
+        0b0001111111111000,
+        0b0111100000011110,
+        0b0110000000000110,
+        0b0000001111000000,
+        0b0000111111110000,
+        0b0000110000110000,
+        0b0000000000000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000001111000000,
+    ],
+    // Warning
+    [
+        0b0000000000000000,
+        0b0000000110000000,
+        0b0000000110000000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000011111100000,
+        0b0000011111100000,
+        0b0000110110110000,
+        0b0001100110011000,
+        0b0001100110011000,
+        0b0011000110001100,
+        0b0011000000001100,
+        0b0110000110000110,
+        0b0110000110000110,
+        0b1111111111111111,
+        0b0000000000000000,
+    ],
+    // Sync
+    [
+        0b0000000000000000,
+        0b0000011111100100,
+        0b0001100000011100,
+        0b0010000000001100,
+        0b0100000000011100,
+        0b0100000000000000,
+        0b1000000000000000,
+        0b1000000000000000,
+        0b0000000000000001,
+        0b0000000000000001,
+        0b0000000000000010,
+        0b0011100000000010,
+        0b0011000000000100,
+        0b0011100000011000,
+        0b0010011111100000,
+        0b0000000000000000,
+    ],
+    // Check
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000011,
+        0b0000000000000111,
+        0b0000000000001110,
+        0b0000000000011100,
+        0b0000000000111000,
+        0b1100000001110000,
+        0b1110000011100000,
+        0b0111000111000000,
+        0b0011101110000000,
+        0b0001111100000000,
+        0b0000111000000000,
+        0b0000010000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Cross
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0011000000001100,
+        0b0011100000011100,
+        0b0001110000111000,
+        0b0000111001110000,
+        0b0000011111100000,
+        0b0000001111000000,
+        0b0000001111000000,
+        0b0000011111100000,
+        0b0000111001110000,
+        0b0001110000111000,
+        0b0011100000011100,
+        0b0011000000001100,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Pause
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0001110000111000,
+        0b0001110000111000,
+        0b0001110000111000,
+        0b0001110000111000,
+        0b0001110000111000,
+        0b0001110000111000,
+        0b0001110000111000,
+        0b0001110000111000,
+        0b0001110000111000,
+        0b0001110000111000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // MoonNew
+    [
+        0b0000000000000000,
+        0b0000011111100000,
+        0b0001110000111000,
+        0b0011000000001100,
+        0b0010000000000100,
+        0b0110000000000110,
+        0b0100000000000010,
+        0b0100000000000010,
+        0b0100000000000010,
+        0b0100000000000010,
+        0b0110000000000110,
+        0b0010000000000100,
+        0b0011000000001100,
+        0b0001110000111000,
+        0b0000011111100000,
+        0b0000000000000000,
+    ],
+    // MoonWaxingCrescent
+    [
+        0b0000000000000000,
+        0b0000011111100000,
+        0b0001110000111000,
+        0b0011000000001100,
+        0b0010000000001100,
+        0b0110000000000110,
+        0b0100000000000110,
+        0b0100000000000110,
+        0b0100000000000110,
+        0b0100000000000110,
+        0b0110000000000110,
+        0b0010000000001100,
+        0b0011000000001100,
+        0b0001110000111000,
+        0b0000011111100000,
+        0b0000000000000000,
+    ],
+    // MoonFirstQuarter
+    [
+        0b0000000000000000,
+        0b0000011111100000,
+        0b0001110011111000,
+        0b0011000011111100,
+        0b0010000011111100,
+        0b0110000011111110,
+        0b0100000011111110,
+        0b0100000011111110,
+        0b0100000011111110,
+        0b0100000011111110,
+        0b0110000011111110,
+        0b0010000011111100,
+        0b0011000011111100,
+        0b0001110011111000,
+        0b0000011111100000,
+        0b0000000000000000,
+    ],
+    // MoonWaxingGibbous
+    [
+        0b0000000000000000,
+        0b0000011111100000,
+        0b0001111111111000,
+        0b0011111111111100,
+        0b0010111111111100,
+        0b0111111111111110,
+        0b0101111111111110,
+        0b0101111111111110,
+        0b0101111111111110,
+        0b0101111111111110,
+        0b0111111111111110,
+        0b0010111111111100,
+        0b0011111111111100,
+        0b0001111111111000,
+        0b0000011111100000,
+        0b0000000000000000,
+    ],
+    // MoonFull
+    [
+        0b0000000000000000,
+        0b0000011111100000,
+        0b0001111111111000,
+        0b0011111111111100,
+        0b0011111111111100,
+        0b0111111111111110,
+        0b0111111111111110,
+        0b0111111111111110,
+        0b0111111111111110,
+        0b0111111111111110,
+        0b0111111111111110,
+        0b0011111111111100,
+        0b0011111111111100,
+        0b0001111111111000,
+        0b0000011111100000,
+        0b0000000000000000,
+    ],
+    // MoonWaningGibbous
+    [
+        0b0000000000000000,
+        0b0000011111100000,
+        0b0001111111111000,
+        0b0011111111111100,
+        0b0011111111110100,
+        0b0111111111111110,
+        0b0111111111111010,
+        0b0111111111111010,
+        0b0111111111111010,
+        0b0111111111111010,
+        0b0111111111111110,
+        0b0011111111110100,
+        0b0011111111111100,
+        0b0001111111111000,
+        0b0000011111100000,
+        0b0000000000000000,
+    ],
+    // MoonLastQuarter
+    [
+        0b0000000000000000,
+        0b0000011111100000,
+        0b0001111100111000,
+        0b0011111100001100,
+        0b0011111100000100,
+        0b0111111100000110,
+        0b0111111100000010,
+        0b0111111100000010,
+        0b0111111100000010,
+        0b0111111100000010,
+        0b0111111100000110,
+        0b0011111100000100,
+        0b0011111100001100,
+        0b0001111100111000,
+        0b0000011111100000,
+        0b0000000000000000,
+    ],
+    // MoonWaningCrescent
+    [
+        0b0000000000000000,
+        0b0000011111100000,
+        0b0001110000111000,
+        0b0011000000001100,
+        0b0011000000000100,
+        0b0110000000000110,
+        0b0110000000000010,
+        0b0110000000000010,
+        0b0110000000000010,
+        0b0110000000000010,
+        0b0110000000000110,
+        0b0011000000000100,
+        0b0011000000001100,
+        0b0001110000111000,
+        0b0000011111100000,
+        0b0000000000000000,
+    ],
+    // Sun
+    [
+        0b0000000100000000,
+        0b0000000000000000,
+        0b0000000100000000,
+        0b0001000000001000,
+        0b0000011111100000,
+        0b0000110000110000,
+        0b0000100000010000,
+        0b0000100000010000,
+        0b1010100000010010,
+        0b0000100000010000,
+        0b0000110000110000,
+        0b0000011111100000,
+        0b0001000000001000,
+        0b0000000000000000,
+        0b0000000010000000,
+        0b0000000000000000,
+    ],
+    // PartlyCloudy
+    [
+        0b0000010000000000,
+        0b0100000001000000,
+        0b0000111000000000,
+        0b0001111100000000,
+        0b0011000110000000,
+        0b1011000111110000,
+        0b0011000100001000,
+        0b0001111000000100,
+        0b0001000000000100,
+        0b0010000000000010,
+        0b0010000000000010,
+        0b0010000000000001,
+        0b0001000000000010,
+        0b0001111111111110,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Cloud
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000011110000,
+        0b0000000100001000,
+        0b0000111000000100,
+        0b0001000000000100,
+        0b0010000000000010,
+        0b0010000000000010,
+        0b0010000000000001,
+        0b0001000000000010,
+        0b0001111111111110,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Rain
+    [
+        0b0000000000000000,
+        0b0000000011110000,
+        0b0000000100001000,
+        0b0000111000000100,
+        0b0001000000000100,
+        0b0010000000000010,
+        0b0010000000000010,
+        0b0010000000000001,
+        0b0001000000000010,
+        0b0001111111111110,
+        0b0000000000000000,
+        0b0000010001000100,
+        0b0000100010001000,
+        0b0001000100010000,
+        0b0010001000100000,
+        0b0000000000000000,
+    ],
+    // Snow
+    [
+        0b0000000000000000,
+        0b0000000011110000,
+        0b0000000100001000,
+        0b0000111000000100,
+        0b0001000000000100,
+        0b0010000000000010,
+        0b0010000000000010,
+        0b0010000000000001,
+        0b0001000000000010,
+        0b0001111111111110,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000100000001000,
+        0b0001010010010100,
+        0b0000100101001000,
+        0b0000000010000000,
+    ],
+    // Thunder
+    [
+        0b0000000000000000,
+        0b0000000011110000,
+        0b0000000100001000,
+        0b0000111000000100,
+        0b0001000000000100,
+        0b0010000000000010,
+        0b0010000000000010,
+        0b0010000000000001,
+        0b0001000000000010,
+        0b0001111111111110,
+        0b0000000000000000,
+        0b0000000010000000,
+        0b0000000100000000,
+        0b0000001110000000,
+        0b0000000100000000,
+        0b0000001000000000,
+    ],
+    // Fog
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0001111111111110,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0111111111111000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0001111111111110,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0111111111111000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+    // Sunrise
+    [
+        0b0000000100000000,
+        0b0000001110000000,
+        0b0000010101000000,
+        0b0000000100000000,
+        0b0000000000000000,
+        0b0010000000000100,
+        0b0001000000001000,
+        0b0000011111100000,
+        0b0000110000110000,
+        0b0000100000010000,
+        0b1100100000010011,
+        0b1111111111111111,
+        0b0000000000000000,
+        0b0011111111111100,
+        0b0000000000000000,
+        0b0000111111110000,
+    ],
+    // Sunset
+    [
+        0b0000000100000000,
+        0b0000010101000000,
+        0b0000001110000000,
+        0b0000000100000000,
+        0b0000000000000000,
+        0b0010000000000100,
+        0b0001000000001000,
+        0b0000011111100000,
+        0b0000110000110000,
+        0b0000100000010000,
+        0b1100100000010011,
+        0b1111111111111111,
+        0b0000000000000000,
+        0b0011111111111100,
+        0b0000000000000000,
+        0b0000111111110000,
+    ],
+    // Tide
+    [
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0110000110000110,
+        0b1001001001001001,
+        0b0000110000110000,
+        0b0000000000000000,
+        0b0110000110000110,
+        0b1001001001001001,
+        0b0000110000110000,
+        0b0000000000000000,
+        0b0110000110000110,
+        0b1001001001001001,
+        0b0000110000110000,
+        0b0000000000000000,
+        0b0000000000000000,
+        0b0000000000000000,
+    ],
+];
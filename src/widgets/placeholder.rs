@@ -0,0 +1,37 @@
+// What a widget draws instead of itself when it can't: a red outline, a
+// warning icon and a short code, inside the widget's own cell. The rest of
+// the page draws as normal, and the details go to the status API (see
+// `metrics::report_widgets`).
+
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Baseline, Text};
+use profont::PROFONT_7_POINT;
+
+use crate::framebuffer::Color;
+use crate::widgets::icon::{self, Icon};
+
+/// Fills `bounds` with as much of the placeholder as fits: the outline always,
+/// then the icon and `code` if there is room for them.
+pub fn draw<T: DrawTarget<Color = Color>>(bounds: Rectangle, code: &str, target: &mut T) -> Result<(), T::Error> {
+    bounds.into_styled(PrimitiveStyle::with_stroke(Color::Red, 1)).draw(target)?;
+    let inner = bounds.offset(-2);
+    let mut left = inner.top_left;
+    if inner.size.width >= icon::SIZE && inner.size.height >= icon::SIZE {
+        let y = (inner.size.height - icon::SIZE) as i32 / 2;
+        icon::draw(Icon::Warning, left + Point::new(0, y), Color::Red, target)?;
+        left.x += icon::SIZE as i32 + 2;
+    }
+    let font = &PROFONT_7_POINT;
+    let room = (inner.top_left.x + inner.size.width as i32 - left.x).max(0) as u32;
+    let columns = (room / font.character_size.width) as usize;
+    if columns == 0 || inner.size.height < font.character_size.height {
+        return Ok(());
+    }
+    let code: String = code.chars().take(columns).collect();
+    let y = (inner.size.height - font.character_size.height) as i32 / 2;
+    Text::with_baseline(&code, left + Point::new(0, y), MonoTextStyle::new(font, Color::Red), Baseline::Top)
+        .draw(target)?;
+    Ok(())
+}
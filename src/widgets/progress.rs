@@ -0,0 +1,242 @@
+// How full something is, as a bar or a dial: a print job, a battery, a disk.
+// Both take a fraction from 0 to 1, fill to it, and turn red past a
+// threshold, in whatever box they're given. `themed` gives them the frame
+// the theme asks for.
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{Arc, Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, StrokeAlignment};
+
+use crate::framebuffer::Color;
+use crate::theme::{Border, BorderStyle, Theme};
+
+/// How the filled part is painted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fill {
+    #[default]
+    Solid,
+    /// Diagonal lines, lighter than solid and still clear on a panel without grey
+    Hatched,
+}
+
+/// Where the colour changes to `alert`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threshold {
+    /// Over this fraction: a disk filling up
+    Above(f32),
+    /// Under this fraction: a battery running down
+    Below(f32),
+}
+
+impl Threshold {
+    pub fn is_crossed(self, value: f32) -> bool {
+        match self {
+            Threshold::Above(limit) => value > limit,
+            Threshold::Below(limit) => value < limit,
+        }
+    }
+}
+
+/// A bar filling left to right, or bottom to top if it is taller than wide.
+#[derive(Clone, Copy, Debug)]
+pub struct ProgressBar {
+    pub bounds: Rectangle,
+    /// Width of the frame round it; 0 for none
+    pub border: u32,
+    pub style: BorderStyle,
+    pub fill: Fill,
+    pub color: Color,
+    pub threshold: Option<Threshold>,
+    /// Colour of the fill once `threshold` is crossed
+    pub alert: Color,
+}
+
+impl ProgressBar {
+    /// A solid black bar in a one-pixel frame with a pixel of space inside it.
+    pub fn new(bounds: Rectangle) -> Self {
+        ProgressBar {
+            bounds,
+            border: 1,
+            style: BorderStyle::Solid,
+            fill: Fill::Solid,
+            color: Color::Black,
+            threshold: None,
+            alert: Color::Red,
+        }
+    }
+
+    /// With the theme's frame.
+    pub fn themed(mut self, theme: &Theme) -> Self {
+        self.border = theme.border.width;
+        self.style = theme.border.style;
+        self
+    }
+
+    /// Draws the bar filled to `value`, clamped to 0..=1; NaN draws just the frame.
+    pub fn draw<T: DrawTarget<Color = Color>>(&self, value: f32, target: &mut T) -> Result<(), T::Error> {
+        let border = Border {
+            width: self.border,
+            style: self.style,
+        };
+        border.draw(self.bounds, self.color, target)?;
+        if value.is_nan() {
+            return Ok(());
+        }
+        let value = value.clamp(0.0, 1.0);
+        // A pixel of daylight between the frame and the fill
+        let inner = match self.border {
+            0 => self.bounds,
+            border => self.bounds.offset(-(border as i32 + 1)),
+        };
+        let filled = if inner.size.height > inner.size.width {
+            let height = (inner.size.height as f32 * value).round() as u32;
+            Rectangle::new(
+                inner.top_left + Point::new(0, (inner.size.height - height) as i32),
+                Size::new(inner.size.width, height),
+            )
+        } else {
+            Rectangle::new(inner.top_left, Size::new((inner.size.width as f32 * value).round() as u32, inner.size.height))
+        };
+        let color = fill_color(value, self.threshold, self.color, self.alert);
+        match self.fill {
+            Fill::Solid => target.fill_solid(&filled, color),
+            Fill::Hatched => filled.into_styled(PrimitiveStyle::with_fill(color)).draw(&mut Hatch(target)),
+        }
+    }
+}
+
+/// Half a dial: an arc over the top from the left end (0) round to the right (1).
+#[derive(Clone, Copy, Debug)]
+pub struct Gauge {
+    pub bounds: Rectangle,
+    /// Thickness of the arc
+    pub thickness: u32,
+    /// Width of the outline round the whole arc; 0 for none
+    pub border: u32,
+    /// `Rounded` is as `Solid`, the outline having no corners
+    pub style: BorderStyle,
+    pub fill: Fill,
+    pub color: Color,
+    pub threshold: Option<Threshold>,
+    /// Colour of the fill once `threshold` is crossed
+    pub alert: Color,
+}
+
+impl Gauge {
+    /// As large as fits in `bounds`, centred along the bottom, with an arc a
+    /// fifth of the radius thick.
+    pub fn new(bounds: Rectangle) -> Self {
+        let radius = (bounds.size.width / 2).min(bounds.size.height);
+        Gauge {
+            bounds,
+            thickness: (radius / 5).max(2),
+            border: 1,
+            style: BorderStyle::Solid,
+            fill: Fill::Solid,
+            color: Color::Black,
+            threshold: None,
+            alert: Color::Red,
+        }
+    }
+
+    /// With the theme's outline.
+    pub fn themed(mut self, theme: &Theme) -> Self {
+        self.border = theme.border.width;
+        self.style = theme.border.style;
+        self
+    }
+
+    /// The circle the arc lies on, as its top-left corner and diameter.
+    pub fn circle(&self) -> (Point, u32) {
+        let radius = (self.bounds.size.width / 2).min(self.bounds.size.height);
+        let center = Point::new(
+            self.bounds.top_left.x + self.bounds.size.width as i32 / 2,
+            self.bounds.top_left.y + ((self.bounds.size.height + radius) / 2) as i32,
+        );
+        (center - Point::new(radius as i32, radius as i32), radius * 2)
+    }
+
+    /// Draws the dial filled to `value`, clamped to 0..=1; NaN draws just the outline.
+    pub fn draw<T: DrawTarget<Color = Color>>(&self, value: f32, target: &mut T) -> Result<(), T::Error> {
+        let (top_left, diameter) = self.circle();
+        let thickness = self.thickness.min(diameter / 2);
+        if self.border > 0 {
+            let border = Border {
+                width: self.border,
+                style: self.style,
+            };
+            let mut target = border.stroke(target);
+            let outline = PrimitiveStyleBuilder::new()
+                .stroke_color(self.color)
+                .stroke_width(self.border)
+                .stroke_alignment(StrokeAlignment::Inside)
+                .build();
+            let inset = (thickness - self.border.min(thickness)) as i32;
+            Arc::new(top_left, diameter, 180.0.deg(), 180.0.deg()).into_styled(outline).draw(&mut target)?;
+            let inner = diameter.saturating_sub(2 * thickness - 2 * self.border.min(thickness));
+            Arc::new(top_left + Point::new(inset, inset), inner, 180.0.deg(), 180.0.deg())
+                .into_styled(outline)
+                .draw(&mut target)?;
+            // Close off the two ends along the bottom
+            let radius = diameter as i32 / 2;
+            let y = top_left.y + radius;
+            for x in [top_left.x, top_left.x + diameter as i32 - thickness as i32] {
+                Line::new(Point::new(x, y), Point::new(x + thickness as i32 - 1, y))
+                    .into_styled(PrimitiveStyle::with_stroke(self.color, self.border))
+                    .draw(&mut target)?;
+            }
+        }
+        if value.is_nan() {
+            return Ok(());
+        }
+        let value = value.clamp(0.0, 1.0);
+        if value == 0.0 {
+            return Ok(());
+        }
+        // Inside the outline, with a pixel of daylight when there is room
+        let gap = if self.border > 0 && thickness >= self.border * 2 + 3 { self.border + 1 } else { self.border };
+        let width = thickness.saturating_sub(2 * gap).max(1);
+        let style = PrimitiveStyleBuilder::new()
+            .stroke_color(fill_color(value, self.threshold, self.color, self.alert))
+            .stroke_width(width)
+            .stroke_alignment(StrokeAlignment::Inside)
+            .build();
+        let arc = Arc::new(
+            top_left + Point::new(gap as i32, gap as i32),
+            diameter.saturating_sub(2 * gap),
+            180.0.deg(),
+            (180.0 * value).deg(),
+        )
+        .into_styled(style);
+        match self.fill {
+            Fill::Solid => arc.draw(target),
+            Fill::Hatched => arc.draw(&mut Hatch(target)),
+        }
+    }
+}
+
+fn fill_color(value: f32, threshold: Option<Threshold>, color: Color, alert: Color) -> Color {
+    if threshold.is_some_and(|threshold| threshold.is_crossed(value)) { alert } else { color }
+}
+
+// Draws only the pixels on every third diagonal
+struct Hatch<'a, T>(&'a mut T);
+
+impl<T: DrawTarget<Color = Color>> DrawTarget for Hatch<'_, T> {
+    type Color = Color;
+    type Error = T::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Color>>,
+    {
+        self.0
+            .draw_iter(pixels.into_iter().filter(|Pixel(point, _)| (point.x + point.y).rem_euclid(3) == 0))
+    }
+}
+
+impl<T: DrawTarget<Color = Color>> Dimensions for Hatch<'_, T> {
+    fn bounding_box(&self) -> Rectangle {
+        self.0.bounding_box()
+    }
+}
@@ -0,0 +1,201 @@
+// Seven-segment digits drawn from rectangles, so they scale to any height
+// without a font.
+
+use chrono::{NaiveTime, Timelike};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+use crate::framebuffer::Color;
+
+// Segment bits: a (top), b (top right), c (bottom right), d (bottom),
+// e (bottom left), f (top left), g (middle)
+const A: u8 = 1 << 0;
+const B: u8 = 1 << 1;
+const C: u8 = 1 << 2;
+const D: u8 = 1 << 3;
+const E: u8 = 1 << 4;
+const F: u8 = 1 << 5;
+const G: u8 = 1 << 6;
+
+const DIGITS: [u8; 10] = [
+    A | B | C | D | E | F,
+    B | C,
+    A | B | D | E | G,
+    A | B | C | D | G,
+    B | C | F | G,
+    A | C | D | F | G,
+    A | C | D | E | F | G,
+    A | B | C,
+    A | B | C | D | E | F | G,
+    A | B | C | D | F | G,
+];
+
+/// Geometry and colour of a run of seven-segment characters.
+#[derive(Clone, Copy, Debug)]
+pub struct SevenSegment {
+    /// Size of one digit cell
+    pub digit: Size,
+    /// Stroke width of each segment
+    pub thickness: u32,
+    /// Blank columns between characters
+    pub spacing: u32,
+    pub color: Color,
+}
+
+impl SevenSegment {
+    /// Digits `height` pixels tall, half as wide, in black.
+    pub fn new(height: u32) -> Self {
+        let height = height.max(5);
+        SevenSegment {
+            digit: Size::new(height / 2, height),
+            thickness: (height / 8).max(1),
+            spacing: (height / 10).max(1),
+            color: Color::Black,
+        }
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Width of `c` alone: digits, `' '` and `'-'` take a digit cell, `':'` and `'.'` a segment's width.
+    pub fn char_width(&self, c: char) -> u32 {
+        match c {
+            ':' | '.' => self.thickness,
+            _ => self.digit.width,
+        }
+    }
+
+    /// Width of `text` including the spacing between characters.
+    pub fn text_width(&self, text: &str) -> u32 {
+        let count = text.chars().count() as u32;
+        text.chars().map(|c| self.char_width(c)).sum::<u32>() + count.saturating_sub(1) * self.spacing
+    }
+
+    /// Draws `text` with its top-left corner at `origin`. Characters other than
+    /// digits, `:`, `.`, `-` and space are skipped but still take a cell.
+    pub fn draw<T: DrawTarget<Color = Color>>(&self, text: &str, origin: Point, target: &mut T) -> Result<(), T::Error> {
+        let mut x = origin.x;
+        for c in text.chars() {
+            let at = Point::new(x, origin.y);
+            match c {
+                '0'..='9' => self.draw_segments(DIGITS[c as usize - '0' as usize], at, target)?,
+                '-' => self.draw_segments(G, at, target)?,
+                ':' => self.draw_colon(at, target)?,
+                '.' => {
+                    let dot = Rectangle::new(
+                        at + Point::new(0, (self.digit.height - self.thickness) as i32),
+                        Size::new(self.thickness, self.thickness),
+                    );
+                    target.fill_solid(&dot, self.color)?;
+                }
+                _ => {}
+            }
+            x += (self.char_width(c) + self.spacing) as i32;
+        }
+        Ok(())
+    }
+
+    fn draw_colon<T: DrawTarget<Color = Color>>(&self, at: Point, target: &mut T) -> Result<(), T::Error> {
+        let t = self.thickness;
+        let dot = Size::new(t, t);
+        for y in [self.digit.height / 3, self.digit.height * 2 / 3] {
+            target.fill_solid(&Rectangle::new(at + Point::new(0, (y - t / 2) as i32), dot), self.color)?;
+        }
+        Ok(())
+    }
+
+    fn draw_segments<T: DrawTarget<Color = Color>>(&self, segments: u8, at: Point, target: &mut T) -> Result<(), T::Error> {
+        let (w, h, t) = (self.digit.width, self.digit.height, self.thickness);
+        // A pixel of daylight where segments meet, once there is room for it
+        let gap = u32::from(t >= 3);
+        let mid = (h - t) / 2;
+        let horizontal = Size::new(w.saturating_sub(2 * (t + gap)), t);
+        let upper = Size::new(t, mid.saturating_sub(t + 2 * gap));
+        let lower = Size::new(t, (h - t).saturating_sub(mid + t + 2 * gap));
+        let rects = [
+            (A, Point::new((t + gap) as i32, 0), horizontal),
+            (B, Point::new((w - t) as i32, (t + gap) as i32), upper),
+            (C, Point::new((w - t) as i32, (mid + t + gap) as i32), lower),
+            (D, Point::new((t + gap) as i32, (h - t) as i32), horizontal),
+            (E, Point::new(0, (mid + t + gap) as i32), lower),
+            (F, Point::new(0, (t + gap) as i32), upper),
+            (G, Point::new((t + gap) as i32, mid as i32), horizontal),
+        ];
+        for (bit, offset, size) in rects {
+            if segments & bit != 0 {
+                target.fill_solid(&Rectangle::new(at + offset, size), self.color)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `HH:MM` or `HH:MM:SS` in seven-segment digits, laid out so each field has
+/// its own region. Redrawing only touches the fields that changed and
+/// returns their regions, ready for a partial refresh.
+#[derive(Clone, Debug)]
+pub struct ClockDigits {
+    digits: SevenSegment,
+    origin: Point,
+    seconds: bool,
+    // Hours, minutes, seconds last drawn
+    shown: Option<[u32; 3]>,
+}
+
+impl ClockDigits {
+    pub fn new(digits: SevenSegment, origin: Point, seconds: bool) -> Self {
+        ClockDigits {
+            digits,
+            origin,
+            seconds,
+            shown: None,
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        let text = if self.seconds { "00:00:00" } else { "00:00" };
+        Size::new(self.digits.text_width(text), self.digits.digit.height)
+    }
+
+    /// Regions of the hours, minutes and seconds (empty without seconds).
+    pub fn regions(&self) -> [Rectangle; 3] {
+        let field = Size::new(self.digits.text_width("00"), self.digits.digit.height);
+        let step = (self.digits.text_width("00:") + self.digits.spacing) as i32;
+        let seconds = if self.seconds { field } else { Size::zero() };
+        [
+            Rectangle::new(self.origin, field),
+            Rectangle::new(self.origin + Point::new(step, 0), field),
+            Rectangle::new(self.origin + Point::new(2 * step, 0), seconds),
+        ]
+    }
+
+    /// Forget what was drawn, so the next `draw` paints everything.
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    /// Draws `time`, repainting (on white) only fields that differ from the last call.
+    /// Returns the regions that changed.
+    pub fn draw<T: DrawTarget<Color = Color>>(&mut self, time: NaiveTime, target: &mut T) -> Result<Vec<Rectangle>, T::Error> {
+        let fields = [time.hour(), time.minute(), if self.seconds { time.second() } else { 0 }];
+        let regions = self.regions();
+        let mut changed = Vec::new();
+        if self.shown.is_none() {
+            // First draw: the colons too
+            let colons = if self.seconds { "  :  :" } else { "  :" };
+            self.digits.draw(colons, self.origin, target)?;
+        }
+        for (index, (value, region)) in fields.iter().zip(regions).enumerate() {
+            if region.size == Size::zero() || self.shown.is_some_and(|shown| shown[index] == *value) {
+                continue;
+            }
+            target.fill_solid(&region, Color::White)?;
+            self.digits.draw(&format!("{value:02}"), region.top_left, target)?;
+            changed.push(region);
+        }
+        self.shown = Some(fields);
+        Ok(changed)
+    }
+}
@@ -0,0 +1,79 @@
+// Flight-board style transitions for numbers: rather than jumping straight
+// to the new value, each changed digit flips forward through the digits in
+// between, one panel refresh per step.
+
+use embedded_hal::blocking::delay::DelayMs;
+
+use crate::epd::EpdController;
+use crate::framebuffer::Framebuffer;
+
+/// The strings to show on the way from `from` to `to`, ending with `to`.
+///
+/// Digits that differ advance one step per frame, wrapping 9 to 0, so a
+/// change from "19" to "20" flips the tens once and the units once. Other
+/// characters, and positions that only one of the strings has, change in the
+/// first frame. At most `max_frames` are produced; the last is always `to`.
+pub fn frames(from: &str, to: &str, max_frames: usize) -> Vec<String> {
+    let mut current: Vec<char> = to
+        .chars()
+        .enumerate()
+        .map(|(index, target)| match from.chars().nth(index) {
+            Some(old) if old.is_ascii_digit() && target.is_ascii_digit() => old,
+            _ => target,
+        })
+        .collect();
+    let target: Vec<char> = to.chars().collect();
+
+    let mut frames = Vec::new();
+    while frames.len() + 1 < max_frames && current != target {
+        for (shown, wanted) in current.iter_mut().zip(&target) {
+            if shown != wanted {
+                *shown = next_digit(*shown);
+            }
+        }
+        if current != target {
+            frames.push(current.iter().collect());
+        }
+    }
+    frames.push(to.to_string());
+    frames
+}
+
+fn next_digit(digit: char) -> char {
+    match digit {
+        '9' => '0',
+        '0'..='8' => (digit as u8 + 1) as char,
+        other => other,
+    }
+}
+
+/// Steps the panel through [`frames`], calling `draw` to paint each one.
+/// Intermediate frames use the controller's fast refresh; the final one gets
+/// a normal refresh to clean up ghosting.
+pub fn animate<E, D, F>(
+    epd: &mut E,
+    fb: &mut Framebuffer,
+    delay: &mut D,
+    from: &str,
+    to: &str,
+    max_frames: usize,
+    mut draw: F,
+) -> Result<(), E::Error>
+where
+    E: EpdController,
+    D: DelayMs<u8>,
+    F: FnMut(&mut Framebuffer, &str),
+{
+    let frames = frames(from, to, max_frames);
+    let last = frames.len() - 1;
+    for (index, frame) in frames.iter().enumerate() {
+        draw(fb, frame);
+        epd.write_planes(fb.bw_plane(), fb.red_plane())?;
+        if index == last {
+            epd.refresh(delay)?;
+        } else {
+            epd.refresh_fast(delay)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,226 @@
+// Rows of text in columns, for departure boards and status lists.
+//
+// Columns start as wide as their widest cell. If that is too wide for the box
+// the `grow` columns give way first, then the rest, widest first, and any
+// cell that no longer fits ends in an ellipsis; if there is room to spare the
+// `grow` columns share it. Widths are whole character cells, since every font
+// we use is monospaced.
+
+use embedded_graphics::mono_font::MonoFont;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+use crate::framebuffer::Color;
+use crate::text::{self, Alignment};
+use crate::theme::Theme;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Column<'a> {
+    /// Heading over the column; the table has a heading row if any column has one
+    pub header: &'a str,
+    pub alignment: Alignment,
+    /// Whether the column takes up spare width and is the first to give it back
+    pub grow: bool,
+}
+
+impl<'a> Column<'a> {
+    pub fn new(header: &'a str) -> Self {
+        Column {
+            header,
+            alignment: Alignment::Left,
+            grow: false,
+        }
+    }
+
+    pub fn alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn grow(mut self) -> Self {
+        self.grow = true;
+        self
+    }
+}
+
+/// One cell's text, in its own colour.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+    pub text: String,
+    pub color: Color,
+}
+
+impl Cell {
+    pub fn colored(text: impl Into<String>, color: Color) -> Self {
+        Cell {
+            text: text.into(),
+            color,
+        }
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::colored(text, Color::Black)
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::colored(text, Color::Black)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Table<'a> {
+    pub bounds: Rectangle,
+    pub columns: &'a [Column<'a>],
+    pub font: &'a MonoFont<'a>,
+    /// Pixels between columns
+    pub gap: u32,
+    /// Extra pixels between rows
+    pub row_spacing: u32,
+    pub header_color: Color,
+}
+
+impl<'a> Table<'a> {
+    /// A character's width between columns and two pixels between rows, as
+    /// the pages lay out lists by hand.
+    pub fn new(bounds: Rectangle, columns: &'a [Column<'a>], font: &'a MonoFont<'a>) -> Self {
+        Table {
+            bounds,
+            columns,
+            font,
+            gap: font.character_size.width,
+            row_spacing: 2,
+            header_color: Color::Black,
+        }
+    }
+
+    /// With the heading in the theme's colour for headings.
+    pub fn themed(mut self, theme: &Theme) -> Self {
+        self.header_color = theme.heading();
+        self
+    }
+
+    pub fn row_height(&self) -> u32 {
+        self.font.character_size.height + self.row_spacing
+    }
+
+    fn has_header(&self) -> bool {
+        self.columns.iter().any(|column| !column.header.is_empty())
+    }
+
+    /// How many of `rows` fit below the heading.
+    pub fn capacity(&self) -> usize {
+        let header = if self.has_header() { self.row_height() } else { 0 };
+        (self.bounds.size.height.saturating_sub(header) / self.row_height()) as usize
+    }
+
+    /// Each column's width in characters, for these rows.
+    pub fn widths(&self, rows: &[Vec<Cell>]) -> Vec<usize> {
+        let mut widths: Vec<usize> = self.columns.iter().map(|column| text::str_columns(column.header)).collect();
+        for row in rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(text::str_columns(&cell.text));
+            }
+        }
+        let gaps = self.gap as usize * self.columns.len().saturating_sub(1);
+        let room = text::columns(self.font, (self.bounds.size.width as usize).saturating_sub(gaps) as u32);
+        let mut total: usize = widths.iter().sum();
+        // Too wide: take a character at a time off the widest column that can spare one
+        for grow_only in [true, false] {
+            while total > room {
+                let widest = (0..widths.len())
+                    .filter(|&i| widths[i] > 1 && (self.columns[i].grow || !grow_only))
+                    .max_by_key(|&i| (widths[i], i));
+                let Some(widest) = widest else { break };
+                widths[widest] -= 1;
+                total -= 1;
+            }
+        }
+        // Room to spare: shared between the `grow` columns
+        let growing: Vec<usize> = (0..widths.len()).filter(|&i| self.columns[i].grow).collect();
+        if !growing.is_empty() && total < room {
+            let spare = room - total;
+            for (n, &i) in growing.iter().enumerate() {
+                widths[i] += spare / growing.len() + usize::from(n < spare % growing.len());
+            }
+        }
+        widths
+    }
+
+    /// Draws the heading and as many of `rows` as fit, returning how many did.
+    pub fn draw<T: DrawTarget<Color = Color>>(&self, rows: &[Vec<Cell>], target: &mut T) -> Result<usize, T::Error> {
+        let widths = self.widths(rows);
+        let mut y = self.bounds.top_left.y;
+        if self.has_header() {
+            let header: Vec<Cell> = self
+                .columns
+                .iter()
+                .map(|column| Cell::colored(column.header, self.header_color))
+                .collect();
+            self.draw_row(&header, &widths, y, target)?;
+            // Ruled off from the rows, in the gap below the text
+            let rule = Rectangle::new(
+                Point::new(self.bounds.top_left.x, y + self.font.character_size.height as i32),
+                Size::new(self.bounds.size.width, 1),
+            );
+            target.fill_solid(&rule, self.header_color)?;
+            y += self.row_height() as i32;
+        }
+        let shown = rows.len().min(self.capacity());
+        for row in &rows[..shown] {
+            self.draw_row(row, &widths, y, target)?;
+            y += self.row_height() as i32;
+        }
+        Ok(shown)
+    }
+
+    fn draw_row<T: DrawTarget<Color = Color>>(&self, row: &[Cell], widths: &[usize], y: i32, target: &mut T) -> Result<(), T::Error> {
+        let font = self.font;
+        let advance = (font.character_size.width + font.character_spacing) as i32;
+        let mut x = self.bounds.top_left.x;
+        for ((column, &width), cell) in self.columns.iter().zip(widths).zip(row) {
+            let count = text::str_columns(&cell.text);
+            let cut = count > width;
+            // As many whole characters as leave a cell for the ellipsis
+            let mut shown = 0;
+            let text: String = cell
+                .text
+                .chars()
+                .take_while(|&c| {
+                    shown += text::char_columns(c);
+                    !cut || shown < width
+                })
+                .collect();
+            let shown = text::str_columns(&text);
+            let used = if cut { shown + 1 } else { count };
+            let slack = width.saturating_sub(used) as i32 * advance;
+            let left = x + match column.alignment {
+                Alignment::Left => 0,
+                Alignment::Center => slack / 2,
+                Alignment::Right => slack,
+            };
+            text::draw_line(&text, font, cell.color, Point::new(left, y), target)?;
+            if cut && width > 0 {
+                self.draw_ellipsis(Point::new(left + shown as i32 * advance, y), cell.color, target)?;
+            }
+            x += width as i32 * advance + self.gap as i32;
+        }
+        Ok(())
+    }
+
+    // ProFont has no `…`, so three dots along the baseline of one character cell
+    fn draw_ellipsis<T: DrawTarget<Color = Color>>(&self, at: Point, color: Color, target: &mut T) -> Result<(), T::Error> {
+        let size = self.font.character_size;
+        let dot = (size.width / 6).max(1);
+        let pitch = (size.width / 3).max(dot + 1) as i32;
+        let y = at.y + self.font.baseline as i32 + 1 - dot as i32;
+        for i in 0..3 {
+            let square = Rectangle::new(Point::new(at.x + i * pitch, y), Size::new_equal(dot));
+            target.fill_solid(&square, color)?;
+        }
+        Ok(())
+    }
+}